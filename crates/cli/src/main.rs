@@ -1,14 +1,13 @@
-use hex;
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fs::{self, File, OpenOptions};
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 
 use anyhow::{anyhow, bail, Context, Result};
-use bincode;
 use clap::{ArgAction, Args, Parser, Subcommand};
 use glob::glob;
 use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
@@ -25,15 +24,20 @@ use reqwest::header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT
 use reqwest::StatusCode;
 
 use three_dcf_core::{
-    estimate_tokens, BenchConfig, BenchMode, BenchRunner, CellType, ChunkConfig, ChunkMode,
-    ChunkRecord, Chunker, CorpusMetrics, Decoder, Document, EmbeddingRecord, Encoder, HashEmbedder,
-    HashEmbedderConfig, HyphenationMode, ImportanceTuning, Metrics, NumGuardAlert, NumGuardIssue,
-    Stats, TableMode, TextSerializer, TextSerializerConfig, TokenizerKind,
+    estimate_tokens, generate_signing_key, parent_window, preprocess_file, read_signing_key,
+    read_verifying_key, BenchConfig, BenchMode, BenchRunner, Bundle, ChunkConfig, ChunkLevel,
+    ChunkMode, ChunkRecord, Chunker, ContextFormat, CorpusMetrics, Decoder, DiffOptions, Document,
+    DocumentReader, DropReason, EmbeddingRecord, Encoder, EncoderBuilder, FaithfulnessPolicy,
+    HashEmbedder, HashEmbedderConfig, HyphenationMode, ImportanceTuning, IntegrityStatus,
+    LinearImportanceModel, Metrics, NumGuardAlert, NumGuardIssue, PiiKind, PreprocessConfig,
+    RedactionConfig, RedactionMode, Redactor, ScanFixture, Selector, Stats, TableMode,
+    TextSerializer, TextSerializerConfig, TokenizerKind, BUNDLE_EXTENSION, FORMAT_VERSION,
+    TRUSTED_KEY_ENV,
 };
 use three_dcf_rag::{
     encryption, execute_rag_query, normalize_level, CellInsert, DocumentInsert, EmbeddingClient,
     LlmClient, LlmProvider, LlmRequest, LlmResponse, PricingConfig, PricingRate, RagAnswer,
-    RagPolicy, RagQuery, RagStore,
+    RagPolicy, RagQuery, RagStore, SensitivityClassifier, SensitivityRules,
 };
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -71,6 +75,8 @@ enum Commands {
         text_out: Option<PathBuf>,
         #[arg(long = "cells-out")]
         cells_out: Option<PathBuf>,
+        #[arg(long = "redaction-log")]
+        redaction_log: Option<PathBuf>,
         #[arg(long, action = ArgAction::SetTrue)]
         quiet: bool,
     },
@@ -80,6 +86,12 @@ enum Commands {
         encode: EncodeArgs,
         #[arg(short = 'o', long = "out")]
         out: Option<PathBuf>,
+        /// ctx3d|xml|json|markdown|minimal
+        #[arg(long, default_value = "ctx3d")]
+        format: String,
+        /// Keep only the cells matching this selector expression.
+        #[arg(long)]
+        select: Option<String>,
         #[arg(long)]
         tokenizer: Option<String>,
         #[arg(long = "tokenizer-file")]
@@ -96,8 +108,10 @@ enum Commands {
         inputs: Vec<PathBuf>,
         #[command(flatten)]
         encode: EncodeArgs,
-        #[arg(long, default_value = "public")]
-        sensitivity: String,
+        #[arg(long)]
+        sensitivity: Option<String>,
+        #[arg(long = "sensitivity-rules")]
+        sensitivity_rules: Option<PathBuf>,
         #[arg(long, action = ArgAction::SetTrue)]
         encrypt: bool,
     },
@@ -115,6 +129,11 @@ enum Commands {
         provider: String,
         #[arg(long)]
         model: Option<String>,
+        #[arg(long, default_value = "flag")]
+        numeric_check: String,
+        /// Answer from each hit's section, cut to this many tokens around the hit.
+        #[arg(long)]
+        expand_parent: Option<usize>,
     },
     AskOpenai {
         input: PathBuf,
@@ -162,6 +181,9 @@ enum Commands {
         page: Option<u32>,
         #[arg(long = "select")]
         select: Option<String>,
+        /// Output format: text (default), markdown or html.
+        #[arg(long, default_value = "text")]
+        format: String,
         #[arg(long = "strict-numguard", action = ArgAction::SetTrue)]
         strict_numguard: bool,
         #[arg(long = "numguard-units")]
@@ -170,6 +192,9 @@ enum Commands {
     Serialize {
         input: PathBuf,
         out: PathBuf,
+        /// ctx3d|xml|json|markdown|minimal
+        #[arg(long, default_value = "ctx3d")]
+        format: String,
         #[arg(long, default_value_t = 64)]
         preview: usize,
         #[arg(long = "table-mode")]
@@ -186,6 +211,104 @@ enum Commands {
         #[arg(long = "tokenizer-file")]
         tokenizer_file: Option<PathBuf>,
     },
+    BundleAdd {
+        bundle: PathBuf,
+        inputs: Vec<PathBuf>,
+        #[arg(long)]
+        name: Option<String>,
+    },
+    BundleList {
+        bundle: PathBuf,
+    },
+    BundleExtract {
+        bundle: PathBuf,
+        name: String,
+        #[arg(long)]
+        out: Option<PathBuf>,
+        #[arg(long = "json-out")]
+        json_out: Option<PathBuf>,
+    },
+    Keygen {
+        /// Secret key file; the public key is written next to it as `<out>.pub`.
+        #[arg(long)]
+        out: PathBuf,
+    },
+    Sign {
+        input: PathBuf,
+        /// Secret key from `keygen`; without it only the Merkle root is recorded.
+        #[arg(long)]
+        key: Option<PathBuf>,
+        /// Defaults to rewriting `input`.
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    Verify {
+        input: PathBuf,
+        /// Trusted public key (hex or `.pub` file); defaults to `THREE_DCF_TRUSTED_KEY`.
+        #[arg(long = "public-key")]
+        public_key: Option<String>,
+    },
+    Migrate {
+        inputs: Vec<PathBuf>,
+        /// Report what would change without rewriting; exits non-zero if any
+        /// input is older than the current format version.
+        #[arg(long)]
+        check: bool,
+    },
+    RenderDebug {
+        /// A `.3dcf`/`.3dcf.json` container, or a source document to encode
+        /// with the encode flags (which also shows the cells they drop).
+        input: PathBuf,
+        #[command(flatten)]
+        encode: EncodeArgs,
+        #[arg(long = "out-dir", default_value = "debug")]
+        out_dir: PathBuf,
+        /// Only render this page.
+        #[arg(long)]
+        page: Option<u32>,
+        /// Also write PNG overlays.
+        #[arg(long)]
+        png: bool,
+        /// PNG pixels per page pixel.
+        #[arg(long, default_value_t = 1.0)]
+        scale: f32,
+    },
+    Inspect {
+        input: PathBuf,
+        /// Print the summary as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    Citations {
+        /// The container the context was built from.
+        input: PathBuf,
+        /// Model output to scan for cell IDs; reads stdin when omitted.
+        #[arg(long)]
+        answer: Option<PathBuf>,
+        #[arg(long)]
+        json: bool,
+        /// Fail when the answer cites IDs that match no cell.
+        #[arg(long)]
+        strict: bool,
+    },
+    Validate {
+        inputs: Vec<PathBuf>,
+        #[arg(long = "json-out")]
+        json_out: Option<PathBuf>,
+        /// Fail on warnings as well as errors.
+        #[arg(long)]
+        strict: bool,
+    },
+    Diff {
+        old: PathBuf,
+        new: PathBuf,
+        #[arg(long, default_value_t = 0.5)]
+        similarity: f32,
+        #[arg(long = "json-out")]
+        json_out: Option<PathBuf>,
+        #[arg(long = "report-out")]
+        report_out: Option<PathBuf>,
+    },
     Bench {
         root: PathBuf,
         #[arg(long)]
@@ -215,6 +338,17 @@ enum Commands {
         #[arg(long = "decode-p95-max")]
         decode_p95_max: Option<f64>,
     },
+    FitImportance {
+        root: PathBuf,
+        #[arg(long = "gold")]
+        gold_root: PathBuf,
+        #[arg(long)]
+        preset: Option<String>,
+        #[arg(long, default_value = "importance.toml")]
+        out: PathBuf,
+        #[arg(long, default_value_t = 0.1)]
+        l2: f32,
+    },
     Report {
         input: PathBuf,
         out: PathBuf,
@@ -226,6 +360,12 @@ enum Commands {
         recipient: String,
         #[arg(long = "redact", value_delimiter = ',')]
         redact_types: Vec<String>,
+        #[arg(long, action = ArgAction::SetTrue)]
+        redact_pii: bool,
+        #[arg(long)]
+        redact_mode: Option<String>,
+        #[arg(long)]
+        pii_gazetteer: Option<PathBuf>,
     },
     Decrypt {
         input: PathBuf,
@@ -239,6 +379,15 @@ enum Commands {
         count: usize,
         #[arg(long, default_value_t = 42)]
         seed: u64,
+        #[arg(long, default_value_t = 0)]
+        scans: usize,
+    },
+    Preprocess {
+        input: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+        #[arg(long, value_delimiter = ',', default_value = "all")]
+        steps: Vec<String>,
     },
     Chunk {
         input: PathBuf,
@@ -253,6 +402,18 @@ enum Commands {
         max_tokens: usize,
         #[arg(long, default_value_t = 64)]
         overlap_tokens: usize,
+        /// Semantic mode: do not split chunks smaller than this at a topic shift.
+        #[arg(long, default_value_t = 64)]
+        min_tokens: usize,
+        /// Semantic mode: split where adjacent similarity falls below this percentile.
+        #[arg(long, default_value_t = 25.0)]
+        breakpoint_percentile: f32,
+        /// Start each chunk's text with its heading breadcrumb ("A > B").
+        #[arg(long)]
+        prepend_breadcrumb: bool,
+        /// Only chunk the cells matching this selector expression.
+        #[arg(long)]
+        select: Option<String>,
     },
     Embed {
         chunks: PathBuf,
@@ -298,6 +459,12 @@ enum Commands {
         filters: Option<String>,
         #[arg(long, action = ArgAction::SetTrue)]
         hybrid: bool,
+        /// Show each hit's parent section, cut to this many tokens around the hit.
+        #[arg(long)]
+        expand_parent: Option<usize>,
+        /// Chunks JSONL holding the parents (defaults to the embeddings' chunk source).
+        #[arg(long)]
+        chunks: Option<PathBuf>,
         #[arg(long)]
         backend: Option<String>,
         #[arg(long)]
@@ -368,6 +535,7 @@ enum Commands {
 
 #[derive(Args, Debug, Clone)]
 struct EncodeArgs {
+    /// reports|slides|news|scans|custom; `scans` also turns on every --preprocess step.
     #[arg(long)]
     preset: Option<String>,
     #[arg(long)]
@@ -386,6 +554,23 @@ struct EncodeArgs {
     force_ocr: bool,
     #[arg(long, value_delimiter = ',')]
     ocr_langs: Vec<String>,
+    #[arg(long)]
+    ocr_min_confidence: Option<f32>,
+    /// orientation,deskew,binarize,despeckle, or none; overrides the preset's steps.
+    #[arg(long, value_delimiter = ',')]
+    preprocess: Vec<String>,
+    #[arg(long, action = ArgAction::SetTrue)]
+    extract_figures: bool,
+    #[arg(long, action = ArgAction::SetTrue)]
+    figure_ocr: bool,
+    #[arg(long, action = ArgAction::SetTrue)]
+    redact_pii: bool,
+    #[arg(long)]
+    redact_mode: Option<String>,
+    #[arg(long, value_delimiter = ',')]
+    pii_kinds: Vec<String>,
+    #[arg(long)]
+    pii_gazetteer: Option<PathBuf>,
     #[arg(long = "heading-boost")]
     heading_boost: Option<f32>,
     #[arg(long = "number-boost")]
@@ -394,6 +579,8 @@ struct EncodeArgs {
     footer_penalty: Option<f32>,
     #[arg(long = "early-line-bonus")]
     early_line_bonus: Option<f32>,
+    #[arg(long = "importance-model")]
+    importance_model: Option<PathBuf>,
     #[arg(long = "table-mode")]
     table_mode: Option<String>,
     #[arg(long = "preset-label")]
@@ -435,6 +622,7 @@ fn main() -> Result<()> {
             json_out,
             text_out,
             cells_out,
+            redaction_log,
             quiet,
         } => {
             let defaults = file_config.defaults.encode.as_ref();
@@ -472,6 +660,9 @@ fn main() -> Result<()> {
             if let Some(manifest) = cells_out {
                 write_cell_manifest(&doc, &input, &manifest, 96)?;
             }
+            if let Some(log) = redaction_log {
+                doc.write_redaction_log(log)?;
+            }
             if !quiet {
                 print_encode_summary(&input, &metrics);
             }
@@ -491,6 +682,7 @@ fn main() -> Result<()> {
             inputs,
             encode,
             sensitivity,
+            sensitivity_rules,
             encrypt,
         } => {
             let files = gather_input_files(&inputs)?;
@@ -502,7 +694,16 @@ fn main() -> Result<()> {
             let store = RagStore::open(&cli.rag_db)?;
             let collection_id = store.ensure_collection(&collection)?;
             let embed_client = EmbeddingClient::from_env()?;
-            let normalized_sensitivity = normalize_level(&sensitivity);
+            let labels = match sensitivity.as_deref() {
+                Some(level) => CellSensitivity::Fixed(normalize_level(level)),
+                None => {
+                    let rules = match sensitivity_rules {
+                        Some(path) => SensitivityRules::load(path)?,
+                        None => file_config.sensitivity.clone().unwrap_or_default(),
+                    };
+                    CellSensitivity::Classified(Box::new(SensitivityClassifier::new(rules)?))
+                }
+            };
             let recipient = if encrypt {
                 Some(env::var("RAG_ENCRYPTION_RECIPIENT").map_err(|_| {
                     anyhow!("RAG_ENCRYPTION_RECIPIENT must be set when --encrypt is used")
@@ -519,7 +720,7 @@ fn main() -> Result<()> {
                     &path,
                     &resolved,
                     &embed_client,
-                    &normalized_sensitivity,
+                    &labels,
                     recipient.as_deref(),
                 )?;
                 total_cells += stats.cells;
@@ -530,6 +731,15 @@ fn main() -> Result<()> {
                     stats.pages,
                     path.display()
                 );
+                if matches!(labels, CellSensitivity::Classified(_)) {
+                    println!("[3DCF RAG] Sensitivity: {}", stats.levels_summary());
+                }
+                if stats.redactions > 0 {
+                    println!(
+                        "[3DCF RAG] Redacted {} PII values before indexing",
+                        stats.redactions
+                    );
+                }
             }
             println!(
                 "[3DCF RAG] Collection '{}' updated ({} documents, {} cells)",
@@ -544,7 +754,10 @@ fn main() -> Result<()> {
             policy,
             provider,
             model,
+            numeric_check,
+            expand_parent,
         } => {
+            let numeric_check = numeric_check.parse::<FaithfulnessPolicy>()?;
             let store = RagStore::open(&cli.rag_db)?;
             let embed_client = EmbeddingClient::from_env()?;
             let tokenizer_name = ask
@@ -578,6 +791,8 @@ fn main() -> Result<()> {
                 policy: policy_value,
                 tokenizer: tokenizer_kind.clone(),
                 tokenizer_name: tokenizer_name.clone(),
+                numeric_check,
+                expand_parent_tokens: expand_parent,
             };
             let answer = execute_rag_query(
                 &store,
@@ -599,12 +814,21 @@ fn main() -> Result<()> {
             input,
             encode,
             out,
+            format,
+            select,
             tokenizer,
             tokenizer_file,
             quiet,
         } => {
             let defaults = file_config.defaults.encode.as_ref();
-            let (artifacts, alerts) = build_context_artifacts(&input, &encode, defaults)?;
+            let format = parse_context_format(&format)?;
+            let selector = select
+                .as_deref()
+                .map(Selector::parse)
+                .transpose()
+                .context("parsing --select")?;
+            let (artifacts, alerts) =
+                build_context_artifacts(&input, &encode, defaults, format, selector.as_ref())?;
             let ContextArtifacts {
                 resolved,
                 mut metrics,
@@ -685,10 +909,11 @@ fn main() -> Result<()> {
             json_out,
             page,
             select,
+            format,
             strict_numguard,
             numguard_units,
         } => {
-            let doc = load_document(&input)?;
+            let format = parse_decode_format(&format)?;
             let defaults = file_config.defaults.encode.as_ref();
             let strict_numguard =
                 strict_numguard || defaults.and_then(|d| d.strict_numguard).unwrap_or(false);
@@ -696,7 +921,12 @@ fn main() -> Result<()> {
                 numguard_units.or_else(|| defaults.and_then(|d| d.numguard_units.clone()));
             let unit_whitelist = load_unit_whitelist(units_path)?;
             let selection = parse_selection(page, select.as_deref())?;
-            let rendered = render_selection(&doc, &selection)?;
+            let doc = if json_out.is_some() {
+                load_document(&input)?
+            } else {
+                load_selection(&input, &selection)?
+            };
+            let rendered = render_selection(&doc, &selection, format)?;
             if let Some(path) = text_out {
                 fs::write(path, &rendered)?;
             } else {
@@ -723,6 +953,7 @@ fn main() -> Result<()> {
         Commands::Serialize {
             input,
             out,
+            format,
             preview,
             table_mode,
             preset_label,
@@ -731,6 +962,7 @@ fn main() -> Result<()> {
             let doc = load_document(&input)?;
             let mode = parse_table_mode(table_mode.as_deref())?;
             let serializer = TextSerializer::with_config(TextSerializerConfig {
+                format: parse_context_format(&format)?,
                 max_preview_chars: preview,
                 table_mode: mode,
                 preset_label,
@@ -744,19 +976,540 @@ fn main() -> Result<()> {
             tokenizer,
             tokenizer_file,
         } => {
-            let doc = load_document(&input)?;
             let tokenizer = tokenizer
                 .or_else(|| file_config.defaults.stats_tokenizer())
                 .unwrap_or_else(|| "cl100k_base".to_string());
             let tokenizer_file =
                 tokenizer_file.or_else(|| file_config.defaults.stats_tokenizer_file());
             let tokenizer = resolve_tokenizer(&tokenizer, tokenizer_file)?;
-            let stats = Stats::measure(&doc, tokenizer)?;
+            if is_bundle(&input) {
+                let bundle = Bundle::load(&input).context("loading bundle")?;
+                let bpe = tokenizer.build()?;
+                let (mut tokens_raw, mut tokens_3dcf) = (0usize, 0usize);
+                for name in bundle.names() {
+                    let stats = Stats::measure_with_bpe(&bundle.extract(name)?, &bpe)?;
+                    tokens_raw += stats.tokens_raw;
+                    tokens_3dcf += stats.tokens_3dcf;
+                }
+                let shared = bundle.stats();
+                let savings = if tokens_3dcf == 0 {
+                    0.0
+                } else {
+                    tokens_raw as f32 / tokens_3dcf as f32
+                };
+                println!(
+                    "tokens_raw={} tokens_3dcf={} savings={:.2}x cells={}",
+                    tokens_raw, tokens_3dcf, savings, shared.cells
+                );
+                println!(
+                    "documents={} payloads={} shared_payloads={} payload_bytes={} shared_payload_bytes={} cross_doc_ratio={:.2}x",
+                    shared.documents,
+                    shared.standalone_payloads,
+                    shared.shared_payloads,
+                    shared.standalone_payload_bytes,
+                    shared.shared_payload_bytes,
+                    shared.cross_document_ratio
+                );
+            } else {
+                let doc = load_document(&input)?;
+                let stats = Stats::measure(&doc, tokenizer)?;
+                println!(
+                    "tokens_raw={} tokens_3dcf={} savings={:.2}x cells={}",
+                    stats.tokens_raw, stats.tokens_3dcf, stats.savings_ratio, stats.cells
+                );
+            }
+        }
+        Commands::BundleAdd {
+            bundle: path,
+            inputs,
+            name,
+        } => {
+            if inputs.is_empty() {
+                return Err(anyhow!("bundle-add needs at least one document"));
+            }
+            if name.is_some() && inputs.len() > 1 {
+                return Err(anyhow!("--name only applies when adding a single document"));
+            }
+            let mut bundle = if path.exists() {
+                Bundle::load(&path).context("loading bundle")?
+            } else {
+                Bundle::new()
+            };
+            for input in &inputs {
+                let doc = load_document(input)?;
+                let entry = name.clone().unwrap_or_else(|| bundle_entry_name(input));
+                bundle
+                    .append(entry.clone(), &doc)
+                    .with_context(|| format!("adding {}", input.display()))?;
+                println!("[3DCF] Added {} as '{}'", input.display(), entry);
+            }
+            bundle.save(&path).context("writing bundle")?;
+            let stats = bundle.stats();
+            println!(
+                "[3DCF] Bundle: {} documents, {} shared payloads, cross-document ratio {:.2}x",
+                stats.documents, stats.shared_payloads, stats.cross_document_ratio
+            );
+        }
+        Commands::BundleList { bundle } => {
+            let bundle = Bundle::load(&bundle).context("loading bundle")?;
+            println!("name\tpages\tcells\tpayloads\tpayload_bytes");
+            for info in bundle.list() {
+                println!(
+                    "{}\t{}\t{}\t{}\t{}",
+                    info.name, info.pages, info.cells, info.payloads, info.payload_bytes
+                );
+            }
+        }
+        Commands::BundleExtract {
+            bundle,
+            name,
+            out,
+            json_out,
+        } => {
+            let bundle = Bundle::load(&bundle).context("loading bundle")?;
+            let doc = bundle.extract(&name)?;
+            let out = match (&out, &json_out) {
+                (None, None) => Some(PathBuf::from(format!("{name}.3dcf"))),
+                _ => out,
+            };
+            if let Some(path) = out {
+                doc.save_bin(&path)?;
+                println!("[3DCF] Wrote {}", path.display());
+            }
+            if let Some(path) = json_out {
+                doc.save_json(&path)?;
+                println!("[3DCF] Wrote {}", path.display());
+            }
+        }
+        Commands::Keygen { out } => {
+            let key = generate_signing_key();
+            let public = PathBuf::from(format!("{}.pub", out.display()));
+            write_secret(&out, &hex::encode(key.to_bytes()))?;
+            fs::write(&public, hex::encode(key.verifying_key().to_bytes()))
+                .with_context(|| format!("writing {}", public.display()))?;
+            println!("[3DCF] Wrote {} and {}", out.display(), public.display());
+        }
+        Commands::Sign { input, key, out } => {
+            let mut doc = load_document_unverified(&input)?;
+            match &key {
+                Some(path) => doc.sign(&read_signing_key(path).context("reading signing key")?),
+                None => doc.seal(),
+            }
+            let out = out.unwrap_or_else(|| input.clone());
+            if is_json(&out) {
+                doc.save_json(&out)?;
+            } else {
+                doc.save_bin(&out)?;
+            }
+            let root = doc
+                .integrity
+                .as_ref()
+                .map(|i| i.merkle_root)
+                .unwrap_or_default();
             println!(
-                "tokens_raw={} tokens_3dcf={} savings={:.2}x cells={}",
-                stats.tokens_raw, stats.tokens_3dcf, stats.savings_ratio, stats.cells
+                "[3DCF] {} {} merkle_root={}",
+                if key.is_some() { "Signed" } else { "Sealed" },
+                out.display(),
+                hex::encode(root)
             );
         }
+        Commands::Verify { input, public_key } => {
+            let trusted = match public_key.or_else(|| env::var(TRUSTED_KEY_ENV).ok()) {
+                Some(spec) => Some(read_verifying_key(spec.trim()).context("reading public key")?),
+                None => None,
+            };
+            let doc = load_document_unverified(&input)?;
+            let status = doc
+                .verify_integrity(trusted.as_ref())
+                .with_context(|| format!("verifying {}", input.display()))?;
+            let root = doc
+                .integrity
+                .as_ref()
+                .map(|i| i.merkle_root)
+                .unwrap_or_default();
+            match status {
+                IntegrityStatus::Signed(key) => println!(
+                    "ok: merkle_root={} signed_by={}",
+                    hex::encode(root),
+                    hex::encode(key.to_bytes())
+                ),
+                IntegrityStatus::Sealed => {
+                    println!("ok: merkle_root={} (unsigned)", hex::encode(root))
+                }
+            }
+        }
+        Commands::Migrate { inputs, check } => {
+            if inputs.is_empty() {
+                return Err(anyhow!("migrate needs at least one input"));
+            }
+            let mut outdated = 0usize;
+            for input in &inputs {
+                let mut doc = load_document_unverified(input)?;
+                let Some(report) = doc.migration.clone() else {
+                    println!(
+                        "{}: version {} (current)",
+                        input.display(),
+                        doc.header.version
+                    );
+                    continue;
+                };
+                outdated += 1;
+                println!("{}: {report}", input.display());
+                if check {
+                    continue;
+                }
+                if doc.integrity.take().is_some() {
+                    eprintln!(
+                        "warning: {}: migration changes the signed content; integrity section dropped, re-run `3dcf sign`",
+                        input.display()
+                    );
+                }
+                if is_json(input) {
+                    doc.save_json(input)?;
+                } else {
+                    doc.save_bin(input)?;
+                }
+            }
+            if check && outdated > 0 {
+                return Err(anyhow!(
+                    "{outdated} of {} documents need migration to format version {FORMAT_VERSION}",
+                    inputs.len()
+                ));
+            }
+        }
+        Commands::RenderDebug {
+            input,
+            encode,
+            out_dir,
+            page,
+            png,
+            scale,
+        } => {
+            let doc = if is_container(&input) {
+                load_document(&input)?
+            } else {
+                let defaults = file_config.defaults.encode.as_ref();
+                let resolved = resolve_encode_config(&encode, defaults)?;
+                let encoder = encoder_builder_from_resolved(&resolved)?
+                    .keep_dropped(true)
+                    .build();
+                encoder.encode_path(&input)?.0
+            };
+            let pages: Vec<u32> = match page {
+                Some(z) => vec![z],
+                None => doc.pages.iter().map(|page| page.z).collect(),
+            };
+            fs::create_dir_all(&out_dir)
+                .with_context(|| format!("creating {}", out_dir.display()))?;
+            for z in &pages {
+                let svg = out_dir.join(format!("page-{z:04}.svg"));
+                fs::write(&svg, doc.overlay_svg(*z)?)
+                    .with_context(|| format!("writing {}", svg.display()))?;
+                if png {
+                    let path = out_dir.join(format!("page-{z:04}.png"));
+                    fs::write(&path, doc.overlay_png(*z, scale)?)
+                        .with_context(|| format!("writing {}", path.display()))?;
+                }
+            }
+            let mut by_type: Vec<(String, usize)> = Vec::new();
+            for cell in doc.cells.iter().filter(|cell| pages.contains(&cell.z)) {
+                let name = format!("{:?}", cell.cell_type).to_lowercase();
+                match by_type.iter_mut().find(|(kind, _)| *kind == name) {
+                    Some((_, n)) => *n += 1,
+                    None => by_type.push((name, 1)),
+                }
+            }
+            let mut dropped: Vec<(DropReason, usize)> = Vec::new();
+            for cell in doc.dropped.iter().filter(|d| pages.contains(&d.cell.z)) {
+                match dropped
+                    .iter_mut()
+                    .find(|(reason, _)| *reason == cell.reason)
+                {
+                    Some((_, n)) => *n += 1,
+                    None => dropped.push((cell.reason, 1)),
+                }
+            }
+            println!(
+                "[3DCF] Wrote {} page overlays to {}",
+                pages.len(),
+                out_dir.display()
+            );
+            println!(
+                "cells: {}",
+                by_type
+                    .iter()
+                    .map(|(kind, n)| format!("{kind}={n}"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            if !dropped.is_empty() {
+                println!(
+                    "dropped: {}",
+                    dropped
+                        .iter()
+                        .map(|(reason, n)| format!("{}={n}", drop_reason_str(*reason)))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                );
+            }
+        }
+        Commands::Inspect { input, json } => {
+            let size = fs::metadata(&input)
+                .with_context(|| format!("reading {}", input.display()))?
+                .len();
+            let (layout, blocks) = if is_json(&input) {
+                ("json", Vec::new())
+            } else {
+                let reader = DocumentReader::open(&input)
+                    .with_context(|| format!("opening {}", input.display()))?;
+                let layout = if reader.is_seekable() {
+                    "seekable"
+                } else {
+                    "blob"
+                };
+                (layout, reader.blocks().to_vec())
+            };
+            let doc = load_document_unverified(&input)?;
+            let stored_version = doc
+                .migration
+                .as_ref()
+                .map_or(doc.header.version, |report| report.from);
+            let integrity = match (&doc.integrity, doc.verify_integrity(None)) {
+                (None, _) => "none".to_string(),
+                (Some(_), Ok(IntegrityStatus::Sealed)) => "sealed".to_string(),
+                (Some(_), Ok(IntegrityStatus::Signed(key))) => {
+                    format!("signed by {}", hex::encode(key.to_bytes()))
+                }
+                (Some(_), Err(err)) => format!("invalid: {err}"),
+            };
+            let sections = doc.section_sizes();
+            let report = doc.validate();
+            if json {
+                let summary = json!({
+                    "path": input.display().to_string(),
+                    "file_bytes": size,
+                    "layout": layout,
+                    "version": stored_version,
+                    "pages": doc.pages.len(),
+                    "cells": doc.cells.len(),
+                    "dict": doc.dict.len(),
+                    "numguards": doc.numguards.len(),
+                    "links": doc.links.len(),
+                    "integrity": integrity,
+                    "sections": sections,
+                    "blocks": blocks
+                        .iter()
+                        .map(|block| json!({
+                            "z": block.z,
+                            "offset": block.offset,
+                            "length": block.length,
+                            "cells": block.cells,
+                        }))
+                        .collect::<Vec<_>>(),
+                    "validation": report,
+                });
+                println!("{}", serde_json::to_string_pretty(&summary)?);
+                return Ok(());
+            }
+            println!(
+                "{}: {size} bytes, {layout}, format version {stored_version}",
+                input.display()
+            );
+            println!(
+                "pages={} cells={} dict={} numguards={} links={}",
+                doc.pages.len(),
+                doc.cells.len(),
+                doc.dict.len(),
+                doc.numguards.len(),
+                doc.links.len()
+            );
+            println!("integrity: {integrity}");
+            println!(
+                "{:<10} {:>8} {:>10} {:>10}",
+                "section", "records", "encoded", "zstd"
+            );
+            for section in &sections {
+                println!(
+                    "{:<10} {:>8} {:>10} {:>10}",
+                    section.section,
+                    section.records,
+                    section.encoded_bytes,
+                    section.compressed_bytes
+                );
+            }
+            if !blocks.is_empty() {
+                println!("page blocks:");
+                for block in &blocks {
+                    println!(
+                        "  z={:<4} offset={:<10} length={:<8} cells={}",
+                        block.z, block.offset, block.length, block.cells
+                    );
+                }
+            }
+            let counts = report
+                .counts()
+                .iter()
+                .map(|(kind, n)| format!("{}={n}", kind.as_str()))
+                .collect::<Vec<_>>();
+            if counts.is_empty() {
+                println!("validation: ok");
+            } else {
+                println!(
+                    "validation: {} errors, {} warnings ({}); run `3dcf validate` for details",
+                    report.errors(),
+                    report.warnings(),
+                    counts.join(", ")
+                );
+            }
+        }
+        Commands::Citations {
+            input,
+            answer,
+            json,
+            strict,
+        } => {
+            let doc = load_document(&input)?;
+            let text = match &answer {
+                Some(path) => fs::read_to_string(path)
+                    .with_context(|| format!("reading {}", path.display()))?,
+                None => io::read_to_string(io::stdin()).context("reading stdin")?,
+            };
+            let citations = doc.parse_citations(&text);
+            let unknown = citations.iter().filter(|c| c.cell.is_none()).count();
+            if json {
+                let rows: Vec<Value> = citations
+                    .iter()
+                    .map(|citation| {
+                        json!({
+                            "id": citation.id,
+                            "start": citation.start,
+                            "end": citation.end,
+                            "found": citation.cell.is_some(),
+                            "cell": citation.cell,
+                            "text": citation
+                                .cell
+                                .as_ref()
+                                .and_then(|cell| doc.payload_for(&cell.code_id)),
+                        })
+                    })
+                    .collect();
+                println!("{}", serde_json::to_string_pretty(&rows)?);
+            } else {
+                for citation in &citations {
+                    match &citation.cell {
+                        Some(cell) => {
+                            let text = doc.payload_for(&cell.code_id).unwrap_or_default();
+                            let preview: String = text
+                                .split_whitespace()
+                                .collect::<Vec<_>>()
+                                .join(" ")
+                                .chars()
+                                .take(80)
+                                .collect();
+                            println!(
+                                "{}  page={} type={} \"{}\"",
+                                citation.id,
+                                cell.z,
+                                format!("{:?}", cell.cell_type).to_lowercase(),
+                                preview
+                            );
+                        }
+                        None => println!("{}  unknown", citation.id),
+                    }
+                }
+                let mut pages: Vec<u32> = citations.iter().filter_map(|c| c.z()).collect();
+                pages.sort_unstable();
+                pages.dedup();
+                println!(
+                    "{} citations, {} unknown, pages {:?}",
+                    citations.len(),
+                    unknown,
+                    pages
+                );
+            }
+            if strict && unknown > 0 {
+                return Err(anyhow!("{unknown} citations match no cell"));
+            }
+        }
+        Commands::Validate {
+            inputs,
+            json_out,
+            strict,
+        } => {
+            if inputs.is_empty() {
+                return Err(anyhow!("validate needs at least one input"));
+            }
+            let mut failed = 0usize;
+            let mut results = Vec::with_capacity(inputs.len());
+            for input in &inputs {
+                match load_document_unverified(input) {
+                    Ok(doc) => {
+                        let report = doc.validate();
+                        if !report.is_valid() || (strict && report.warnings() > 0) {
+                            failed += 1;
+                        }
+                        println!("{}: {report}", input.display());
+                        results.push(json!({
+                            "path": input.display().to_string(),
+                            "valid": report.is_valid(),
+                            "errors": report.errors(),
+                            "warnings": report.warnings(),
+                            "issues": report.issues,
+                        }));
+                    }
+                    Err(err) => {
+                        failed += 1;
+                        println!("{}: unreadable: {err:#}", input.display());
+                        results.push(json!({
+                            "path": input.display().to_string(),
+                            "valid": false,
+                            "error": format!("{err:#}"),
+                        }));
+                    }
+                }
+            }
+            if let Some(path) = json_out {
+                fs::write(&path, serde_json::to_string_pretty(&results)?)
+                    .with_context(|| format!("writing {}", path.display()))?;
+            }
+            if failed > 0 {
+                return Err(anyhow!(
+                    "{failed} of {} documents failed validation",
+                    inputs.len()
+                ));
+            }
+        }
+        Commands::Diff {
+            old,
+            new,
+            similarity,
+            json_out,
+            report_out,
+        } => {
+            if !(0.0..=1.0).contains(&similarity) {
+                return Err(anyhow!("--similarity must be between 0 and 1"));
+            }
+            let old_doc = load_document(&old)?;
+            let new_doc = load_document(&new)?;
+            let diff = old_doc.diff_with(&new_doc, &DiffOptions { similarity });
+            if let Some(path) = json_out {
+                fs::write(&path, serde_json::to_string_pretty(&diff)?)
+                    .with_context(|| format!("writing {}", path.display()))?;
+            }
+            let report = format!("{} -> {}\n{diff}", old.display(), new.display());
+            match report_out {
+                Some(path) => {
+                    fs::write(&path, report)
+                        .with_context(|| format!("writing {}", path.display()))?;
+                    let s = &diff.summary;
+                    println!(
+                        "inserted={} deleted={} modified={} moved={} unchanged={} numbers_changed={}",
+                        s.inserted, s.deleted, s.modified, s.moved, s.unchanged, s.numbers_changed
+                    );
+                }
+                None => print!("{report}"),
+            }
+        }
         Commands::Bench {
             root,
             preset,
@@ -819,6 +1572,41 @@ fn main() -> Result<()> {
                 decode_p95_max,
             )?;
         }
+        Commands::FitImportance {
+            root,
+            gold_root,
+            preset,
+            out,
+            l2,
+        } => {
+            let preset = preset
+                .or_else(|| {
+                    file_config
+                        .defaults
+                        .bench
+                        .as_ref()
+                        .and_then(|d| d.preset.clone())
+                })
+                .unwrap_or_else(|| "reports".to_string());
+            let config = BenchConfig {
+                mode: BenchMode::Encode,
+                root,
+                gold_root: Some(gold_root),
+                output: None,
+                preset,
+                tokenizer: TokenizerKind::Cl100k,
+                budgets: Vec::new(),
+            };
+            let runner = BenchRunner::new(config)?;
+            let model = runner.fit_importance(l2)?;
+            model.save(&out)?;
+            println!(
+                "fit-importance: bias={:.3} idf_terms={} -> {}",
+                model.bias,
+                model.idf.terms.len(),
+                out.display()
+            );
+        }
         Commands::Report { input, out } => {
             write_report(&input, &out)?;
         }
@@ -827,8 +1615,15 @@ fn main() -> Result<()> {
             out,
             recipient,
             redact_types,
+            redact_pii,
+            redact_mode,
+            pii_gazetteer,
         } => {
-            encrypt_file(&input, &out, &recipient, &redact_types)?;
+            let redactor =
+                redaction_config(redact_pii, redact_mode.as_deref(), &[], pii_gazetteer)?
+                    .map(Redactor::new)
+                    .transpose()?;
+            encrypt_file(&input, &out, &recipient, &redact_types, redactor.as_ref())?;
         }
         Commands::Decrypt {
             input,
@@ -841,8 +1636,17 @@ fn main() -> Result<()> {
             out_dir,
             count,
             seed,
+            scans,
         } => {
             generate_synthetic(&out_dir, count, seed)?;
+            if scans > 0 {
+                generate_scan_fixtures(&out_dir.join("scans"), scans, seed)?;
+            }
+        }
+        Commands::Preprocess { input, out, steps } => {
+            let config = PreprocessConfig::from_steps(&steps)?;
+            let correction = preprocess_file(&input, &out, &config)?;
+            println!("{}", serde_json::to_string_pretty(&correction)?);
         }
         Commands::Chunk {
             input,
@@ -852,8 +1656,15 @@ fn main() -> Result<()> {
             mode,
             max_tokens,
             overlap_tokens,
+            min_tokens,
+            breakpoint_percentile,
+            prepend_breadcrumb,
+            select,
         } => {
-            let doc = load_document(&input)?;
+            let mut doc = load_document(&input)?;
+            if let Some(expr) = select {
+                doc.retain_selected(&Selector::parse(&expr).context("parsing --select")?);
+            }
             let chunk_mode = parse_chunk_mode(&mode)?;
             let chunker = Chunker::new(ChunkConfig {
                 mode: chunk_mode,
@@ -861,6 +1672,9 @@ fn main() -> Result<()> {
                 overlap_cells: overlap,
                 max_tokens,
                 overlap_tokens,
+                min_tokens,
+                breakpoint_percentile,
+                prepend_breadcrumb,
             });
             let doc_id = input.to_string_lossy().to_string();
             let chunks = chunker.chunk_document(&doc, &doc_id);
@@ -956,6 +1770,10 @@ fn main() -> Result<()> {
                 let chunk: ChunkRecord = serde_json::from_str(line).with_context(|| {
                     format!("invalid chunk row {} in {}", idx + 1, chunks.display())
                 })?;
+                // Sections are looked up through `parent_id`, not retrieved.
+                if chunk.level == ChunkLevel::Parent {
+                    continue;
+                }
                 let text_hash = hash_text(&chunk.text);
                 if let Some(hit) = cache.get(&chunk.chunk_id, &text_hash) {
                     cached_records.push(hit);
@@ -987,8 +1805,8 @@ fn main() -> Result<()> {
                 cache.insert(record.clone(), &text_hash);
             }
 
-            records.extend(cached_records.into_iter());
-            records.extend(pending_results.into_iter());
+            records.extend(cached_records);
+            records.extend(pending_results);
             records.sort_by_key(|r| (r.chunk_index, r.cell_start));
             cache.flush()?;
             let mut writer = BufWriter::new(
@@ -1034,6 +1852,8 @@ fn main() -> Result<()> {
             seed,
             filters,
             hybrid,
+            expand_parent,
+            chunks,
             openai_model,
             openai_api_key,
             openai_base_url,
@@ -1043,6 +1863,14 @@ fn main() -> Result<()> {
         } => {
             let source = load_embeddings_source(embeddings.as_ref(), index.as_ref())?;
             let filter_predicate = filters.map(parse_filters).transpose()?;
+            let chunk_tree = match expand_parent {
+                Some(_) => {
+                    let path =
+                        chunks.unwrap_or_else(|| PathBuf::from(&source.metadata.chunk_source));
+                    Some(load_chunk_map(&path)?)
+                }
+                None => None,
+            };
             let backend_handle = build_backend_from_metadata(
                 &source.metadata,
                 backend.as_deref(),
@@ -1073,11 +1901,15 @@ fn main() -> Result<()> {
                 if hits.is_empty() {
                     println!("search: no candidates");
                 } else {
+                    let mut shown = HashSet::new();
                     for hit in hits.into_iter().take(top_k) {
                         println!(
                             "score={:.3} doc={} chunk={} {}",
                             hit.score, hit.doc, hit.chunk_index, hit.preview
                         );
+                        if let (Some(budget), Some(tree)) = (expand_parent, chunk_tree.as_ref()) {
+                            print_parent_window(tree, &hit.chunk_id, budget, &mut shown);
+                        }
                     }
                 }
             }
@@ -1194,6 +2026,76 @@ fn main() -> Result<()> {
     Ok(())
 }
 
+/// `.3dcf` and `.json` files are decoded; anything else is a source document.
+fn is_container(path: &Path) -> bool {
+    is_json(path)
+        || path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("3dcf"))
+}
+
+fn drop_reason_str(reason: DropReason) -> &'static str {
+    match reason {
+        DropReason::Budget => "budget",
+        DropReason::Footer => "footer",
+        DropReason::Duplicate => "duplicate",
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("json"))
+}
+
+/// Like [`load_document`] but without the `THREE_DCF_TRUSTED_KEY` check, for
+/// commands that inspect or replace the integrity section themselves.
+fn load_document_unverified(path: &Path) -> Result<Document> {
+    if is_json(path) {
+        return Document::load_json(path).context("loading json document");
+    }
+    let bytes = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    Document::from_bytes(&bytes).context("loading binary document")
+}
+
+fn write_secret(path: &Path, contents: &str) -> Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents.as_bytes()))
+        .with_context(|| format!("writing {}", path.display()))
+}
+
+fn is_bundle(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case(BUNDLE_EXTENSION))
+}
+
+/// `reports/acme.3dcf.json` is bundled as `acme`.
+fn bundle_entry_name(path: &Path) -> String {
+    let file = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or_default();
+    let mut name = file;
+    for suffix in [".json", ".3dcf"] {
+        name = name.strip_suffix(suffix).unwrap_or(name);
+    }
+    if name.is_empty() {
+        file.to_string()
+    } else {
+        name.to_string()
+    }
+}
+
 fn load_document(path: &Path) -> Result<Document> {
     match path
         .extension()
@@ -1206,6 +2108,26 @@ fn load_document(path: &Path) -> Result<Document> {
     }
 }
 
+/// Loads only the selected page of a binary document; JSON, selections that
+/// do not pin one page and a configured trusted key (which needs every page to
+/// check the Merkle root) fall back to [`load_document`].
+fn load_selection(path: &Path, selection: &DecodeSelection) -> Result<Document> {
+    let z = match selection {
+        DecodeSelection::Page(z) => *z,
+        DecodeSelection::Select(selector) => match selector.single_page() {
+            Some(z) => z,
+            None => return load_document(path),
+        },
+        DecodeSelection::All => return load_document(path),
+    };
+    if is_json(path) || env::var_os(TRUSTED_KEY_ENV).is_some() {
+        return load_document(path);
+    }
+    DocumentReader::open(path)
+        .and_then(|mut reader| reader.read_page(z))
+        .context("loading binary document")
+}
+
 fn enforce_bench_thresholds(
     metrics: &CorpusMetrics,
     cer: Option<f64>,
@@ -1269,62 +2191,10 @@ fn enforce_bench_thresholds(
     }
 }
 
-fn parse_filters(expr: String) -> Result<FilterPredicate> {
-    let mut predicate = FilterPredicate {
-        doc_ids: HashSet::new(),
-        cell_types: HashSet::new(),
-        min_importance: None,
-    };
-    for raw in expr.split(',') {
-        let part = raw.trim();
-        if part.is_empty() {
-            continue;
-        }
-        if let Some(value) = part.strip_prefix("doc_id=") {
-            predicate.doc_ids.insert(value.trim().to_string());
-        } else if let Some(value) = part.strip_prefix("type=") {
-            let cell = match value.trim().to_uppercase().as_str() {
-                "TEXT" => CellType::Text,
-                "TABLE" => CellType::Table,
-                "FIGURE" => CellType::Figure,
-                "FOOTER" => CellType::Footer,
-                "HEADER" => CellType::Header,
-                other => {
-                    return Err(anyhow!(
-                        "unknown cell type '{}' in --filters (valid: TEXT|TABLE|FIGURE|FOOTER|HEADER)",
-                        other
-                    ));
-                }
-            };
-            predicate.cell_types.insert(cell);
-        } else if let Some(value) = part.strip_prefix("min_importance=") {
-            let raw_val: f32 = value.trim().parse()?;
-            predicate.min_importance = Some((raw_val / 255.0).clamp(0.0, 1.0));
-        } else {
-            return Err(anyhow!(
-                "unrecognized filter '{}'. Use doc_id=..., type=..., min_importance=...",
-                part
-            ));
-        }
-    }
-    Ok(predicate)
-}
-
-impl FilterPredicate {
-    fn matches(&self, record: &EmbeddingRecord) -> bool {
-        if !self.doc_ids.is_empty() && !self.doc_ids.contains(&record.doc) {
-            return false;
-        }
-        if !self.cell_types.is_empty() && !self.cell_types.contains(&record.dominant_type) {
-            return false;
-        }
-        if let Some(min_imp) = self.min_importance {
-            if record.importance_mean < min_imp {
-                return false;
-            }
-        }
-        true
-    }
+/// `--filters` takes a selector expression or the old `doc_id=..,type=..,min_importance=..`
+/// list, whose repeated keys are alternatives.
+fn parse_filters(expr: String) -> Result<Selector> {
+    Selector::parse_filters(&expr).context("parsing --filters")
 }
 
 fn init_tracing() {
@@ -1336,26 +2206,23 @@ fn init_tracing() {
 enum DecodeSelection {
     All,
     Page(u32),
-    Bbox {
-        z: u32,
-        x0: i32,
-        y0: i32,
-        x1: i32,
-        y1: i32,
-    },
+    Select(Selector),
 }
 
+/// `--page Z --select EXPR` selects `page=Z and (EXPR)`.
 fn parse_selection(page: Option<u32>, select: Option<&str>) -> Result<DecodeSelection> {
-    if let Some(expr) = select {
-        if page.is_some() {
-            return Err(anyhow!("cannot combine --page and --select"));
+    match (page, select) {
+        (_, Some(expr)) => {
+            let expr = match page {
+                Some(z) => format!("page={z} and ({expr})"),
+                None => expr.to_string(),
+            };
+            Ok(DecodeSelection::Select(
+                Selector::parse(&expr).context("parsing --select")?,
+            ))
         }
-        return parse_bbox(expr);
-    }
-    if let Some(z) = page {
-        Ok(DecodeSelection::Page(z))
-    } else {
-        Ok(DecodeSelection::All)
+        (Some(z), None) => Ok(DecodeSelection::Page(z)),
+        (None, None) => Ok(DecodeSelection::All),
     }
 }
 
@@ -1365,8 +2232,10 @@ fn parse_chunk_mode(value: &str) -> Result<ChunkMode> {
         "tokens" => Ok(ChunkMode::Tokens),
         "headings" => Ok(ChunkMode::Headings),
         "table-rows" | "tablerows" | "tables" => Ok(ChunkMode::TableRows),
+        "semantic" => Ok(ChunkMode::Semantic),
+        "hierarchical" => Ok(ChunkMode::Hierarchical),
         other => Err(anyhow!(
-            "unknown chunk mode '{}'. choose cells|tokens|headings|table-rows",
+            "unknown chunk mode '{}'. choose cells|tokens|headings|table-rows|semantic|hierarchical",
             other
         )),
     }
@@ -1378,6 +2247,8 @@ fn chunk_mode_string(mode: ChunkMode) -> String {
         ChunkMode::Tokens => "tokens",
         ChunkMode::Headings => "headings",
         ChunkMode::TableRows => "table_rows",
+        ChunkMode::Semantic => "semantic",
+        ChunkMode::Hierarchical => "hierarchical",
     }
     .to_string()
 }
@@ -1386,65 +2257,63 @@ fn default_chunk_mode_str() -> String {
     "cells".to_string()
 }
 
-fn parse_bbox(expr: &str) -> Result<DecodeSelection> {
-    let mut z = None;
-    let mut x = None;
-    let mut y = None;
-    for part in expr.split(',') {
-        let (key, value) = part
-            .split_once('=')
-            .ok_or_else(|| anyhow!("invalid selector segment: {part}"))?;
-        match key.trim().to_lowercase().as_str() {
-            "z" => {
-                z = Some(
-                    value
-                        .trim()
-                        .parse::<u32>()
-                        .map_err(|_| anyhow!("invalid z"))?,
-                );
-            }
-            "x" => x = Some(parse_range(value.trim())?),
-            "y" => y = Some(parse_range(value.trim())?),
-            other => return Err(anyhow!("unknown selector key {other}")),
-        }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DecodeFormat {
+    Text,
+    Markdown,
+    Html,
+}
+
+fn parse_decode_format(value: &str) -> Result<DecodeFormat> {
+    match value.to_lowercase().as_str() {
+        "text" | "txt" => Ok(DecodeFormat::Text),
+        "markdown" | "md" => Ok(DecodeFormat::Markdown),
+        "html" => Ok(DecodeFormat::Html),
+        other => Err(anyhow!(
+            "unknown decode format '{}'. choose text|markdown|html",
+            other
+        )),
     }
-    let z = z.ok_or_else(|| anyhow!("selector requires z=<page>"))?;
-    let (x0, x1) = x.ok_or_else(|| anyhow!("selector requires x=<start..end>"))?;
-    let (y0, y1) = y.ok_or_else(|| anyhow!("selector requires y=<start..end>"))?;
-    Ok(DecodeSelection::Bbox { z, x0, y0, x1, y1 })
 }
 
-fn parse_range(value: &str) -> Result<(i32, i32)> {
-    if let Some((start, end)) = value.split_once("..") {
-        let a = start
-            .trim()
-            .parse::<i32>()
-            .map_err(|_| anyhow!("invalid range"))?;
-        let b = end
-            .trim()
-            .parse::<i32>()
-            .map_err(|_| anyhow!("invalid range"))?;
-        Ok((a, b))
-    } else {
-        let v = value
-            .trim()
-            .parse::<i32>()
-            .map_err(|_| anyhow!("invalid value"))?;
-        Ok((v, v))
+fn parse_context_format(value: &str) -> Result<ContextFormat> {
+    match value.to_lowercase().as_str() {
+        "ctx3d" => Ok(ContextFormat::Ctx3d),
+        "xml" => Ok(ContextFormat::Xml),
+        "json" => Ok(ContextFormat::Json),
+        "markdown" | "md" => Ok(ContextFormat::Markdown),
+        "minimal" => Ok(ContextFormat::Minimal),
+        other => Err(anyhow!(
+            "unknown context format '{}'. choose ctx3d|xml|json|markdown|minimal",
+            other
+        )),
     }
 }
 
-fn render_selection(doc: &Document, selection: &DecodeSelection) -> Result<String> {
+fn render_selection(
+    doc: &Document,
+    selection: &DecodeSelection,
+    format: DecodeFormat,
+) -> Result<String> {
     let decoder = Decoder::new();
-    match selection {
-        DecodeSelection::All => decoder.to_text(doc).map_err(|e| anyhow!(e.to_string())),
-        DecodeSelection::Page(z) => decoder
-            .page_to_text(doc, *z)
-            .map_err(|e| anyhow!(e.to_string())),
-        DecodeSelection::Bbox { z, x0, y0, x1, y1 } => decoder
-            .bbox_to_text(doc, *z, *x0, *y0, *x1, *y1)
-            .map_err(|e| anyhow!(e.to_string())),
-    }
+    let rendered = match (selection, format) {
+        (DecodeSelection::All, DecodeFormat::Text) => decoder.to_text(doc),
+        (DecodeSelection::All, DecodeFormat::Markdown) => decoder.to_markdown(doc),
+        (DecodeSelection::All, DecodeFormat::Html) => decoder.to_html(doc),
+        (DecodeSelection::Page(z), DecodeFormat::Text) => decoder.page_to_text(doc, *z),
+        (DecodeSelection::Page(z), DecodeFormat::Markdown) => decoder.page_to_markdown(doc, *z),
+        (DecodeSelection::Page(z), DecodeFormat::Html) => decoder.page_to_html(doc, *z),
+        (DecodeSelection::Select(selector), DecodeFormat::Text) => {
+            decoder.selection_to_text(doc, selector)
+        }
+        (DecodeSelection::Select(selector), DecodeFormat::Markdown) => {
+            decoder.selection_to_markdown(doc, selector)
+        }
+        (DecodeSelection::Select(selector), DecodeFormat::Html) => {
+            decoder.selection_to_html(doc, selector)
+        }
+    };
+    rendered.map_err(|e| anyhow!(e.to_string()))
 }
 
 fn load_config(path: &Path) -> Result<AppConfig> {
@@ -1547,6 +2416,7 @@ fn summarize_numguard(alerts: &[NumGuardAlert]) -> String {
                 NumGuardIssue::MissingCell => "missing_cell",
                 NumGuardIssue::MissingPayload => "missing_payload",
                 NumGuardIssue::HashMismatch => "hash_mismatch",
+                NumGuardIssue::ValueMismatch => "value_mismatch",
                 NumGuardIssue::UnitNotAllowed => "unit_not_allowed",
             };
             format!(
@@ -1590,9 +2460,34 @@ fn gather_input_files(inputs: &[PathBuf]) -> Result<Vec<PathBuf>> {
     Ok(files)
 }
 
+/// `--sensitivity` applies one level to every cell; otherwise cells are classified.
+enum CellSensitivity {
+    Fixed(String),
+    Classified(Box<SensitivityClassifier>),
+}
+
 struct RagIndexStats {
     cells: usize,
     pages: usize,
+    redactions: usize,
+    levels: Vec<String>,
+}
+
+impl RagIndexStats {
+    fn levels_summary(&self) -> String {
+        let counts = three_dcf_rag::sensitivity::levels()
+            .iter()
+            .filter_map(|level| {
+                let count = self.levels.iter().filter(|l| l == level).count();
+                (count > 0).then(|| format!("{level} {count}"))
+            })
+            .collect::<Vec<_>>();
+        if counts.is_empty() {
+            "no cells".to_string()
+        } else {
+            counts.join(", ")
+        }
+    }
 }
 
 fn rag_index_file(
@@ -1601,7 +2496,7 @@ fn rag_index_file(
     path: &Path,
     resolved: &ResolvedEncodeConfig,
     embed_client: &EmbeddingClient,
-    sensitivity: &str,
+    labels: &CellSensitivity,
     recipient: Option<&str>,
 ) -> Result<RagIndexStats> {
     let encoder = build_encoder_from_resolved(resolved)?;
@@ -1619,6 +2514,8 @@ fn rag_index_file(
         return Ok(RagIndexStats {
             cells: 0,
             pages: doc.total_pages(),
+            redactions: doc.redactions.len(),
+            levels: Vec::new(),
         });
     }
     let mut texts = Vec::with_capacity(ordered.len());
@@ -1631,8 +2528,20 @@ fn rag_index_file(
             "embedding backend returned mismatched vector count"
         ));
     }
+    let levels = match labels {
+        CellSensitivity::Fixed(level) => vec![level.clone(); ordered.len()],
+        CellSensitivity::Classified(classifier) => classifier.classify_document(&doc),
+    };
+    let parents =
+        Chunker::new(ChunkConfig::default()).section_parents(&doc, &doc_record.source_path);
     let mut inserts = Vec::with_capacity(ordered.len());
-    for ((cell, text), embedding) in ordered.iter().zip(texts.iter()).zip(embeddings.into_iter()) {
+    for ((((cell, text), embedding), level), parent_id) in ordered
+        .iter()
+        .zip(texts.iter())
+        .zip(embeddings)
+        .zip(levels.iter())
+        .zip(parents)
+    {
         let (plain_text, encrypted, encryption_label) = if let Some(recipient) = recipient {
             if text.is_empty() {
                 (None, None, None)
@@ -1654,7 +2563,7 @@ fn rag_index_file(
         inserts.push(CellInsert {
             page: cell.z,
             importance: cell.importance,
-            sensitivity: sensitivity.to_string(),
+            sensitivity: level.clone(),
             text: plain_text,
             text_encrypted: encrypted,
             encryption: encryption_label,
@@ -1663,12 +2572,15 @@ fn rag_index_file(
             bbox_y: cell.y,
             bbox_w: cell.w,
             bbox_h: cell.h,
+            parent_id,
         });
     }
     store.add_cells(doc_record.id, &inserts)?;
     Ok(RagIndexStats {
         cells: inserts.len(),
         pages: doc.total_pages(),
+        redactions: doc.redactions.len(),
+        levels,
     })
 }
 
@@ -1736,6 +2648,16 @@ fn print_rag_answer(
             "[3DCF RAG] Compression factor:  {}",
             format_ratio(answer.metrics.compression_factor)
         );
+        println!(
+            "[3DCF RAG] Numeric faithfulness: {:.0}%",
+            answer.metrics.numeric_faithfulness * 100.0
+        );
+        if !answer.metrics.unsupported_numbers.is_empty() {
+            println!(
+                "[3DCF RAG] Unsupported numbers: {}",
+                answer.metrics.unsupported_numbers.join(", ")
+            );
+        }
         if let Some(rate) = pricing.lookup(provider_key, llm.model()) {
             let prompt_cost = answer.response.prompt_tokens as f64 / 1000.0 * rate.prompt_per_1k;
             let completion_cost =
@@ -1817,6 +2739,31 @@ fn print_encode_summary(input: &Path, metrics: &Metrics) {
         "[3DCF] NumGuard fields: {}",
         format_number(metrics.numguard_count as u64)
     );
+    if let Some(confidence) = metrics.mean_ocr_confidence() {
+        println!(
+            "[3DCF] OCR confidence:  {:.1} ({} low-confidence words dropped)",
+            confidence,
+            format_number(metrics.ocr_words_dropped as u64)
+        );
+    }
+    if metrics.figures > 0 {
+        println!(
+            "[3DCF] Figures:         {}",
+            format_number(metrics.figures as u64)
+        );
+    }
+    if metrics.links > 0 {
+        println!(
+            "[3DCF] Links:           {}",
+            format_number(metrics.links as u64)
+        );
+    }
+    if metrics.redactions > 0 {
+        println!(
+            "[3DCF] PII redacted:    {}",
+            format_number(metrics.redactions as u64)
+        );
+    }
 }
 
 fn removal_note(total: u32, kept: u32) -> String {
@@ -1927,7 +2874,8 @@ fn run_ask_flow(
     client: LlmClient,
 ) -> Result<()> {
     let defaults = config.defaults.encode.as_ref();
-    let (artifacts, alerts) = build_context_artifacts(input, encode, defaults)?;
+    let (artifacts, alerts) =
+        build_context_artifacts(input, encode, defaults, ContextFormat::Ctx3d, None)?;
     let ContextArtifacts {
         resolved,
         mut metrics,
@@ -2057,12 +3005,18 @@ fn print_ask_metrics(
     println!("[3DCF] Tokenizer:               {}", tokenizer);
 }
 
-fn encrypt_file(input: &Path, out: &Path, recipient: &str, redact_types: &[String]) -> Result<()> {
+fn encrypt_file(
+    input: &Path,
+    out: &Path,
+    recipient: &str,
+    redact_types: &[String],
+    redactor: Option<&Redactor>,
+) -> Result<()> {
     let mut data =
         fs::read(input).with_context(|| format!("failed to read {}", input.display()))?;
-    if !redact_types.is_empty() {
+    if !redact_types.is_empty() || redactor.is_some() {
         let doc = load_document(input).context("redacting cells")?;
-        let rows = redact_cells(&doc, redact_types, input);
+        let rows = redact_cells(&doc, redact_types, input, redactor);
         data = serde_json::to_vec(&rows)?;
     }
     let recipient = age::x25519::Recipient::from_str(recipient.trim())
@@ -2102,7 +3056,12 @@ fn decrypt_file(input: &Path, out: &Path, identity_path: &Path) -> Result<()> {
     Ok(())
 }
 
-fn redact_cells(doc: &Document, types: &[String], source: &Path) -> Vec<RedactedCell> {
+fn redact_cells(
+    doc: &Document,
+    types: &[String],
+    source: &Path,
+    redactor: Option<&Redactor>,
+) -> Vec<RedactedCell> {
     let allow = types
         .iter()
         .map(|t| t.trim().to_uppercase())
@@ -2115,6 +3074,10 @@ fn redact_cells(doc: &Document, types: &[String], source: &Path) -> Vec<Redacted
             let ctype = format!("{:?}", cell.cell_type).to_uppercase();
             let keep = allow.is_empty() || allow.contains(&ctype);
             let payload = doc.payload_for(&cell.code_id).unwrap_or("<missing>");
+            let payload = match redactor {
+                Some(redactor) => redactor.redact_text(payload),
+                None => payload.to_string(),
+            };
             RedactedCell {
                 doc: doc_path.clone(),
                 cell_index: idx,
@@ -2126,12 +3089,12 @@ fn redact_cells(doc: &Document, types: &[String], source: &Path) -> Vec<Redacted
                 h: cell.h,
                 importance: cell.importance,
                 cell_type: ctype,
+                preview: short_preview(&payload, 96),
                 text: if keep {
-                    payload.to_string()
+                    payload
                 } else {
                     "<redacted>".to_string()
                 },
-                preview: short_preview(payload, 96),
             }
         })
         .collect()
@@ -2171,6 +3134,58 @@ fn write_cell_manifest(
     Ok(())
 }
 
+/// Chunks of a `3dcf chunk` JSONL by `chunk_id`.
+fn load_chunk_map(path: &Path) -> Result<HashMap<String, ChunkRecord>> {
+    let reader = BufReader::new(
+        File::open(path).with_context(|| format!("failed to open chunks {}", path.display()))?,
+    );
+    let mut chunks = HashMap::new();
+    for (idx, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Ok(meta) = serde_json::from_str::<ChunkMetadataRow>(line) {
+            if meta.row_type == "chunk_meta" {
+                continue;
+            }
+        }
+        let chunk: ChunkRecord = serde_json::from_str(line)
+            .with_context(|| format!("invalid chunk row {} in {}", idx + 1, path.display()))?;
+        chunks.insert(chunk.chunk_id.clone(), chunk);
+    }
+    Ok(chunks)
+}
+
+/// Prints the parent section of a search hit once per parent, indented
+/// under the hit.
+fn print_parent_window(
+    chunks: &HashMap<String, ChunkRecord>,
+    chunk_id: &str,
+    budget: usize,
+    shown: &mut HashSet<String>,
+) {
+    let Some(child) = chunks.get(chunk_id) else {
+        return;
+    };
+    let Some(parent) = child.parent_id.as_ref().and_then(|id| chunks.get(id)) else {
+        return;
+    };
+    if !shown.insert(parent.chunk_id.clone()) {
+        println!("  parent chunk={} (shown above)", parent.chunk_index);
+        return;
+    }
+    let window = parent_window(&parent.text, &child.text, budget);
+    println!(
+        "  parent chunk={} pages={}-{}:",
+        parent.chunk_index, parent.z_start, parent.z_end
+    );
+    for line in window.lines() {
+        println!("    {line}");
+    }
+}
+
 fn short_preview(payload: &str, limit: usize) -> String {
     if payload.len() <= limit {
         payload.to_string()
@@ -2190,13 +3205,6 @@ struct SearchHit {
     preview: String,
 }
 
-#[derive(Debug, Clone)]
-struct FilterPredicate {
-    doc_ids: HashSet<String>,
-    cell_types: HashSet<CellType>,
-    min_importance: Option<f32>,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 struct ChunkMetadataRow {
     row_type: String,
@@ -2300,6 +3308,7 @@ fn embed_chunk_with_retry(
         token_count: chunk.token_count,
         dominant_type: chunk.dominant_type,
         importance_mean: chunk.importance_mean,
+        breadcrumb: chunk.breadcrumb.clone(),
         embedding: vector,
         text: chunk.text.clone(),
     })
@@ -2487,6 +3496,7 @@ impl BackendHandle {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn build_embed_backend(
     backend: &str,
     dimensions: usize,
@@ -2506,19 +3516,19 @@ fn build_embed_backend(
         "hash" => Ok(BackendHandle::new_hash(dimensions, seed)),
         "openai" => {
             let model = openai_model
-                .or_else(|| openai_model_env.as_deref())
+                .or(openai_model_env.as_deref())
                 .ok_or_else(|| anyhow!("--openai-model or OPENAI_EMBEDDING_MODEL required"))?;
             let api_key = openai_api_key
-                .or_else(|| openai_key_env.as_deref())
+                .or(openai_key_env.as_deref())
                 .ok_or_else(|| anyhow!("--openai-api-key or OPENAI_API_KEY required"))?;
             BackendHandle::new_openai(model, api_key, openai_base_url)
         }
         "cohere" => {
             let model = cohere_model
-                .or_else(|| cohere_model_env.as_deref())
+                .or(cohere_model_env.as_deref())
                 .unwrap_or("embed-multilingual-v3.0");
             let api_key = cohere_api_key
-                .or_else(|| cohere_key_env.as_deref())
+                .or(cohere_key_env.as_deref())
                 .ok_or_else(|| anyhow!("--cohere-api-key or COHERE_API_KEY required"))?;
             BackendHandle::new_cohere(model, api_key, cohere_base_url)
         }
@@ -2526,6 +3536,7 @@ fn build_embed_backend(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn build_backend_from_metadata(
     meta: &EmbeddingMetadataRow,
     backend_override: Option<&str>,
@@ -2552,7 +3563,7 @@ fn build_backend_from_metadata(
                 .or(meta.model.as_deref())
                 .ok_or_else(|| anyhow!("embedding metadata missing model; pass --openai-model"))?;
             let api_key = openai_api_key
-                .or_else(|| openai_key_env.as_deref())
+                .or(openai_key_env.as_deref())
                 .ok_or_else(|| anyhow!("--openai-api-key or OPENAI_API_KEY required"))?;
             BackendHandle::new_openai(model, api_key, openai_base_url)
         }
@@ -2561,7 +3572,7 @@ fn build_backend_from_metadata(
                 .or(meta.model.as_deref())
                 .unwrap_or("embed-multilingual-v3.0");
             let api_key = cohere_api_key
-                .or_else(|| cohere_key_env.as_deref())
+                .or(cohere_key_env.as_deref())
                 .ok_or_else(|| anyhow!("--cohere-api-key or COHERE_API_KEY required"))?;
             BackendHandle::new_cohere(model, api_key, cohere_base_url)
         }
@@ -2807,13 +3818,14 @@ impl QdrantClient {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn search(
         &self,
         collection: &str,
         query_text: &str,
         vector: &[f32],
         limit: usize,
-        filter: Option<&FilterPredicate>,
+        filter: Option<&Selector>,
         hybrid: bool,
         records: &[EmbeddingRecord],
     ) -> Result<Vec<SearchHit>> {
@@ -2905,7 +3917,7 @@ impl QdrantClient {
                 None => continue,
             };
             if let Some(pred) = filter {
-                if !pred.matches(record) {
+                if !pred.matches(&record.select_target()) {
                     continue;
                 }
             }
@@ -3070,12 +4082,9 @@ fn write_report(input: &Path, out: &Path) -> Result<()> {
         return Err(anyhow!("no benchmark rows found"));
     }
     let total = rows.len() as f64;
-    let mean = rows.iter().map(|r| r.savings_ratio as f64).sum::<f64>() / total;
+    let mean = rows.iter().map(|r| r.savings_ratio).sum::<f64>() / total;
     let median = percentile(
-        &rows
-            .iter()
-            .map(|r| r.savings_ratio as f64)
-            .collect::<Vec<_>>(),
+        &rows.iter().map(|r| r.savings_ratio).collect::<Vec<_>>(),
         0.5,
     );
     let encode_vals = rows.iter().map(|r| r.encode_ms as f64).collect::<Vec<_>>();
@@ -3168,6 +4177,29 @@ struct RedactedCell {
     preview: String,
 }
 
+/// Writes rotated, skewed, and speckled page images plus a `manifest.jsonl`
+/// recording what was applied, so preprocessing can be checked end to end.
+fn generate_scan_fixtures(out_dir: &Path, count: usize, seed: u64) -> Result<()> {
+    fs::create_dir_all(out_dir)?;
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut manifest = File::create(out_dir.join("manifest.jsonl"))?;
+    for idx in 0..count {
+        let fixture = ScanFixture {
+            rotation: [0, 90, 180, 270][rng.gen_range(0..4)],
+            skew_degrees: (rng.gen_range(-60..=60) as f32) / 10.0,
+            specks: rng.gen_range(0..300),
+            seed: rng.gen(),
+            ..ScanFixture::default()
+        };
+        let name = format!("scan_{idx:03}.png");
+        fixture.write_png(&out_dir.join(&name))?;
+        let mut entry = serde_json::to_value(&fixture)?;
+        entry["file"] = serde_json::Value::String(name);
+        writeln!(manifest, "{}", serde_json::to_string(&entry)?)?;
+    }
+    Ok(())
+}
+
 fn generate_synthetic(out_dir: &Path, count: usize, seed: u64) -> Result<()> {
     fs::create_dir_all(out_dir)?;
     let mut rng = StdRng::seed_from_u64(seed);
@@ -3195,6 +4227,8 @@ struct AppConfig {
     defaults: DefaultSections,
     #[serde(default)]
     pricing: PricingConfig,
+    #[serde(default)]
+    sensitivity: Option<SensitivityRules>,
 }
 
 #[derive(Debug, Default, Deserialize)]
@@ -3228,10 +4262,19 @@ struct EncodeDefaults {
     enable_ocr: Option<bool>,
     force_ocr: Option<bool>,
     ocr_langs: Option<Vec<String>>,
+    ocr_min_confidence: Option<f32>,
+    preprocess: Option<Vec<String>>,
+    extract_figures: Option<bool>,
+    figure_ocr: Option<bool>,
+    redact_pii: Option<bool>,
+    redact_mode: Option<String>,
+    pii_kinds: Option<Vec<String>>,
+    pii_gazetteer: Option<PathBuf>,
     heading_boost: Option<f32>,
     number_boost: Option<f32>,
     footer_penalty: Option<f32>,
     early_line_bonus: Option<f32>,
+    importance_model: Option<PathBuf>,
     table_mode: Option<String>,
     preset_label: Option<String>,
     budget_label: Option<String>,
@@ -3250,7 +4293,13 @@ struct ResolvedEncodeConfig {
     enable_ocr: bool,
     force_ocr: bool,
     ocr_langs: Vec<String>,
+    ocr_min_confidence: f32,
+    preprocess: Option<PreprocessConfig>,
+    extract_figures: bool,
+    figure_ocr: bool,
+    redaction: Option<RedactionConfig>,
     importance: ImportanceTuning,
+    importance_model: Option<PathBuf>,
     table_mode: TableMode,
     preset_label: String,
     budget_label: String,
@@ -3304,6 +4353,39 @@ fn resolve_encode_config(
         || force_ocr
         || defaults.and_then(|d| d.enable_ocr).unwrap_or(false)
         || !ocr_langs.is_empty();
+    let ocr_min_confidence = cli
+        .ocr_min_confidence
+        .or_else(|| defaults.and_then(|d| d.ocr_min_confidence))
+        .unwrap_or(0.0);
+    let preprocess = if !cli.preprocess.is_empty() {
+        Some(PreprocessConfig::from_steps(&cli.preprocess)?)
+    } else {
+        defaults
+            .and_then(|d| d.preprocess.as_ref())
+            .map(PreprocessConfig::from_steps)
+            .transpose()?
+    };
+    let figure_ocr = cli.figure_ocr || defaults.and_then(|d| d.figure_ocr).unwrap_or(false);
+    let extract_figures = cli.extract_figures
+        || figure_ocr
+        || defaults.and_then(|d| d.extract_figures).unwrap_or(false);
+    let redact_pii = cli.redact_pii || defaults.and_then(|d| d.redact_pii).unwrap_or(false);
+    let redaction = redaction_config(
+        redact_pii,
+        cli.redact_mode
+            .as_deref()
+            .or_else(|| defaults.and_then(|d| d.redact_mode.as_deref())),
+        if cli.pii_kinds.is_empty() {
+            defaults
+                .and_then(|d| d.pii_kinds.as_deref())
+                .unwrap_or_default()
+        } else {
+            &cli.pii_kinds
+        },
+        cli.pii_gazetteer
+            .clone()
+            .or_else(|| defaults.and_then(|d| d.pii_gazetteer.clone())),
+    )?;
     let drop_footers = cli.drop_footers || defaults.and_then(|d| d.drop_footers).unwrap_or(false);
     let strict_numguard =
         cli.strict_numguard || defaults.and_then(|d| d.strict_numguard).unwrap_or(false);
@@ -3338,6 +4420,10 @@ fn resolve_encode_config(
     if let Some(v) = cli.early_line_bonus {
         importance.early_line_bonus = v;
     }
+    let importance_model = cli
+        .importance_model
+        .clone()
+        .or_else(|| defaults.and_then(|d| d.importance_model.clone()));
     let table_mode = parse_table_mode(
         cli.table_mode
             .as_deref()
@@ -3364,7 +4450,13 @@ fn resolve_encode_config(
         enable_ocr,
         force_ocr,
         ocr_langs,
+        ocr_min_confidence,
+        preprocess,
+        extract_figures,
+        figure_ocr,
+        redaction,
         importance,
+        importance_model,
         table_mode,
         preset_label,
         budget_label,
@@ -3374,7 +4466,11 @@ fn resolve_encode_config(
 }
 
 fn build_encoder_from_resolved(resolved: &ResolvedEncodeConfig) -> Result<Encoder> {
-    let builder = Encoder::builder(&resolved.preset)?
+    Ok(encoder_builder_from_resolved(resolved)?.build())
+}
+
+fn encoder_builder_from_resolved(resolved: &ResolvedEncodeConfig) -> Result<EncoderBuilder> {
+    let mut builder = Encoder::builder(&resolved.preset)?
         .budget(resolved.budget)
         .drop_footers(resolved.drop_footers)
         .dedup_window(resolved.dedup_window)
@@ -3383,19 +4479,64 @@ fn build_encoder_from_resolved(resolved: &ResolvedEncodeConfig) -> Result<Encode
         .enable_ocr(resolved.enable_ocr)
         .force_ocr(resolved.force_ocr)
         .ocr_languages(resolved.ocr_langs.clone())
-        .importance_tuning(resolved.importance.clone());
-    Ok(builder.build())
+        .ocr_min_confidence(resolved.ocr_min_confidence)
+        .extract_figures(resolved.extract_figures)
+        .figure_ocr(resolved.figure_ocr)
+        .importance_tuning(resolved.importance);
+    if let Some(path) = &resolved.importance_model {
+        let model = LinearImportanceModel::load(path)
+            .with_context(|| format!("failed to load importance model {}", path.display()))?;
+        builder = builder.importance_scorer(Arc::new(model.into_scorer()));
+    }
+    if let Some(preprocess) = resolved.preprocess {
+        builder = builder.preprocess(preprocess);
+    }
+    if let Some(redaction) = &resolved.redaction {
+        builder = builder.redactor(Redactor::new(redaction.clone())?);
+    }
+    Ok(builder)
+}
+
+/// `None` unless redaction was requested; the pseudonym key comes from the environment.
+fn redaction_config(
+    enabled: bool,
+    mode: Option<&str>,
+    kinds: &[String],
+    gazetteer: Option<PathBuf>,
+) -> Result<Option<RedactionConfig>> {
+    if !enabled {
+        return Ok(None);
+    }
+    let mode = mode
+        .map(str::parse::<RedactionMode>)
+        .transpose()?
+        .unwrap_or_default();
+    let kinds = kinds
+        .iter()
+        .map(|kind| kind.parse::<PiiKind>())
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(Some(RedactionConfig {
+        kinds,
+        gazetteer,
+        ..RedactionConfig::with_mode(mode)
+    }))
 }
 
 fn build_context_artifacts(
     input: &Path,
     encode: &EncodeArgs,
     defaults: Option<&EncodeDefaults>,
+    format: ContextFormat,
+    selector: Option<&Selector>,
 ) -> Result<(ContextArtifacts, Vec<NumGuardAlert>)> {
     let resolved = resolve_encode_config(encode, defaults)?;
     let encoder = build_encoder_from_resolved(&resolved)?;
-    let (doc, metrics, raw_text) = encoder.encode_path_with_plaintext(input)?;
+    let (mut doc, metrics, raw_text) = encoder.encode_path_with_plaintext(input)?;
+    if let Some(selector) = selector {
+        doc.retain_selected(selector);
+    }
     let serializer = TextSerializer::with_config(TextSerializerConfig {
+        format,
         table_mode: resolved.table_mode,
         preset_label: Some(resolved.preset_label.clone()),
         budget_label: Some(resolved.budget_label.clone()),
@@ -3443,12 +4584,12 @@ fn percentile(values: &[f64], quantile: f64) -> f64 {
 fn search_dense_only(
     query_vec: &[f32],
     records: &[EmbeddingRecord],
-    filter: Option<&FilterPredicate>,
+    filter: Option<&Selector>,
 ) -> Vec<SearchHit> {
     let mut hits = Vec::new();
     for record in records {
         if let Some(pred) = filter {
-            if !pred.matches(record) {
+            if !pred.matches(&record.select_target()) {
                 continue;
             }
         }
@@ -3473,7 +4614,7 @@ fn search_hybrid(
     query_text: &str,
     query_vec: &[f32],
     records: &[EmbeddingRecord],
-    filter: Option<&FilterPredicate>,
+    filter: Option<&Selector>,
 ) -> Vec<SearchHit> {
     let query_tokens = tokenize(query_text);
     if query_tokens.is_empty() {
@@ -3500,7 +4641,7 @@ fn search_hybrid(
     let mut hits = Vec::new();
     for (idx, record) in records.iter().enumerate() {
         if let Some(pred) = filter {
-            if !pred.matches(record) {
+            if !pred.matches(&record.select_target()) {
                 continue;
             }
         }
@@ -12,7 +12,7 @@ fn main() {
     println!("cargo:rerun-if-changed={}", proto_file.display());
 
     let mut config = prost_build::Config::new();
-    config.bytes(&[
+    config.bytes([
         ".dcf.v1.Cell.code_id",
         ".dcf.v1.DictEntry.code_id",
         ".dcf.v1.NumGuard.sha1",
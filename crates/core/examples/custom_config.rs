@@ -28,6 +28,7 @@ fn main() -> Result<()> {
 
     // Serializer configuration
     let serializer = TextSerializer::with_config(TextSerializerConfig {
+        format: ContextFormat::Ctx3d,
         include_header: true,
         include_grammar: false,
         max_preview_chars: 200,
@@ -43,6 +44,7 @@ fn main() -> Result<()> {
         overlap_cells: 20,
         max_tokens: 512,
         overlap_tokens: 64,
+        ..Default::default()
     };
     let chunker = Chunker::new(chunk_config);
 
@@ -78,7 +80,10 @@ This is a paragraph with some **bold** text.
     );
 
     let serialized = serializer.to_string(&document)?;
-    println!("\nSerialized preview:\n{}", &serialized[..serialized.len().min(300)]);
+    println!(
+        "\nSerialized preview:\n{}",
+        &serialized[..serialized.len().min(300)]
+    );
 
     let chunks = chunker.chunk_document(&document, "markdown_doc");
     println!("Generated {} chunks", chunks.len());
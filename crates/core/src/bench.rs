@@ -10,7 +10,8 @@ use walkdir::WalkDir;
 
 use crate::document::Document;
 use crate::encoder::Encoder;
-use crate::error::Result;
+use crate::error::{DcfError, Result};
+use crate::importance::{importance_samples, IdfTable, LinearImportanceModel};
 use crate::metrics::{cer, numeric_stats, wer};
 use crate::stats::{Stats, TokenizerKind};
 
@@ -201,6 +202,49 @@ impl BenchRunner {
         })
     }
 
+    /// Fits a [`LinearImportanceModel`] on every source that has gold text under `gold_root`.
+    ///
+    /// Cells whose payload appears in the gold text are labelled important; everything else
+    /// (boilerplate, OCR noise, repeated footers) is labelled as droppable.
+    pub fn fit_importance(&self, l2: f32) -> Result<LinearImportanceModel> {
+        if self.config.gold_root.is_none() {
+            return Err(DcfError::Bench(
+                "fitting importance weights requires a gold root".to_string(),
+            ));
+        }
+        let encoder = self.build_encoder(None)?;
+        let mut labelled = Vec::new();
+        for entry in WalkDir::new(&self.config.root)
+            .into_iter()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().is_file())
+        {
+            let path = entry.path();
+            if !is_supported_source(path) {
+                continue;
+            }
+            let Some((doc, _)) = skip_without_ocr(path, encoder.encode_path(path))? else {
+                continue;
+            };
+            let rel = self.relative_path(path);
+            if let Some(gold) = self.load_gold(&rel, doc.total_pages())? {
+                let gold_text = gold.doc.clone().unwrap_or_else(|| gold.joined_pages());
+                labelled.push((doc, gold_text));
+            }
+        }
+        if labelled.is_empty() {
+            return Err(DcfError::Bench(
+                "no documents with gold text found for importance fitting".to_string(),
+            ));
+        }
+        let idf = IdfTable::from_documents(labelled.iter().map(|(doc, _)| doc.decode_to_text()));
+        let samples = labelled
+            .iter()
+            .flat_map(|(doc, gold)| importance_samples(doc, gold, &idf))
+            .collect::<Vec<_>>();
+        LinearImportanceModel::fit(&samples, l2, idf)
+    }
+
     fn run_encode_cycle(&self, budget: Option<usize>) -> Result<Vec<BenchResult>> {
         let encoder = self.build_encoder(budget)?;
         let mut rows = Vec::new();
@@ -213,7 +257,11 @@ impl BenchRunner {
             if !is_supported_source(path) {
                 continue;
             }
-            let (row, pages) = self.process_encode_doc(&encoder, path, budget)?;
+            let Some((row, pages)) =
+                skip_without_ocr(path, self.process_encode_doc(&encoder, path, budget))?
+            else {
+                continue;
+            };
             self.append_row(&row)?;
             for page in pages {
                 self.append_page_row(&page)?;
@@ -489,16 +537,40 @@ impl GoldDoc {
 }
 
 fn is_supported_source(path: &Path) -> bool {
-    match path
-        .extension()
-        .and_then(|ext| ext.to_str())
-        .map(|s| s.to_lowercase())
-        .as_deref()
-    {
-        Some("pdf") | Some("txt") | Some("text") | Some("md") | Some("markdown") | Some("html")
-        | Some("htm") | Some("json") | Some("tex") | Some("bib") => true,
-        None => true,
-        _ => false,
+    matches!(
+        path.extension()
+            .and_then(|ext| ext.to_str())
+            .map(|s| s.to_lowercase())
+            .as_deref(),
+        Some("pdf")
+            | Some("txt")
+            | Some("text")
+            | Some("md")
+            | Some("markdown")
+            | Some("html")
+            | Some("htm")
+            | Some("json")
+            | Some("tex")
+            | Some("bib")
+            | Some("png")
+            | Some("jpg")
+            | Some("jpeg")
+            | Some("tif")
+            | Some("tiff")
+            | Some("bmp")
+            | None
+    )
+}
+
+/// Scans are skipped with a warning, rather than failing the run, when this
+/// build cannot OCR them.
+fn skip_without_ocr<T>(path: &Path, result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Err(DcfError::OcrSupportDisabled) => {
+            tracing::warn!("skipping {}: OCR support is disabled", path.display());
+            Ok(None)
+        }
+        other => other.map(Some),
     }
 }
 
@@ -0,0 +1,364 @@
+//! Multi-document bundles with one shared payload dictionary.
+//!
+//! Boilerplate repeated across a corpus (disclaimers, headers, legal
+//! notices) is stored once: every document keeps its own cell stream, pages,
+//! NumGuards and links, while payloads live in the bundle's dictionary and
+//! each document lists the entries it uses.
+
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+
+use indexmap::IndexMap;
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::document::{fixed_bytes, CodeHash, Document};
+use crate::error::{DcfError, Result};
+use crate::proto;
+
+/// Conventional file extension for bundles.
+pub const BUNDLE_EXTENSION: &str = "3dcfb";
+
+const BUNDLE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone)]
+struct BundleEntry {
+    name: String,
+    /// The document without its dictionary.
+    document: Document,
+    /// Indices into the shared dictionary, in the document's own dict order.
+    dict_refs: Vec<u32>,
+}
+
+/// Summary of one bundled document, as returned by [`Bundle::list`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleDocInfo {
+    pub name: String,
+    pub pages: usize,
+    pub cells: usize,
+    pub payloads: usize,
+    /// UTF-8 bytes of this document's payloads, i.e. its standalone dictionary size.
+    pub payload_bytes: usize,
+}
+
+/// Dictionary sharing across a bundle.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BundleStats {
+    pub documents: usize,
+    pub cells: usize,
+    /// Sum of every document's own dictionary size, in entries and bytes.
+    pub standalone_payloads: usize,
+    pub standalone_payload_bytes: usize,
+    /// Size of the shared dictionary.
+    pub shared_payloads: usize,
+    pub shared_payload_bytes: usize,
+    /// `standalone_payload_bytes / shared_payload_bytes`; 1.0 when nothing is shared.
+    pub cross_document_ratio: f32,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Bundle {
+    dict: IndexMap<CodeHash, String>,
+    entries: Vec<BundleEntry>,
+}
+
+impl Bundle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.iter().any(|entry| entry.name == name)
+    }
+
+    /// Adds `document` under `name`, moving its payloads into the shared dictionary.
+    /// Figure images stay with the entry and are written next to the bundle by
+    /// [`Bundle::save`]; the redaction log comes back from [`Bundle::extract`]
+    /// until the bundle is saved, since, as with a `.3dcf`, the file stores neither.
+    pub fn append(&mut self, name: impl Into<String>, document: &Document) -> Result<()> {
+        let name = name.into();
+        if name.is_empty() {
+            return Err(DcfError::Other(
+                "bundle entry name must not be empty".into(),
+            ));
+        }
+        if self.contains(&name) {
+            return Err(DcfError::Other(format!(
+                "bundle already contains a document named '{name}'"
+            )));
+        }
+        let mut dict_refs = Vec::with_capacity(document.dict.len());
+        for (code_id, payload) in &document.dict {
+            let (idx, _) = self.dict.insert_full(*code_id, payload.clone());
+            dict_refs.push(idx as u32);
+        }
+        let mut stripped = document.clone();
+        stripped.dict.clear();
+        stripped.dropped.clear();
+        self.entries.push(BundleEntry {
+            name,
+            document: stripped,
+            dict_refs,
+        });
+        Ok(())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(|entry| entry.name.as_str())
+    }
+
+    pub fn list(&self) -> Vec<BundleDocInfo> {
+        self.entries
+            .iter()
+            .map(|entry| BundleDocInfo {
+                name: entry.name.clone(),
+                pages: entry.document.pages.len(),
+                cells: entry.document.cells.len(),
+                payloads: entry.dict_refs.len(),
+                payload_bytes: self.payload_bytes(&entry.dict_refs),
+            })
+            .collect()
+    }
+
+    /// Rebuilds the standalone document stored under `name`.
+    pub fn extract(&self, name: &str) -> Result<Document> {
+        let entry = self
+            .entries
+            .iter()
+            .find(|entry| entry.name == name)
+            .ok_or_else(|| DcfError::Other(format!("bundle has no document named '{name}'")))?;
+        let mut document = entry.document.clone();
+        for idx in &entry.dict_refs {
+            let (code_id, payload) =
+                self.dict
+                    .get_index(*idx as usize)
+                    .ok_or(DcfError::CorruptContainer(
+                        "bundle dict reference out of range",
+                    ))?;
+            document.dict.insert(*code_id, payload.clone());
+        }
+        document.migrate()?;
+        Ok(document)
+    }
+
+    pub fn stats(&self) -> BundleStats {
+        let standalone_payloads = self.entries.iter().map(|e| e.dict_refs.len()).sum();
+        let standalone_payload_bytes = self
+            .entries
+            .iter()
+            .map(|entry| self.payload_bytes(&entry.dict_refs))
+            .sum();
+        let shared_payload_bytes: usize = self.dict.values().map(String::len).sum();
+        BundleStats {
+            documents: self.entries.len(),
+            cells: self.entries.iter().map(|e| e.document.cells.len()).sum(),
+            standalone_payloads,
+            standalone_payload_bytes,
+            shared_payloads: self.dict.len(),
+            shared_payload_bytes,
+            cross_document_ratio: if shared_payload_bytes == 0 {
+                1.0
+            } else {
+                standalone_payload_bytes as f32 / shared_payload_bytes as f32
+            },
+        }
+    }
+
+    fn payload_bytes(&self, refs: &[u32]) -> usize {
+        refs.iter()
+            .filter_map(|idx| self.dict.get_index(*idx as usize))
+            .map(|(_, payload)| payload.len())
+            .sum()
+    }
+
+    pub fn to_proto(&self) -> proto::Bundle {
+        proto::Bundle {
+            version: BUNDLE_VERSION,
+            dict: self
+                .dict
+                .iter()
+                .map(|(code_id, payload)| proto::DictEntry {
+                    code_id: code_id.to_vec().into(),
+                    payload_utf8: payload.clone(),
+                })
+                .collect(),
+            documents: self
+                .entries
+                .iter()
+                .map(|entry| proto::BundleEntry {
+                    name: entry.name.clone(),
+                    document: Some(entry.document.to_proto()),
+                    dict_refs: entry.dict_refs.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn from_proto(bundle: proto::Bundle) -> Result<Self> {
+        if bundle.version > BUNDLE_VERSION {
+            return Err(DcfError::UnsupportedVersion {
+                found: bundle.version,
+                supported: BUNDLE_VERSION,
+            });
+        }
+        let mut dict = IndexMap::with_capacity(bundle.dict.len());
+        for entry in bundle.dict {
+            let code_id: CodeHash = fixed_bytes("bundle dict code_id", &entry.code_id)?;
+            dict.insert(code_id, entry.payload_utf8);
+        }
+        let mut entries = Vec::with_capacity(bundle.documents.len());
+        for entry in bundle.documents {
+            if entry
+                .dict_refs
+                .iter()
+                .any(|idx| *idx as usize >= dict.len())
+            {
+                return Err(DcfError::CorruptContainer(
+                    "bundle dict reference out of range",
+                ));
+            }
+            let document = entry
+                .document
+                .map(Document::from_proto_unmigrated)
+                .transpose()?
+                .unwrap_or_default();
+            entries.push(BundleEntry {
+                name: entry.name,
+                document,
+                dict_refs: entry.dict_refs,
+            });
+        }
+        Ok(Self { dict, entries })
+    }
+
+    /// Protobuf, compressed with zstd like a single-document `.3dcf`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let proto = self.to_proto();
+        let mut buf = Vec::with_capacity(proto.encoded_len());
+        proto.encode(&mut buf)?;
+        let mut encoder = zstd::stream::Encoder::new(Vec::new(), 3)?;
+        encoder.write_all(&buf)?;
+        Ok(encoder.finish()?)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        let mut decoder = zstd::stream::Decoder::new(bytes)?;
+        let mut buf = Vec::new();
+        decoder.read_to_end(&mut buf)?;
+        Self::from_proto(proto::Bundle::decode(&*buf)?)
+    }
+
+    /// Writes the bundle and, beside it, the figure images of its documents.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let bytes = self.to_bytes()?;
+        File::create(path)?.write_all(&bytes)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        for entry in &self.entries {
+            entry.document.write_figures(dir)?;
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let mut buf = Vec::new();
+        File::open(path)?.read_to_end(&mut buf)?;
+        Self::from_bytes(&buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOTICE: &str = "This filing contains forward-looking statements subject to risks.";
+
+    #[test]
+    fn shared_payloads_are_stored_once() {
+        let a = Document::fixture(&[&["Acme Corp annual report", NOTICE]]);
+        let b = Document::fixture(&[&["Globex annual report", NOTICE]]);
+        let mut bundle = Bundle::new();
+        bundle.append("acme", &a).unwrap();
+        bundle.append("globex", &b).unwrap();
+        assert!(bundle.append("acme", &a).is_err());
+
+        let stats = bundle.stats();
+        assert_eq!(stats.documents, 2);
+        assert_eq!(stats.standalone_payloads, 4);
+        assert_eq!(stats.shared_payloads, 3);
+        let shared = "Acme Corp annual report".len() + "Globex annual report".len() + NOTICE.len();
+        assert_eq!(stats.shared_payload_bytes, shared);
+        assert!(stats.cross_document_ratio > 1.5);
+
+        let listed = bundle.list();
+        assert_eq!(listed[1].name, "globex");
+        assert_eq!(
+            listed[1].payload_bytes,
+            "Globex annual report".len() + NOTICE.len()
+        );
+    }
+
+    #[test]
+    fn extracted_documents_match_the_originals() {
+        let a = Document::fixture(&[&["Acme Corp annual report", NOTICE]]);
+        let b = Document::fixture(&[&[NOTICE, "Globex annual report", "Revenue rose 12%"]]);
+        let mut bundle = Bundle::new();
+        bundle.append("acme", &a).unwrap();
+        bundle.append("globex", &b).unwrap();
+
+        let decoded = Bundle::from_bytes(&bundle.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.names().collect::<Vec<_>>(), vec!["acme", "globex"]);
+        let globex = decoded.extract("globex").unwrap();
+        assert_eq!(globex.ordered_cells(), b.ordered_cells());
+        assert_eq!(
+            globex.dict.iter().collect::<Vec<_>>(),
+            b.dict.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(globex.decode_to_text(), b.decode_to_text());
+        assert!(decoded.extract("initech").is_err());
+    }
+
+    #[test]
+    fn figures_and_redactions_are_kept() {
+        use crate::pii::{PiiKind, PiiMatch, Redaction};
+
+        let mut doc = Document::fixture(&[&["Contact [EMAIL] for details"]]);
+        doc.figure_files.insert(
+            "figures/chart.png".to_string(),
+            vec![0x89, b'P', b'N', b'G'],
+        );
+        doc.redactions.push(Redaction {
+            z: 0,
+            x: 64,
+            y: 64,
+            pii: PiiMatch {
+                kind: PiiKind::Email,
+                start: 8,
+                end: 24,
+                replacement: "[EMAIL]".to_string(),
+                fingerprint: String::new(),
+            },
+        });
+        let mut bundle = Bundle::new();
+        bundle.append("acme", &doc).unwrap();
+        let extracted = bundle.extract("acme").unwrap();
+        assert_eq!(extracted.figure_files, doc.figure_files);
+        assert_eq!(extracted.redactions, doc.redactions);
+
+        let dir = tempfile::tempdir().unwrap();
+        bundle.save(dir.path().join("corpus.3dcfb")).unwrap();
+        assert_eq!(
+            std::fs::read(dir.path().join("figures/chart.png")).unwrap(),
+            vec![0x89, b'P', b'N', b'G']
+        );
+    }
+}
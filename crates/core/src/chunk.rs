@@ -1,13 +1,21 @@
-use hex;
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tiktoken_rs::CoreBPE;
 
+use crate::citation::CellIds;
 use crate::document::{CellRecord, CellType, Document};
+use crate::embedding::{Embedder, HashEmbedder, HashEmbedderConfig};
+use crate::render::HeadingLevels;
+use crate::select::section_paths;
+use crate::sentence::{sentence_ranges, word_ranges};
 
 static TOKENIZER: Lazy<CoreBPE> = Lazy::new(|| tiktoken_rs::cl100k_base().expect("tokenizer"));
-const CHUNK_VERSION: u32 = 1;
+const CHUNK_VERSION: u32 = 2;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "snake_case")]
@@ -16,6 +24,34 @@ pub enum ChunkMode {
     Tokens,
     Headings,
     TableRows,
+    /// Splits where the embedding similarity of adjacent cells drops below
+    /// the `breakpoint_percentile` of all adjacent similarities, keeping
+    /// chunks between `min_tokens` and `max_tokens` and never crossing a
+    /// heading.
+    Semantic,
+    /// Emits one parent chunk per section and token-bounded child chunks
+    /// inside it that point back through `parent_id`, for retrieving on
+    /// children and answering from their parent.
+    Hierarchical,
+}
+
+/// Where a chunk sits in a [`ChunkMode::Hierarchical`] tree.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ChunkLevel {
+    /// Produced by a non-hierarchical mode.
+    #[default]
+    Flat,
+    /// A whole section; not meant to be embedded.
+    Parent,
+    /// A retrieval unit inside the section named by `parent_id`.
+    Child,
+}
+
+impl ChunkLevel {
+    fn is_flat(&self) -> bool {
+        *self == ChunkLevel::Flat
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -25,6 +61,18 @@ pub struct ChunkConfig {
     pub overlap_cells: usize,
     pub max_tokens: usize,
     pub overlap_tokens: usize,
+    /// Semantic chunks below this size are not split at a similarity drop.
+    #[serde(default = "default_min_tokens")]
+    pub min_tokens: usize,
+    /// Percentile (0–100) of adjacent-cell similarities below which semantic
+    /// chunking splits.
+    #[serde(default = "default_breakpoint_percentile")]
+    pub breakpoint_percentile: f32,
+    /// Start the text of every chunk (except hierarchical parents) with its
+    /// heading breadcrumb, so embeddings see which section it came from.
+    /// Packing leaves room for the longest breadcrumb within `max_tokens`.
+    #[serde(default)]
+    pub prepend_breadcrumb: bool,
 }
 
 impl Default for ChunkConfig {
@@ -35,10 +83,21 @@ impl Default for ChunkConfig {
             overlap_cells: 20,
             max_tokens: 512,
             overlap_tokens: 64,
+            min_tokens: default_min_tokens(),
+            breakpoint_percentile: default_breakpoint_percentile(),
+            prepend_breadcrumb: false,
         }
     }
 }
 
+fn default_min_tokens() -> usize {
+    64
+}
+
+fn default_breakpoint_percentile() -> f32 {
+    25.0
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ChunkRecord {
     pub chunk_id: String,
@@ -55,15 +114,53 @@ pub struct ChunkRecord {
     pub dominant_type: CellType,
     #[serde(default)]
     pub importance_mean: f32,
+    #[serde(default, skip_serializing_if = "ChunkLevel::is_flat")]
+    pub level: ChunkLevel,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<String>,
+    /// Citation IDs (see [`CellIds`]) of the chunk's cells in reading order.
+    /// Unlike `cell_start`/`cell_end` they survive re-encoding.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cell_ids: Vec<String>,
+    /// Union of the chunk's cell boxes on each page it touches.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bboxes: Vec<PageBox>,
+    /// Headings enclosing the chunk's first non-heading cell, outermost first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breadcrumb: Vec<String>,
+}
+
+/// A box in page pixels, in the same coordinates as [`CellRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageBox {
+    pub z: u32,
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
 }
 
 pub struct Chunker {
     config: ChunkConfig,
+    embedder: Arc<dyn Embedder>,
 }
 
 impl Chunker {
+    /// Semantic chunking embeds cells with a 256-dimension [`HashEmbedder`]
+    /// unless [`Chunker::with_embedder`] supplies another backend.
     pub fn new(config: ChunkConfig) -> Self {
-        Self { config }
+        Self {
+            config,
+            embedder: Arc::new(HashEmbedder::new(HashEmbedderConfig {
+                dimensions: 256,
+                ..Default::default()
+            })),
+        }
+    }
+
+    pub fn with_embedder(mut self, embedder: Arc<dyn Embedder>) -> Self {
+        self.embedder = embedder;
+        self
     }
 
     pub fn chunk_document(&self, document: &Document, doc_id: &str) -> Vec<ChunkRecord> {
@@ -71,12 +168,102 @@ impl Chunker {
         if ordered.is_empty() {
             return Vec::new();
         }
-        match self.config.mode {
-            ChunkMode::Cells => self.chunk_by_cells(document, doc_id, &ordered),
-            ChunkMode::Tokens => self.chunk_by_tokens(document, doc_id, &ordered),
-            ChunkMode::Headings => self.chunk_by_headings(document, doc_id, &ordered),
-            ChunkMode::TableRows => self.chunk_table_blocks(document, doc_id, &ordered),
+        let paths = section_paths(document);
+        // Pack against what is left of `max_tokens` once the longest
+        // breadcrumb line is prepended.
+        let reserve = if self.config.prepend_breadcrumb && self.config.max_tokens > 0 {
+            paths
+                .values()
+                .filter(|path| !path.is_empty())
+                .map(|path| count_tokens(&path.join(" > ")) + 1)
+                .max()
+                .unwrap_or(0)
+        } else {
+            0
+        };
+        let packer = Chunker {
+            config: ChunkConfig {
+                max_tokens: self.config.max_tokens.saturating_sub(reserve).max(1),
+                ..self.config
+            },
+            embedder: self.embedder.clone(),
+        };
+        let packer = if reserve > 0 { &packer } else { self };
+        let mut chunks = match self.config.mode {
+            ChunkMode::Cells => packer.chunk_by_cells(document, doc_id, &ordered),
+            ChunkMode::Tokens => packer.chunk_by_tokens(document, doc_id, &ordered),
+            ChunkMode::Headings => packer.chunk_by_headings(document, doc_id, &ordered),
+            ChunkMode::TableRows => packer.chunk_table_blocks(document, doc_id, &ordered),
+            ChunkMode::Semantic => packer.chunk_semantic(document, doc_id, &ordered),
+            ChunkMode::Hierarchical => packer.chunk_hierarchical(document, doc_id, &ordered),
+        };
+        self.add_provenance(document, &ordered, &paths, &mut chunks);
+        chunks
+    }
+
+    fn add_provenance(
+        &self,
+        document: &Document,
+        ordered: &[CellRecord],
+        paths: &HashMap<(u32, i32, i32), Vec<String>>,
+        chunks: &mut [ChunkRecord],
+    ) {
+        let ids = CellIds::new(document);
+        for chunk in chunks {
+            let cells = &ordered[chunk.cell_start..=chunk.cell_end];
+            chunk.cell_ids = cells.iter().map(|cell| ids.id(cell)).collect();
+            chunk.bboxes = page_boxes(cells);
+            let lead = cells
+                .iter()
+                .find(|cell| cell.cell_type != CellType::Header)
+                .unwrap_or(&cells[0]);
+            chunk.breadcrumb = paths.get(&lead.key()).cloned().unwrap_or_default();
+            // Headings the chunk starts with are already in its text.
+            let shown: Vec<&String> = cells
+                .iter()
+                .take_while(|cell| cell.cell_type == CellType::Header)
+                .filter_map(|cell| paths.get(&cell.key())?.last())
+                .collect();
+            let mut crumbs = chunk.breadcrumb.as_slice();
+            while let [rest @ .., last] = crumbs {
+                if !shown.contains(&last) {
+                    break;
+                }
+                crumbs = rest;
+            }
+            if self.config.prepend_breadcrumb
+                && chunk.level != ChunkLevel::Parent
+                && !crumbs.is_empty()
+            {
+                let line = crumbs.join(" > ");
+                chunk.token_count += count_tokens(&line) + 1;
+                chunk.text = format!("{line}\n{}", chunk.text);
+            }
+        }
+    }
+
+    /// For each cell in reading order, the `chunk_id` of the
+    /// [`ChunkMode::Hierarchical`] parent chunk (section) containing it,
+    /// whatever mode this chunker is configured with. Blank cells outside
+    /// any parent get `None`.
+    pub fn section_parents(&self, document: &Document, doc_id: &str) -> Vec<Option<String>> {
+        let ordered = document.ordered_cells();
+        let mut parents = vec![None; ordered.len()];
+        let hierarchical = Chunker {
+            config: ChunkConfig {
+                mode: ChunkMode::Hierarchical,
+                ..self.config
+            },
+            embedder: self.embedder.clone(),
+        };
+        for chunk in hierarchical.chunk_hierarchical(document, doc_id, &ordered) {
+            if chunk.level == ChunkLevel::Parent {
+                for slot in &mut parents[chunk.cell_start..=chunk.cell_end] {
+                    *slot = Some(chunk.chunk_id.clone());
+                }
+            }
         }
+        parents
     }
 
     fn chunk_by_cells(
@@ -87,11 +274,27 @@ impl Chunker {
     ) -> Vec<ChunkRecord> {
         let chunk_size = self.config.cells_per_chunk.max(1);
         let overlap = self.config.overlap_cells.min(chunk_size.saturating_sub(1));
+        let segments = Segments::new(document, ordered, usize::MAX);
         let mut start = 0usize;
+        let mut prev_end = 0usize;
         let mut chunk_index = 0usize;
         let mut chunks = Vec::new();
         while start < ordered.len() {
-            let end = (start + chunk_size).min(ordered.len());
+            // Pull the cut back to the last sentence start in the window, or
+            // push it forward when the window holds a single sentence.
+            let mut end = (start + chunk_size).min(ordered.len());
+            let floor = start.max(prev_end) + 1;
+            let mut back = end;
+            while back > floor && !segments.starts_sentence(back) {
+                back -= 1;
+            }
+            if back >= floor && segments.starts_sentence(back) {
+                end = back;
+            } else {
+                while !segments.starts_sentence(end) {
+                    end += 1;
+                }
+            }
             if let Some(record) =
                 self.build_chunk(document, doc_id, chunk_index, start, end, ordered)
             {
@@ -101,10 +304,15 @@ impl Chunker {
             if end == ordered.len() {
                 break;
             }
+            prev_end = end;
             start = if overlap == 0 {
                 end
             } else {
-                end.saturating_sub(overlap)
+                let mut next = end.saturating_sub(overlap).max(start + 1);
+                while !segments.starts_sentence(next) {
+                    next += 1;
+                }
+                next
             };
         }
         chunks
@@ -118,46 +326,13 @@ impl Chunker {
     ) -> Vec<ChunkRecord> {
         let max_tokens = self.config.max_tokens.max(1);
         let overlap_tokens = self.config.overlap_tokens.min(max_tokens.saturating_sub(1));
-        let tokens_per_cell = token_counts(document, ordered);
-        let mut start = 0usize;
-        let mut chunk_index = 0usize;
+        let segments = Segments::new(document, ordered, max_tokens);
         let mut chunks = Vec::new();
-        while start < ordered.len() {
-            let mut end = start;
-            let mut used_tokens = 0usize;
-            while end < ordered.len() {
-                let cell_tokens = tokens_per_cell[end].max(1);
-                if end > start && used_tokens + cell_tokens > max_tokens {
-                    break;
-                }
-                used_tokens += cell_tokens;
-                end += 1;
-            }
-            if end == start {
-                end += 1;
-            }
+        for span in segments.pack(0..segments.sentences.len(), max_tokens, overlap_tokens) {
             if let Some(record) =
-                self.build_chunk(document, doc_id, chunk_index, start, end, ordered)
+                self.build_span(document, doc_id, chunks.len(), ordered, &segments, span)
             {
                 chunks.push(record);
-                chunk_index += 1;
-            }
-            if end == ordered.len() {
-                break;
-            }
-            if overlap_tokens == 0 {
-                start = end;
-            } else {
-                let mut back_tokens = 0usize;
-                let mut new_start = end;
-                while new_start > start {
-                    new_start -= 1;
-                    back_tokens += tokens_per_cell[new_start].max(1);
-                    if back_tokens >= overlap_tokens {
-                        break;
-                    }
-                }
-                start = new_start;
             }
         }
         chunks
@@ -170,8 +345,7 @@ impl Chunker {
         ordered: &[CellRecord],
     ) -> Vec<ChunkRecord> {
         let mut chunks = Vec::new();
-        let tokens_per_cell = token_counts(document, ordered);
-        let mut chunk_index = 0usize;
+        let segments = Segments::new(document, ordered, self.config.max_tokens.max(1));
         let mut idx = 0usize;
         while idx < ordered.len() {
             if ordered[idx].cell_type != CellType::Header {
@@ -179,26 +353,146 @@ impl Chunker {
                 continue;
             }
             let start = idx;
-            let mut end = idx;
-            let mut tokens = 0usize;
-            while end < ordered.len() {
-                if end > start && ordered[end].cell_type == CellType::Header {
-                    break;
-                }
-                tokens += tokens_per_cell[end];
-                if self.config.max_tokens > 0 && tokens >= self.config.max_tokens {
-                    end += 1;
-                    break;
+            let mut end = idx + 1;
+            while end < ordered.len() && ordered[end].cell_type != CellType::Header {
+                end += 1;
+            }
+            self.push_bounded(
+                document,
+                doc_id,
+                ordered,
+                &segments,
+                start..end,
+                &mut chunks,
+            );
+            idx = end;
+        }
+        chunks
+    }
+
+    fn chunk_semantic(
+        &self,
+        document: &Document,
+        doc_id: &str,
+        ordered: &[CellRecord],
+    ) -> Vec<ChunkRecord> {
+        let texts: Vec<&str> = ordered
+            .iter()
+            .map(|cell| document.payload_for(&cell.code_id).unwrap_or_default())
+            .collect();
+        let vectors = match self.embedder.embed_batch(&texts) {
+            Ok(vectors) if vectors.len() == ordered.len() => vectors,
+            Ok(vectors) => {
+                tracing::warn!(
+                    "embedder returned {} vectors for {} cells; falling back to token chunks",
+                    vectors.len(),
+                    ordered.len()
+                );
+                return self.chunk_by_tokens(document, doc_id, ordered);
+            }
+            Err(err) => {
+                tracing::warn!("embedding cells failed: {err}; falling back to token chunks");
+                return self.chunk_by_tokens(document, doc_id, ordered);
+            }
+        };
+        // similarity[i] is between cell i and cell i + 1.
+        let similarity: Vec<f32> = vectors
+            .windows(2)
+            .map(|pair| cosine(&pair[0], &pair[1]))
+            .collect();
+        let threshold = percentile(&similarity, self.config.breakpoint_percentile);
+        let max_tokens = self.config.max_tokens.max(1);
+        let min_tokens = self.config.min_tokens.min(max_tokens);
+        let segments = Segments::new(document, ordered, max_tokens);
+        let is_heading = &segments.headings;
+        let tokens_per_cell = &segments.cell_tokens;
+
+        let mut chunks = Vec::new();
+        let mut start = 0usize;
+        let mut used_tokens = 0usize;
+        // A run of headings ("2 Results", "2.1 Revenue") stays with the text
+        // that follows it.
+        let mut only_headings = true;
+        for idx in 0..ordered.len() {
+            // Cutting between the wrapped lines of a sentence is deferred;
+            // an oversized chunk is then split at sentences instead.
+            if idx > start && segments.starts_sentence(idx) {
+                let section_break = is_heading[idx] && !only_headings;
+                let too_big = used_tokens + tokens_per_cell[idx] > max_tokens;
+                let topic_shift = similarity[idx - 1] < threshold && used_tokens >= min_tokens;
+                if section_break || too_big || topic_shift {
+                    self.push_bounded(
+                        document,
+                        doc_id,
+                        ordered,
+                        &segments,
+                        start..idx,
+                        &mut chunks,
+                    );
+                    start = idx;
+                    used_tokens = 0;
+                    only_headings = true;
                 }
+            }
+            used_tokens += tokens_per_cell[idx];
+            only_headings &= is_heading[idx];
+        }
+        self.push_bounded(
+            document,
+            doc_id,
+            ordered,
+            &segments,
+            start..ordered.len(),
+            &mut chunks,
+        );
+        chunks
+    }
+
+    fn chunk_hierarchical(
+        &self,
+        document: &Document,
+        doc_id: &str,
+        ordered: &[CellRecord],
+    ) -> Vec<ChunkRecord> {
+        let max_tokens = self.config.max_tokens.max(1);
+        let overlap_tokens = self.config.overlap_tokens.min(max_tokens.saturating_sub(1));
+        let segments = Segments::new(document, ordered, max_tokens);
+        let mut chunks = Vec::new();
+        let mut start = 0usize;
+        while start < ordered.len() {
+            // A section runs from a heading (or a run of headings) to the
+            // next heading; text before the first heading is its own section.
+            let mut end = start;
+            while end < ordered.len() && segments.headings[end] {
                 end += 1;
             }
-            if let Some(record) =
-                self.build_chunk(document, doc_id, chunk_index, start, end, ordered)
-            {
-                chunks.push(record);
-                chunk_index += 1;
+            while end < ordered.len() && !segments.headings[end] {
+                end += 1;
             }
-            idx = end;
+            let Some(mut parent) =
+                self.build_chunk(document, doc_id, chunks.len(), start, end, ordered)
+            else {
+                start = end;
+                continue;
+            };
+            parent.level = ChunkLevel::Parent;
+            let parent_id = parent.chunk_id.clone();
+            chunks.push(parent);
+            let spans = segments.pack(
+                segments.sentences_of(start..end),
+                max_tokens,
+                overlap_tokens,
+            );
+            for span in spans {
+                if let Some(mut child) =
+                    self.build_span(document, doc_id, chunks.len(), ordered, &segments, span)
+                {
+                    child.level = ChunkLevel::Child;
+                    child.parent_id = Some(parent_id.clone());
+                    chunks.push(child);
+                }
+            }
+            start = end;
         }
         chunks
     }
@@ -252,8 +546,6 @@ impl Chunker {
         let slice = &ordered[start..end];
         let mut parts = Vec::with_capacity(slice.len());
         let mut token_total = 0usize;
-        let mut importance_sum = 0usize;
-        let mut type_hist = [0usize; 5];
         for cell in slice {
             if let Some(payload) = document.payload_for(&cell.code_id) {
                 if !payload.trim().is_empty() {
@@ -261,13 +553,119 @@ impl Chunker {
                 }
                 token_total += count_tokens(payload);
             }
-            importance_sum += cell.importance as usize;
-            increment_histogram(&mut type_hist, cell.cell_type);
         }
+        self.record(doc_id, chunk_index, start, slice, None, parts, token_total)
+    }
+
+    /// Emits the cells in `cells` as one chunk, or, when they exceed
+    /// `max_tokens`, as several chunks cut at sentence boundaries.
+    fn push_bounded(
+        &self,
+        document: &Document,
+        doc_id: &str,
+        ordered: &[CellRecord],
+        segments: &Segments<'_>,
+        cells: Range<usize>,
+        chunks: &mut Vec<ChunkRecord>,
+    ) {
+        let total: usize = segments.cell_tokens[cells.clone()].iter().sum();
+        if self.config.max_tokens == 0 || total <= self.config.max_tokens {
+            if let Some(record) = self.build_chunk(
+                document,
+                doc_id,
+                chunks.len(),
+                cells.start,
+                cells.end,
+                ordered,
+            ) {
+                chunks.push(record);
+            }
+            return;
+        }
+        for span in segments.pack(segments.sentences_of(cells), self.config.max_tokens, 0) {
+            if let Some(record) =
+                self.build_span(document, doc_id, chunks.len(), ordered, segments, span)
+            {
+                chunks.push(record);
+            }
+        }
+    }
+
+    /// Builds a chunk from a range of sentences, which may begin or end
+    /// inside a cell.
+    fn build_span(
+        &self,
+        document: &Document,
+        doc_id: &str,
+        chunk_index: usize,
+        ordered: &[CellRecord],
+        segments: &Segments<'_>,
+        span: Range<usize>,
+    ) -> Option<ChunkRecord> {
+        if span.is_empty() {
+            return None;
+        }
+        let start = segments.sentences[span.start].start;
+        let end = segments.sentences[span.end - 1].end;
+        let end_payload = segments.payloads[end.cell];
+        if start.byte == 0 && end_payload[end.byte..].trim().is_empty() {
+            return self.build_chunk(
+                document,
+                doc_id,
+                chunk_index,
+                start.cell,
+                end.cell + 1,
+                ordered,
+            );
+        }
+        let mut parts = Vec::new();
+        for cell in start.cell..=end.cell {
+            let payload = segments.payloads[cell];
+            let from = if cell == start.cell { start.byte } else { 0 };
+            let to = if cell == end.cell {
+                end.byte
+            } else {
+                payload.len()
+            };
+            let piece = payload[from..to].trim();
+            if !piece.is_empty() {
+                parts.push(piece.to_string());
+            }
+        }
+        let token_total = segments.sentences[span].iter().map(|s| s.tokens).sum();
+        self.record(
+            doc_id,
+            chunk_index,
+            start.cell,
+            &ordered[start.cell..=end.cell],
+            Some((start.byte, end.byte)),
+            parts,
+            token_total,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn record(
+        &self,
+        doc_id: &str,
+        chunk_index: usize,
+        start: usize,
+        slice: &[CellRecord],
+        bytes: Option<(usize, usize)>,
+        parts: Vec<String>,
+        token_total: usize,
+    ) -> Option<ChunkRecord> {
         let text = parts.join("\n");
         if text.trim().is_empty() {
             return None;
         }
+        let end = start + slice.len();
+        let mut importance_sum = 0usize;
+        let mut type_hist = [0usize; 6];
+        for cell in slice {
+            importance_sum += cell.importance as usize;
+            increment_histogram(&mut type_hist, cell.cell_type);
+        }
         let z_start = slice.first().map(|c| c.z).unwrap_or(0);
         let z_end = slice.last().map(|c| c.z).unwrap_or(z_start);
         let chunk_id = stable_chunk_id(
@@ -275,6 +673,7 @@ impl Chunker {
             chunk_index,
             start,
             end.saturating_sub(1),
+            bytes,
             self.config.mode,
             CHUNK_VERSION,
         );
@@ -296,21 +695,355 @@ impl Chunker {
             token_count: token_total,
             dominant_type,
             importance_mean,
+            level: ChunkLevel::Flat,
+            parent_id: None,
+            cell_ids: Vec::new(),
+            bboxes: Vec::new(),
+            breadcrumb: Vec::new(),
         })
     }
 }
 
-fn increment_histogram(hist: &mut [usize; 5], cell_type: CellType) {
+/// A position inside the payload of one of the ordered cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Pos {
+    cell: usize,
+    byte: usize,
+}
+
+struct Sentence {
+    block: usize,
+    start: Pos,
+    end: Pos,
+    tokens: usize,
+}
+
+/// The ordered cells split into sentences, so that every mode can place its
+/// cuts between sentences. Consecutive text cells (usually the wrapped lines
+/// of one paragraph) are segmented together because sentences run across
+/// them; every other cell is a block of its own.
+struct Segments<'a> {
+    payloads: Vec<&'a str>,
+    cell_tokens: Vec<usize>,
+    headings: Vec<bool>,
+    sentences: Vec<Sentence>,
+    /// Sentence indices of each block.
+    blocks: Vec<Range<usize>>,
+    block_tokens: Vec<usize>,
+    /// Index of the first sentence starting in or after each cell, plus one
+    /// trailing entry for the end of the document.
+    first_sentence: Vec<usize>,
+    block_start: Vec<bool>,
+}
+
+impl<'a> Segments<'a> {
+    /// Sentences longer than `max_tokens` are split between words.
+    fn new(document: &'a Document, ordered: &[CellRecord], max_tokens: usize) -> Self {
+        let payloads: Vec<&str> = ordered
+            .iter()
+            .map(|cell| document.payload_for(&cell.code_id).unwrap_or_default())
+            .collect();
+        let levels = HeadingLevels::new(document);
+        let headings: Vec<bool> = ordered
+            .iter()
+            .zip(&payloads)
+            .map(|(cell, text)| levels.heading(cell, text.trim()).is_some())
+            .collect();
+        let block_start: Vec<bool> = (0..ordered.len())
+            .map(|idx| {
+                idx == 0
+                    || ordered[idx].cell_type != CellType::Text
+                    || ordered[idx - 1].cell_type != CellType::Text
+                    || headings[idx]
+                    || headings[idx - 1]
+            })
+            .collect();
+        let mut segments = Self {
+            cell_tokens: token_counts(document, ordered),
+            payloads,
+            headings,
+            sentences: Vec::new(),
+            blocks: Vec::new(),
+            block_tokens: Vec::new(),
+            first_sentence: Vec::with_capacity(ordered.len() + 1),
+            block_start,
+        };
+        let mut cell = 0usize;
+        while cell < ordered.len() {
+            let mut end = cell + 1;
+            while end < ordered.len() && !segments.block_start[end] {
+                end += 1;
+            }
+            segments.push_block(cell..end, max_tokens.max(1));
+            cell = end;
+        }
+        let mut next = 0usize;
+        for cell in 0..=ordered.len() {
+            while next < segments.sentences.len() && segments.sentences[next].start.cell < cell {
+                next += 1;
+            }
+            segments.first_sentence.push(next);
+        }
+        segments
+    }
+
+    fn push_block(&mut self, cells: Range<usize>, max_tokens: usize) {
+        let block = self.blocks.len();
+        let mut text = String::new();
+        let mut offsets = Vec::with_capacity(cells.len());
+        for cell in cells.clone() {
+            if cell > cells.start {
+                text.push(' ');
+            }
+            offsets.push(text.len());
+            text.push_str(self.payloads[cell]);
+        }
+        let locate = |offset: usize, is_end: bool| {
+            let idx = offsets.partition_point(|&start| {
+                if is_end {
+                    start < offset
+                } else {
+                    start <= offset
+                }
+            });
+            let idx = idx.max(1) - 1;
+            let cell = cells.start + idx;
+            Pos {
+                cell,
+                byte: (offset - offsets[idx]).min(self.payloads[cell].len()),
+            }
+        };
+        let first = self.sentences.len();
+        for range in sentence_ranges(&text) {
+            let sentence = &text[range.clone()];
+            if sentence.trim().is_empty() {
+                continue;
+            }
+            let tokens = count_tokens(sentence);
+            let pieces = if tokens <= max_tokens {
+                vec![range]
+            } else {
+                word_ranges(sentence, max_tokens, count_tokens)
+                    .into_iter()
+                    .map(|words| range.start + words.start..range.start + words.end)
+                    .collect()
+            };
+            for piece in pieces {
+                let mut start = locate(piece.start, false);
+                if start.byte == self.payloads[start.cell].len() && start.cell + 1 < cells.end {
+                    start = Pos {
+                        cell: start.cell + 1,
+                        byte: 0,
+                    };
+                }
+                self.sentences.push(Sentence {
+                    block,
+                    start,
+                    end: locate(piece.end, true),
+                    tokens: count_tokens(&text[piece]),
+                });
+            }
+        }
+        self.blocks.push(first..self.sentences.len());
+        self.block_tokens.push(self.cell_tokens[cells].iter().sum());
+    }
+
+    /// Whether a chunk may start at `cell` without cutting a sentence.
+    fn starts_sentence(&self, cell: usize) -> bool {
+        if cell >= self.payloads.len() || self.block_start[cell] {
+            return true;
+        }
+        self.sentences
+            .get(self.first_sentence[cell])
+            .is_some_and(|sentence| sentence.start == Pos { cell, byte: 0 })
+    }
+
+    /// Sentences of the cells in `cells`, whose ends must be sentence starts.
+    fn sentences_of(&self, cells: Range<usize>) -> Range<usize> {
+        self.first_sentence[cells.start]..self.first_sentence[cells.end]
+    }
+
+    /// Tokens of a range of sentences within one block; a whole block counts
+    /// as its cells tokenized one by one.
+    fn tokens(&self, range: Range<usize>) -> usize {
+        if range.is_empty() {
+            return 0;
+        }
+        let block = self.sentences[range.start].block;
+        if range == self.blocks[block] {
+            self.block_tokens[block]
+        } else {
+            self.sentences[range].iter().map(|s| s.tokens).sum()
+        }
+    }
+
+    /// Packs `sentences` into spans of at most `max_tokens`. Blocks that fit
+    /// are kept whole; each span after the first repeats up to `overlap`
+    /// tokens of whole sentences from the end of the previous one.
+    fn pack(
+        &self,
+        sentences: Range<usize>,
+        max_tokens: usize,
+        overlap: usize,
+    ) -> Vec<Range<usize>> {
+        let max_tokens = max_tokens.max(1);
+        let mut spans = Vec::new();
+        let mut start = sentences.start;
+        // First sentence not covered by an earlier span.
+        let mut fresh = sentences.start;
+        while fresh < sentences.end {
+            let mut end = start;
+            let mut used = 0usize;
+            while end < sentences.end {
+                let block_end = self.blocks[self.sentences[end].block]
+                    .end
+                    .min(sentences.end);
+                let rest = self.tokens(end..block_end);
+                if used + rest <= max_tokens {
+                    used += rest;
+                    end = block_end;
+                    continue;
+                }
+                if end > fresh && rest <= max_tokens {
+                    // Start the next span with the whole block.
+                    break;
+                }
+                while end < block_end
+                    && (end == fresh || used + self.sentences[end].tokens <= max_tokens)
+                {
+                    used += self.sentences[end].tokens;
+                    end += 1;
+                }
+                break;
+            }
+            spans.push(start..end);
+            fresh = end;
+            if end >= sentences.end {
+                break;
+            }
+            let budget = overlap.min(max_tokens.saturating_sub(self.sentences[end].tokens));
+            let mut back = end;
+            let mut back_tokens = 0usize;
+            while back > start + 1 && back_tokens + self.sentences[back - 1].tokens <= budget {
+                back -= 1;
+                back_tokens += self.sentences[back].tokens;
+            }
+            start = back;
+        }
+        spans
+    }
+}
+
+/// Cuts `parent` down to at most `max_tokens` around `focus` (usually the
+/// text of one of its child chunks), growing the window one sentence at a
+/// time on alternating sides. Returns `parent` whole when it fits, and
+/// `focus` alone when even that is over budget.
+pub fn parent_window(parent: &str, focus: &str, max_tokens: usize) -> String {
+    if count_tokens(parent) <= max_tokens {
+        return parent.to_string();
+    }
+    let focus = focus.trim();
+    if focus.is_empty() {
+        return String::new();
+    }
+    // A focus that does not occur verbatim (say, one that starts with a
+    // breadcrumb) is anchored on its first line that does.
+    let anchor = parent.find(focus).map(|at| (at, focus)).or_else(|| {
+        focus
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .find_map(|line| parent.find(line).map(|at| (at, line)))
+    });
+    let Some((at, focus)) = anchor else {
+        return focus.to_string();
+    };
+    let mut used = count_tokens(focus);
+    if used >= max_tokens {
+        return focus.to_string();
+    }
+    let sentences = sentence_ranges(parent);
+    let focus_end = at + focus.len();
+    let mut before: Vec<&Range<usize>> = sentences.iter().filter(|r| r.start < at).collect();
+    let mut after = sentences.iter().filter(|r| r.end > focus_end);
+    let (mut from, mut to) = (at, focus_end);
+    let mut grow_before = true;
+    loop {
+        let next = if grow_before {
+            before.pop().map(|range| (range, true))
+        } else {
+            after.next().map(|range| (range, false))
+        }
+        .or_else(|| {
+            if grow_before {
+                after.next().map(|range| (range, false))
+            } else {
+                before.pop().map(|range| (range, true))
+            }
+        });
+        let Some((range, is_before)) = next else {
+            break;
+        };
+        let added = if is_before {
+            range.start..from
+        } else {
+            to..range.end
+        };
+        let tokens = count_tokens(&parent[added]);
+        if used + tokens > max_tokens {
+            break;
+        }
+        used += tokens;
+        if is_before {
+            from = range.start;
+        } else {
+            to = range.end;
+        }
+        grow_before = !grow_before;
+    }
+    parent[from..to].trim().to_string()
+}
+
+fn page_boxes(cells: &[CellRecord]) -> Vec<PageBox> {
+    let mut boxes: Vec<(u32, i64, i64, i64, i64)> = Vec::new();
+    for cell in cells {
+        let (x0, y0) = (cell.x as i64, cell.y as i64);
+        let (x1, y1) = (x0 + cell.w as i64, y0 + cell.h as i64);
+        match boxes.iter_mut().find(|b| b.0 == cell.z) {
+            Some(b) => {
+                b.1 = b.1.min(x0);
+                b.2 = b.2.min(y0);
+                b.3 = b.3.max(x1);
+                b.4 = b.4.max(y1);
+            }
+            None => boxes.push((cell.z, x0, y0, x1, y1)),
+        }
+    }
+    boxes.sort_by_key(|b| b.0);
+    boxes
+        .into_iter()
+        .map(|(z, x0, y0, x1, y1)| PageBox {
+            z,
+            x: x0 as i32,
+            y: y0 as i32,
+            w: (x1 - x0) as u32,
+            h: (y1 - y0) as u32,
+        })
+        .collect()
+}
+
+fn increment_histogram(hist: &mut [usize; 6], cell_type: CellType) {
     match cell_type {
         CellType::Text => hist[0] += 1,
         CellType::Table => hist[1] += 1,
         CellType::Figure => hist[2] += 1,
         CellType::Footer => hist[3] += 1,
         CellType::Header => hist[4] += 1,
+        CellType::Equation => hist[5] += 1,
     }
 }
 
-fn dominant_cell_type(hist: &[usize; 5]) -> CellType {
+fn dominant_cell_type(hist: &[usize; 6]) -> CellType {
     let mut max_idx = 0usize;
     let mut max_val = 0usize;
     for (idx, val) in hist.iter().enumerate() {
@@ -324,7 +1057,8 @@ fn dominant_cell_type(hist: &[usize; 5]) -> CellType {
         1 => CellType::Table,
         2 => CellType::Figure,
         3 => CellType::Footer,
-        _ => CellType::Header,
+        4 => CellType::Header,
+        _ => CellType::Equation,
     }
 }
 
@@ -348,21 +1082,49 @@ fn count_tokens(text: &str) -> usize {
     TOKENIZER.encode_with_special_tokens(text).len()
 }
 
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm =
+        a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|y| y * y).sum::<f32>().sqrt();
+    if norm == 0.0 {
+        0.0
+    } else {
+        dot / norm
+    }
+}
+
+/// Nearest-rank percentile; an empty input never splits.
+fn percentile(values: &[f32], pct: f32) -> f32 {
+    if values.is_empty() {
+        return f32::NEG_INFINITY;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let rank = (pct.clamp(0.0, 100.0) / 100.0 * (sorted.len() - 1) as f32).round() as usize;
+    sorted[rank]
+}
+
 fn stable_chunk_id(
     doc_id: &str,
     chunk_index: usize,
     cell_start: usize,
     cell_end: usize,
+    bytes: Option<(usize, usize)>,
     mode: ChunkMode,
     version: u32,
 ) -> String {
     let mut hasher = Sha256::new();
     hasher.update(doc_id.as_bytes());
-    hasher.update(&version.to_be_bytes());
-    hasher.update(&(mode_discriminant(mode)).to_be_bytes());
-    hasher.update(&chunk_index.to_be_bytes());
-    hasher.update(&cell_start.to_be_bytes());
-    hasher.update(&cell_end.to_be_bytes());
+    hasher.update(version.to_be_bytes());
+    hasher.update((mode_discriminant(mode)).to_be_bytes());
+    hasher.update(chunk_index.to_be_bytes());
+    hasher.update(cell_start.to_be_bytes());
+    hasher.update(cell_end.to_be_bytes());
+    // Chunks cut between sentences share cells with their neighbours.
+    if let Some((start, end)) = bytes {
+        hasher.update(start.to_be_bytes());
+        hasher.update(end.to_be_bytes());
+    }
     hex::encode(hasher.finalize())
 }
 
@@ -372,5 +1134,458 @@ fn mode_discriminant(mode: ChunkMode) -> u32 {
         ChunkMode::Tokens => 1,
         ChunkMode::Headings => 2,
         ChunkMode::TableRows => 3,
+        ChunkMode::Semantic => 4,
+        ChunkMode::Hierarchical => 5,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{hash_payload, Header};
+    use crate::error::{DcfError, Result};
+    use crate::sentence::split_sentences;
+
+    fn document(cells: &[(CellType, &str)]) -> Document {
+        let mut doc = Document::new(Header::default());
+        for (idx, (cell_type, text)) in cells.iter().enumerate() {
+            doc.push_fixture_cell(0, 40 + idx as i32 * 30, *cell_type, text)
+                .importance = 128;
+        }
+        doc
+    }
+
+    /// Maps texts mentioning cats and cars onto orthogonal axes.
+    struct TopicEmbedder;
+
+    impl Embedder for TopicEmbedder {
+        fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Ok(texts
+                .iter()
+                .map(|text| {
+                    if text.contains("cat") {
+                        vec![1.0, 0.0]
+                    } else {
+                        vec![0.0, 1.0]
+                    }
+                })
+                .collect())
+        }
+    }
+
+    struct FailingEmbedder;
+
+    impl Embedder for FailingEmbedder {
+        fn embed_batch(&self, _texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+            Err(DcfError::Other("backend unavailable".to_string()))
+        }
+    }
+
+    fn semantic(min_tokens: usize, max_tokens: usize) -> ChunkConfig {
+        ChunkConfig {
+            mode: ChunkMode::Semantic,
+            min_tokens,
+            max_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn semantic_splits_where_topic_changes() {
+        let doc = document(&[
+            (CellType::Text, "The cat sleeps on the mat."),
+            (CellType::Text, "A cat purrs when it is content."),
+            (CellType::Text, "Every cat likes warm places."),
+            (CellType::Text, "The car needs new tyres."),
+            (CellType::Text, "A car engine runs on fuel."),
+            (CellType::Text, "Each car has a spare wheel."),
+        ]);
+        let chunks = Chunker::new(semantic(1, 512))
+            .with_embedder(Arc::new(TopicEmbedder))
+            .chunk_document(&doc, "doc");
+        let spans: Vec<_> = chunks.iter().map(|c| (c.cell_start, c.cell_end)).collect();
+        assert_eq!(spans, vec![(0, 2), (3, 5)]);
+    }
+
+    #[test]
+    fn semantic_respects_min_and_max_tokens() {
+        let doc = document(&[
+            (CellType::Text, "The cat sleeps on the mat."),
+            (CellType::Text, "The car needs new tyres."),
+            (CellType::Text, "A cat purrs when it is content."),
+            (CellType::Text, "A car engine runs on fuel."),
+        ]);
+        let unsplit = Chunker::new(semantic(1_000, 1_000))
+            .with_embedder(Arc::new(TopicEmbedder))
+            .chunk_document(&doc, "doc");
+        assert_eq!(unsplit.len(), 1);
+
+        let capped = Chunker::new(semantic(1_000, 16))
+            .with_embedder(Arc::new(TopicEmbedder))
+            .chunk_document(&doc, "doc");
+        assert!(capped.len() > 1);
+        assert!(capped.iter().all(|chunk| chunk.token_count <= 16));
+    }
+
+    #[test]
+    fn semantic_starts_a_chunk_at_each_heading() {
+        let doc = document(&[
+            (CellType::Header, "1 Overview"),
+            (CellType::Text, "The cat sleeps on the mat."),
+            (CellType::Text, "A cat purrs when it is content."),
+            (CellType::Header, "2 Results"),
+            (CellType::Header, "2.1 Cats"),
+            (CellType::Text, "Every cat likes warm places."),
+        ]);
+        let chunks = Chunker::new(semantic(1_000, 1_000)).chunk_document(&doc, "doc");
+        let spans: Vec<_> = chunks.iter().map(|c| (c.cell_start, c.cell_end)).collect();
+        assert_eq!(spans, vec![(0, 2), (3, 5)]);
+        assert!(chunks[1].text.starts_with("2 Results"));
+    }
+
+    const ENGLISH: &str =
+        "Dr. Smith opened the meeting at nine. The budget grew by four percent this year. \
+        Hiring stays flat until the spring. Fig. 2 shows the regional split. \
+        Questions were taken at the end.";
+    const GERMAN: &str = "Der Umsatz stieg z.B. in Bayern deutlich. Die Kosten blieben stabil. \
+        Im Herbst folgt ein neuer Bericht. Siehe Abb. 3 für Details.";
+    const JAPANESE: &str = "東京の売上は増加しました。大阪では横ばいでした。\
+        来年は新しい店舗を開きます。詳細は別紙をご覧ください。";
+
+    /// Every sentence of every chunk must be a whole sentence of the source.
+    fn assert_sentence_aligned(chunks: &[ChunkRecord], sources: &[&str]) {
+        let whole: Vec<&str> = sources
+            .iter()
+            .flat_map(|text| split_sentences(text))
+            .map(str::trim)
+            .collect();
+        for chunk in chunks {
+            for sentence in split_sentences(&chunk.text) {
+                assert!(
+                    whole.contains(&sentence.trim()),
+                    "{:?} is not a whole sentence",
+                    sentence
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn token_chunks_split_long_cells_between_sentences() {
+        let doc = document(&[
+            (CellType::Text, ENGLISH),
+            (CellType::Text, GERMAN),
+            (CellType::Text, JAPANESE),
+        ]);
+        let chunks = Chunker::new(ChunkConfig {
+            mode: ChunkMode::Tokens,
+            max_tokens: 24,
+            overlap_tokens: 12,
+            ..Default::default()
+        })
+        .chunk_document(&doc, "doc");
+        assert!(chunks.len() > 3);
+        assert!(chunks.iter().all(|chunk| chunk.token_count <= 24));
+        assert_sentence_aligned(&chunks, &[ENGLISH, GERMAN, JAPANESE]);
+        // Overlap windows repeat whole sentences from the previous chunk.
+        assert!(chunks.windows(2).any(|pair| {
+            let first = split_sentences(&pair[1].text)[0].trim();
+            pair[0].text.contains(first)
+        }));
+        assert!(chunks.last().unwrap().text.ends_with("ご覧ください。"));
+    }
+
+    #[test]
+    fn span_chunk_ids_depend_on_the_sentence_offsets() {
+        let id = |bytes| stable_chunk_id("doc", 1, 2, 2, bytes, ChunkMode::Tokens, CHUNK_VERSION);
+        assert_ne!(id(Some((0, 40))), id(Some((0, 52))));
+        assert_ne!(id(Some((0, 40))), id(None));
+        assert_eq!(id(Some((12, 40))), id(Some((12, 40))));
+    }
+
+    #[test]
+    fn token_chunks_keep_paragraphs_whole_when_they_fit() {
+        let doc = document(&[
+            (CellType::Text, "First cell. It has two sentences."),
+            (CellType::Table, "a | b"),
+            (CellType::Text, "Second cell. Also two sentences."),
+        ]);
+        let chunks = Chunker::new(ChunkConfig {
+            mode: ChunkMode::Tokens,
+            max_tokens: 12,
+            overlap_tokens: 0,
+            ..Default::default()
+        })
+        .chunk_document(&doc, "doc");
+        let texts: Vec<_> = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "First cell. It has two sentences.\na | b",
+                "Second cell. Also two sentences."
+            ]
+        );
+    }
+
+    #[test]
+    fn cell_chunks_do_not_cut_wrapped_sentences() {
+        let doc = document(&[
+            (CellType::Text, "Alpha beta gamma. The second sentence"),
+            (CellType::Text, "wraps onto this line."),
+            (CellType::Text, "Третье предложение."),
+        ]);
+        let chunks = Chunker::new(ChunkConfig {
+            mode: ChunkMode::Cells,
+            cells_per_chunk: 1,
+            overlap_cells: 0,
+            ..Default::default()
+        })
+        .chunk_document(&doc, "doc");
+        let spans: Vec<_> = chunks.iter().map(|c| (c.cell_start, c.cell_end)).collect();
+        assert_eq!(spans, vec![(0, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn heading_and_semantic_chunks_cut_oversized_sections_at_sentences() {
+        let doc = document(&[
+            (CellType::Header, "1 Overview"),
+            (CellType::Text, ENGLISH),
+            (CellType::Text, GERMAN),
+            (CellType::Header, "2 Japan"),
+            (CellType::Text, JAPANESE),
+        ]);
+        for mode in [ChunkMode::Headings, ChunkMode::Semantic] {
+            let chunks = Chunker::new(ChunkConfig {
+                mode,
+                max_tokens: 30,
+                min_tokens: 0,
+                ..Default::default()
+            })
+            .chunk_document(&doc, "doc");
+            assert!(chunks.iter().all(|chunk| chunk.token_count <= 30));
+            assert_sentence_aligned(
+                &chunks,
+                &["1 Overview", ENGLISH, GERMAN, "2 Japan", JAPANESE],
+            );
+            let text: String = chunks.iter().map(|chunk| chunk.text.as_str()).collect();
+            assert!(text.contains("Questions were taken at the end."));
+            assert!(text.contains("ご覧ください。"));
+        }
+    }
+
+    #[test]
+    fn hierarchical_emits_sections_with_bounded_children() {
+        let doc = document(&[
+            (CellType::Text, "Preface before any heading."),
+            (CellType::Header, "1 Overview"),
+            (CellType::Text, ENGLISH),
+            (CellType::Header, "2 Japan"),
+            (CellType::Header, "2.1 Sales"),
+            (CellType::Text, JAPANESE),
+        ]);
+        let chunks = Chunker::new(ChunkConfig {
+            mode: ChunkMode::Hierarchical,
+            max_tokens: 24,
+            overlap_tokens: 0,
+            ..Default::default()
+        })
+        .chunk_document(&doc, "doc");
+        let parents: Vec<_> = chunks
+            .iter()
+            .filter(|chunk| chunk.level == ChunkLevel::Parent)
+            .collect();
+        let spans: Vec<_> = parents.iter().map(|c| (c.cell_start, c.cell_end)).collect();
+        assert_eq!(spans, vec![(0, 0), (1, 2), (3, 5)]);
+        assert!(parents.iter().all(|parent| parent.parent_id.is_none()));
+        for (idx, chunk) in chunks.iter().enumerate() {
+            assert_eq!(chunk.chunk_index, idx);
+            if chunk.level == ChunkLevel::Child {
+                assert!(chunk.token_count <= 24);
+                let parent = parents
+                    .iter()
+                    .find(|parent| Some(&parent.chunk_id) == chunk.parent_id.as_ref())
+                    .expect("child points at a parent");
+                assert!(parent.cell_start <= chunk.cell_start && chunk.cell_end <= parent.cell_end);
+                assert!(parent.text.contains(&chunk.text));
+            }
+        }
+        assert!(
+            chunks
+                .iter()
+                .filter(|c| c.level == ChunkLevel::Child)
+                .count()
+                > 3
+        );
+
+        let sections = Chunker::new(ChunkConfig {
+            max_tokens: 24,
+            overlap_tokens: 0,
+            ..Default::default()
+        })
+        .section_parents(&doc, "doc");
+        assert_eq!(sections[2].as_ref(), Some(&parents[1].chunk_id));
+        assert_eq!(sections[5].as_ref(), Some(&parents[2].chunk_id));
+
+        let json = serde_json::to_value(&chunks[1]).unwrap();
+        assert_eq!(json["level"], "child");
+        let flat = Chunker::new(ChunkConfig::default()).chunk_document(&doc, "doc");
+        let json = serde_json::to_value(&flat[0]).unwrap();
+        assert!(json.get("level").is_none() && json.get("parent_id").is_none());
+    }
+
+    #[test]
+    fn parent_window_grows_around_the_focus_within_budget() {
+        assert_eq!(parent_window(ENGLISH, "anything", 1_000), ENGLISH);
+        let focus = "Hiring stays flat until the spring.";
+        let window = parent_window(ENGLISH, focus, 20);
+        assert!(window.contains(focus));
+        assert!(count_tokens(&window) <= 20);
+        assert!(window.len() > focus.len());
+        assert!(split_sentences(&window)
+            .iter()
+            .all(|sentence| ENGLISH.contains(sentence.trim())));
+        assert_eq!(parent_window(ENGLISH, focus, 3), focus);
+    }
+
+    #[test]
+    fn chunks_record_cell_ids_page_boxes_and_breadcrumbs() {
+        let mut doc = document(&[
+            (CellType::Header, "1 Overview"),
+            (CellType::Header, "1.1 Revenue"),
+            (CellType::Text, "Revenue grew in every region."),
+            (CellType::Text, "Margins held steady."),
+        ]);
+        let mut next_page = doc.cells[3].clone();
+        next_page.z = 1;
+        next_page.x = 100;
+        next_page.y = 60;
+        next_page.w = 200;
+        doc.push_cell(next_page, "Margins held steady.".to_string());
+        let config = ChunkConfig {
+            mode: ChunkMode::Cells,
+            cells_per_chunk: 8,
+            overlap_cells: 0,
+            ..Default::default()
+        };
+        let chunks = Chunker::new(config).chunk_document(&doc, "doc");
+        assert_eq!(chunks.len(), 1);
+        let ids = doc.cell_ids();
+        let expected: Vec<_> = doc
+            .ordered_cells()
+            .iter()
+            .map(|cell| ids.id(cell))
+            .collect();
+        assert_eq!(chunks[0].cell_ids, expected);
+        assert_eq!(
+            chunks[0].bboxes,
+            vec![
+                PageBox {
+                    z: 0,
+                    x: 64,
+                    y: 40,
+                    w: 896,
+                    h: 114
+                },
+                PageBox {
+                    z: 1,
+                    x: 100,
+                    y: 60,
+                    w: 200,
+                    h: 24
+                },
+            ]
+        );
+        assert_eq!(chunks[0].breadcrumb, vec!["1 Overview", "1.1 Revenue"]);
+
+        // A cell added above the text shifts the indices but not the IDs.
+        let mut preface = doc.cells[0].clone();
+        preface.y = 10;
+        preface.code_id = hash_payload("Draft");
+        doc.push_cell(preface, "Draft".to_string());
+        let headings = Chunker::new(ChunkConfig {
+            mode: ChunkMode::Headings,
+            ..config
+        });
+        let shifted = headings.chunk_document(&doc, "doc");
+        let section = shifted.last().unwrap();
+        assert_eq!(section.cell_ids, chunks[0].cell_ids[1..]);
+        assert_eq!(section.cell_start, 2);
+        assert_eq!(section.breadcrumb, vec!["1 Overview", "1.1 Revenue"]);
+        assert_eq!(
+            section.select_target().section,
+            Some(section.breadcrumb.as_slice())
+        );
+    }
+
+    #[test]
+    fn prepended_breadcrumbs_still_locate_children_in_their_parent() {
+        let doc = document(&[
+            (CellType::Header, "2 Japan"),
+            (CellType::Header, "2.1 Sales"),
+            (CellType::Text, JAPANESE),
+        ]);
+        let config = ChunkConfig {
+            mode: ChunkMode::Hierarchical,
+            max_tokens: 24,
+            overlap_tokens: 0,
+            prepend_breadcrumb: true,
+            ..Default::default()
+        };
+        let chunks = Chunker::new(config).chunk_document(&doc, "doc");
+        // The breadcrumb line's tokens come out of the children's budget.
+        let bare = Chunker::new(ChunkConfig {
+            max_tokens: 24 - count_tokens("2 Japan > 2.1 Sales") - 1,
+            prepend_breadcrumb: false,
+            ..config
+        })
+        .chunk_document(&doc, "doc");
+        let parent = &chunks[0];
+        assert_eq!(parent.level, ChunkLevel::Parent);
+        assert_eq!(parent.text, bare[0].text);
+        assert!(chunks.len() > 2);
+        assert!(chunks.iter().skip(1).all(|chunk| chunk.token_count <= 24));
+        for (child, bare) in chunks[1..].iter().zip(&bare[1..]) {
+            if bare.text.starts_with("2 Japan") {
+                assert_eq!(child.text, bare.text);
+                continue;
+            }
+            let (crumb, body) = child.text.split_once('\n').unwrap();
+            assert_eq!(crumb, "2 Japan > 2.1 Sales");
+            assert_eq!(body, bare.text);
+            assert_eq!(
+                child.token_count,
+                bare.token_count + count_tokens(crumb) + 1
+            );
+            let window = parent_window(&parent.text, &child.text, bare.token_count);
+            assert!(window.contains(body.lines().next().unwrap()));
+        }
+
+        let headings = Chunker::new(ChunkConfig {
+            mode: ChunkMode::Headings,
+            prepend_breadcrumb: true,
+            ..Default::default()
+        })
+        .chunk_document(&doc, "doc");
+        // The section's own headings are not repeated as a breadcrumb.
+        assert_eq!(headings[0].text, "2 Japan");
+        assert!(headings[1].text.starts_with("2 Japan\n2.1 Sales\n東京"));
+
+        let plain = Chunker::new(ChunkConfig::default()).chunk_document(&doc, "doc");
+        assert!(plain[0].text.starts_with("2 Japan"));
+        let json = serde_json::to_value(&plain[0]).unwrap();
+        assert_eq!(json["breadcrumb"][0], "2 Japan");
+    }
+
+    #[test]
+    fn semantic_falls_back_to_tokens_when_embedding_fails() {
+        let doc = document(&[
+            (CellType::Text, "The cat sleeps on the mat."),
+            (CellType::Text, "The car needs new tyres."),
+        ]);
+        let chunks = Chunker::new(semantic(1, 512))
+            .with_embedder(Arc::new(FailingEmbedder))
+            .chunk_document(&doc, "doc");
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].chunk_id.len(), 64);
     }
 }
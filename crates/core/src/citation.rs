@@ -0,0 +1,171 @@
+//! Short cell IDs for LLM contexts and a parser that maps the IDs a model
+//! cites back to cells and pages.
+//!
+//! An ID is `c` followed by the first 8 hex digits of
+//! `BLAKE3(z || x || y || code_id)`, so it depends only on the cell itself:
+//! the same cell gets the same ID in every context format, selection and
+//! budget. Cells whose 8-digit prefixes collide within a document get longer
+//! prefixes until they differ.
+
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::document::{CellRecord, Document};
+
+const ID_DIGITS: usize = 8;
+
+static CITATION_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?i)\bc[0-9a-f]{8,64}\b").unwrap());
+
+/// Full-length hex digest the IDs are prefixes of.
+fn digest(cell: &CellRecord) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&cell.z.to_le_bytes());
+    hasher.update(&cell.x.to_le_bytes());
+    hasher.update(&cell.y.to_le_bytes());
+    hasher.update(&cell.code_id);
+    hasher.finalize().to_hex().to_string()
+}
+
+/// The default (8 digit) ID of a cell, ignoring collisions with other cells.
+pub fn cell_id(cell: &CellRecord) -> String {
+    format!("c{}", &digest(cell)[..ID_DIGITS])
+}
+
+/// The IDs of every cell in a document, collision-free.
+#[derive(Debug, Clone, Default)]
+pub struct CellIds {
+    ids: HashMap<(u32, i32, i32), String>,
+    cells: HashMap<String, CellRecord>,
+}
+
+impl CellIds {
+    pub fn new(document: &Document) -> Self {
+        let mut by_prefix: HashMap<String, Vec<(String, &CellRecord)>> = HashMap::new();
+        for cell in &document.cells {
+            let digest = digest(cell);
+            by_prefix
+                .entry(digest[..ID_DIGITS].to_string())
+                .or_default()
+                .push((digest, cell));
+        }
+        let mut out = Self::default();
+        for (_, mut group) in by_prefix {
+            group.sort_by(|a, b| a.0.cmp(&b.0));
+            group.dedup_by(|a, b| a.0 == b.0);
+            let mut digits = ID_DIGITS;
+            while digits < 64
+                && group
+                    .windows(2)
+                    .any(|pair| pair[0].0[..digits] == pair[1].0[..digits])
+            {
+                digits += 4;
+            }
+            for (digest, cell) in group {
+                let id = format!("c{}", &digest[..digits]);
+                out.ids.insert((cell.z, cell.x, cell.y), id.clone());
+                out.cells.insert(id, cell.clone());
+            }
+        }
+        out
+    }
+
+    pub fn id(&self, cell: &CellRecord) -> String {
+        self.ids
+            .get(&(cell.z, cell.x, cell.y))
+            .cloned()
+            .unwrap_or_else(|| cell_id(cell))
+    }
+
+    pub fn cell(&self, id: &str) -> Option<&CellRecord> {
+        self.cells.get(&id.to_ascii_lowercase())
+    }
+}
+
+/// One cell ID found in model output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Citation {
+    pub id: String,
+    /// Byte range of the ID in the text.
+    pub start: usize,
+    pub end: usize,
+    /// The cited cell; `None` when no cell of the document has this ID.
+    pub cell: Option<CellRecord>,
+}
+
+impl Citation {
+    /// Page of the cited cell.
+    pub fn z(&self) -> Option<u32> {
+        self.cell.as_ref().map(|cell| cell.z)
+    }
+}
+
+impl Document {
+    pub fn cell_ids(&self) -> CellIds {
+        CellIds::new(self)
+    }
+
+    /// Every cell ID in `text`, in order, however it is bracketed (`[c1f0e9a2b]`,
+    /// `(c1f0e9a2b, c77d01c3e)`, `<cite>c1f0e9a2b</cite>`). IDs that match no
+    /// cell are returned with `cell: None` so callers can flag invented
+    /// citations.
+    pub fn parse_citations(&self, text: &str) -> Vec<Citation> {
+        let ids = self.cell_ids();
+        CITATION_RE
+            .find_iter(text)
+            .map(|found| {
+                let id = found.as_str().to_ascii_lowercase();
+                Citation {
+                    cell: ids.cell(&id).cloned(),
+                    id,
+                    start: found.start(),
+                    end: found.end(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn document() -> Document {
+        let page: &[&str] = &["Quarterly results", "Revenue was $12.4M"];
+        Document::fixture(&[page, page])
+    }
+
+    #[test]
+    fn ids_are_short_stable_and_distinct() {
+        let doc = document();
+        let ids = doc.cell_ids();
+        let all: Vec<String> = doc.cells.iter().map(|cell| ids.id(cell)).collect();
+        assert!(all
+            .iter()
+            .all(|id| id.len() == 1 + ID_DIGITS && id.starts_with('c')));
+        // Same payload on another page is a different cell.
+        assert_ne!(all[0], all[2]);
+        let mut subset = doc.clone();
+        subset.cells.truncate(1);
+        assert_eq!(subset.cell_ids().id(&subset.cells[0]), all[0]);
+        assert_eq!(ids.cell(&all[3].to_uppercase()), Some(&doc.cells[3]));
+    }
+
+    #[test]
+    fn citations_map_back_to_cells_and_pages() {
+        let doc = document();
+        let ids = doc.cell_ids();
+        let first = ids.id(&doc.cells[0]);
+        let last = ids.id(&doc.cells[3]);
+        let answer = format!("Revenue was $12.4M [{last}] per the results ({first}, c00000000).");
+        let citations = doc.parse_citations(&answer);
+        assert_eq!(citations.len(), 3);
+        assert_eq!(citations[0].id, last);
+        assert_eq!(&answer[citations[0].start..citations[0].end], last);
+        assert_eq!(citations[0].z(), Some(1));
+        assert_eq!(citations[1].cell.as_ref(), Some(&doc.cells[0]));
+        assert_eq!(citations[2].cell, None);
+    }
+}
@@ -0,0 +1,421 @@
+//! Seekable `.3dcf` layout with a footer page index.
+//!
+//! ```text
+//! "3DCFSEEK"                        8-byte magic
+//! page block, page block, ...       zstd(proto Document), one per page
+//! index                             zstd(proto ContainerIndex)
+//! index offset, index length        u64 little-endian each
+//! "3DCFSEEK"                        8-byte magic
+//! ```
+//!
+//! [`DocumentReader`] reads the trailer and index first and then only the
+//! blocks it is asked for. Files written as a single zstd blob have no
+//! trailer; the reader falls back to decoding them whole.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, Cursor, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use prost::Message;
+
+use crate::document::{Document, Header, PageInfo};
+use crate::error::{DcfError, Result};
+use crate::integrity::Integrity;
+use crate::proto;
+
+pub(crate) const SEEKABLE_MAGIC: &[u8; 8] = b"3DCFSEEK";
+const TRAILER_LEN: u64 = 8 + 8 + 8;
+
+impl Document {
+    /// Serializes into the seekable layout; `save_bin` writes this form.
+    pub fn to_seekable_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        self.write_seekable(&mut out)?;
+        Ok(out)
+    }
+
+    pub fn write_seekable<W: Write>(&self, mut writer: W) -> Result<()> {
+        let mut blocks: BTreeMap<u32, Document> = BTreeMap::new();
+        for cell in &self.cells {
+            let page = blocks.entry(cell.z).or_default();
+            if let Some(payload) = self.payload_for(&cell.code_id) {
+                page.dict
+                    .entry(cell.code_id)
+                    .or_insert_with(|| payload.to_string());
+            }
+            page.cells.push(cell.clone());
+        }
+        for guard in &self.numguards {
+            blocks
+                .entry(guard.z)
+                .or_default()
+                .numguards
+                .push(guard.clone());
+        }
+        for link in &self.links {
+            blocks.entry(link.z).or_default().links.push(link.clone());
+        }
+        // Payloads no cell points at still have to survive a round trip.
+        let referenced: HashSet<_> = self.cells.iter().map(|cell| cell.code_id).collect();
+        let stray: Vec<_> = self
+            .dict
+            .iter()
+            .filter(|(code, _)| !referenced.contains(*code))
+            .map(|(code, payload)| (*code, payload.clone()))
+            .collect();
+        if !stray.is_empty() {
+            let first = blocks.keys().next().copied().unwrap_or(0);
+            blocks.entry(first).or_default().dict.extend(stray);
+        }
+
+        writer.write_all(SEEKABLE_MAGIC)?;
+        let mut offset = SEEKABLE_MAGIC.len() as u64;
+        let mut head = Document::new(self.header.clone());
+        head.pages = self.pages.clone();
+        let head = head.to_proto();
+        let mut index = proto::ContainerIndex {
+            header: head.header,
+            pages: head.pages,
+            blocks: Vec::with_capacity(blocks.len()),
+            integrity: self.integrity.as_ref().map(Integrity::to_proto),
+        };
+        for (z, page) in &blocks {
+            let mut proto = page.to_proto();
+            proto.header = None;
+            let bytes = compress(&proto)?;
+            writer.write_all(&bytes)?;
+            index.blocks.push(proto::PageBlock {
+                z: *z,
+                offset,
+                length: bytes.len() as u64,
+                cells: page.cells.len() as u32,
+            });
+            offset += bytes.len() as u64;
+        }
+        let bytes = compress(&index)?;
+        writer.write_all(&bytes)?;
+        writer.write_all(&offset.to_le_bytes())?;
+        writer.write_all(&(bytes.len() as u64).to_le_bytes())?;
+        writer.write_all(SEEKABLE_MAGIC)?;
+        Ok(())
+    }
+}
+
+/// Random access to a `.3dcf` file: header and page list up front, page
+/// blocks on demand.
+#[derive(Debug)]
+pub struct DocumentReader<R> {
+    source: R,
+    header: Header,
+    pages: Vec<PageInfo>,
+    blocks: Vec<proto::PageBlock>,
+    integrity: Option<Integrity>,
+    /// A single-blob file, decoded whole because it has no index.
+    legacy: Option<Document>,
+}
+
+impl DocumentReader<BufReader<File>> {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl DocumentReader<Cursor<Vec<u8>>> {
+    pub fn from_bytes(bytes: Vec<u8>) -> Result<Self> {
+        Self::new(Cursor::new(bytes))
+    }
+}
+
+impl<R: Read + Seek> DocumentReader<R> {
+    pub fn new(mut source: R) -> Result<Self> {
+        let len = source.seek(SeekFrom::End(0))?;
+        if let Some((offset, length)) = read_trailer(&mut source, len)? {
+            if offset
+                .checked_add(length)
+                .map_or(true, |end| end > len - TRAILER_LEN)
+            {
+                return Err(DcfError::CorruptContainer("index out of bounds"));
+            }
+            let index: proto::ContainerIndex = read_block(&mut source, offset, length)?;
+            let head = Document::from_proto_unmigrated(proto::Document {
+                header: index.header,
+                pages: index.pages,
+                ..Default::default()
+            })?;
+            return Ok(Self {
+                source,
+                header: head.header,
+                pages: head.pages,
+                blocks: index.blocks,
+                integrity: index.integrity.map(Integrity::from_proto).transpose()?,
+                legacy: None,
+            });
+        }
+        source.seek(SeekFrom::Start(0))?;
+        let mut bytes = Vec::with_capacity(len as usize);
+        source.read_to_end(&mut bytes)?;
+        let document = Document::from_blob_unmigrated(&bytes)?;
+        Ok(Self {
+            source,
+            header: document.header.clone(),
+            pages: document.pages.clone(),
+            blocks: Vec::new(),
+            integrity: document.integrity.clone(),
+            legacy: Some(document),
+        })
+    }
+
+    /// The header as stored; `version` may be older than [`FORMAT_VERSION`],
+    /// while the documents returned by the `read_*` methods are migrated.
+    ///
+    /// [`FORMAT_VERSION`]: crate::FORMAT_VERSION
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    pub fn pages(&self) -> &[PageInfo] {
+        &self.pages
+    }
+
+    /// The integrity section; page reads leave it off the returned
+    /// documents since a single page cannot be checked against the root.
+    pub fn integrity(&self) -> Option<&Integrity> {
+        self.integrity.as_ref()
+    }
+
+    /// Offset, compressed length and cell count of every page block, in file
+    /// order. Empty for single-blob files.
+    pub fn blocks(&self) -> &[proto::PageBlock] {
+        &self.blocks
+    }
+
+    /// False for single-blob files, which were read in full on open.
+    pub fn is_seekable(&self) -> bool {
+        self.legacy.is_none()
+    }
+
+    /// The cells, payloads, NumGuards and links of page `z`, with the
+    /// document header and page list.
+    pub fn read_page(&mut self, z: u32) -> Result<Document> {
+        if let Some(document) = &self.legacy {
+            let mut page = page_subset(document, z);
+            page.migrate()?;
+            return Ok(page);
+        }
+        let mut document = Document::new(self.header.clone());
+        document.pages = self.pages.clone();
+        let Some(block) = self.blocks.iter().find(|block| block.z == z).cloned() else {
+            return if self.pages.iter().any(|page| page.z == z) {
+                Ok(document)
+            } else {
+                Err(DcfError::Other(format!("document has no page {z}")))
+            };
+        };
+        merge(&mut document, self.read_page_block(&block)?);
+        document.migrate()?;
+        Ok(document)
+    }
+
+    /// Cells of page `z` that intersect the box, with their payloads.
+    pub fn read_bbox(&mut self, z: u32, x0: i32, y0: i32, x1: i32, y1: i32) -> Result<Document> {
+        let mut document = self.read_page(z)?;
+        let keep = document.cells_in_bbox(z, x0, y0, x1, y1);
+        let kept = |x: i32, y: i32| keep.iter().any(|cell| (cell.x, cell.y) == (x, y));
+        document.numguards.retain(|g| kept(g.x as i32, g.y as i32));
+        document.links.retain(|link| kept(link.x, link.y));
+        document.cells = keep;
+        document.retain_dict_for_cells();
+        Ok(document)
+    }
+
+    /// Every page, i.e. the whole document.
+    pub fn read_all(&mut self) -> Result<Document> {
+        if let Some(document) = &self.legacy {
+            let mut document = document.clone();
+            document.migrate()?;
+            return Ok(document);
+        }
+        let mut document = Document::new(self.header.clone());
+        document.pages = self.pages.clone();
+        document.integrity = self.integrity.clone();
+        for block in self.blocks.clone() {
+            merge(&mut document, self.read_page_block(&block)?);
+        }
+        document.migrate()?;
+        Ok(document)
+    }
+
+    fn read_page_block(&mut self, block: &proto::PageBlock) -> Result<Document> {
+        let proto: proto::Document = read_block(&mut self.source, block.offset, block.length)?;
+        Document::from_proto_unmigrated(proto)
+    }
+}
+
+pub(crate) fn is_seekable(bytes: &[u8]) -> bool {
+    bytes.starts_with(SEEKABLE_MAGIC)
+}
+
+fn read_trailer<R: Read + Seek>(source: &mut R, len: u64) -> Result<Option<(u64, u64)>> {
+    let mut magic = [0u8; 8];
+    source.seek(SeekFrom::Start(0))?;
+    if len < magic.len() as u64 || {
+        source.read_exact(&mut magic)?;
+        &magic != SEEKABLE_MAGIC
+    } {
+        return Ok(None);
+    }
+    if len < SEEKABLE_MAGIC.len() as u64 + TRAILER_LEN {
+        return Err(DcfError::CorruptContainer("missing trailer"));
+    }
+    let mut trailer = [0u8; TRAILER_LEN as usize];
+    source.seek(SeekFrom::Start(len - TRAILER_LEN))?;
+    source.read_exact(&mut trailer)?;
+    if &trailer[16..] != SEEKABLE_MAGIC {
+        return Err(DcfError::CorruptContainer("missing trailer"));
+    }
+    let offset = u64::from_le_bytes(trailer[..8].try_into().expect("8 bytes"));
+    let length = u64::from_le_bytes(trailer[8..16].try_into().expect("8 bytes"));
+    Ok(Some((offset, length)))
+}
+
+fn read_block<M: Message + Default, R: Read + Seek>(
+    source: &mut R,
+    offset: u64,
+    length: u64,
+) -> Result<M> {
+    source.seek(SeekFrom::Start(offset))?;
+    let mut compressed = Vec::new();
+    source.take(length).read_to_end(&mut compressed)?;
+    if compressed.len() as u64 != length {
+        return Err(DcfError::CorruptContainer("page block is truncated"));
+    }
+    let mut buf = Vec::new();
+    zstd::stream::Decoder::new(&compressed[..])?.read_to_end(&mut buf)?;
+    Ok(M::decode(&*buf)?)
+}
+
+fn compress<M: Message>(message: &M) -> Result<Vec<u8>> {
+    let mut buf = Vec::with_capacity(message.encoded_len());
+    message.encode(&mut buf)?;
+    let mut encoder = zstd::stream::Encoder::new(Vec::new(), 3)?;
+    encoder.write_all(&buf)?;
+    Ok(encoder.finish()?)
+}
+
+fn merge(document: &mut Document, page: Document) {
+    document.cells.extend(page.cells);
+    for (code, payload) in page.dict {
+        document.dict.entry(code).or_insert(payload);
+    }
+    document.numguards.extend(page.numguards);
+    document.links.extend(page.links);
+}
+
+fn page_subset(document: &Document, z: u32) -> Document {
+    let mut page = Document::new(document.header.clone());
+    page.pages = document.pages.clone();
+    page.cells = document
+        .cells
+        .iter()
+        .filter(|cell| cell.z == z)
+        .cloned()
+        .collect();
+    for cell in &page.cells {
+        if let Some(payload) = document.payload_for(&cell.code_id) {
+            page.dict.insert(cell.code_id, payload.to_string());
+        }
+    }
+    page.numguards = document
+        .numguards
+        .iter()
+        .filter(|guard| guard.z == z)
+        .cloned()
+        .collect();
+    page.links = document
+        .links
+        .iter()
+        .filter(|link| link.z == z)
+        .cloned()
+        .collect();
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::CellType;
+    use crate::links::{Link, LinkKind, LinkTarget};
+
+    fn sample() -> Document {
+        let mut doc = Document::new(Header::default());
+        for z in 0..3u32 {
+            for (row, text) in [
+                format!("Page {z} heading"),
+                format!("Revenue was ${}M", z + 1),
+            ]
+            .iter()
+            .enumerate()
+            {
+                doc.push_fixture_cell(z, 64 + row as i32 * 30, CellType::Text, text);
+            }
+        }
+        doc.links.push(Link {
+            z: 2,
+            x: 64,
+            y: 64,
+            start: 0,
+            end: 4,
+            kind: LinkKind::CrossRef,
+            target: LinkTarget::Cell { z: 0, x: 64, y: 64 },
+        });
+        doc
+    }
+
+    #[test]
+    fn reads_single_pages_from_the_index() {
+        let doc = sample();
+        let bytes = doc.to_seekable_bytes().unwrap();
+        let mut reader = DocumentReader::from_bytes(bytes).unwrap();
+        assert!(reader.is_seekable());
+        assert_eq!(reader.pages().len(), 3);
+        assert_eq!(reader.header().grid, doc.header.grid);
+
+        let page = reader.read_page(2).unwrap();
+        assert_eq!(page.cells.len(), 2);
+        assert_eq!(page.decode_page_to_text(2), doc.decode_page_to_text(2));
+        let guards: Vec<_> = doc.numguards.iter().filter(|g| g.z == 2).cloned().collect();
+        assert_eq!(page.numguards, guards);
+        assert_eq!(page.links, doc.links);
+        assert!(reader.read_page(7).is_err());
+
+        let bbox = reader.read_bbox(1, 0, 90, 1024, 100).unwrap();
+        assert_eq!(bbox.decode_to_text(), "Revenue was $2M");
+        assert_eq!(bbox.dict.len(), 1);
+        assert!(bbox.numguards.iter().all(|g| g.y == 94));
+    }
+
+    #[test]
+    fn whole_document_round_trips_in_both_layouts() {
+        let doc = sample();
+        let seekable = Document::from_bytes(&doc.to_seekable_bytes().unwrap()).unwrap();
+        assert_eq!(seekable.ordered_cells(), doc.ordered_cells());
+        assert_eq!(seekable.decode_to_text(), doc.decode_to_text());
+        assert_eq!(seekable.numguards, doc.numguards);
+        assert_eq!(seekable.pages, doc.pages);
+
+        let mut legacy = DocumentReader::from_bytes(doc.to_bytes().unwrap()).unwrap();
+        assert!(!legacy.is_seekable());
+        assert_eq!(
+            legacy.read_page(1).unwrap().decode_to_text(),
+            doc.decode_page_to_text(1)
+        );
+    }
+
+    #[test]
+    fn truncated_containers_are_rejected() {
+        let mut bytes = sample().to_seekable_bytes().unwrap();
+        bytes.truncate(bytes.len() - 4);
+        assert!(DocumentReader::from_bytes(bytes).is_err());
+    }
+}
@@ -1,5 +1,7 @@
-use crate::document::Document;
+use crate::document::{CellRecord, Document};
 use crate::error::Result;
+use crate::render::{all_pages, render, Markup};
+use crate::select::Selector;
 
 #[derive(Debug, Default)]
 pub struct Decoder;
@@ -29,4 +31,107 @@ impl Decoder {
         let cells = document.cells_in_bbox(z, x0, y0, x1, y1);
         Ok(document.decode_cells_to_text(&cells))
     }
+
+    /// Markdown with heading levels, pipe tables, figure images and a `---`
+    /// break between pages.
+    pub fn to_markdown(&self, document: &Document) -> Result<String> {
+        Ok(render(
+            document,
+            &all_pages(document),
+            &document.ordered_cells(),
+            Markup::Markdown,
+        ))
+    }
+
+    pub fn page_to_markdown(&self, document: &Document, z: u32) -> Result<String> {
+        Ok(render(
+            document,
+            &[z],
+            &document.ordered_cells(),
+            Markup::Markdown,
+        ))
+    }
+
+    pub fn bbox_to_markdown(
+        &self,
+        document: &Document,
+        z: u32,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+    ) -> Result<String> {
+        let cells = document.cells_in_bbox(z, x0, y0, x1, y1);
+        Ok(render(document, &[z], &cells, Markup::Markdown))
+    }
+
+    /// Semantic HTML: an `<article>` with one `<section class="page">` per
+    /// page holding headings, paragraphs, lists, tables, figures and footers.
+    pub fn to_html(&self, document: &Document) -> Result<String> {
+        Ok(render(
+            document,
+            &all_pages(document),
+            &document.ordered_cells(),
+            Markup::Html,
+        ))
+    }
+
+    pub fn page_to_html(&self, document: &Document, z: u32) -> Result<String> {
+        Ok(render(
+            document,
+            &[z],
+            &document.ordered_cells(),
+            Markup::Html,
+        ))
+    }
+
+    pub fn bbox_to_html(
+        &self,
+        document: &Document,
+        z: u32,
+        x0: i32,
+        y0: i32,
+        x1: i32,
+        y1: i32,
+    ) -> Result<String> {
+        let cells = document.cells_in_bbox(z, x0, y0, x1, y1);
+        Ok(render(document, &[z], &cells, Markup::Html))
+    }
+
+    /// Text of the cells matching `selector`, in reading order.
+    pub fn selection_to_text(&self, document: &Document, selector: &Selector) -> Result<String> {
+        Ok(document.decode_cells_to_text(&document.select_cells(selector)))
+    }
+
+    /// Markdown for the cells matching `selector`, with a page break between
+    /// the pages they are on.
+    pub fn selection_to_markdown(
+        &self,
+        document: &Document,
+        selector: &Selector,
+    ) -> Result<String> {
+        let cells = document.select_cells(selector);
+        Ok(render(
+            document,
+            &selected_pages(&cells),
+            &cells,
+            Markup::Markdown,
+        ))
+    }
+
+    pub fn selection_to_html(&self, document: &Document, selector: &Selector) -> Result<String> {
+        let cells = document.select_cells(selector);
+        Ok(render(
+            document,
+            &selected_pages(&cells),
+            &cells,
+            Markup::Html,
+        ))
+    }
+}
+
+fn selected_pages(cells: &[CellRecord]) -> Vec<u32> {
+    let mut pages: Vec<u32> = cells.iter().map(|cell| cell.z).collect();
+    pages.dedup();
+    pages
 }
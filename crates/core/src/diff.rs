@@ -0,0 +1,529 @@
+//! Cell-level structural diff between two revisions of a document.
+//!
+//! Cells are aligned by `code_id` first; identical cells that fall out of
+//! reading order are reported as moved. The remaining cells are paired by
+//! text similarity into modifications, and NumGuards of modified cells show
+//! which numbers changed.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::document::{CellRecord, CellType, Document};
+use crate::numguard::{extract_entities, NumericEntity, NumericKind};
+
+/// Tuning for [`Document::diff_with`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiffOptions {
+    /// Minimum similarity (0–1, character bigram Dice) for a deleted and an
+    /// inserted cell to be reported as one modified cell.
+    pub similarity: f32,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self { similarity: 0.5 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    Inserted,
+    Deleted,
+    Modified,
+    Moved,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Inserted => "inserted",
+            Self::Deleted => "deleted",
+            Self::Modified => "modified",
+            Self::Moved => "moved",
+        }
+    }
+}
+
+impl fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// One side of a change: where the cell sits and what it says.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiffCell {
+    pub z: u32,
+    pub x: i32,
+    pub y: i32,
+    pub cell_type: CellType,
+    pub text: String,
+}
+
+/// A number whose guard differs between the two sides of a modified cell;
+/// `None` on one side means the number was added or removed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NumberChange {
+    pub kind: NumericKind,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CellChange {
+    pub kind: ChangeKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old: Option<DiffCell>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new: Option<DiffCell>,
+    /// Text similarity of a modified pair.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub numbers: Vec<NumberChange>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiffSummary {
+    pub inserted: usize,
+    pub deleted: usize,
+    pub modified: usize,
+    pub moved: usize,
+    pub unchanged: usize,
+    pub numbers_changed: usize,
+}
+
+/// Changes from an old to a new document, in the reading order of the new one.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DocumentDiff {
+    pub summary: DiffSummary,
+    pub changes: Vec<CellChange>,
+}
+
+impl DocumentDiff {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+impl Document {
+    /// Diffs `self` (the old revision) against `new` with default options.
+    pub fn diff(&self, new: &Document) -> DocumentDiff {
+        self.diff_with(new, &DiffOptions::default())
+    }
+
+    pub fn diff_with(&self, new: &Document, options: &DiffOptions) -> DocumentDiff {
+        let old_cells = self.ordered_cells();
+        let new_cells = new.ordered_cells();
+        let mut pair_of_old: Vec<Option<usize>> = vec![None; old_cells.len()];
+        let mut pair_of_new: Vec<Option<usize>> = vec![None; new_cells.len()];
+
+        let mut by_code: HashMap<_, Vec<usize>> = HashMap::new();
+        for (idx, cell) in new_cells.iter().enumerate().rev() {
+            by_code.entry(cell.code_id).or_default().push(idx);
+        }
+        for (idx, cell) in old_cells.iter().enumerate() {
+            if let Some(candidate) = by_code.get_mut(&cell.code_id).and_then(Vec::pop) {
+                pair_of_old[idx] = Some(candidate);
+                pair_of_new[candidate] = Some(idx);
+            }
+        }
+        let old_text = |idx: usize| {
+            self.payload_for(&old_cells[idx].code_id)
+                .unwrap_or_default()
+        };
+        let new_text = |idx: usize| new.payload_for(&new_cells[idx].code_id).unwrap_or_default();
+        let mut modified: HashMap<usize, (usize, f32)> = HashMap::new();
+        let unmatched_new: Vec<(usize, Bigrams)> = (0..new_cells.len())
+            .filter(|idx| pair_of_new[*idx].is_none())
+            .map(|idx| (idx, Bigrams::new(new_text(idx))))
+            .collect();
+        let mut candidates = Vec::new();
+        for old in (0..old_cells.len()).filter(|idx| pair_of_old[*idx].is_none()) {
+            let grams = Bigrams::new(old_text(old));
+            for (new, other) in &unmatched_new {
+                // Dice can't exceed 2·min/(a+b), so very different lengths are skipped unscored.
+                let bound = 2.0 * grams.total.min(other.total) as f32
+                    / (grams.total + other.total).max(1) as f32;
+                if bound < options.similarity {
+                    continue;
+                }
+                let score = grams.dice(other);
+                if score >= options.similarity {
+                    candidates.push((score, old, *new));
+                }
+            }
+        }
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)).then(a.2.cmp(&b.2)));
+        for (score, old, new) in candidates {
+            if pair_of_old[old].is_none() && pair_of_new[new].is_none() {
+                pair_of_old[old] = Some(new);
+                pair_of_new[new] = Some(old);
+                modified.insert(new, (old, score));
+            }
+        }
+
+        // Modified pairs anchor the reading order too, so an edited cell
+        // doesn't make its unchanged neighbours look moved.
+        let pairs: Vec<usize> = pair_of_old.iter().flatten().copied().collect();
+        let in_order = longest_increasing(&pairs);
+        let mut moved = vec![false; new_cells.len()];
+        for (pos, new) in pairs.iter().enumerate() {
+            moved[*new] = !in_order[pos];
+        }
+
+        let mut summary = DiffSummary::default();
+        // Deleted cells are listed after the new cell their predecessor became.
+        let mut keyed: Vec<((usize, usize, usize), CellChange)> = Vec::new();
+        for (idx, pair) in pair_of_new.iter().enumerate() {
+            let change = match (pair, modified.get(&idx)) {
+                (None, _) => CellChange {
+                    kind: ChangeKind::Inserted,
+                    old: None,
+                    new: Some(diff_cell(&new_cells[idx], new_text(idx))),
+                    similarity: None,
+                    numbers: Vec::new(),
+                },
+                (Some(_), Some(&(old, score))) => {
+                    let numbers = number_changes(
+                        self,
+                        &old_cells[old],
+                        old_text(old),
+                        new,
+                        &new_cells[idx],
+                        new_text(idx),
+                    );
+                    summary.numbers_changed += numbers.len();
+                    CellChange {
+                        kind: ChangeKind::Modified,
+                        old: Some(diff_cell(&old_cells[old], old_text(old))),
+                        new: Some(diff_cell(&new_cells[idx], new_text(idx))),
+                        similarity: Some((score * 1000.0).round() / 1000.0),
+                        numbers,
+                    }
+                }
+                (Some(old), None) if moved[idx] => CellChange {
+                    kind: ChangeKind::Moved,
+                    old: Some(diff_cell(&old_cells[*old], old_text(*old))),
+                    new: Some(diff_cell(&new_cells[idx], new_text(idx))),
+                    similarity: None,
+                    numbers: Vec::new(),
+                },
+                (Some(_), None) => {
+                    summary.unchanged += 1;
+                    continue;
+                }
+            };
+            keyed.push(((idx + 1, 0, 0), change));
+        }
+        let mut anchor = 0;
+        for (idx, pair) in pair_of_old.iter().enumerate() {
+            match pair {
+                Some(new) => anchor = anchor.max(new + 1),
+                None => keyed.push((
+                    (anchor, 1, idx),
+                    CellChange {
+                        kind: ChangeKind::Deleted,
+                        old: Some(diff_cell(&old_cells[idx], old_text(idx))),
+                        new: None,
+                        similarity: None,
+                        numbers: Vec::new(),
+                    },
+                )),
+            }
+        }
+        keyed.sort_by_key(|(key, _)| *key);
+        let changes: Vec<CellChange> = keyed.into_iter().map(|(_, change)| change).collect();
+        for change in &changes {
+            match change.kind {
+                ChangeKind::Inserted => summary.inserted += 1,
+                ChangeKind::Deleted => summary.deleted += 1,
+                ChangeKind::Modified => summary.modified += 1,
+                ChangeKind::Moved => summary.moved += 1,
+            }
+        }
+        DocumentDiff { summary, changes }
+    }
+}
+
+impl fmt::Display for DocumentDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = &self.summary;
+        writeln!(
+            f,
+            "{} inserted, {} deleted, {} modified, {} moved, {} unchanged, {} numbers changed",
+            s.inserted, s.deleted, s.modified, s.moved, s.unchanged, s.numbers_changed
+        )?;
+        for change in &self.changes {
+            let sign = match change.kind {
+                ChangeKind::Inserted => '+',
+                ChangeKind::Deleted => '-',
+                ChangeKind::Modified => '~',
+                ChangeKind::Moved => '>',
+            };
+            let place = match (&change.old, &change.new) {
+                (Some(old), Some(new)) => format!("{} -> {}", position(old), position(new)),
+                (Some(cell), None) | (None, Some(cell)) => position(cell),
+                (None, None) => String::new(),
+            };
+            let cell_type = change
+                .new
+                .as_ref()
+                .or(change.old.as_ref())
+                .map(|cell| format!("{:?}", cell.cell_type).to_uppercase());
+            write!(
+                f,
+                "\n{sign} {kind} {place} {cell_type}",
+                kind = change.kind,
+                cell_type = cell_type.unwrap_or_default()
+            )?;
+            if let Some(similarity) = change.similarity {
+                write!(f, " similarity={similarity:.2}")?;
+            }
+            writeln!(f)?;
+            match change.kind {
+                ChangeKind::Modified => {
+                    for (mark, cell) in [('-', &change.old), ('+', &change.new)] {
+                        if let Some(cell) = cell {
+                            write_text(f, mark, &cell.text)?;
+                        }
+                    }
+                }
+                _ => {
+                    if let Some(cell) = change.new.as_ref().or(change.old.as_ref()) {
+                        write_text(f, ' ', &cell.text)?;
+                    }
+                }
+            }
+            for number in &change.numbers {
+                writeln!(
+                    f,
+                    "    ! {} {} -> {}",
+                    format!("{:?}", number.kind).to_lowercase(),
+                    number.old.as_deref().unwrap_or("(none)"),
+                    number.new.as_deref().unwrap_or("(none)")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn position(cell: &DiffCell) -> String {
+    format!("z={} ({},{})", cell.z, cell.x, cell.y)
+}
+
+fn write_text(f: &mut fmt::Formatter<'_>, mark: char, text: &str) -> fmt::Result {
+    for line in text.lines() {
+        writeln!(f, "    {mark} {line}")?;
+    }
+    Ok(())
+}
+
+fn diff_cell(cell: &CellRecord, text: &str) -> DiffCell {
+    DiffCell {
+        z: cell.z,
+        x: cell.x,
+        y: cell.y,
+        cell_type: cell.cell_type,
+        text: text.to_string(),
+    }
+}
+
+/// Numbers of a cell as recorded by its NumGuards; guards written before typed
+/// entities existed fall back to the numbers in the payload.
+fn cell_numbers(doc: &Document, cell: &CellRecord, payload: &str) -> Vec<NumericEntity> {
+    let guards: Vec<_> = doc
+        .numguards
+        .iter()
+        .filter(|g| g.z == cell.z && g.x == cell.x.max(0) as u32 && g.y == cell.y.max(0) as u32)
+        .collect();
+    if !guards.is_empty() && guards.iter().all(|g| g.entity.is_some()) {
+        guards.iter().filter_map(|g| g.entity.clone()).collect()
+    } else {
+        extract_entities(payload)
+    }
+}
+
+fn number_changes(
+    old_doc: &Document,
+    old_cell: &CellRecord,
+    old_text: &str,
+    new_doc: &Document,
+    new_cell: &CellRecord,
+    new_text: &str,
+) -> Vec<NumberChange> {
+    let old = cell_numbers(old_doc, old_cell, old_text);
+    let mut added = cell_numbers(new_doc, new_cell, new_text);
+    let mut removed = Vec::new();
+    for entity in old {
+        match added.iter().position(|other| other.equivalent(&entity)) {
+            Some(pos) => {
+                added.remove(pos);
+            }
+            None => removed.push(entity),
+        }
+    }
+    let mut changes = Vec::new();
+    for entity in removed {
+        let replacement = added
+            .iter()
+            .position(|other| other.kind == entity.kind)
+            .map(|pos| added.remove(pos));
+        changes.push(NumberChange {
+            kind: entity.kind,
+            old: Some(entity.raw),
+            new: replacement.map(|other| other.raw),
+        });
+    }
+    changes.extend(added.into_iter().map(|entity| NumberChange {
+        kind: entity.kind,
+        old: None,
+        new: Some(entity.raw),
+    }));
+    changes
+}
+
+/// Marks the members of one longest strictly increasing subsequence.
+fn longest_increasing(values: &[usize]) -> Vec<bool> {
+    let mut tails: Vec<usize> = Vec::new();
+    let mut prev = vec![usize::MAX; values.len()];
+    for (idx, value) in values.iter().enumerate() {
+        let pos = tails.partition_point(|&tail| values[tail] < *value);
+        if pos > 0 {
+            prev[idx] = tails[pos - 1];
+        }
+        if pos == tails.len() {
+            tails.push(idx);
+        } else {
+            tails[pos] = idx;
+        }
+    }
+    let mut keep = vec![false; values.len()];
+    let mut cursor = tails.last().copied().unwrap_or(usize::MAX);
+    while cursor != usize::MAX {
+        keep[cursor] = true;
+        cursor = prev[cursor];
+    }
+    keep
+}
+
+/// Character bigram multiset of lowercased, whitespace-collapsed text.
+struct Bigrams {
+    counts: HashMap<(char, char), u32>,
+    total: u32,
+}
+
+impl Bigrams {
+    fn new(text: &str) -> Self {
+        let chars: Vec<char> = text
+            .split_whitespace()
+            .collect::<Vec<_>>()
+            .join(" ")
+            .to_lowercase()
+            .chars()
+            .collect();
+        let mut counts = HashMap::new();
+        for pair in chars.windows(2) {
+            *counts.entry((pair[0], pair[1])).or_insert(0) += 1;
+        }
+        let total = chars.len().saturating_sub(1) as u32;
+        Self { counts, total }
+    }
+
+    fn dice(&self, other: &Bigrams) -> f32 {
+        if self.total + other.total == 0 {
+            return 0.0;
+        }
+        let shared: u32 = self
+            .counts
+            .iter()
+            .map(|(gram, count)| (*count).min(other.counts.get(gram).copied().unwrap_or(0)))
+            .sum();
+        2.0 * shared as f32 / (self.total + other.total) as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_each_kind_of_change() {
+        let old = Document::fixture(&[&[
+            "Master Services Agreement",
+            "Payment is due within 30 days of invoice.",
+            "The fee is $12,000 per year.",
+            "Either party may terminate with notice.",
+            "Confidentiality survives termination.",
+            "Governing law is Delaware.",
+        ]]);
+        let new = Document::fixture(&[&[
+            "Master Services Agreement",
+            "Governing law is Delaware.",
+            "Payment is due within 30 days of invoice.",
+            "The fee is $15,000 per year.",
+            "Confidentiality survives termination.",
+            "Data is stored in the EU only.",
+        ]]);
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.summary,
+            DiffSummary {
+                inserted: 1,
+                deleted: 1,
+                modified: 1,
+                moved: 1,
+                unchanged: 3,
+                numbers_changed: 1,
+            }
+        );
+        let kinds: Vec<ChangeKind> = diff.changes.iter().map(|c| c.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ChangeKind::Moved,
+                ChangeKind::Modified,
+                ChangeKind::Deleted,
+                ChangeKind::Inserted
+            ]
+        );
+        let modified = &diff.changes[1];
+        assert_eq!(
+            modified.numbers,
+            vec![NumberChange {
+                kind: NumericKind::Currency,
+                old: Some("$12,000".into()),
+                new: Some("$15,000".into()),
+            }]
+        );
+        let moved = &diff.changes[0];
+        assert_eq!(moved.old.as_ref().map(|c| c.y), Some(214));
+        assert_eq!(moved.new.as_ref().map(|c| c.y), Some(94));
+
+        let report = diff.to_string();
+        assert!(report.starts_with("1 inserted, 1 deleted, 1 modified, 1 moved, 3 unchanged"));
+        assert!(report.contains("~ modified z=0 (64,124) -> z=0 (64,154) TEXT similarity="));
+        assert!(report.contains("    - The fee is $12,000 per year.\n    + The fee is $15,000 per year.\n    ! currency $12,000 -> $15,000\n"));
+        assert!(report.contains("- deleted z=0 (64,154) TEXT\n      Either party may terminate"));
+    }
+
+    #[test]
+    fn identical_documents_have_no_changes() {
+        let doc = Document::fixture(&[&["One", "Two", "One"]]);
+        let diff = doc.diff(&doc.clone());
+        assert!(diff.is_empty());
+        assert_eq!(diff.summary.unchanged, 3);
+    }
+
+    #[test]
+    fn longest_increasing_keeps_the_stable_order() {
+        assert_eq!(
+            longest_increasing(&[0, 3, 1, 2, 4]),
+            vec![true, false, true, true, true]
+        );
+    }
+}
@@ -1,14 +1,21 @@
 use std::collections::HashSet;
 use std::convert::TryFrom;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{Cursor, Read, Write};
 use std::path::Path;
 
 use indexmap::IndexMap;
 use prost::Message;
 use serde::{Deserialize, Serialize};
 
-use crate::error::Result;
+use crate::container::{self, DocumentReader};
+use crate::error::{DcfError, Result};
+use crate::figures::FigureRef;
+use crate::integrity::{self, Integrity, VerifyingKey};
+use crate::links::{Link, LinkKind, LinkTarget};
+use crate::migrate::{self, MigrationReport, FORMAT_VERSION};
+use crate::numguard::NumericEntity;
+use crate::pii::Redaction;
 use crate::proto;
 
 pub type CodeHash = [u8; 32];
@@ -18,14 +25,18 @@ pub struct Header {
     pub version: u32,
     pub grid: String,
     pub codeset: String,
+    /// Dominant language of the document (ISO 639-1), when detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
 }
 
 impl Default for Header {
     fn default() -> Self {
         Self {
-            version: 1,
+            version: FORMAT_VERSION,
             grid: "coarse".to_string(),
             codeset: "HASH256".to_string(),
+            lang: None,
         }
     }
 }
@@ -38,6 +49,8 @@ pub enum CellType {
     Figure,
     Footer,
     Header,
+    /// A formula; the payload is its LaTeX source when the input had one.
+    Equation,
 }
 
 impl From<CellType> for proto::CellType {
@@ -48,6 +61,7 @@ impl From<CellType> for proto::CellType {
             CellType::Figure => proto::CellType::Figure,
             CellType::Footer => proto::CellType::Footer,
             CellType::Header => proto::CellType::Header,
+            CellType::Equation => proto::CellType::Equation,
         }
     }
 }
@@ -60,6 +74,7 @@ impl From<proto::CellType> for CellType {
             proto::CellType::Figure => CellType::Figure,
             proto::CellType::Footer => CellType::Footer,
             proto::CellType::Header => CellType::Header,
+            proto::CellType::Equation => CellType::Equation,
         }
     }
 }
@@ -83,6 +98,12 @@ pub struct CellRecord {
     pub rle: u32,
     pub cell_type: CellType,
     pub importance: u8,
+    /// Mean Tesseract word confidence (0–100) for cells produced by OCR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ocr_confidence: Option<u8>,
+    /// Detected language (ISO 639-1); `None` when the text was too short to tell.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
 }
 
 impl CellRecord {
@@ -91,7 +112,7 @@ impl CellRecord {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct NumGuard {
     pub z: u32,
     pub x: u32,
@@ -99,6 +120,27 @@ pub struct NumGuard {
     pub units: String,
     #[serde(with = "numhash_serde")]
     pub sha1: [u8; 20],
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub entity: Option<NumericEntity>,
+}
+
+/// Why the encoder left a placed cell out of the document.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// Below the importance cut-off of `budget`.
+    Budget,
+    /// Removed by `drop_footers`.
+    Footer,
+    /// Repeated within `dedup_window_pages` of an earlier copy.
+    Duplicate,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DroppedCell {
+    pub cell: CellRecord,
+    pub payload: String,
+    pub reason: DropReason,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -109,6 +151,28 @@ pub struct Document {
     #[serde(with = "dict_serde")]
     pub dict: IndexMap<CodeHash, String>,
     pub numguards: Vec<NumGuard>,
+    /// Hyperlinks, cross-references and footnotes anchored in cell payloads.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<Link>,
+    /// Figure images keyed by their sidecar path (`figures/<blake3>.<ext>`).
+    /// Not part of the container; written next to it by `save_bin`/`save_json`.
+    #[serde(skip)]
+    pub figure_files: IndexMap<String, Vec<u8>>,
+    /// PII replaced during encoding. Never stored in the container; write it
+    /// out with `write_redaction_log`.
+    #[serde(skip)]
+    pub redactions: Vec<Redaction>,
+    /// Cells the budget and post-encode filters removed, when the encoder
+    /// was built with `keep_dropped`. Never stored in the container;
+    /// `3dcf render-debug` draws them.
+    #[serde(skip)]
+    pub dropped: Vec<DroppedCell>,
+    /// Merkle root and optional signature, set by `seal`/`sign`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrity: Option<Integrity>,
+    /// Set when decoding upgraded an older format version in memory.
+    #[serde(skip)]
+    pub migration: Option<MigrationReport>,
 }
 
 impl Document {
@@ -119,6 +183,12 @@ impl Document {
             cells: Vec::new(),
             dict: IndexMap::new(),
             numguards: Vec::new(),
+            links: Vec::new(),
+            figure_files: IndexMap::new(),
+            redactions: Vec::new(),
+            dropped: Vec::new(),
+            integrity: None,
+            migration: None,
         }
     }
 
@@ -156,17 +226,7 @@ impl Document {
                 let dx = cell.x as i64 - prev.1;
                 let dy = cell.y as i64 - prev.2;
                 prev = (cell.z as i64, cell.x as i64, cell.y as i64);
-                proto::Cell {
-                    dz: dz as i32,
-                    dx: dx as i32,
-                    dy: dy as i32,
-                    w: cell.w,
-                    h: cell.h,
-                    code_id: cell.code_id.to_vec().into(),
-                    rle: cell.rle,
-                    r#type: proto::CellType::from(cell.cell_type) as i32,
-                    importance_q: cell.importance as u32,
-                }
+                cell_to_proto(&cell, (dz as i32, dx as i32, dy as i32))
             })
             .collect();
 
@@ -179,23 +239,14 @@ impl Document {
             })
             .collect();
 
-        let numguards = self
-            .numguards
-            .iter()
-            .map(|guard| proto::NumGuard {
-                z: guard.z,
-                x: guard.x,
-                y: guard.y,
-                units: guard.units.clone(),
-                sha1: guard.sha1.to_vec().into(),
-            })
-            .collect();
+        let numguards = self.numguards.iter().map(numguard_to_proto).collect();
 
         proto::Document {
             header: Some(proto::Header {
                 version: self.header.version,
                 grid: self.header.grid.clone(),
                 codeset: self.header.codeset.clone(),
+                lang: self.header.lang.clone(),
             }),
             pages: self
                 .pages
@@ -209,18 +260,30 @@ impl Document {
             cells,
             dict,
             numguards,
+            links: self.links.iter().map(link_to_proto).collect(),
+            integrity: self.integrity.as_ref().map(Integrity::to_proto),
         }
     }
 
+    /// Decodes a document and upgrades it to [`FORMAT_VERSION`].
     pub fn from_proto(doc: proto::Document) -> Result<Self> {
-        let header = doc
-            .header
-            .map(|h| Header {
-                version: h.version,
+        let mut document = Self::from_proto_unmigrated(doc)?;
+        document.migrate()?;
+        Ok(document)
+    }
+
+    /// Decodes without upgrading, so `header.version` is the stored version.
+    /// Page blocks and bundle entries are migrated once reassembled.
+    pub(crate) fn from_proto_unmigrated(doc: proto::Document) -> Result<Self> {
+        let header = match doc.header {
+            Some(h) => Header {
+                version: migrate::check_version(h.version)?,
                 grid: h.grid,
                 codeset: h.codeset,
-            })
-            .unwrap_or_default();
+                lang: h.lang.filter(|lang| !lang.is_empty()),
+            },
+            None => Header::default(),
+        };
 
         let pages = doc
             .pages
@@ -232,51 +295,51 @@ impl Document {
             })
             .collect();
 
-        let mut cells = Vec::new();
+        let mut cells = Vec::with_capacity(doc.cells.len());
         let mut prev = (0i64, 0i64, 0i64);
         for cell in doc.cells {
             prev.0 += cell.dz as i64;
             prev.1 += cell.dx as i64;
             prev.2 += cell.dy as i64;
-            let mut code_id = [0u8; 32];
-            code_id.copy_from_slice(&cell.code_id);
+            let cell_type =
+                proto::CellType::try_from(cell.r#type).map_err(|_| DcfError::UnknownEnumValue {
+                    field: "cell type",
+                    value: cell.r#type,
+                })?;
             cells.push(CellRecord {
                 z: prev.0 as u32,
                 x: prev.1 as i32,
                 y: prev.2 as i32,
                 w: cell.w,
                 h: cell.h,
-                code_id,
+                code_id: fixed_bytes("cell code_id", &cell.code_id)?,
                 rle: cell.rle,
-                cell_type: proto::CellType::try_from(cell.r#type)
-                    .map(CellType::from)
-                    .unwrap_or(CellType::Text),
+                cell_type: CellType::from(cell_type),
                 importance: cell.importance_q as u8,
+                ocr_confidence: cell.ocr_confidence.map(|c| c.min(100) as u8),
+                lang: cell.lang.filter(|lang| !lang.is_empty()),
             });
         }
 
-        let mut dict = IndexMap::new();
+        let mut dict = IndexMap::with_capacity(doc.dict.len());
         for entry in doc.dict {
-            let mut code_id = [0u8; 32];
-            code_id.copy_from_slice(&entry.code_id);
-            dict.insert(code_id, entry.payload_utf8);
+            dict.insert(
+                fixed_bytes("dict code_id", &entry.code_id)?,
+                entry.payload_utf8,
+            );
         }
 
-        let numguards = doc
-            .numguards
-            .into_iter()
-            .map(|guard| {
-                let mut sha = [0u8; 20];
-                sha.copy_from_slice(&guard.sha1);
-                NumGuard {
-                    z: guard.z,
-                    x: guard.x,
-                    y: guard.y,
-                    units: guard.units,
-                    sha1: sha,
-                }
-            })
-            .collect();
+        let mut numguards = Vec::with_capacity(doc.numguards.len());
+        for guard in doc.numguards {
+            numguards.push(NumGuard {
+                z: guard.z,
+                x: guard.x,
+                y: guard.y,
+                sha1: fixed_bytes("NumGuard sha1", &guard.sha1)?,
+                units: guard.units,
+                entity: guard.value.and_then(NumericEntity::from_proto),
+            });
+        }
 
         Ok(Self {
             header,
@@ -284,6 +347,16 @@ impl Document {
             cells,
             dict,
             numguards,
+            links: doc
+                .links
+                .into_iter()
+                .map(link_from_proto)
+                .collect::<Result<_>>()?,
+            figure_files: IndexMap::new(),
+            redactions: Vec::new(),
+            dropped: Vec::new(),
+            integrity: doc.integrity.map(Integrity::from_proto).transpose()?,
+            migration: None,
         })
     }
 
@@ -297,37 +370,121 @@ impl Document {
         Ok(data)
     }
 
+    /// Decodes either layout: a single zstd blob or a seekable container.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if container::is_seekable(bytes) {
+            return DocumentReader::new(Cursor::new(bytes))?.read_all();
+        }
+        let mut document = Self::from_blob_unmigrated(bytes)?;
+        document.migrate()?;
+        Ok(document)
+    }
+
+    pub(crate) fn from_blob_unmigrated(bytes: &[u8]) -> Result<Self> {
         let mut decoder = zstd::stream::Decoder::new(bytes)?;
         let mut buf = Vec::new();
         decoder.read_to_end(&mut buf)?;
         let proto = proto::Document::decode(&*buf)?;
-        Self::from_proto(proto)
+        Self::from_proto_unmigrated(proto)
     }
 
+    /// Writes the seekable container layout (see [`DocumentReader`]).
     pub fn save_bin<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let bytes = self.to_bytes()?;
-        let mut file = File::create(path)?;
+        let bytes = self.to_seekable_bytes()?;
+        let mut file = File::create(path.as_ref())?;
         file.write_all(&bytes)?;
-        Ok(())
+        self.write_figure_sidecars(path.as_ref())
     }
 
+    /// Loads a binary document. When `THREE_DCF_TRUSTED_KEY` names a public
+    /// key, the document must carry a valid signature by that key.
     pub fn load_bin<P: AsRef<Path>>(path: P) -> Result<Self> {
         let mut file = File::open(path)?;
         let mut buf = Vec::new();
         file.read_to_end(&mut buf)?;
-        Self::from_bytes(&buf)
+        let doc = Self::from_bytes(&buf)?;
+        if let Some(key) = integrity::trusted_key_from_env()? {
+            doc.verify_integrity(Some(&key))?;
+        }
+        Ok(doc)
+    }
+
+    /// Loads a binary document and requires a valid signature by `key`.
+    pub fn load_bin_verified<P: AsRef<Path>>(path: P, key: &VerifyingKey) -> Result<Self> {
+        let doc = Self::from_bytes(&std::fs::read(path)?)?;
+        doc.verify_integrity(Some(key))?;
+        Ok(doc)
     }
 
     pub fn save_json<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        let mut file = File::create(path)?;
+        let mut file = File::create(path.as_ref())?;
         serde_json::to_writer_pretty(&mut file, self)?;
+        self.write_figure_sidecars(path.as_ref())
+    }
+
+    fn write_figure_sidecars(&self, container: &Path) -> Result<()> {
+        if self.figure_files.is_empty() {
+            return Ok(());
+        }
+        let dir = container.parent().unwrap_or_else(|| Path::new(""));
+        self.write_figures(dir)?;
         Ok(())
     }
 
+    /// Writes figure images under `dir` (normally the container's directory);
+    /// returns how many new files were written.
+    pub fn write_figures<P: AsRef<Path>>(&self, dir: P) -> Result<usize> {
+        crate::figures::write_sidecars(dir.as_ref(), &self.figure_files)
+    }
+
+    /// Writes the redaction log as JSON lines, one entry per replaced value.
+    pub fn write_redaction_log<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut file = File::create(path.as_ref())?;
+        for redaction in &self.redactions {
+            serde_json::to_writer(&mut file, redaction)?;
+            file.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Links whose anchor text sits in `cell`.
+    pub fn links_for<'a>(&'a self, cell: &CellRecord) -> impl Iterator<Item = &'a Link> + 'a {
+        let source = (cell.z, cell.x, cell.y);
+        self.links
+            .iter()
+            .filter(move |link| link.source() == source)
+    }
+
+    /// Drops links whose cell is gone; links into a dropped cell fall back
+    /// to its page.
+    pub fn retain_links_for_cells(&mut self) {
+        let kept: HashSet<_> = self.cells.iter().map(|c| (c.z, c.x, c.y)).collect();
+        self.links.retain(|link| kept.contains(&link.source()));
+        for link in &mut self.links {
+            if let LinkTarget::Cell { z, x, y } = link.target {
+                if !kept.contains(&(z, x, y)) {
+                    link.target = LinkTarget::Page(z);
+                }
+            }
+        }
+    }
+
+    /// Figure cells in reading order with their parsed references.
+    pub fn figures(&self) -> Vec<(CellRecord, FigureRef)> {
+        self.ordered_cells()
+            .into_iter()
+            .filter(|cell| cell.cell_type == CellType::Figure)
+            .filter_map(|cell| {
+                let reference = FigureRef::parse(self.payload_for(&cell.code_id)?)?;
+                Some((cell, reference))
+            })
+            .collect()
+    }
+
     pub fn load_json<P: AsRef<Path>>(path: P) -> Result<Self> {
         let file = File::open(path)?;
-        let doc: Document = serde_json::from_reader(file)?;
+        let mut doc: Document = serde_json::from_reader(file)?;
+        doc.migrate()?;
         Ok(doc)
     }
 
@@ -398,6 +555,102 @@ impl Document {
     }
 }
 
+/// `delta` is the cell position relative to the previous cell in the stream.
+pub(crate) fn cell_to_proto(cell: &CellRecord, delta: (i32, i32, i32)) -> proto::Cell {
+    proto::Cell {
+        dz: delta.0,
+        dx: delta.1,
+        dy: delta.2,
+        w: cell.w,
+        h: cell.h,
+        code_id: cell.code_id.to_vec().into(),
+        rle: cell.rle,
+        r#type: proto::CellType::from(cell.cell_type) as i32,
+        importance_q: cell.importance as u32,
+        ocr_confidence: cell.ocr_confidence.map(u32::from),
+        lang: cell.lang.clone(),
+    }
+}
+
+pub(crate) fn numguard_to_proto(guard: &NumGuard) -> proto::NumGuard {
+    proto::NumGuard {
+        z: guard.z,
+        x: guard.x,
+        y: guard.y,
+        units: guard.units.clone(),
+        sha1: guard.sha1.to_vec().into(),
+        value: guard.entity.as_ref().map(NumericEntity::to_proto),
+    }
+}
+
+pub(crate) fn link_to_proto(link: &Link) -> proto::Link {
+    let kind = match link.kind {
+        LinkKind::Hyperlink => proto::LinkKind::Hyperlink,
+        LinkKind::CrossRef => proto::LinkKind::CrossRef,
+        LinkKind::Footnote => proto::LinkKind::Footnote,
+    };
+    let target = match &link.target {
+        LinkTarget::Url(url) => proto::link::Target::Url(url.clone()),
+        LinkTarget::Page(z) => proto::link::Target::Page(*z),
+        LinkTarget::Cell { z, x, y } => proto::link::Target::Cell(proto::CellRef {
+            z: *z,
+            x: *x,
+            y: *y,
+        }),
+    };
+    proto::Link {
+        z: link.z,
+        x: link.x,
+        y: link.y,
+        start: link.start as u32,
+        end: link.end as u32,
+        kind: kind as i32,
+        target: Some(target),
+    }
+}
+
+fn link_from_proto(link: proto::Link) -> Result<Link> {
+    let kind = proto::LinkKind::try_from(link.kind).map_err(|_| DcfError::UnknownEnumValue {
+        field: "link kind",
+        value: link.kind,
+    })?;
+    let kind = match kind {
+        proto::LinkKind::Hyperlink => LinkKind::Hyperlink,
+        proto::LinkKind::CrossRef => LinkKind::CrossRef,
+        proto::LinkKind::Footnote => LinkKind::Footnote,
+    };
+    let target = match link
+        .target
+        .ok_or(DcfError::InvalidDocument("link without a target"))?
+    {
+        proto::link::Target::Url(url) => LinkTarget::Url(url),
+        proto::link::Target::Page(z) => LinkTarget::Page(z),
+        proto::link::Target::Cell(cell) => LinkTarget::Cell {
+            z: cell.z,
+            x: cell.x,
+            y: cell.y,
+        },
+    };
+    Ok(Link {
+        z: link.z,
+        x: link.x,
+        y: link.y,
+        start: link.start as usize,
+        end: link.end as usize,
+        kind,
+        target,
+    })
+}
+
+/// Copies a fixed-size hash out of a proto `bytes` field.
+pub(crate) fn fixed_bytes<const N: usize>(field: &'static str, bytes: &[u8]) -> Result<[u8; N]> {
+    bytes.try_into().map_err(|_| DcfError::InvalidLength {
+        field,
+        expected: N,
+        found: bytes.len(),
+    })
+}
+
 pub fn hash_payload(payload: &str) -> CodeHash {
     let mut hasher = blake3::Hasher::new();
     hasher.update(payload.as_bytes());
@@ -411,6 +664,15 @@ impl Document {
     pub fn retain_dict_for_cells(&mut self) {
         let used: HashSet<_> = self.cells.iter().map(|c| c.code_id).collect();
         self.dict.retain(|code, _| used.contains(code));
+        if !self.figure_files.is_empty() {
+            let referenced: HashSet<String> = self
+                .figures()
+                .into_iter()
+                .map(|(_, reference)| reference.path)
+                .collect();
+            self.figure_files
+                .retain(|path, _| referenced.contains(path));
+        }
     }
 
     pub fn numguard_mismatches(&self) -> Vec<NumGuardAlert> {
@@ -440,6 +702,19 @@ impl Document {
             });
             if let Some(cell) = cell {
                 if let Some(payload) = self.payload_for(&cell.code_id) {
+                    if let Some(expected) = &guard.entity {
+                        let found = crate::numguard::extract_entities(payload)
+                            .iter()
+                            .any(|observed| observed.equivalent(expected));
+                        if !found {
+                            alerts.push(NumGuardAlert {
+                                guard: guard.clone(),
+                                observed: None,
+                                issue: NumGuardIssue::ValueMismatch,
+                            });
+                        }
+                        continue;
+                    }
                     if let Some(actual) = crate::numguard::hash_digits_from_payload(payload) {
                         if actual != guard.sha1 {
                             alerts.push(NumGuardAlert {
@@ -468,6 +743,61 @@ impl Document {
     }
 }
 
+/// Fixtures shared by the unit tests of other modules.
+#[cfg(test)]
+impl Document {
+    /// One 1024×1400 page per entry of `pages`, each line a text cell 30 px
+    /// below the previous one.
+    pub(crate) fn fixture(pages: &[&[&str]]) -> Self {
+        let mut doc = Self::new(Header::default());
+        for (z, lines) in pages.iter().enumerate() {
+            for (row, text) in lines.iter().enumerate() {
+                doc.push_fixture_cell(z as u32, 64 + row as i32 * 30, CellType::Text, text);
+            }
+        }
+        doc
+    }
+
+    /// Adds a full-width 24 px cell at `(z, 64, y)` with NumGuards for its
+    /// figures, and the page when it is missing. Returns the cell so a test
+    /// can adjust its height or importance.
+    pub(crate) fn push_fixture_cell(
+        &mut self,
+        z: u32,
+        y: i32,
+        cell_type: CellType,
+        text: &str,
+    ) -> &mut CellRecord {
+        if !self.pages.iter().any(|page| page.z == z) {
+            self.add_page(PageInfo {
+                z,
+                width_px: 1024,
+                height_px: 1400,
+            });
+        }
+        for guard in crate::numguard::extract_guards(text, z, 64, y as u32) {
+            self.add_numguard(guard);
+        }
+        self.push_cell(
+            CellRecord {
+                z,
+                x: 64,
+                y,
+                w: 896,
+                h: 24,
+                code_id: hash_payload(text),
+                rle: 0,
+                cell_type,
+                importance: 100,
+                ocr_confidence: None,
+                lang: None,
+            },
+            text.to_string(),
+        );
+        self.cells.last_mut().expect("cell just pushed")
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NumGuardAlert {
     pub guard: NumGuard,
@@ -480,6 +810,8 @@ pub enum NumGuardIssue {
     MissingCell,
     MissingPayload,
     HashMismatch,
+    /// No normalized value in the cell payload equals the guard's typed entity.
+    ValueMismatch,
     UnitNotAllowed,
 }
 
@@ -3,6 +3,13 @@ use std::collections::hash_map::DefaultHasher;
 use std::hash::{Hash, Hasher};
 
 use crate::document::CellType;
+use crate::error::Result;
+
+/// Turns texts into vectors. [`HashEmbedder`] is the offline default; wrap a
+/// model client to plug real embeddings into semantic chunking.
+pub trait Embedder: Send + Sync {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>>;
+}
 
 #[derive(Debug, Clone, Copy)]
 pub struct HashEmbedderConfig {
@@ -48,6 +55,12 @@ impl HashEmbedder {
     }
 }
 
+impl Embedder for HashEmbedder {
+    fn embed_batch(&self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        Ok(texts.iter().map(|text| self.embed_text(text)).collect())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct EmbeddingRecord {
     pub chunk_id: String,
@@ -67,6 +80,9 @@ pub struct EmbeddingRecord {
     pub dominant_type: CellType,
     #[serde(default)]
     pub importance_mean: f32,
+    /// The chunk's [`ChunkRecord::breadcrumb`](crate::ChunkRecord::breadcrumb).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub breadcrumb: Vec<String>,
     pub embedding: Vec<f32>,
     pub text: String,
 }
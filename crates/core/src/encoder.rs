@@ -2,22 +2,39 @@ use std::cmp::Reverse;
 use std::collections::HashMap;
 use std::fs;
 use std::path::Path;
+use std::sync::Arc;
 
 use html2text::from_read;
 use image::{self, DynamicImage};
-use pulldown_cmark::{html, Parser};
+use pulldown_cmark::{html, Options, Parser};
 use rayon::prelude::*;
 
 use crate::document::{
-    hash_payload, CellRecord, CellType, CodeHash, Document, Header, NumGuard, PageInfo,
+    hash_payload, CellRecord, CellType, CodeHash, Document, DropReason, DroppedCell, Header,
+    NumGuard, PageInfo,
 };
 use crate::error::{DcfError, Result};
+use crate::figures::{self, FigureConfig, FigureImage, FigureRef, PageFigure};
+use crate::importance::{HeuristicScorer, ImportanceContext, ImportanceScorer};
+#[cfg(any(feature = "ocr", feature = "pdfium"))]
+use crate::language::ocr_languages_for;
+use crate::language::{detect_language, LanguageTally};
+use crate::links::{
+    self, LineSpan, LinkKind, LinkTarget, PendingLink, SourceLinks, SourceTarget, SpanTarget,
+};
+use crate::math::{self, Formula};
 use crate::metrics::Metrics;
+use crate::migrate::FORMAT_VERSION;
 use crate::normalization::{
-    classify_cell_type, importance_score, looks_like_table_with_tolerance, normalize_lines,
-    HyphenationMode, ImportanceTuning,
+    classify_cell_type, ends_with_break, looks_like_table_with_tolerance, normalize_line,
+    normalize_lines, HyphenationMode, ImportanceTuning,
 };
 use crate::numguard;
+use crate::ocr::{OcrLine, OcrPage};
+use crate::pii::{Redaction, Redactor};
+#[cfg(any(feature = "ocr", feature = "pdfium"))]
+use crate::preprocess::preprocess;
+use crate::preprocess::PreprocessConfig;
 
 #[cfg(feature = "pdfium")]
 use pdfium_render::prelude::*;
@@ -41,7 +58,22 @@ pub struct EncoderConfig {
     pub enable_ocr: bool,
     pub force_ocr: bool,
     pub ocr_languages: Vec<String>,
+    /// OCR words below this Tesseract confidence (0–100) are discarded.
+    pub ocr_min_confidence: f32,
+    /// Image clean-up applied before OCR; the `scans` preset enables every step.
+    pub preprocess: PreprocessConfig,
+    /// Embedded image extraction; off by default.
+    pub figures: FigureConfig,
+    /// Record the language of each cell and of the document.
+    pub detect_language: bool,
+    /// Replaces PII in every cell before it is hashed; off when `None`.
+    pub redactor: Option<Arc<Redactor>>,
     pub importance: ImportanceTuning,
+    /// Overrides the heuristic scorer built from `importance` when set.
+    pub scorer: Option<Arc<dyn ImportanceScorer>>,
+    /// Keep the cells the budget and filters remove in `Document::dropped`
+    /// (with their payloads) for `render-debug`; off by default.
+    pub keep_dropped: bool,
 }
 
 impl EncoderConfig {
@@ -70,7 +102,17 @@ impl EncoderConfig {
             enable_ocr: false,
             force_ocr: false,
             ocr_languages: vec!["eng".to_string()],
+            ocr_min_confidence: 0.0,
+            preprocess: match preset {
+                EncoderPreset::Scans => PreprocessConfig::all(),
+                _ => PreprocessConfig::default(),
+            },
+            figures: FigureConfig::default(),
+            detect_language: true,
+            redactor: None,
             importance: ImportanceTuning::default(),
+            scorer: None,
+            keep_dropped: false,
         }
     }
 }
@@ -129,11 +171,76 @@ impl EncoderBuilder {
         self
     }
 
+    pub fn ocr_min_confidence(mut self, confidence: f32) -> Self {
+        self.config.ocr_min_confidence = confidence.clamp(0.0, 100.0);
+        self
+    }
+
+    pub fn preprocess(mut self, config: PreprocessConfig) -> Self {
+        self.config.preprocess = config;
+        self
+    }
+
+    pub fn detect_orientation(mut self, enable: bool) -> Self {
+        self.config.preprocess.orientation = enable;
+        self
+    }
+
+    pub fn deskew(mut self, enable: bool) -> Self {
+        self.config.preprocess.deskew = enable;
+        self
+    }
+
+    pub fn binarize(mut self, enable: bool) -> Self {
+        self.config.preprocess.binarize = enable;
+        self
+    }
+
+    pub fn despeckle(mut self, enable: bool) -> Self {
+        self.config.preprocess.despeckle = enable;
+        self
+    }
+
+    pub fn figures(mut self, config: FigureConfig) -> Self {
+        self.config.figures = config;
+        self
+    }
+
+    pub fn extract_figures(mut self, enable: bool) -> Self {
+        self.config.figures.extract = enable;
+        self
+    }
+
+    pub fn figure_ocr(mut self, enable: bool) -> Self {
+        self.config.figures.ocr = enable;
+        self
+    }
+
+    pub fn detect_language(mut self, enable: bool) -> Self {
+        self.config.detect_language = enable;
+        self
+    }
+
+    pub fn redactor(mut self, redactor: Redactor) -> Self {
+        self.config.redactor = Some(Arc::new(redactor));
+        self
+    }
+
+    pub fn keep_dropped(mut self, keep: bool) -> Self {
+        self.config.keep_dropped = keep;
+        self
+    }
+
     pub fn importance_tuning(mut self, tuning: ImportanceTuning) -> Self {
         self.config.importance = tuning;
         self
     }
 
+    pub fn importance_scorer(mut self, scorer: Arc<dyn ImportanceScorer>) -> Self {
+        self.config.scorer = Some(scorer);
+        self
+    }
+
     pub fn build(self) -> Encoder {
         Encoder {
             config: self.config,
@@ -178,19 +285,25 @@ impl Encoder {
     }
 
     pub fn encode_with_plaintext(&self, input: EncodeInput) -> Result<(Document, Metrics, String)> {
-        let raw_text = input.to_plaintext();
+        let mut raw_text = input.to_plaintext();
+        if let Some(redactor) = &self.config.redactor {
+            raw_text = redactor.redact_text(&raw_text);
+        }
         let (document, metrics) = self.encode(input)?;
         Ok((document, metrics, raw_text))
     }
 
     pub fn encode(&self, input: EncodeInput) -> Result<(Document, Metrics)> {
         let mut document = Document::new(Header {
-            version: 1,
+            version: FORMAT_VERSION,
             grid: self.config.grid.clone(),
             codeset: self.config.codeset.clone(),
+            lang: None,
         });
-        let mut metrics = Metrics::default();
-        metrics.pages = clamp_usize_to_u32(input.pages.len());
+        let mut metrics = Metrics {
+            pages: clamp_usize_to_u32(input.pages.len()),
+            ..Default::default()
+        };
 
         for page in &input.pages {
             document.add_page(PageInfo {
@@ -199,6 +312,13 @@ impl Encoder {
                 height_px: page.height_px,
             });
         }
+        if input.pages.iter().any(|page| page.ocr.is_some()) {
+            metrics.ocr_page_confidence = input
+                .pages
+                .iter()
+                .map(|page| page.ocr.as_ref().and_then(OcrPage::mean_confidence))
+                .collect();
+        }
 
         let processed_pages = input
             .pages
@@ -208,10 +328,20 @@ impl Encoder {
 
         let mut cells_total = 0usize;
         let mut lines_total = 0usize;
+        let mut ocr_words_dropped = 0usize;
+        let mut pending_links = Vec::new();
+        let mut anchors = HashMap::new();
         for page_output in processed_pages {
             let page_output = page_output?;
             cells_total += page_output.cells.len();
+            pending_links.extend(page_output.links);
+            for (name, position) in page_output.anchors {
+                anchors.entry(name).or_insert(position);
+            }
+            document.figure_files.extend(page_output.figure_files);
+            document.redactions.extend(page_output.redactions);
             lines_total += page_output.line_count;
+            ocr_words_dropped += page_output.ocr_words_dropped;
             document.cells.extend(page_output.cells);
             for guard in page_output.numguards {
                 document.add_numguard(guard);
@@ -220,16 +350,32 @@ impl Encoder {
                 document.dict.entry(code).or_insert(payload);
             }
         }
+        self.fit_pages_to_cells(&mut document);
         metrics.cells_total = clamp_usize_to_u32(cells_total);
         metrics.lines_total = clamp_usize_to_u32(lines_total);
+        metrics.ocr_words_dropped = clamp_usize_to_u32(ocr_words_dropped);
+        metrics.redactions = clamp_usize_to_u32(document.redactions.len());
+        document.links = links::resolve(pending_links, &anchors);
 
         let unique_payloads = document.dict.len();
 
         self.apply_budget(&mut document);
         self.post_filters(&mut document);
+        document.retain_links_for_cells();
         self.annotate_rle(&mut document.cells);
+        if self.config.detect_language {
+            document.header.lang = document_language(&document);
+        }
         metrics.cells_kept = clamp_usize_to_u32(document.cells.len());
         metrics.numguard_count = clamp_usize_to_u32(document.numguards.len());
+        metrics.links = clamp_usize_to_u32(document.links.len());
+        metrics.figures = clamp_usize_to_u32(
+            document
+                .cells
+                .iter()
+                .filter(|cell| cell.cell_type == CellType::Figure)
+                .count(),
+        );
         metrics.dedup_ratio = if unique_payloads == 0 {
             0.0
         } else {
@@ -239,82 +385,424 @@ impl Encoder {
         Ok((document, metrics))
     }
 
+    /// Text flows have no page breaks besides form feeds, so a long flow runs
+    /// past the configured height; its page grows to hold every line.
+    fn fit_pages_to_cells(&self, document: &mut Document) {
+        let mut bottoms: HashMap<u32, i64> = HashMap::new();
+        for cell in &document.cells {
+            let bottom = bottoms.entry(cell.z).or_default();
+            *bottom = (*bottom).max(i64::from(cell.y) + i64::from(cell.h));
+        }
+        for page in &mut document.pages {
+            if let Some(bottom) = bottoms.get(&page.z) {
+                let needed = bottom + i64::from(self.config.margin_top_px);
+                page.height_px = page
+                    .height_px
+                    .max(u32::try_from(needed.max(0)).unwrap_or(u32::MAX));
+            }
+        }
+    }
+
     fn encode_page(&self, page: PageBuffer) -> Result<PageResult> {
-        let normalized = normalize_lines(&page.lines, self.config.hyphenation);
-        let mut y = self.config.margin_top_px;
-        let mut cells = Vec::with_capacity(normalized.len());
+        let (mut placed, ocr_words_dropped) = match &page.ocr {
+            Some(ocr) => self.place_ocr_lines(ocr),
+            None => (self.place_text_lines(&page), 0),
+        };
+        placed.extend(place_boxed_figures(&page.figures));
+        place_area_links(&page.links, page.height_px, &mut placed);
+        mark_footnotes(&mut placed);
+        let mut redactions = Vec::new();
+        for line in placed.iter_mut().filter(|line| line.figure.is_none()) {
+            let logged = redactions.len();
+            line.text = self.redact(page.index, line.x, line.y, &line.text, &mut redactions);
+            links::shift_spans(
+                &mut line.spans,
+                redactions[logged..].iter().map(|redaction| &redaction.pii),
+            );
+        }
+        let (links, anchors) = self.page_links(&page, &placed);
+        let mut cells = Vec::with_capacity(placed.len());
         let mut dict_entries = Vec::new();
         let mut numguards_acc = Vec::new();
-        for (line_index, line) in normalized.iter().enumerate() {
-            let mut cell_type: CellType = classify_cell_type(line);
+        let mut figure_files = Vec::new();
+        let heuristic = HeuristicScorer::new(self.config.importance);
+        let scorer: &dyn ImportanceScorer = self.config.scorer.as_deref().unwrap_or(&heuristic);
+        let page_lang = self.page_language(&placed);
+        for (line_index, line) in placed.iter().enumerate() {
+            if let Some(figure) = line.figure.and_then(|idx| page.figures.get(idx)) {
+                let mut redact =
+                    |text: &str| self.redact(page.index, line.x, line.y, text, &mut redactions);
+                let reference = FigureRef {
+                    path: figure.image.relative_path(),
+                    caption: figure.label().map(&mut redact),
+                    text: if self.config.figures.ocr {
+                        self.figure_text(&figure.image).map(|text| redact(&text))
+                    } else {
+                        None
+                    },
+                };
+                let searchable = reference.searchable_text();
+                let importance = scorer.score(&ImportanceContext {
+                    line: &searchable,
+                    cell_type: CellType::Figure,
+                    line_index,
+                    line_count: placed.len(),
+                });
+                let payload = reference.to_payload();
+                let code_id = hash_payload(&payload);
+                cells.push(CellRecord {
+                    z: page.index,
+                    x: line.x,
+                    y: line.y,
+                    w: line.w,
+                    h: line.h,
+                    code_id,
+                    rle: 0,
+                    cell_type: CellType::Figure,
+                    importance,
+                    ocr_confidence: None,
+                    lang: self.cell_language(&searchable, page_lang.as_deref()),
+                });
+                dict_entries.push((code_id, payload));
+                numguards_acc.extend(numguard::extract_guards(
+                    &searchable,
+                    page.index,
+                    line.x as u32,
+                    line.y as u32,
+                ));
+                figure_files.push((reference.path, figure.image.bytes.clone()));
+                continue;
+            }
+            let mut cell_type: CellType = if line.equation {
+                CellType::Equation
+            } else {
+                classify_cell_type(&line.text)
+            };
             if cell_type == CellType::Text
-                && looks_like_table_with_tolerance(line, self.config.table_column_tolerance)
+                && looks_like_table_with_tolerance(&line.text, self.config.table_column_tolerance)
             {
                 cell_type = CellType::Table;
             }
-            let importance = importance_score(line, cell_type, line_index, &self.config.importance);
-            let code_id = hash_payload(line);
-            let w = (page.width_px as i32 - self.config.margin_left_px * 2).max(0) as u32;
+            let importance = scorer.score(&ImportanceContext {
+                line: &line.text,
+                cell_type,
+                line_index,
+                line_count: placed.len(),
+            });
+            let code_id = hash_payload(&line.text);
             let cell = CellRecord {
                 z: page.index,
-                x: self.config.margin_left_px,
-                y,
-                w,
-                h: self.config.line_height_px,
+                x: line.x,
+                y: line.y,
+                w: line.w,
+                h: line.h,
                 code_id,
                 rle: 0,
                 cell_type,
                 importance,
+                ocr_confidence: line.ocr_confidence,
+                lang: if cell_type == CellType::Equation {
+                    None
+                } else {
+                    self.cell_language(&line.text, page_lang.as_deref())
+                },
             };
             cells.push(cell);
-            dict_entries.push((code_id, line.clone()));
-            let guards = numguard::extract_guards(
-                line,
-                page.index,
-                self.config.margin_left_px as u32,
-                y as u32,
-            );
-            numguards_acc.extend(guards);
-            y += (self.config.line_height_px + self.config.line_gap_px) as i32;
+            dict_entries.push((code_id, line.text.clone()));
+            // Digits in a formula are symbols, not figures a summary could misquote.
+            if cell_type != CellType::Equation {
+                let guards =
+                    numguard::extract_guards(&line.text, page.index, line.x as u32, line.y as u32);
+                numguards_acc.extend(guards);
+            }
         }
         Ok(PageResult {
             cells,
             dict_entries,
             numguards: numguards_acc,
-            line_count: normalized.len(),
+            line_count: placed.len(),
+            ocr_words_dropped,
+            figure_files,
+            redactions,
+            links,
+            anchors,
         })
     }
 
+    /// Turns the line spans of a page into links on its cells.
+    fn page_links(
+        &self,
+        page: &PageBuffer,
+        placed: &[PlacedLine],
+    ) -> (Vec<PendingLink>, PageAnchors) {
+        let mut pending = Vec::new();
+        let mut anchors = Vec::new();
+        for line in placed {
+            for name in line
+                .anchors
+                .iter()
+                .filter_map(|id| page.links.anchors.get(*id))
+            {
+                anchors.push((name.clone(), (page.index, line.x, line.y)));
+            }
+            for span in &line.spans {
+                let (kind, target) = match span.target {
+                    SpanTarget::Line(idx) => {
+                        let note = &placed[idx];
+                        let target = LinkTarget::Cell {
+                            z: page.index,
+                            x: note.x,
+                            y: note.y,
+                        };
+                        (LinkKind::Footnote, Ok(target))
+                    }
+                    SpanTarget::Source(id) => {
+                        let Some(source) = page.links.links.get(id) else {
+                            continue;
+                        };
+                        let target = match &source.target {
+                            SourceTarget::Url(url) => {
+                                Ok(LinkTarget::Url(match &self.config.redactor {
+                                    Some(redactor) => redactor.redact_text(url),
+                                    None => url.clone(),
+                                }))
+                            }
+                            SourceTarget::Page(z) => Ok(LinkTarget::Page(*z)),
+                            SourceTarget::Anchor(name) => Err(name.clone()),
+                        };
+                        (source.kind, target)
+                    }
+                };
+                pending.push(PendingLink {
+                    z: page.index,
+                    x: line.x,
+                    y: line.y,
+                    start: span.start,
+                    end: span.end,
+                    kind,
+                    target,
+                });
+            }
+        }
+        (pending, anchors)
+    }
+
+    fn redact(&self, z: u32, x: i32, y: i32, text: &str, log: &mut Vec<Redaction>) -> String {
+        let Some(redactor) = &self.config.redactor else {
+            return text.to_string();
+        };
+        let (redacted, found) = redactor.redact(text);
+        log.extend(found.into_iter().map(|pii| Redaction { z, x, y, pii }));
+        redacted
+    }
+
+    fn page_language(&self, placed: &[PlacedLine]) -> Option<String> {
+        if !self.config.detect_language {
+            return None;
+        }
+        let text = placed
+            .iter()
+            .filter(|line| line.figure.is_none() && !line.equation)
+            .map(|line| line.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        detect_language(&text).map(|guess| guess.code)
+    }
+
+    /// Short or ambiguous cells inherit the language of their page.
+    fn cell_language(&self, text: &str, page_lang: Option<&str>) -> Option<String> {
+        if !self.config.detect_language {
+            return None;
+        }
+        match detect_language(text) {
+            Some(guess) if guess.reliable => Some(guess.code),
+            _ => page_lang.map(str::to_string),
+        }
+    }
+
+    /// Recognises text inside a figure (axis labels, legends). Failures only
+    /// cost the figure its text, so they are logged rather than returned.
+    fn figure_text(&self, image: &FigureImage) -> Option<String> {
+        let decoded = image.decode()?;
+        match crate::ocr::recognize(&decoded, &self.config.ocr_languages) {
+            Ok(ocr) => {
+                let text = ocr
+                    .lines(self.config.ocr_min_confidence)
+                    .iter()
+                    .map(|line| normalize_line(&line.text))
+                    .filter(|line| !line.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                (!text.is_empty()).then_some(text)
+            }
+            Err(err) => {
+                tracing::warn!("figure OCR failed: {err}");
+                None
+            }
+        }
+    }
+
+    /// Lays text lines out top to bottom on the configured grid. Figure
+    /// markers take the figure's height, scaled to the text width.
+    fn place_text_lines(&self, page: &PageBuffer) -> Vec<PlacedLine> {
+        let normalized = normalize_lines(&page.lines, self.config.hyphenation);
+        let w = (page.width_px as i32 - self.config.margin_left_px * 2).max(0) as u32;
+        let mut y = self.config.margin_top_px;
+        let mut placed = Vec::with_capacity(normalized.len());
+        let mut open_link = None;
+        let mut anchors = Vec::new();
+        for marked in normalized {
+            let marked = math::restore_inline(&marked, &page.formulas);
+            let (mut text, marks) = links::strip_markers(&marked, &mut open_link);
+            anchors.extend(marks.anchors);
+            if text.is_empty() {
+                continue;
+            }
+            let formula = math::parse_display(&text).and_then(|idx| page.formulas.get(idx));
+            if let Some(formula) = formula {
+                text = formula.tex.clone();
+            }
+            let (figure, w, h) = match figures::parse_marker(&text) {
+                Some(marker) => {
+                    let Some(idx) = page.figures.iter().position(|f| f.marker == Some(marker))
+                    else {
+                        continue;
+                    };
+                    let image = &page.figures[idx].image;
+                    let fig_w = image.width.min(w).max(1);
+                    let fig_h = (u64::from(image.height) * u64::from(fig_w)
+                        / u64::from(image.width.max(1))) as u32;
+                    (Some(idx), fig_w, fig_h.max(self.config.line_height_px))
+                }
+                None => (None, w, self.config.line_height_px),
+            };
+            placed.push(PlacedLine {
+                text,
+                x: self.config.margin_left_px,
+                y,
+                w,
+                h,
+                ocr_confidence: None,
+                spans: if figure.is_some() || formula.is_some() {
+                    Vec::new()
+                } else {
+                    marks.spans
+                },
+                anchors: std::mem::take(&mut anchors),
+                figure,
+                equation: formula.is_some(),
+            });
+            y += (h + self.config.line_gap_px) as i32;
+        }
+        placed
+    }
+
+    /// Keeps OCR line boxes and confidences, dropping low-confidence words.
+    fn place_ocr_lines(&self, ocr: &OcrPage) -> (Vec<PlacedLine>, usize) {
+        let min_confidence = self.config.ocr_min_confidence;
+        let dropped = ocr
+            .words
+            .iter()
+            .filter(|word| word.confidence < min_confidence)
+            .count();
+        let mut placed = Vec::new();
+        let mut group: Vec<OcrLine> = Vec::new();
+        for line in ocr.lines(min_confidence) {
+            let joins_next =
+                self.config.hyphenation == HyphenationMode::Merge && ends_with_break(&line.text);
+            group.push(line);
+            if !joins_next {
+                self.place_ocr_group(std::mem::take(&mut group), &mut placed);
+            }
+        }
+        self.place_ocr_group(group, &mut placed);
+        (placed, dropped)
+    }
+
+    /// Places OCR lines that hyphenation merges into one cell, spanning their
+    /// boxes with their mean confidence.
+    fn place_ocr_group(&self, group: Vec<OcrLine>, placed: &mut Vec<PlacedLine>) {
+        let Some(first) = group.first() else {
+            return;
+        };
+        let bbox = group
+            .iter()
+            .skip(1)
+            .fold(first.bbox, |acc, line| acc.union(&line.bbox));
+        let confidence = group.iter().map(|line| line.confidence).sum::<f32>() / group.len() as f32;
+        let texts: Vec<String> = group.into_iter().map(|line| line.text).collect();
+        for text in normalize_lines(&texts, self.config.hyphenation) {
+            placed.push(PlacedLine {
+                text,
+                x: bbox.left as i32,
+                y: bbox.top as i32,
+                w: bbox.width,
+                h: bbox.height,
+                ocr_confidence: Some(confidence.round().clamp(0.0, 100.0) as u8),
+                spans: Vec::new(),
+                anchors: Vec::new(),
+                figure: None,
+                equation: false,
+            });
+        }
+    }
+
     fn apply_budget(&self, doc: &mut Document) {
         if let Some(limit) = self.config.budget {
             if doc.cells.len() <= limit {
                 return;
             }
             doc.cells.sort_by_key(|c| (Reverse(c.importance), c.key()));
-            doc.cells.truncate(limit);
+            let dropped = doc.cells.split_off(limit);
+            self.record_dropped(doc, dropped, DropReason::Budget);
             doc.cells.sort_by_key(|c| c.key());
             doc.retain_dict_for_cells();
         }
     }
 
+    fn record_dropped(&self, doc: &mut Document, cells: Vec<CellRecord>, reason: DropReason) {
+        if !self.config.keep_dropped {
+            return;
+        }
+        for cell in cells {
+            let payload = doc
+                .payload_for(&cell.code_id)
+                .unwrap_or_default()
+                .to_string();
+            doc.dropped.push(DroppedCell {
+                cell,
+                payload,
+                reason,
+            });
+        }
+    }
+
     fn post_filters(&self, doc: &mut Document) {
         if self.config.drop_footers {
-            doc.cells.retain(|c| c.cell_type != CellType::Footer);
+            let (footers, kept) = std::mem::take(&mut doc.cells)
+                .into_iter()
+                .partition(|c| c.cell_type == CellType::Footer);
+            doc.cells = kept;
+            self.record_dropped(doc, footers, DropReason::Footer);
         }
         if self.config.dedup_window_pages > 0 {
             let mut seen: HashMap<CodeHash, Vec<u32>> = HashMap::new();
-            doc.cells.retain(|cell| {
-                let entry = seen.entry(cell.code_id).or_insert_with(Vec::new);
-                if entry
-                    .iter()
-                    .any(|z| cell.z.abs_diff(*z) <= self.config.dedup_window_pages)
-                {
-                    false
-                } else {
-                    entry.push(cell.z);
-                    true
-                }
-            });
+            let (kept, repeats) = std::mem::take(&mut doc.cells)
+                .into_iter()
+                .partition(|cell| {
+                    let entry = seen.entry(cell.code_id).or_default();
+                    if entry
+                        .iter()
+                        .any(|z| cell.z.abs_diff(*z) <= self.config.dedup_window_pages)
+                    {
+                        false
+                    } else {
+                        entry.push(cell.z);
+                        true
+                    }
+                });
+            doc.cells = kept;
+            self.record_dropped(doc, repeats, DropReason::Duplicate);
         }
         doc.cells.sort_by_key(|c| c.key());
         doc.retain_dict_for_cells();
@@ -360,19 +848,39 @@ impl EncodeInput {
             }
             Some("md") | Some("markdown") => {
                 let content = read_text_lossy(path)?;
-                let markdown = markdown_to_text(&content);
+                let (content, formulas) = math::protect_markdown(&content);
+                let (html, figures) = html_figures(&markdown_to_html(&content), path, config);
+                let (html, links) = links::extract_html_links(&html);
+                let markdown = html_to_plaintext(&html);
+                let pages = text_to_pages_with_figures(&markdown, figures, config);
                 Ok(Self {
-                    pages: text_to_pages(&markdown, config),
+                    pages: share_formulas(share_links(pages, links), formulas),
                 })
             }
             Some("html") | Some("htm") => {
                 let content = read_text_lossy(path)?;
-                let flattened = html_to_plaintext(&content);
+                let (content, formulas) = math::protect_html(&content);
+                let (html, figures) = html_figures(&content, path, config);
+                let (html, links) = links::extract_html_links(&html);
+                let flattened = html_to_plaintext(&html);
+                let pages = text_to_pages_with_figures(&flattened, figures, config);
+                Ok(Self {
+                    pages: share_formulas(share_links(pages, links), formulas),
+                })
+            }
+            Some("docx") => {
+                let (text, figures) = figures::docx_to_text(path, &config.figures)?;
+                Ok(Self {
+                    pages: text_to_pages_with_figures(&text, figures, config),
+                })
+            }
+            Some("tex") => {
+                let (content, formulas) = math::protect_tex(&read_text_lossy(path)?);
                 Ok(Self {
-                    pages: text_to_pages(&flattened, config),
+                    pages: share_formulas(text_to_pages(&content, config), formulas),
                 })
             }
-            Some("tex") | Some("json") | Some("bib") => {
+            Some("json") | Some("bib") => {
                 let content = read_text_lossy(path)?;
                 Ok(Self {
                     pages: text_to_pages(&content, config),
@@ -394,13 +902,17 @@ impl EncodeInput {
         #[cfg(feature = "pdfium")]
         {
             match pdfium_pdf_to_pages(path, config) {
-                Ok(pages) => return Ok(Self { pages }),
+                Ok(mut pages) => {
+                    attach_pdf_objects(path, &mut pages, config);
+                    return Ok(Self { pages });
+                }
                 Err(err) => {
                     tracing::warn!("pdfium read failed: {err}");
                 }
             }
         }
-        let pages = fallback_pdf_to_pages(path, config)?;
+        let mut pages = fallback_pdf_to_pages(path, config)?;
+        attach_pdf_objects(path, &mut pages, config);
         Ok(Self { pages })
     }
 
@@ -419,7 +931,10 @@ impl EncodeInput {
                 buffer.push_str("\n\n");
             }
             for line in &page.lines {
-                buffer.push_str(line);
+                if figures::parse_marker(line).is_some() {
+                    continue;
+                }
+                buffer.push_str(&links::strip_text(&math::render_text(line, &page.formulas)));
                 buffer.push('\n');
             }
         }
@@ -433,6 +948,14 @@ pub struct PageBuffer {
     pub width_px: u32,
     pub height_px: u32,
     pub lines: Vec<String>,
+    /// Word-level OCR layout; when present the encoder keeps its geometry.
+    pub ocr: Option<OcrPage>,
+    /// Embedded images that become Figure cells.
+    pub figures: Vec<PageFigure>,
+    /// Links referenced by markers in `lines`, or PDF link annotations.
+    pub(crate) links: Arc<SourceLinks>,
+    /// Formulas referenced by markers in `lines`.
+    pub(crate) formulas: Arc<Vec<Formula>>,
 }
 
 impl PageBuffer {
@@ -456,6 +979,33 @@ impl PageBuffer {
             width_px: config.page_width_px,
             height_px: config.page_height_px,
             lines,
+            ocr: None,
+            figures: Vec::new(),
+            links: Arc::default(),
+            formulas: Arc::default(),
+        }
+    }
+
+    /// Builds a page from word-level OCR output, e.g. a parsed Tesseract TSV or hOCR file.
+    pub fn from_ocr(index: u32, ocr: OcrPage, config: &EncoderConfig) -> Self {
+        let lines = ocr.lines(0.0).into_iter().map(|line| line.text).collect();
+        Self {
+            index,
+            width_px: if ocr.width > 0 {
+                ocr.width
+            } else {
+                config.page_width_px
+            },
+            height_px: if ocr.height > 0 {
+                ocr.height
+            } else {
+                config.page_height_px
+            },
+            lines,
+            ocr: Some(ocr),
+            figures: Vec::new(),
+            links: Arc::default(),
+            formulas: Arc::default(),
         }
     }
 }
@@ -466,6 +1016,32 @@ struct PageResult {
     dict_entries: Vec<(CodeHash, String)>,
     numguards: Vec<NumGuard>,
     line_count: usize,
+    ocr_words_dropped: usize,
+    figure_files: Vec<(String, Vec<u8>)>,
+    redactions: Vec<Redaction>,
+    links: Vec<PendingLink>,
+    anchors: PageAnchors,
+}
+
+/// Named anchors found on a page and the position of the cell each starts.
+type PageAnchors = Vec<(String, (u32, i32, i32))>;
+
+#[derive(Debug, Clone)]
+struct PlacedLine {
+    text: String,
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+    ocr_confidence: Option<u8>,
+    /// Linked byte ranges of `text`.
+    spans: Vec<LineSpan>,
+    /// Named anchors that start at this line.
+    anchors: Vec<usize>,
+    /// Index into the page's figures when this slot holds an image.
+    figure: Option<usize>,
+    /// `text` is the source of a display formula.
+    equation: bool,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -473,11 +1049,13 @@ pub enum EncoderPreset {
     Reports,
     Slides,
     News,
+    /// Scanned pages: also enables every [`PreprocessConfig`] step before OCR.
     Scans,
     Custom,
 }
 
 impl EncoderPreset {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(name: &str) -> Result<Self> {
         match name.to_lowercase().as_str() {
             "reports" => Ok(Self::Reports),
@@ -497,6 +1075,209 @@ fn text_to_pages(text: &str, config: &EncoderConfig) -> Vec<PageBuffer> {
         .collect()
 }
 
+/// Most common cell language, weighted by payload length.
+fn document_language(document: &Document) -> Option<String> {
+    let mut tally = LanguageTally::default();
+    for cell in &document.cells {
+        if let Some(lang) = &cell.lang {
+            let weight = document
+                .payload_for(&cell.code_id)
+                .map_or(1, |payload| payload.chars().count());
+            tally.add(lang, weight);
+        }
+    }
+    tally.dominant()
+}
+
+/// Splits text into pages and hands each page the figures whose markers it contains.
+fn text_to_pages_with_figures(
+    text: &str,
+    figures: Vec<PageFigure>,
+    config: &EncoderConfig,
+) -> Vec<PageBuffer> {
+    let mut pages = text_to_pages(text, config);
+    if figures.is_empty() {
+        return pages;
+    }
+    let mut figures: Vec<Option<PageFigure>> = figures.into_iter().map(Some).collect();
+    for page in &mut pages {
+        for line in &page.lines {
+            let Some(marker) = figures::parse_marker(line) else {
+                continue;
+            };
+            let slot = figures
+                .iter_mut()
+                .find(|slot| slot.as_ref().is_some_and(|f| f.marker == Some(marker)));
+            if let Some(figure) = slot.and_then(Option::take) {
+                page.figures.push(figure);
+            }
+        }
+    }
+    pages
+}
+
+/// Every page of a text-flow input resolves markers against the same links.
+fn share_links(mut pages: Vec<PageBuffer>, links: SourceLinks) -> Vec<PageBuffer> {
+    if links.links.is_empty() && links.anchors.is_empty() {
+        return pages;
+    }
+    let links = Arc::new(links);
+    for page in &mut pages {
+        page.links = Arc::clone(&links);
+    }
+    pages
+}
+
+/// Formula markers may land on any page of a text-flow input.
+fn share_formulas(mut pages: Vec<PageBuffer>, formulas: Vec<Formula>) -> Vec<PageBuffer> {
+    if formulas.is_empty() {
+        return pages;
+    }
+    let formulas = Arc::new(formulas);
+    for page in &mut pages {
+        page.formulas = Arc::clone(&formulas);
+    }
+    pages
+}
+
+/// PDF link annotations carry no anchor text: a URL printed on the page is
+/// linked exactly, otherwise the whole line nearest the rectangle's height.
+fn place_area_links(links: &SourceLinks, page_height: u32, placed: &mut [PlacedLine]) {
+    let text_lines: Vec<usize> = (0..placed.len())
+        .filter(|idx| placed[*idx].figure.is_none())
+        .collect();
+    if text_lines.is_empty() {
+        return;
+    }
+    for (id, link) in links.links.iter().enumerate() {
+        let Some(area) = link.area else {
+            continue;
+        };
+        let printed = match &link.target {
+            SourceTarget::Url(url) => {
+                let bare = ["mailto:", "https://", "http://"]
+                    .iter()
+                    .find_map(|scheme| url.strip_prefix(scheme))
+                    .unwrap_or(url);
+                text_lines.iter().find_map(|&idx| {
+                    let start = placed[idx].text.find(bare).filter(|_| !bare.is_empty())?;
+                    Some((idx, start, start + bare.len()))
+                })
+            }
+            _ => None,
+        };
+        let (idx, start, end) = printed.unwrap_or_else(|| {
+            let target = area * page_height as f32;
+            let distance = |idx: usize| {
+                let line = &placed[idx];
+                (line.y as f32 + line.h as f32 / 2.0 - target).abs()
+            };
+            let idx = text_lines
+                .iter()
+                .copied()
+                .min_by(|a, b| distance(*a).total_cmp(&distance(*b)))
+                .unwrap_or(text_lines[0]);
+            (idx, 0, placed[idx].text.len())
+        });
+        placed[idx].spans.push(LineSpan {
+            start,
+            end,
+            target: SpanTarget::Source(id),
+        });
+    }
+}
+
+fn mark_footnotes(placed: &mut [PlacedLine]) {
+    let found = {
+        let texts: Vec<&str> = placed
+            .iter()
+            .map(|line| match line.figure {
+                Some(_) => "",
+                None => line.text.as_str(),
+            })
+            .collect();
+        links::footnote_spans(&texts)
+    };
+    for (idx, span) in found {
+        let spans = &mut placed[idx].spans;
+        if !spans.iter().any(|existing| existing.overlaps(&span)) {
+            spans.push(span);
+        }
+    }
+}
+
+fn html_figures(html: &str, path: &Path, config: &EncoderConfig) -> (String, Vec<PageFigure>) {
+    if !config.figures.extract {
+        return (html.to_string(), Vec::new());
+    }
+    figures::extract_html_figures(html, path.parent(), &config.figures)
+}
+
+/// Adds what the text layer leaves out: link annotations and, when enabled,
+/// images painted on each page matched with "Figure N" caption lines.
+fn attach_pdf_objects(path: &Path, pages: &mut [PageBuffer], config: &EncoderConfig) {
+    let doc = match lopdf::Document::load(path) {
+        Ok(doc) => doc,
+        Err(err) => {
+            tracing::warn!("links and figures skipped for {}: {err}", path.display());
+            return;
+        }
+    };
+    // `get_pages` is keyed by 1-based page number.
+    let page_ids = doc.get_pages();
+    let page_index: HashMap<_, _> = page_ids
+        .iter()
+        .map(|(number, id)| (*id, number.saturating_sub(1)))
+        .collect();
+    for page in pages.iter_mut() {
+        let Some(&page_id) = page_ids.get(&(page.index + 1)) else {
+            continue;
+        };
+        let annotations = links::pdf_page_links(&doc, page_id, &page_index);
+        if !annotations.is_empty() {
+            page.links = Arc::new(SourceLinks {
+                links: annotations,
+                anchors: Vec::new(),
+            });
+        }
+        if !config.figures.extract {
+            continue;
+        }
+        let mut found = figures::pdf_page_figures(
+            &doc,
+            page_id,
+            page.width_px,
+            page.height_px,
+            &config.figures,
+        );
+        figures::attach_captions(&page.lines, page.height_px, &mut found);
+        page.figures = found;
+    }
+}
+
+/// Figures with a known position (PDF images) are placed where they were drawn.
+fn place_boxed_figures(figures: &[PageFigure]) -> Vec<PlacedLine> {
+    figures
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, figure)| {
+            let bbox = figure.bbox?;
+            Some(PlacedLine {
+                text: String::new(),
+                x: bbox.left as i32,
+                y: bbox.top as i32,
+                w: bbox.width,
+                h: bbox.height,
+                ocr_confidence: None,
+                spans: Vec::new(),
+                anchors: Vec::new(),
+                figure: Some(idx),
+                equation: false,
+            })
+        })
+        .collect()
+}
+
 fn fallback_pdf_to_pages(path: &Path, config: &EncoderConfig) -> Result<Vec<PageBuffer>> {
     let pages = pdf_extract::extract_text_by_pages(path)
         .map_err(|e| DcfError::Other(format!("pdf extract failed: {e}")))?;
@@ -517,7 +1298,7 @@ fn pdfium_pdf_to_pages(path: &Path, config: &EncoderConfig) -> Result<Vec<PageBu
         .map_err(|e| DcfError::Other(format!("pdfium load failed: {e}")))?;
     let mut buffers = Vec::new();
     for (idx, page) in document.pages().iter().enumerate() {
-        let mut page_text = page.text().ok().map(|t| t.all()).unwrap_or_default();
+        let page_text = page.text().ok().map(|t| t.all()).unwrap_or_default();
         let mut should_ocr = config.force_ocr;
         if !should_ocr {
             let trimmed = page_text.trim();
@@ -544,17 +1325,34 @@ fn pdfium_pdf_to_pages(path: &Path, config: &EncoderConfig) -> Result<Vec<PageBu
                 .render_with_config(&render_config)
                 .map_err(|e| DcfError::Other(format!("pdf render failed: {e}")))?;
             let image = render.as_image();
-            page_text = crate::ocr::image_to_text(&image, &config.ocr_languages)?;
+            let ocr = recognize_scan(&image, config)?;
+            buffers.push(PageBuffer::from_ocr(idx as u32, ocr, config));
+            continue;
         }
         buffers.push(PageBuffer::from_text(idx as u32, &page_text, config));
     }
     Ok(buffers)
 }
 
-fn markdown_to_text(md: &str) -> String {
+fn markdown_to_html(md: &str) -> String {
     let mut html_buf = String::new();
-    html::push_html(&mut html_buf, Parser::new(md));
-    html_to_plaintext(&html_buf)
+    html::push_html(&mut html_buf, Parser::new_ext(md, markdown_options(md)));
+    html_buf
+}
+
+/// Footnotes and `{#id}` heading attributes are only parsed in Markdown that
+/// uses them, so other input renders exactly as plain CommonMark.
+fn markdown_options(md: &str) -> Options {
+    let mut options = Options::empty();
+    for line in md.lines().map(str::trim) {
+        if line.starts_with("[^") && line.contains("]:") {
+            options |= Options::ENABLE_FOOTNOTES;
+        }
+        if line.starts_with('#') && line.ends_with('}') && line.contains("{#") {
+            options |= Options::ENABLE_HEADING_ATTRIBUTES;
+        }
+    }
+    options
 }
 
 fn html_to_plaintext(html_src: &str) -> String {
@@ -607,8 +1405,47 @@ fn ocr_image_to_pages(image: DynamicImage, config: &EncoderConfig) -> Result<Vec
     if !config.enable_ocr {
         return Err(DcfError::OcrSupportDisabled);
     }
-    let text = crate::ocr::image_to_text(&image, &config.ocr_languages)?;
-    Ok(text_to_pages(&text, config))
+    let ocr = recognize_scan(&image, config)?;
+    Ok(vec![PageBuffer::from_ocr(0, ocr, config)])
+}
+
+#[cfg(any(feature = "ocr", feature = "pdfium"))]
+fn recognize_scan(image: &DynamicImage, config: &EncoderConfig) -> Result<OcrPage> {
+    if !config.preprocess.is_enabled() {
+        return recognize_with_language(image, config);
+    }
+    let (prepared, correction) = preprocess(image, &config.preprocess);
+    tracing::debug!(
+        rotation = correction.rotation,
+        skew = correction.skew_degrees,
+        specks = correction.specks_removed,
+        "scan preprocessed"
+    );
+    recognize_with_language(&DynamicImage::ImageLuma8(prepared), config)
+}
+
+/// With several OCR languages configured, a second pass restricted to the
+/// language detected on the page is kept when Tesseract is more confident in it.
+#[cfg(any(feature = "ocr", feature = "pdfium"))]
+fn recognize_with_language(image: &DynamicImage, config: &EncoderConfig) -> Result<OcrPage> {
+    let page = crate::ocr::recognize(image, &config.ocr_languages)?;
+    if !config.detect_language || config.ocr_languages.len() < 2 {
+        return Ok(page);
+    }
+    let Some(langs) = ocr_languages_for(&page.text(), &config.ocr_languages) else {
+        return Ok(page);
+    };
+    if langs == config.ocr_languages {
+        return Ok(page);
+    }
+    let focused = crate::ocr::recognize(image, &langs)?;
+    let confidence = |page: &OcrPage| page.mean_confidence().unwrap_or(0.0);
+    tracing::debug!(langs = %langs.join("+"), "page OCR language narrowed");
+    if confidence(&focused) >= confidence(&page) {
+        Ok(focused)
+    } else {
+        Ok(page)
+    }
 }
 
 #[cfg(not(feature = "ocr"))]
@@ -20,12 +20,26 @@ pub enum DcfError {
     UnsupportedInput(PathBuf),
     #[error("invalid document: {0}")]
     InvalidDocument(&'static str),
+    #[error("unsupported format version {found} (this build reads up to {supported})")]
+    UnsupportedVersion { found: u32, supported: u32 },
+    #[error("invalid {field}: expected {expected} bytes, found {found}")]
+    InvalidLength {
+        field: &'static str,
+        expected: usize,
+        found: usize,
+    },
+    #[error("unknown {field} value {value}")]
+    UnknownEnumValue { field: &'static str, value: i32 },
+    #[error("corrupt container: {0}")]
+    CorruptContainer(&'static str),
     #[error("unknown preset: {0}")]
     UnknownPreset(String),
     #[error("tokenizer error: {0}")]
     Tokenizer(String),
     #[error("benchmark error: {0}")]
     Bench(String),
+    #[error("integrity check failed: {0}")]
+    Integrity(String),
     #[error("other: {0}")]
     Other(String),
 }
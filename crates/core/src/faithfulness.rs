@@ -0,0 +1,306 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::document::{CellRecord, Document, NumGuard};
+use crate::error::DcfError;
+use crate::numguard::{extract_entities, extract_entity_spans, NumericEntity, NumericKind};
+
+/// What to do with numbers in generated text that no source cell supports.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FaithfulnessPolicy {
+    /// Skip the check entirely.
+    Off,
+    /// Report unsupported numbers but keep the text unchanged.
+    #[default]
+    Flag,
+    /// Replace unsupported numbers (RAG answers) or discard the sample (datasets).
+    Drop,
+}
+
+impl FromStr for FaithfulnessPolicy {
+    type Err = DcfError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(Self::Off),
+            "flag" => Ok(Self::Flag),
+            "drop" => Ok(Self::Drop),
+            other => Err(DcfError::Other(format!(
+                "unknown faithfulness policy '{other}' (expected off, flag, or drop)"
+            ))),
+        }
+    }
+}
+
+/// One numeric mention found in generated text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NumericClaim {
+    pub entity: NumericEntity,
+    /// Byte offsets of the mention in the verified text.
+    pub start: usize,
+    pub end: usize,
+    pub supported: bool,
+}
+
+/// Outcome of checking generated text against its sources.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FaithfulnessReport {
+    pub claims: Vec<NumericClaim>,
+}
+
+impl FaithfulnessReport {
+    /// Share of numeric claims backed by a source; `1.0` when the text has none.
+    pub fn score(&self) -> f32 {
+        if self.claims.is_empty() {
+            return 1.0;
+        }
+        self.supported_count() as f32 / self.claims.len() as f32
+    }
+
+    pub fn supported_count(&self) -> usize {
+        self.claims.iter().filter(|claim| claim.supported).count()
+    }
+
+    pub fn unsupported(&self) -> impl Iterator<Item = &NumericClaim> {
+        self.claims.iter().filter(|claim| !claim.supported)
+    }
+
+    /// Source spellings of the unsupported numbers, in order of appearance.
+    pub fn unsupported_raw(&self) -> Vec<String> {
+        self.unsupported()
+            .map(|claim| claim.entity.raw.clone())
+            .collect()
+    }
+
+    pub fn is_faithful(&self) -> bool {
+        self.claims.iter().all(|claim| claim.supported)
+    }
+
+    /// Rewrites `text` (the string this report was produced from) with every
+    /// unsupported number replaced by `marker`.
+    pub fn redact(&self, text: &str, marker: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for claim in self.unsupported() {
+            if claim.start < cursor || claim.end > text.len() {
+                continue;
+            }
+            out.push_str(&text[cursor..claim.start]);
+            out.push_str(marker);
+            cursor = claim.end;
+        }
+        out.push_str(&text[cursor..]);
+        out
+    }
+}
+
+/// Checks that numbers in LLM output appear in the cells it was generated from.
+///
+/// Matching is lenient in the ways paraphrase usually is: a plain number may
+/// stand in for a currency or unit value, rounding to the precision written in
+/// the answer is accepted (`$1.2M` for `$1,234,567`), and a bare year matches
+/// any source date in that year. Small integers are ignored by default because
+/// they are mostly list markers and counts ("3 reasons").
+#[derive(Debug, Clone)]
+pub struct NumericVerifier {
+    pub rel_tolerance: f64,
+    pub allow_rounding: bool,
+    /// Plain integers with absolute value up to this bound are not checked.
+    pub ignore_small_integers: u32,
+}
+
+impl Default for NumericVerifier {
+    fn default() -> Self {
+        Self {
+            rel_tolerance: 1e-6,
+            allow_rounding: true,
+            ignore_small_integers: 10,
+        }
+    }
+}
+
+impl NumericVerifier {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_tolerance(mut self, rel_tolerance: f64) -> Self {
+        self.rel_tolerance = rel_tolerance;
+        self
+    }
+
+    /// Verifies `text` against already extracted source entities.
+    pub fn verify(&self, text: &str, sources: &[NumericEntity]) -> FaithfulnessReport {
+        let claims = extract_entity_spans(text)
+            .into_iter()
+            .filter(|(_, entity)| !self.is_ignored(entity))
+            .map(|(range, entity)| {
+                let supported = sources.iter().any(|source| self.supports(source, &entity));
+                NumericClaim {
+                    entity,
+                    start: range.start,
+                    end: range.end,
+                    supported,
+                }
+            })
+            .collect();
+        FaithfulnessReport { claims }
+    }
+
+    /// Verifies `text` against raw source passages, extracting their numbers first.
+    pub fn verify_against_text<'a, I>(&self, text: &str, sources: I) -> FaithfulnessReport
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let entities: Vec<NumericEntity> = sources.into_iter().flat_map(extract_entities).collect();
+        self.verify(text, &entities)
+    }
+
+    /// Verifies `text` against typed NumGuards. Legacy guards without an entity are skipped.
+    pub fn verify_guards(&self, text: &str, guards: &[NumGuard]) -> FaithfulnessReport {
+        let entities: Vec<NumericEntity> = guards
+            .iter()
+            .filter_map(|guard| guard.entity.clone())
+            .collect();
+        self.verify(text, &entities)
+    }
+
+    /// Verifies `text` against the guards of `cells` in `doc`, falling back to the
+    /// cell payload for cells written before guards carried typed values.
+    pub fn verify_cells(
+        &self,
+        text: &str,
+        doc: &Document,
+        cells: &[CellRecord],
+    ) -> FaithfulnessReport {
+        // Guards are positioned by (z, x, y); `CellRecord::key` is (z, y, x).
+        let keys: HashSet<(u32, i32, i32)> =
+            cells.iter().map(|cell| (cell.z, cell.x, cell.y)).collect();
+        let mut typed = HashSet::new();
+        let mut entities = Vec::new();
+        for guard in &doc.numguards {
+            let key = (guard.z, guard.x as i32, guard.y as i32);
+            if !keys.contains(&key) {
+                continue;
+            }
+            if let Some(entity) = &guard.entity {
+                typed.insert(key);
+                entities.push(entity.clone());
+            }
+        }
+        for cell in cells {
+            if typed.contains(&(cell.z, cell.x, cell.y)) {
+                continue;
+            }
+            if let Some(payload) = doc.payload_for(&cell.code_id) {
+                entities.extend(extract_entities(payload));
+            }
+        }
+        self.verify(text, &entities)
+    }
+
+    fn is_ignored(&self, entity: &NumericEntity) -> bool {
+        entity.kind == NumericKind::Plain
+            && entity.value.fract() == 0.0
+            && entity.value.abs() <= f64::from(self.ignore_small_integers)
+    }
+
+    fn supports(&self, source: &NumericEntity, claim: &NumericEntity) -> bool {
+        if claim.matches(source, self.rel_tolerance) {
+            return true;
+        }
+        if claim.kind == NumericKind::Plain && source.kind == NumericKind::Date {
+            return source
+                .year()
+                .is_some_and(|year| f64::from(year) == claim.value);
+        }
+        if !self.allow_rounding {
+            return false;
+        }
+        let tolerance = claim.rounding_tolerance().max(self.rel_tolerance);
+        claim.approximates(source, tolerance)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paraphrased_numbers_are_supported() {
+        let verifier = NumericVerifier::new();
+        let report = verifier.verify_against_text(
+            "Revenue reached 1.2 million USD, up 5% since March 2023.",
+            ["Revenue: $1,234,567 (+5.0%) as of 2023-03-31."],
+        );
+        assert_eq!(report.claims.len(), 3, "{:?}", report.claims);
+        assert!(report.is_faithful(), "{:?}", report.unsupported_raw());
+        assert_eq!(report.score(), 1.0);
+    }
+
+    #[test]
+    fn invented_numbers_are_flagged_and_redacted() {
+        let verifier = NumericVerifier::new();
+        let answer = "Net income was $4.1M on 12 kg of output and 7 items.";
+        let report = verifier.verify_against_text(answer, ["Net income $4,100,000; 3 kg"]);
+        assert_eq!(report.unsupported_raw(), vec!["12 kg".to_string()]);
+        assert!((report.score() - 0.5).abs() < 1e-6);
+        assert_eq!(
+            report.redact(answer, "[unverified]"),
+            "Net income was $4.1M on [unverified] of output and 7 items."
+        );
+    }
+
+    #[test]
+    fn rounding_is_bounded_by_the_stated_precision() {
+        let verifier = NumericVerifier::new();
+        let supported =
+            |claim: &str, source: &str| verifier.verify_against_text(claim, [source]).is_faithful();
+        assert!(supported("about 1 million users", "1,040,000 users"));
+        assert!(!supported("about 1 million users", "1,600,000 users"));
+        assert!(!supported("about 1 million users", "600,000 users"));
+        assert!(supported("roughly 1.6 million", "1,630,000"));
+        assert!(!supported("roughly 1.6 million", "1,700,000"));
+    }
+
+    #[test]
+    fn cell_guards_are_found_by_position() {
+        use crate::document::{CellType, Header};
+        use crate::numguard::extract_guards;
+
+        let mut doc = Document::new(Header::default());
+        let cell = doc.push_fixture_cell(0, 200, CellType::Text, "Revenue was $4.2M.");
+        cell.importance = 128;
+        let cell = cell.clone();
+        // A typed guard that disagrees with the payload shows which one was used.
+        doc.numguards = extract_guards("Revenue was $5.1M.", 0, 64, 200);
+
+        let cells = [cell];
+        let verifier = NumericVerifier::new();
+        assert!(verifier
+            .verify_cells("It made $5.1M.", &doc, &cells)
+            .is_faithful());
+        assert!(!verifier
+            .verify_cells("It made $4.2M.", &doc, &cells)
+            .is_faithful());
+    }
+
+    #[test]
+    fn text_without_numbers_is_fully_faithful() {
+        let report = NumericVerifier::new().verify("No figures here.", &[]);
+        assert!(report.claims.is_empty());
+        assert_eq!(report.score(), 1.0);
+    }
+
+    #[test]
+    fn policy_parses_from_str() {
+        assert_eq!(
+            "Drop".parse::<FaithfulnessPolicy>().unwrap(),
+            FaithfulnessPolicy::Drop
+        );
+        assert!("maybe".parse::<FaithfulnessPolicy>().is_err());
+    }
+}
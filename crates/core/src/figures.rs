@@ -0,0 +1,1033 @@
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{Cursor, Read};
+use std::path::Path;
+
+use base64::Engine;
+use image::{DynamicImage, GrayImage, ImageFormat, RgbImage};
+use lopdf::{Object, ObjectId};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{DcfError, Result};
+use crate::ocr::OcrBox;
+
+/// Directory, relative to the container, that holds figure sidecar files.
+pub const FIGURE_DIR: &str = "figures";
+
+/// Wraps figure placeholders in text-flow inputs (HTML, Markdown, DOCX).
+pub(crate) const MARKER_DELIM: char = '\u{F8FF}';
+
+/// Images covering more of the page than this are scans or backgrounds, not figures.
+const FULL_PAGE_AREA: f32 = 0.9;
+
+static FIGURE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<figure\b[^>]*>(.*?)</figure\s*>").unwrap());
+static FIGCAPTION_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<figcaption\b[^>]*>(.*?)</figcaption\s*>").unwrap());
+static IMG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<img\b[^>]*>").unwrap());
+static ATTR_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)([a-z_][a-z0-9_:.-]*)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap()
+});
+static TAG_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?s)<[^>]*>").unwrap());
+static CAPTION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?i)^(figure|fig\.|chart|exhibit|graph|image|plate)\s*[0-9ivxlc]+[a-z]?\b")
+        .unwrap()
+});
+static PAYLOAD_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^!\[((?:[^\]\\]|\\.)*)\]\(([^)\s]+)\)").unwrap());
+static DOCX_PARAGRAPH_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<w:p\b[^>]*/>|<w:p\b[^>]*>.*?</w:p>").unwrap());
+static DOCX_STYLE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"<w:pStyle\b[^>]*w:val="([^"]*)""#).unwrap());
+static DOCX_TOKEN_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)<w:t\b[^>]*/>|<w:t(?:\s[^>]*)?>(.*?)</w:t>|<w:tab\b[^>]*/>|<w:br\b[^>]*/>|<wp:docPr\b[^>]*>|<a:blip\b[^>]*>",
+    )
+    .unwrap()
+});
+static DOCX_RELATIONSHIP_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?s)<Relationship\b[^>]*>").unwrap());
+
+/// Controls extraction of embedded images as Figure cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FigureConfig {
+    /// Extract images from PDF, DOCX, HTML and Markdown inputs.
+    pub extract: bool,
+    /// Run OCR over extracted images so chart labels become searchable.
+    pub ocr: bool,
+    /// Images smaller than this (in source pixels) on either side are ignored.
+    pub min_side_px: u32,
+}
+
+impl Default for FigureConfig {
+    fn default() -> Self {
+        Self {
+            extract: false,
+            ocr: false,
+            min_side_px: 32,
+        }
+    }
+}
+
+/// Encoded image bytes as they will be written to the sidecar directory.
+#[derive(Debug, Clone)]
+pub struct FigureImage {
+    pub bytes: Vec<u8>,
+    pub extension: &'static str,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FigureImage {
+    /// Sniffs the format and dimensions; `None` for data the image crate can't read.
+    pub fn from_bytes(bytes: Vec<u8>) -> Option<Self> {
+        let extension = match image::guess_format(&bytes).ok()? {
+            ImageFormat::Png => "png",
+            ImageFormat::Jpeg => "jpg",
+            ImageFormat::Gif => "gif",
+            ImageFormat::Tiff => "tif",
+            ImageFormat::Bmp => "bmp",
+            _ => return None,
+        };
+        let (width, height) = image::ImageReader::new(Cursor::new(&bytes))
+            .with_guessed_format()
+            .ok()?
+            .into_dimensions()
+            .ok()?;
+        Some(Self {
+            bytes,
+            extension,
+            width,
+            height,
+        })
+    }
+
+    /// Encodes decoded pixels as PNG.
+    pub fn from_image(image: &DynamicImage) -> Option<Self> {
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .ok()?;
+        Some(Self {
+            bytes,
+            extension: "png",
+            width: image.width(),
+            height: image.height(),
+        })
+    }
+
+    /// Content-addressed file name: the BLAKE3 hash of the bytes plus the extension.
+    pub fn file_name(&self) -> String {
+        format!("{}.{}", blake3::hash(&self.bytes).to_hex(), self.extension)
+    }
+
+    pub fn relative_path(&self) -> String {
+        format!("{FIGURE_DIR}/{}", self.file_name())
+    }
+
+    pub fn decode(&self) -> Option<DynamicImage> {
+        image::load_from_memory(&self.bytes).ok()
+    }
+
+    fn is_large_enough(&self, config: &FigureConfig) -> bool {
+        self.width >= config.min_side_px && self.height >= config.min_side_px
+    }
+}
+
+/// An image found on a page, before it becomes a Figure cell.
+#[derive(Debug, Clone)]
+pub struct PageFigure {
+    pub image: FigureImage,
+    /// Position on the page in pixels. Figures without a box are laid out in
+    /// the text flow where their marker line appears.
+    pub bbox: Option<OcrBox>,
+    pub caption: Option<String>,
+    pub alt: Option<String>,
+    /// Id of the placeholder line standing in for this figure in the page text.
+    pub marker: Option<usize>,
+}
+
+impl PageFigure {
+    fn in_flow(image: FigureImage, marker: usize) -> Self {
+        Self {
+            image,
+            bbox: None,
+            caption: None,
+            alt: None,
+            marker: Some(marker),
+        }
+    }
+
+    /// Caption if one was found, otherwise the alt text.
+    pub fn label(&self) -> Option<&str> {
+        self.caption.as_deref().or(self.alt.as_deref())
+    }
+}
+
+/// What a Figure cell's payload points at.
+///
+/// Payloads are a Markdown image (`![caption](figures/<blake3>.png)`) followed
+/// by any OCR text on the next line, so they stay readable in text output and
+/// searchable in the index.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FigureRef {
+    /// Sidecar path relative to the container.
+    pub path: String,
+    pub caption: Option<String>,
+    /// Text recognised inside the image.
+    pub text: Option<String>,
+}
+
+impl FigureRef {
+    pub fn to_payload(&self) -> String {
+        let caption = self
+            .caption
+            .as_deref()
+            .unwrap_or_default()
+            .replace('\\', "\\\\")
+            .replace(']', "\\]");
+        let mut payload = format!("![{caption}]({})", self.path);
+        if let Some(text) = self.text.as_deref().filter(|text| !text.is_empty()) {
+            payload.push('\n');
+            payload.push_str(text);
+        }
+        payload
+    }
+
+    pub fn parse(payload: &str) -> Option<Self> {
+        let (head, rest) = match payload.split_once('\n') {
+            Some((head, rest)) => (head, Some(rest)),
+            None => (payload, None),
+        };
+        let caps = PAYLOAD_RE.captures(head.trim())?;
+        let caption = unescape_caption(&caps[1]);
+        Some(Self {
+            path: caps[2].to_string(),
+            caption: (!caption.is_empty()).then_some(caption),
+            text: rest
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(str::to_string),
+        })
+    }
+
+    /// Caption and OCR text, i.e. the part of the payload worth searching.
+    pub fn searchable_text(&self) -> String {
+        [self.caption.as_deref(), self.text.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+fn unescape_caption(raw: &str) -> String {
+    let mut out = String::with_capacity(raw.len());
+    let mut chars = raw.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\\' {
+            if let Some(next) = chars.next() {
+                out.push(next);
+            }
+        } else {
+            out.push(ch);
+        }
+    }
+    out
+}
+
+/// Writes sidecar files under `dir`, skipping ones that already exist. Returns
+/// the number of files written.
+pub fn write_sidecars<'a, I>(dir: &Path, files: I) -> Result<usize>
+where
+    I: IntoIterator<Item = (&'a String, &'a Vec<u8>)>,
+{
+    let mut written = 0;
+    for (relative, bytes) in files {
+        let target = dir.join(relative);
+        if target.exists() {
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&target, bytes)?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+pub(crate) fn marker(id: usize) -> String {
+    format!("{MARKER_DELIM}figure:{id}{MARKER_DELIM}")
+}
+
+pub(crate) fn parse_marker(line: &str) -> Option<usize> {
+    line.trim()
+        .strip_prefix(MARKER_DELIM)?
+        .strip_suffix(MARKER_DELIM)?
+        .strip_prefix("figure:")?
+        .parse()
+        .ok()
+}
+
+/// Matches caption lines ("Figure 3: ...") on a page to the nearest figure
+/// above or below them. The text layer has no coordinates, so a line's height
+/// is estimated from its position among the page's lines; captions further
+/// than [`MAX_CAPTION_GAP`] of the page height from every figure stay text.
+pub(crate) fn attach_captions(lines: &[String], page_height: u32, figures: &mut [PageFigure]) {
+    figures.sort_by_key(|figure| figure.bbox.map(|b| (b.top, b.left)).unwrap_or_default());
+    let height = page_height.max(1) as f32;
+    let mut captions: Vec<(f32, &str)> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| is_caption(line.trim()))
+        .map(|(idx, line)| {
+            let y = (idx as f32 + 0.5) / lines.len() as f32 * height;
+            (y, line.trim())
+        })
+        .collect();
+    for figure in figures.iter_mut().filter(|f| f.caption.is_none()) {
+        let Some(bbox) = figure.bbox else {
+            continue;
+        };
+        let gap = |y: f32| {
+            if y < bbox.top as f32 {
+                bbox.top as f32 - y
+            } else {
+                (y - bbox.bottom() as f32).max(0.0)
+            }
+        };
+        let nearest = captions
+            .iter()
+            .enumerate()
+            .map(|(idx, (y, _))| (idx, gap(*y)))
+            .filter(|(_, gap)| *gap <= MAX_CAPTION_GAP * height)
+            .min_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((idx, _)) = nearest {
+            figure.caption = Some(captions.remove(idx).1.to_string());
+        }
+    }
+}
+
+/// Largest distance between a figure and its caption, as a fraction of the page height.
+const MAX_CAPTION_GAP: f32 = 0.15;
+
+fn is_caption(line: &str) -> bool {
+    CAPTION_RE.is_match(line)
+}
+
+/// Replaces `<figure>` blocks and standalone `<img>` tags whose image can be
+/// loaded with marker paragraphs. Remote images, and local files outside
+/// `base_dir` (or any local file without one), are left untouched.
+pub(crate) fn extract_html_figures(
+    html: &str,
+    base_dir: Option<&Path>,
+    config: &FigureConfig,
+) -> (String, Vec<PageFigure>) {
+    let mut figures = Vec::new();
+    let with_figures = FIGURE_RE.replace_all(html, |caps: &regex::Captures| {
+        let inner = &caps[1];
+        let Some(img) = IMG_RE.find(inner) else {
+            return caps[0].to_string();
+        };
+        let attrs = parse_attrs(img.as_str());
+        let Some(image) = load_html_image(&attrs, base_dir, config) else {
+            return caps[0].to_string();
+        };
+        let id = figures.len();
+        let mut figure = PageFigure::in_flow(image, id);
+        figure.caption = FIGCAPTION_RE
+            .captures(inner)
+            .map(|c| clean_html_text(&c[1]))
+            .filter(|c| !c.is_empty());
+        figure.alt = attrs.get("alt").cloned().filter(|a| !a.is_empty());
+        figures.push(figure);
+        format!("<p>{}</p>", marker(id))
+    });
+    let with_images = IMG_RE.replace_all(&with_figures, |caps: &regex::Captures| {
+        let attrs = parse_attrs(&caps[0]);
+        let Some(image) = load_html_image(&attrs, base_dir, config) else {
+            return caps[0].to_string();
+        };
+        let id = figures.len();
+        let mut figure = PageFigure::in_flow(image, id);
+        figure.caption = attrs.get("title").cloned().filter(|t| !t.is_empty());
+        figure.alt = attrs.get("alt").cloned().filter(|a| !a.is_empty());
+        figures.push(figure);
+        format!("<p>{}</p>", marker(id))
+    });
+    (with_images.into_owned(), figures)
+}
+
+pub(crate) fn parse_attrs(tag: &str) -> HashMap<String, String> {
+    ATTR_RE
+        .captures_iter(tag)
+        .map(|caps| {
+            let value = caps
+                .get(2)
+                .or_else(|| caps.get(3))
+                .map_or("", |m| m.as_str());
+            (caps[1].to_ascii_lowercase(), unescape_entities(value))
+        })
+        .collect()
+}
+
+fn load_html_image(
+    attrs: &HashMap<String, String>,
+    base_dir: Option<&Path>,
+    config: &FigureConfig,
+) -> Option<FigureImage> {
+    let src = attrs.get("src")?.trim();
+    let bytes = if let Some(data) = src.strip_prefix("data:") {
+        let (meta, body) = data.split_once(',')?;
+        if !meta.ends_with(";base64") {
+            return None;
+        }
+        base64::engine::general_purpose::STANDARD
+            .decode(body.trim())
+            .ok()?
+    } else if src.contains("://") && !src.starts_with("file://") {
+        return None;
+    } else {
+        let local = src.trim_start_matches("file://");
+        let local = local.split(['?', '#']).next().unwrap_or(local);
+        let base = match base_dir? {
+            dir if dir.as_os_str().is_empty() => Path::new("."),
+            dir => dir,
+        };
+        // Absolute paths and `..` must not pull arbitrary files into the container.
+        let base = fs::canonicalize(base).ok()?;
+        let path = fs::canonicalize(base.join(local)).ok()?;
+        if !path.starts_with(&base) {
+            return None;
+        }
+        fs::read(path).ok()?
+    };
+    FigureImage::from_bytes(bytes).filter(|image| image.is_large_enough(config))
+}
+
+pub(crate) fn clean_html_text(fragment: &str) -> String {
+    let stripped = TAG_RE.replace_all(fragment, " ");
+    unescape_entities(&stripped)
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub(crate) fn unescape_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        let tail = &rest[start..];
+        let Some(end) = tail.find(';').filter(|end| *end <= 10) else {
+            out.push('&');
+            rest = &tail[1..];
+            continue;
+        };
+        let entity = &tail[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some(' '),
+            _ => entity
+                .strip_prefix("#x")
+                .or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|dec| dec.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(ch) => {
+                out.push(ch);
+                rest = &tail[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &tail[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Flattens a DOCX body to text, one paragraph per line and `\f` at page
+/// breaks. With extraction enabled, inline drawings become marker lines and
+/// their figures carry the alt text and the adjacent caption paragraph.
+pub(crate) fn docx_to_text(
+    path: &Path,
+    config: &FigureConfig,
+) -> Result<(String, Vec<PageFigure>)> {
+    let file = File::open(path)?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| DcfError::Other(format!("failed to open docx {}: {e}", path.display())))?;
+    let document = read_zip_text(&mut archive, "word/document.xml")?
+        .ok_or_else(|| DcfError::Other(format!("{} has no word/document.xml", path.display())))?;
+    let relationships = if config.extract {
+        read_zip_text(&mut archive, "word/_rels/document.xml.rels")?
+            .map(|rels| parse_relationships(&rels))
+            .unwrap_or_default()
+    } else {
+        HashMap::new()
+    };
+
+    let mut text = String::new();
+    let mut figures: Vec<PageFigure> = Vec::new();
+    // Figures from the previous paragraph still waiting for a caption below them.
+    let mut uncaptioned: Vec<usize> = Vec::new();
+    let mut pending_caption: Option<String> = None;
+    for paragraph in DOCX_PARAGRAPH_RE.find_iter(&document) {
+        let paragraph = paragraph.as_str();
+        let mut line = String::new();
+        let mut alt: Option<String> = None;
+        let mut found = Vec::new();
+        for token in DOCX_TOKEN_RE.captures_iter(paragraph) {
+            let raw = token.get(0).map_or("", |m| m.as_str());
+            if let Some(run) = token.get(1) {
+                line.push_str(&unescape_entities(run.as_str()));
+            } else if raw.starts_with("<w:tab") {
+                line.push('\t');
+            } else if raw.starts_with("<w:br") {
+                if raw.contains("w:type=\"page\"") {
+                    line.push('\u{c}');
+                } else {
+                    line.push('\n');
+                }
+            } else if raw.starts_with("<wp:docPr") {
+                let attrs = parse_attrs(raw);
+                alt = attrs
+                    .get("descr")
+                    .or_else(|| attrs.get("title"))
+                    .cloned()
+                    .filter(|a| !a.is_empty());
+            } else if raw.starts_with("<a:blip") && config.extract {
+                let attrs = parse_attrs(raw);
+                let target = attrs.get("r:embed").and_then(|id| relationships.get(id));
+                let image = target.and_then(|target| read_docx_image(&mut archive, target, config));
+                if let Some(image) = image {
+                    let id = figures.len();
+                    let mut figure = PageFigure::in_flow(image, id);
+                    figure.alt = alt.take();
+                    figures.push(figure);
+                    found.push(id);
+                    line.push('\n');
+                    line.push_str(&marker(id));
+                    line.push('\n');
+                }
+            }
+        }
+
+        let style = DOCX_STYLE_RE
+            .captures(paragraph)
+            .map(|c| c[1].to_ascii_lowercase())
+            .unwrap_or_default();
+        let trimmed = line.trim();
+        let caption_like = found.is_empty()
+            && !trimmed.is_empty()
+            && (style.contains("caption") || is_caption(trimmed));
+        if caption_like {
+            if uncaptioned.is_empty() {
+                pending_caption = Some(trimmed.to_string());
+            } else {
+                for id in uncaptioned.drain(..) {
+                    figures[id].caption = Some(trimmed.to_string());
+                }
+            }
+        } else {
+            if let Some(caption) = pending_caption.take() {
+                for id in &found {
+                    figures[*id].caption = Some(caption.clone());
+                }
+            }
+            uncaptioned = found;
+        }
+        if !trimmed.is_empty() || line.contains('\u{c}') {
+            text.push_str(&line);
+            text.push('\n');
+        }
+    }
+    Ok((text, figures))
+}
+
+fn read_zip_text(archive: &mut zip::ZipArchive<File>, name: &str) -> Result<Option<String>> {
+    let mut entry = match archive.by_name(name) {
+        Ok(entry) => entry,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(DcfError::Other(format!("failed to read {name}: {e}"))),
+    };
+    let mut contents = String::new();
+    entry.read_to_string(&mut contents)?;
+    Ok(Some(contents))
+}
+
+fn parse_relationships(xml: &str) -> HashMap<String, String> {
+    DOCX_RELATIONSHIP_RE
+        .find_iter(xml)
+        .filter_map(|tag| {
+            let attrs = parse_attrs(tag.as_str());
+            Some((attrs.get("id")?.clone(), attrs.get("target")?.clone()))
+        })
+        .collect()
+}
+
+fn read_docx_image(
+    archive: &mut zip::ZipArchive<File>,
+    target: &str,
+    config: &FigureConfig,
+) -> Option<FigureImage> {
+    let name = match target.strip_prefix('/') {
+        Some(absolute) => absolute.to_string(),
+        None => format!("word/{target}"),
+    };
+    let mut entry = archive.by_name(&name).ok()?;
+    let mut bytes = Vec::new();
+    entry.read_to_end(&mut bytes).ok()?;
+    FigureImage::from_bytes(bytes).filter(|image| image.is_large_enough(config))
+}
+
+type Matrix = [f32; 6];
+
+const IDENTITY: Matrix = [1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+
+/// Finds image XObjects painted on a PDF page and maps their placement into a
+/// `width_px` × `height_px` page. Images inside form XObjects are not followed.
+pub(crate) fn pdf_page_figures(
+    doc: &lopdf::Document,
+    page_id: ObjectId,
+    width_px: u32,
+    height_px: u32,
+    config: &FigureConfig,
+) -> Vec<PageFigure> {
+    let [mx0, my0, mx1, my1] = media_box(doc, page_id).unwrap_or([0.0, 0.0, 612.0, 792.0]);
+    let (page_w, page_h) = ((mx1 - mx0).abs().max(1.0), (my1 - my0).abs().max(1.0));
+    let (sx, sy) = (width_px as f32 / page_w, height_px as f32 / page_h);
+    let xobjects = page_xobjects(doc, page_id);
+    let Ok(content) = doc.get_and_decode_page_content(page_id) else {
+        return Vec::new();
+    };
+
+    let mut ctm = IDENTITY;
+    let mut stack = Vec::new();
+    let mut figures = Vec::new();
+    for op in content.operations {
+        match op.operator.as_str() {
+            "q" => stack.push(ctm),
+            "Q" => ctm = stack.pop().unwrap_or(IDENTITY),
+            "cm" => {
+                let values: Vec<f32> = op
+                    .operands
+                    .iter()
+                    .filter_map(|o| o.as_float().ok())
+                    .collect();
+                if let Ok(m) = <Matrix>::try_from(values.as_slice()) {
+                    ctm = multiply(&m, &ctm);
+                }
+            }
+            "Do" => {
+                let Some(id) = op
+                    .operands
+                    .first()
+                    .and_then(|o| o.as_name().ok())
+                    .and_then(|name| xobjects.get(name))
+                else {
+                    continue;
+                };
+                let Some(image) = pdf_image(doc, *id).filter(|i| i.is_large_enough(config)) else {
+                    continue;
+                };
+                let corners = [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| {
+                    (
+                        ctm[0] * x + ctm[2] * y + ctm[4],
+                        ctm[1] * x + ctm[3] * y + ctm[5],
+                    )
+                });
+                let xs = corners.map(|c| c.0);
+                let ys = corners.map(|c| c.1);
+                let min = |v: [f32; 4]| v.into_iter().fold(f32::INFINITY, f32::min);
+                let max = |v: [f32; 4]| v.into_iter().fold(f32::NEG_INFINITY, f32::max);
+                let (x0, x1, y0, y1) = (min(xs), max(xs), min(ys), max(ys));
+                if (x1 - x0) * (y1 - y0) >= FULL_PAGE_AREA * page_w * page_h {
+                    continue;
+                }
+                let left = ((x0 - mx0) * sx).clamp(0.0, width_px as f32);
+                let top = ((my1 - y1) * sy).clamp(0.0, height_px as f32);
+                let right = ((x1 - mx0) * sx).clamp(0.0, width_px as f32);
+                let bottom = ((my1 - y0) * sy).clamp(0.0, height_px as f32);
+                figures.push(PageFigure {
+                    image,
+                    bbox: Some(OcrBox {
+                        left: left.round() as u32,
+                        top: top.round() as u32,
+                        width: (right - left).round().max(1.0) as u32,
+                        height: (bottom - top).round().max(1.0) as u32,
+                    }),
+                    caption: None,
+                    alt: None,
+                    marker: None,
+                });
+            }
+            _ => {}
+        }
+    }
+    figures
+}
+
+fn multiply(m: &Matrix, ctm: &Matrix) -> Matrix {
+    [
+        m[0] * ctm[0] + m[1] * ctm[2],
+        m[0] * ctm[1] + m[1] * ctm[3],
+        m[2] * ctm[0] + m[3] * ctm[2],
+        m[2] * ctm[1] + m[3] * ctm[3],
+        m[4] * ctm[0] + m[5] * ctm[2] + ctm[4],
+        m[4] * ctm[1] + m[5] * ctm[3] + ctm[5],
+    ]
+}
+
+pub(crate) fn media_box(doc: &lopdf::Document, page_id: ObjectId) -> Option<[f32; 4]> {
+    let mut node = doc.get_dictionary(page_id).ok()?;
+    for _ in 0..32 {
+        if let Ok(array) = node.get_deref(b"MediaBox", doc).and_then(Object::as_array) {
+            let values: Vec<f32> = array.iter().filter_map(|o| o.as_float().ok()).collect();
+            return <[f32; 4]>::try_from(values.as_slice()).ok();
+        }
+        let parent = node.get(b"Parent").and_then(Object::as_reference).ok()?;
+        node = doc.get_dictionary(parent).ok()?;
+    }
+    None
+}
+
+fn page_xobjects(doc: &lopdf::Document, page_id: ObjectId) -> HashMap<Vec<u8>, ObjectId> {
+    let mut out = HashMap::new();
+    let Ok((inline, ids)) = doc.get_page_resources(page_id) else {
+        return out;
+    };
+    let dicts = inline
+        .into_iter()
+        .chain(ids.into_iter().filter_map(|id| doc.get_dictionary(id).ok()));
+    for resources in dicts {
+        let Ok(xobjects) = resources
+            .get_deref(b"XObject", doc)
+            .and_then(Object::as_dict)
+        else {
+            continue;
+        };
+        for (name, value) in xobjects.iter() {
+            if let Ok(id) = value.as_reference() {
+                out.entry(name.clone()).or_insert(id);
+            }
+        }
+    }
+    out
+}
+
+/// Re-encodes an image XObject: JPEG streams are kept as-is, 8-bit gray, RGB
+/// and CMYK rasters become PNG. Other encodings are skipped.
+fn pdf_image(doc: &lopdf::Document, id: ObjectId) -> Option<FigureImage> {
+    let stream = doc.get_object(id).ok()?.as_stream().ok()?;
+    let dict = &stream.dict;
+    if dict.get(b"Subtype").and_then(Object::as_name).ok()? != b"Image" {
+        return None;
+    }
+    let filters = stream.filters().unwrap_or_default();
+    if filters.last().map(String::as_str) == Some("DCTDecode") && filters.len() == 1 {
+        return FigureImage::from_bytes(stream.content.clone());
+    }
+    if filters
+        .iter()
+        .any(|f| !matches!(f.as_str(), "FlateDecode" | "LZWDecode" | "ASCII85Decode"))
+    {
+        return None;
+    }
+    let width = u32::try_from(dict.get(b"Width").and_then(Object::as_i64).ok()?).ok()?;
+    let height = u32::try_from(dict.get(b"Height").and_then(Object::as_i64).ok()?).ok()?;
+    if dict
+        .get(b"BitsPerComponent")
+        .and_then(Object::as_i64)
+        .unwrap_or(8)
+        != 8
+    {
+        return None;
+    }
+    let components = color_components(doc, dict.get_deref(b"ColorSpace", doc).ok()?)?;
+    let pixels = if filters.is_empty() {
+        stream.content.clone()
+    } else {
+        // lopdf refuses to decompress image streams, so decode a copy without the subtype.
+        let mut plain = stream.clone();
+        plain.dict.remove(b"Subtype");
+        plain.decompressed_content().ok()?
+    };
+    let len = width as usize * height as usize * components;
+    let pixels = pixels.get(..len)?.to_vec();
+    let image = match components {
+        1 => DynamicImage::ImageLuma8(GrayImage::from_raw(width, height, pixels)?),
+        3 => DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, pixels)?),
+        4 => {
+            let rgb = pixels
+                .chunks_exact(4)
+                .flat_map(|px| {
+                    let k = 255 - px[3] as u16;
+                    [0, 1, 2].map(|i| ((255 - px[i] as u16) * k / 255) as u8)
+                })
+                .collect();
+            DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, rgb)?)
+        }
+        _ => return None,
+    };
+    FigureImage::from_image(&image)
+}
+
+fn color_components(doc: &lopdf::Document, space: &Object) -> Option<usize> {
+    match space {
+        Object::Name(name) => match name.as_slice() {
+            b"DeviceGray" | b"CalGray" | b"G" => Some(1),
+            b"DeviceRGB" | b"CalRGB" | b"RGB" => Some(3),
+            b"DeviceCMYK" | b"CMYK" => Some(4),
+            _ => None,
+        },
+        Object::Array(items) if items.first()?.as_name().ok()? == b"ICCBased" => {
+            let profile = doc.get_object(items.get(1)?.as_reference().ok()?).ok()?;
+            let n = profile
+                .as_stream()
+                .ok()?
+                .dict
+                .get(b"N")
+                .and_then(Object::as_i64)
+                .ok()?;
+            usize::try_from(n).ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    use lopdf::content::{Content, Operation};
+    use lopdf::{dictionary, Stream};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(
+            width,
+            height,
+            image::Rgb([200, 40, 40]),
+        ));
+        FigureImage::from_image(&image).unwrap().bytes
+    }
+
+    #[test]
+    fn payload_roundtrips_caption_and_text() {
+        let reference = FigureRef {
+            path: "figures/abc.png".to_string(),
+            caption: Some("Figure 2: Revenue [USD]".to_string()),
+            text: Some("Q1 Q2 Q3".to_string()),
+        };
+        let payload = reference.to_payload();
+        assert_eq!(
+            payload,
+            "![Figure 2: Revenue [USD\\]](figures/abc.png)\nQ1 Q2 Q3"
+        );
+        assert_eq!(FigureRef::parse(&payload), Some(reference));
+        assert_eq!(FigureRef::parse("plain text"), None);
+    }
+
+    #[test]
+    fn captions_attach_to_nearby_figures_only() {
+        let boxed = |top: u32| PageFigure {
+            image: FigureImage::from_bytes(png(8, 8)).unwrap(),
+            bbox: Some(OcrBox {
+                left: 100,
+                top,
+                width: 400,
+                height: 200,
+            }),
+            caption: None,
+            alt: None,
+            marker: None,
+        };
+        // Ten lines over a 1000px page: line 3 sits at y=350, line 9 at y=950.
+        let mut lines: Vec<String> = (0..10).map(|idx| format!("Body line {idx}")).collect();
+        lines[3] = "Figure 1: Revenue by quarter".to_string();
+        lines[9] = "Figure 7 is discussed in the appendix".to_string();
+        let mut figures = vec![boxed(500), boxed(100)];
+        attach_captions(&lines, 1000, &mut figures);
+        assert_eq!(
+            figures[0].caption.as_deref(),
+            Some("Figure 1: Revenue by quarter")
+        );
+        assert_eq!(figures[1].caption, None);
+    }
+
+    #[test]
+    fn html_figures_keep_caption_and_alt() {
+        let data = base64::engine::general_purpose::STANDARD.encode(png(64, 48));
+        let html = format!(
+            "<p>Intro</p><figure><img src=\"data:image/png;base64,{data}\" alt=\"bar chart\">\
+             <figcaption>Figure 1: Sales &amp; margin</figcaption></figure>\
+             <img src=\"https://example.com/remote.png\"><img src=\"data:image/png;base64,{data}\" title=\"Logo\">"
+        );
+        let (rewritten, figures) = extract_html_figures(&html, None, &FigureConfig::default());
+        assert_eq!(figures.len(), 2);
+        assert_eq!(
+            figures[0].caption.as_deref(),
+            Some("Figure 1: Sales & margin")
+        );
+        assert_eq!(figures[0].alt.as_deref(), Some("bar chart"));
+        assert_eq!(figures[1].label(), Some("Logo"));
+        assert_eq!(figures[0].image.file_name(), figures[1].image.file_name());
+        assert!(rewritten.contains(&marker(0)) && rewritten.contains(&marker(1)));
+        assert!(rewritten.contains("remote.png"));
+        assert!(!rewritten.contains("figcaption"));
+    }
+
+    #[test]
+    fn html_images_outside_the_document_directory_are_not_read() {
+        let dir = tempfile::tempdir().unwrap();
+        let docs = dir.path().join("docs");
+        fs::create_dir_all(docs.join("img")).unwrap();
+        fs::write(docs.join("img/chart.png"), png(64, 48)).unwrap();
+        fs::write(dir.path().join("secret.png"), png(64, 48)).unwrap();
+        let secret = dir.path().join("secret.png");
+        let html = format!(
+            "<img src=\"img/chart.png\"><img src=\"../secret.png\">\
+             <img src=\"{}\"><img src=\"file://{}\"><img src=\"img/../../secret.png\">",
+            secret.display(),
+            secret.display()
+        );
+        let config = FigureConfig::default();
+        let (rewritten, figures) = extract_html_figures(&html, Some(&docs), &config);
+        assert_eq!(figures.len(), 1);
+        assert!(rewritten.contains(&marker(0)));
+        assert_eq!(rewritten.matches("secret.png").count(), 4);
+
+        let (_, figures) = extract_html_figures("<img src=\"img/chart.png\">", None, &config);
+        assert!(figures.is_empty());
+    }
+
+    #[test]
+    fn docx_drawings_become_figures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.docx");
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options = zip::write::FileOptions::default();
+        zip.start_file("word/document.xml", options).unwrap();
+        zip.write_all(
+            br#"<w:document><w:body>
+<w:p><w:r><w:t>Quarterly results</w:t></w:r></w:p>
+<w:p><w:r><w:drawing><wp:inline><wp:docPr id="1" name="Picture 1" descr="line chart"/><a:graphic><a:blip r:embed="rId7"/></a:graphic></wp:inline></w:drawing></w:r></w:p>
+<w:p><w:pPr><w:pStyle w:val="Caption"/></w:pPr><w:r><w:t xml:space="preserve">Revenue by quarter</w:t></w:r></w:p>
+<w:p><w:r><w:br w:type="page"/></w:r></w:p>
+<w:p><w:r><w:t>Appendix</w:t></w:r></w:p>
+</w:body></w:document>"#,
+        )
+        .unwrap();
+        zip.start_file("word/_rels/document.xml.rels", options)
+            .unwrap();
+        zip.write_all(br#"<Relationships><Relationship Id="rId7" Type="image" Target="media/image1.png"/></Relationships>"#)
+            .unwrap();
+        zip.start_file("word/media/image1.png", options).unwrap();
+        zip.write_all(&png(80, 40)).unwrap();
+        zip.finish().unwrap();
+
+        let config = FigureConfig {
+            extract: true,
+            ..FigureConfig::default()
+        };
+        let (text, figures) = docx_to_text(&path, &config).unwrap();
+        assert_eq!(figures.len(), 1);
+        assert_eq!(figures[0].caption.as_deref(), Some("Revenue by quarter"));
+        assert_eq!(figures[0].alt.as_deref(), Some("line chart"));
+        assert_eq!((figures[0].image.width, figures[0].image.height), (80, 40));
+        let lines: Vec<&str> = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .collect();
+        assert_eq!(lines[0], "Quarterly results");
+        assert_eq!(parse_marker(lines[1]), Some(0));
+        assert!(text.contains('\u{c}'));
+
+        let (plain, none) = docx_to_text(&path, &FigureConfig::default()).unwrap();
+        assert!(none.is_empty());
+        assert!(plain.contains("Appendix") && parse_marker(&plain).is_none());
+    }
+
+    #[test]
+    fn pdf_images_are_placed_from_the_ctm() {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pixels = vec![90u8; 100 * 50 * 3];
+        let image_id = doc.add_object(Stream::new(
+            dictionary! {
+                "Type" => "XObject",
+                "Subtype" => "Image",
+                "Width" => 100,
+                "Height" => 50,
+                "ColorSpace" => "DeviceRGB",
+                "BitsPerComponent" => 8,
+            },
+            pixels,
+        ));
+        let content = Content {
+            operations: vec![
+                Operation::new("q", vec![]),
+                Operation::new(
+                    "cm",
+                    vec![
+                        200.into(),
+                        0.into(),
+                        0.into(),
+                        100.into(),
+                        100.into(),
+                        592.into(),
+                    ],
+                ),
+                Operation::new("Do", vec![Object::Name(b"Im0".to_vec())]),
+                Operation::new("Q", vec![]),
+            ],
+        };
+        let content_id = doc.add_object(Stream::new(dictionary! {}, content.encode().unwrap()));
+        let pages_id = doc.new_object_id();
+        let page_id = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "Contents" => content_id,
+            "Resources" => dictionary! { "XObject" => dictionary! { "Im0" => image_id } },
+        });
+        doc.objects.insert(
+            pages_id,
+            Object::Dictionary(dictionary! {
+                "Type" => "Pages",
+                "Kids" => vec![page_id.into()],
+                "Count" => 1,
+                "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            }),
+        );
+
+        let figures = pdf_page_figures(&doc, page_id, 612, 792, &FigureConfig::default());
+        assert_eq!(figures.len(), 1);
+        let bbox = figures[0].bbox.unwrap();
+        assert_eq!(
+            (bbox.left, bbox.top, bbox.width, bbox.height),
+            (100, 100, 200, 100)
+        );
+        assert_eq!(figures[0].image.extension, "png");
+
+        let mut figures = figures;
+        let mut lines = vec!["Body text".to_string(); 8];
+        lines[1] = "Figure 4: Throughput".to_string();
+        attach_captions(&lines, 792, &mut figures);
+        assert_eq!(figures[0].caption.as_deref(), Some("Figure 4: Throughput"));
+    }
+}
@@ -0,0 +1,557 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::document::{CellType, Document};
+use crate::error::{DcfError, Result};
+use crate::normalization::{importance_score, ImportanceTuning};
+
+/// Everything a scorer may look at when ranking a single line.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportanceContext<'a> {
+    pub line: &'a str,
+    pub cell_type: CellType,
+    pub line_index: usize,
+    pub line_count: usize,
+}
+
+/// Assigns the 0-255 importance used by budgets, chunk metadata, and search filters.
+pub trait ImportanceScorer: Debug + Send + Sync {
+    fn score(&self, ctx: &ImportanceContext<'_>) -> u8;
+}
+
+/// The built-in rule set (type base + all-caps, digits, and early-line bonuses).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HeuristicScorer {
+    pub tuning: ImportanceTuning,
+}
+
+impl HeuristicScorer {
+    pub fn new(tuning: ImportanceTuning) -> Self {
+        Self { tuning }
+    }
+}
+
+impl ImportanceScorer for HeuristicScorer {
+    fn score(&self, ctx: &ImportanceContext<'_>) -> u8 {
+        importance_score(ctx.line, ctx.cell_type, ctx.line_index, &self.tuning)
+    }
+}
+
+/// Numeric features fed into [`LinearImportanceModel`]. All values are roughly in `0..=1`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct LineFeatures {
+    pub position: f32,
+    pub length: f32,
+    pub header: f32,
+    pub table: f32,
+    pub footer: f32,
+    pub figure: f32,
+    pub numeric_density: f32,
+    pub heading_depth: f32,
+    pub rarity: f32,
+}
+
+impl LineFeatures {
+    pub const COUNT: usize = 9;
+
+    pub fn extract(ctx: &ImportanceContext<'_>, idf: &IdfTable) -> Self {
+        let line = ctx.line;
+        let position = if ctx.line_count <= 1 {
+            1.0
+        } else {
+            1.0 - ctx.line_index.min(ctx.line_count - 1) as f32 / (ctx.line_count - 1) as f32
+        };
+        let visible = line.chars().filter(|c| !c.is_whitespace()).count();
+        let digits = line.chars().filter(|c| c.is_ascii_digit()).count();
+        let numeric_density = if visible == 0 {
+            0.0
+        } else {
+            digits as f32 / visible as f32
+        };
+        let depth = heading_depth(line);
+        Self {
+            position,
+            length: (line.chars().count() as f32 / 200.0).min(1.0),
+            header: flag(ctx.cell_type == CellType::Header),
+            table: flag(ctx.cell_type == CellType::Table),
+            footer: flag(ctx.cell_type == CellType::Footer),
+            figure: flag(ctx.cell_type == CellType::Figure),
+            numeric_density,
+            heading_depth: if depth == 0 { 0.0 } else { 1.0 / depth as f32 },
+            rarity: idf.rarity(line),
+        }
+    }
+
+    pub fn as_array(&self) -> [f32; Self::COUNT] {
+        [
+            self.position,
+            self.length,
+            self.header,
+            self.table,
+            self.footer,
+            self.figure,
+            self.numeric_density,
+            self.heading_depth,
+            self.rarity,
+        ]
+    }
+}
+
+/// Per-feature weights of the linear scorer; the score is `bias + w·x` clamped to `0..=1`.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeatureWeights {
+    pub position: f32,
+    pub length: f32,
+    pub header: f32,
+    pub table: f32,
+    pub footer: f32,
+    pub figure: f32,
+    pub numeric_density: f32,
+    pub heading_depth: f32,
+    pub rarity: f32,
+}
+
+impl Default for FeatureWeights {
+    fn default() -> Self {
+        // Hand-picked so an untrained model ranks roughly like the heuristic scorer.
+        Self {
+            position: 0.06,
+            length: -0.05,
+            header: 0.47,
+            table: 0.24,
+            footer: -0.31,
+            figure: 0.1,
+            numeric_density: 0.15,
+            heading_depth: 0.1,
+            rarity: 0.05,
+        }
+    }
+}
+
+impl FeatureWeights {
+    fn as_array(&self) -> [f32; LineFeatures::COUNT] {
+        [
+            self.position,
+            self.length,
+            self.header,
+            self.table,
+            self.footer,
+            self.figure,
+            self.numeric_density,
+            self.heading_depth,
+            self.rarity,
+        ]
+    }
+
+    fn from_array(values: [f32; LineFeatures::COUNT]) -> Self {
+        Self {
+            position: values[0],
+            length: values[1],
+            header: values[2],
+            table: values[3],
+            footer: values[4],
+            figure: values[5],
+            numeric_density: values[6],
+            heading_depth: values[7],
+            rarity: values[8],
+        }
+    }
+}
+
+/// Inverse document frequencies used for the TF-IDF rarity feature.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IdfTable {
+    /// IDF assumed for terms missing from `terms` (usually the IDF of a singleton term).
+    pub default_idf: f32,
+    pub terms: HashMap<String, f32>,
+}
+
+impl IdfTable {
+    /// Builds an IDF table treating every entry of `documents` as one document.
+    pub fn from_documents<I, S>(documents: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut df: HashMap<String, usize> = HashMap::new();
+        let mut total = 0usize;
+        for doc in documents {
+            total += 1;
+            let unique = tokenize(doc.as_ref()).collect::<HashSet<_>>();
+            for term in unique {
+                *df.entry(term).or_default() += 1;
+            }
+        }
+        let n = total.max(1) as f32;
+        let terms = df
+            .into_iter()
+            .map(|(term, count)| (term, (n / count as f32).ln() + 1.0))
+            .collect();
+        Self {
+            default_idf: n.ln() + 1.0,
+            terms,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty()
+    }
+
+    /// Mean IDF of the line's terms, scaled by the largest possible IDF.
+    pub fn rarity(&self, line: &str) -> f32 {
+        if self.default_idf <= 0.0 {
+            return 0.0;
+        }
+        let mut sum = 0.0f32;
+        let mut count = 0usize;
+        for term in tokenize(line) {
+            sum += self.terms.get(&term).copied().unwrap_or(self.default_idf);
+            count += 1;
+        }
+        if count == 0 {
+            return 0.0;
+        }
+        (sum / count as f32 / self.default_idf).clamp(0.0, 1.0)
+    }
+}
+
+/// Feature-based linear scorer whose weights can be fitted offline and loaded from TOML/JSON.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LinearImportanceModel {
+    #[serde(default = "default_bias")]
+    pub bias: f32,
+    #[serde(default)]
+    pub weights: FeatureWeights,
+    #[serde(default)]
+    pub idf: IdfTable,
+}
+
+fn default_bias() -> f32 {
+    0.39
+}
+
+impl Default for LinearImportanceModel {
+    fn default() -> Self {
+        Self {
+            bias: default_bias(),
+            weights: FeatureWeights::default(),
+            idf: IdfTable::default(),
+        }
+    }
+}
+
+impl LinearImportanceModel {
+    /// Loads a model from `.json`, or from TOML for any other extension.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let raw = fs::read_to_string(path)?;
+        if is_json(path) {
+            Ok(serde_json::from_str(&raw)?)
+        } else {
+            toml::from_str(&raw).map_err(|e| {
+                DcfError::Other(format!("invalid importance model {}: {e}", path.display()))
+            })
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let raw = if is_json(path) {
+            serde_json::to_string_pretty(self)?
+        } else {
+            toml::to_string_pretty(self)
+                .map_err(|e| DcfError::Other(format!("failed to encode importance model: {e}")))?
+        };
+        fs::write(path, raw)?;
+        Ok(())
+    }
+
+    pub fn features(&self, ctx: &ImportanceContext<'_>) -> LineFeatures {
+        LineFeatures::extract(ctx, &self.idf)
+    }
+
+    /// Raw model output before clamping and quantisation.
+    pub fn predict(&self, features: &LineFeatures) -> f32 {
+        self.bias
+            + self
+                .weights
+                .as_array()
+                .iter()
+                .zip(features.as_array())
+                .map(|(w, x)| w * x)
+                .sum::<f32>()
+    }
+
+    /// Fits weights by ridge regression on `(features, target)` pairs, targets in `0..=1`.
+    pub fn fit(samples: &[ImportanceSample], l2: f32, idf: IdfTable) -> Result<Self> {
+        if samples.is_empty() {
+            return Err(DcfError::Other(
+                "cannot fit importance model without samples".to_string(),
+            ));
+        }
+        const DIM: usize = LineFeatures::COUNT + 1;
+        let mut xtx = [[0f64; DIM]; DIM];
+        let mut xty = [0f64; DIM];
+        for sample in samples {
+            let mut row = [1f64; DIM];
+            for (slot, value) in row[1..].iter_mut().zip(sample.features.as_array()) {
+                *slot = value as f64;
+            }
+            for i in 0..DIM {
+                xty[i] += row[i] * sample.target as f64;
+                for j in 0..DIM {
+                    xtx[i][j] += row[i] * row[j];
+                }
+            }
+        }
+        // The bias is left unregularised.
+        for (i, row) in xtx.iter_mut().enumerate().skip(1) {
+            row[i] += l2.max(0.0) as f64;
+        }
+        let solution = solve_linear_system(xtx, xty).ok_or_else(|| {
+            DcfError::Other("importance model fit is singular; add l2 or samples".to_string())
+        })?;
+        let mut weights = [0f32; LineFeatures::COUNT];
+        for (slot, value) in weights.iter_mut().zip(&solution[1..]) {
+            *slot = *value as f32;
+        }
+        Ok(Self {
+            bias: solution[0] as f32,
+            weights: FeatureWeights::from_array(weights),
+            idf,
+        })
+    }
+
+    pub fn into_scorer(self) -> LinearScorer {
+        LinearScorer { model: self }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LinearScorer {
+    model: LinearImportanceModel,
+}
+
+impl LinearScorer {
+    pub fn new(model: LinearImportanceModel) -> Self {
+        Self { model }
+    }
+
+    pub fn model(&self) -> &LinearImportanceModel {
+        &self.model
+    }
+}
+
+impl ImportanceScorer for LinearScorer {
+    fn score(&self, ctx: &ImportanceContext<'_>) -> u8 {
+        let features = self.model.features(ctx);
+        let value = self.model.predict(&features).clamp(0.0, 1.0);
+        (value * 255.0).round() as u8
+    }
+}
+
+/// One labelled line for [`LinearImportanceModel::fit`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ImportanceSample {
+    pub features: LineFeatures,
+    pub target: f32,
+}
+
+/// Labels each cell of `doc` with 1.0 when its payload occurs in `gold_text`, 0.0 otherwise.
+pub fn importance_samples(
+    doc: &Document,
+    gold_text: &str,
+    idf: &IdfTable,
+) -> Vec<ImportanceSample> {
+    let gold = collapse_whitespace(gold_text).to_lowercase();
+    let ordered = doc.ordered_cells();
+    let mut per_page: HashMap<u32, usize> = HashMap::new();
+    for cell in &ordered {
+        *per_page.entry(cell.z).or_default() += 1;
+    }
+    let mut seen_on_page: HashMap<u32, usize> = HashMap::new();
+    let mut samples = Vec::with_capacity(ordered.len());
+    for cell in &ordered {
+        let line_index = {
+            let entry = seen_on_page.entry(cell.z).or_default();
+            let idx = *entry;
+            *entry += 1;
+            idx
+        };
+        let payload = match doc.payload_for(&cell.code_id) {
+            Some(payload) => payload,
+            None => continue,
+        };
+        let needle = collapse_whitespace(payload).to_lowercase();
+        if needle.is_empty() {
+            continue;
+        }
+        let ctx = ImportanceContext {
+            line: payload,
+            cell_type: cell.cell_type,
+            line_index,
+            line_count: per_page.get(&cell.z).copied().unwrap_or(1),
+        };
+        samples.push(ImportanceSample {
+            features: LineFeatures::extract(&ctx, idf),
+            target: flag(gold.contains(&needle)),
+        });
+    }
+    samples
+}
+
+/// Markdown-style `#` depth or numbered-section depth (`2.1.3 Scope` → 3); 0 when neither.
+fn heading_depth(line: &str) -> usize {
+    static SECTION_RE: Lazy<Regex> =
+        Lazy::new(|| Regex::new(r"^(\d{1,2}(?:\.\d{1,2})*)\.?\s+\p{L}").expect("valid regex"));
+    let trimmed = line.trim_start();
+    let hashes = trimmed.chars().take_while(|c| *c == '#').count();
+    if hashes > 0 && trimmed[hashes..].starts_with(' ') {
+        return hashes.min(6);
+    }
+    SECTION_RE
+        .captures(trimmed)
+        .and_then(|caps| caps.get(1))
+        .map(|m| m.as_str().split('.').count())
+        .unwrap_or(0)
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|t| t.chars().count() > 1)
+        .map(|t| t.to_lowercase())
+}
+
+fn collapse_whitespace(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn flag(value: bool) -> f32 {
+    if value {
+        1.0
+    } else {
+        0.0
+    }
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.eq_ignore_ascii_case("json"))
+        .unwrap_or(false)
+}
+
+fn solve_linear_system<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| {
+            a[i][col]
+                .abs()
+                .partial_cmp(&a[j][col].abs())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })?;
+        if a[pivot][col].abs() < 1e-12 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in 0..N {
+            if row == col {
+                continue;
+            }
+            let factor = a[row][col] / a[col][col];
+            if factor == 0.0 {
+                continue;
+            }
+            let pivot_row = a[col];
+            for (value, pivot_value) in a[row].iter_mut().zip(pivot_row).skip(col) {
+                *value -= factor * pivot_value;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut out = [0f64; N];
+    for (i, slot) in out.iter_mut().enumerate() {
+        *slot = b[i] / a[i][i];
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ctx(line: &str, cell_type: CellType, line_index: usize) -> ImportanceContext<'_> {
+        ImportanceContext {
+            line,
+            cell_type,
+            line_index,
+            line_count: 10,
+        }
+    }
+
+    #[test]
+    fn heuristic_matches_importance_score() {
+        let tuning = ImportanceTuning::default();
+        let scorer = HeuristicScorer::new(tuning);
+        let c = ctx("REVENUE 2024", CellType::Header, 0);
+        assert_eq!(
+            scorer.score(&c),
+            importance_score(c.line, c.cell_type, c.line_index, &tuning)
+        );
+    }
+
+    #[test]
+    fn default_linear_model_prefers_headers_over_footers() {
+        let scorer = LinearImportanceModel::default().into_scorer();
+        let header = scorer.score(&ctx("1.2 Scope of work", CellType::Header, 1));
+        let footer = scorer.score(&ctx("Page 3 of 9", CellType::Footer, 9));
+        assert!(header > footer);
+    }
+
+    #[test]
+    fn fit_learns_separating_weight() {
+        let mut samples = Vec::new();
+        for i in 0..20 {
+            let table = i % 2 == 0;
+            samples.push(ImportanceSample {
+                features: LineFeatures {
+                    table: flag(table),
+                    position: (i as f32) / 20.0,
+                    ..Default::default()
+                },
+                target: flag(table),
+            });
+        }
+        let model = LinearImportanceModel::fit(&samples, 0.01, IdfTable::default()).unwrap();
+        assert!(model.weights.table > 0.9);
+    }
+
+    #[test]
+    fn model_roundtrips_through_toml_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+        let model = LinearImportanceModel {
+            idf: IdfTable::from_documents(["alpha beta", "beta gamma"]),
+            ..Default::default()
+        };
+        for name in ["model.toml", "model.json"] {
+            let path = dir.path().join(name);
+            model.save(&path).unwrap();
+            assert_eq!(LinearImportanceModel::load(&path).unwrap(), model);
+        }
+    }
+
+    #[test]
+    fn heading_depth_reads_markdown_and_numbering() {
+        assert_eq!(heading_depth("## Results"), 2);
+        assert_eq!(heading_depth("2.1.3 Scope"), 3);
+        assert_eq!(heading_depth("2024 revenue grew"), 0);
+    }
+}
@@ -29,6 +29,9 @@ use anyhow::Result;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::links::LinkKind;
+use crate::numguard::{extract_entities, NumericEntity};
+
 /// Metadata record for a processed document.
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct DocumentRecord {
@@ -62,11 +65,54 @@ pub struct CellRecord {
     pub text: String,
     pub importance: f32,
     pub bbox: Option<[f32; 4]>,
+    /// Typed numeric entities guarded in this cell, as a JSON array of [`NumericEntity`].
     pub numguard: Option<Value>,
     #[serde(default)]
     pub meta: Value,
 }
 
+impl CellRecord {
+    /// Numbers this cell vouches for: the guarded entities when present,
+    /// otherwise whatever can be extracted from `text`.
+    pub fn numeric_entities(&self) -> Vec<NumericEntity> {
+        self.numguard
+            .as_ref()
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_else(|| extract_entities(&self.text))
+    }
+
+    /// Links recorded in `meta.links`.
+    pub fn links(&self) -> Vec<CellLink> {
+        self.meta
+            .get("links")
+            .and_then(|value| serde_json::from_value(value.clone()).ok())
+            .unwrap_or_default()
+    }
+
+    /// Detected language from `meta.lang` (ISO 639-1), if recorded.
+    pub fn lang(&self) -> Option<&str> {
+        self.meta
+            .get("lang")
+            .and_then(Value::as_str)
+            .filter(|lang| !lang.is_empty())
+    }
+}
+
+/// A link in a cell's `meta.links`: the byte span of its anchor text and
+/// exactly one of `url`, `cell_id` or `page_id`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct CellLink {
+    pub start: usize,
+    pub end: usize,
+    pub kind: LinkKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cell_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_id: Option<String>,
+}
+
 /// A streaming JSONL writer for efficient dataset export.
 pub struct JsonlWriter<W> {
     writer: W,
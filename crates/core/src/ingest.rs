@@ -6,8 +6,13 @@ use std::path::{Path, PathBuf};
 use anyhow::Result;
 use serde_json::json;
 
-use crate::index::{CellRecord as IndexCellRecord, DocumentRecord, JsonlWriter, PageRecord};
+use crate::index::{
+    CellLink, CellRecord as IndexCellRecord, DocumentRecord, JsonlWriter, PageRecord,
+};
 
+use crate::language::LanguageTally;
+use crate::links::LinkTarget;
+use crate::pii::{PiiKind, RedactionConfig, Redactor};
 use crate::{document::CellType, Document, Encoder};
 
 #[derive(Debug, Clone)]
@@ -17,6 +22,8 @@ pub struct IngestOptions {
     pub force_ocr: bool,
     pub ocr_languages: Vec<String>,
     pub source_override: Option<PathBuf>,
+    /// Redact PII while encoding and log it to `index/redactions.jsonl`.
+    pub redaction: Option<RedactionConfig>,
 }
 
 impl Default for IngestOptions {
@@ -27,6 +34,7 @@ impl Default for IngestOptions {
             force_ocr: false,
             ocr_languages: vec!["eng".to_string()],
             source_override: None,
+            redaction: None,
         }
     }
 }
@@ -49,10 +57,13 @@ pub fn ingest_to_index_with_opts(
     } else {
         opts.ocr_languages.clone()
     };
-    let builder = Encoder::builder(&opts.preset)?
+    let mut builder = Encoder::builder(&opts.preset)?
         .enable_ocr(opts.enable_ocr)
         .force_ocr(opts.force_ocr)
         .ocr_languages(ocr_langs);
+    if let Some(redaction) = &opts.redaction {
+        builder = builder.redactor(Redactor::new(redaction.clone())?);
+    }
     let encoder = builder.build();
     let (document, _metrics) = encoder.encode_path(input_path)?;
     let source_path = opts.source_override.as_deref().unwrap_or(input_path);
@@ -65,47 +76,6 @@ pub fn ingest_to_index_with_opts(
     Ok(())
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use serde_json::Value;
-    use tempfile::tempdir;
-
-    #[test]
-    fn ingest_creates_raw_and_index_files() {
-        let dir = tempdir().unwrap();
-        let input = dir.path().join("sample.md");
-        std::fs::write(
-            &input,
-            "## Heading\n\n".to_string() + &"Body text ".repeat(50),
-        )
-        .unwrap();
-        let output_dir = dir.path().join("dataset");
-
-        ingest_to_index(&input, &output_dir).unwrap();
-
-        assert!(output_dir.join("raw/3dcf/doc_0001.3dcf").exists());
-        assert!(output_dir.join("raw/3dcf/doc_0001.3dcf.json").exists());
-
-        let docs_path = output_dir.join("index/documents.jsonl");
-        let docs_content = std::fs::read_to_string(&docs_path).unwrap();
-        let first_line = docs_content.lines().next().unwrap();
-        let doc_record: DocumentRecord = serde_json::from_str(first_line).unwrap();
-        assert_eq!(doc_record.doc_id, "doc_0001");
-        assert_eq!(doc_record.source_format, "md");
-
-        let cells_path = output_dir.join("index/cells.jsonl");
-        let cells_content = std::fs::read_to_string(cells_path).unwrap();
-        assert!(!cells_content.is_empty());
-        let first_cell: Value =
-            serde_json::from_str(cells_content.lines().next().unwrap()).unwrap();
-        assert_eq!(
-            first_cell.get("doc_id").unwrap().as_str().unwrap(),
-            "doc_0001"
-        );
-    }
-}
-
 fn write_raw(document: &Document, output_dir: &Path, doc_id: &str) -> Result<()> {
     let raw_dir = output_dir.join("raw/3dcf");
     fs::create_dir_all(&raw_dir)?;
@@ -158,6 +128,16 @@ fn write_index_records(
     };
     documents_writer.write_record(&doc_record)?;
 
+    let mut page_langs: HashMap<u32, LanguageTally> = HashMap::new();
+    for cell in &document.cells {
+        if let (Some(lang), Some(payload)) = (&cell.lang, document.payload_for(&cell.code_id)) {
+            page_langs
+                .entry(cell.z)
+                .or_default()
+                .add(lang, payload.chars().count());
+        }
+    }
+
     let mut page_lookup = HashMap::new();
     for (idx, page) in document.pages.iter().enumerate() {
         let page_id = format!("{doc_id}_page_{:04}", idx + 1);
@@ -167,22 +147,71 @@ fn write_index_records(
         } else {
             Some(page_text.split_whitespace().count() as u32)
         };
+        let mut meta = json!({
+            "width_px": page.width_px,
+            "height_px": page.height_px,
+            "z": page.z,
+        });
+        if let Some(lang) = page_langs.get(&page.z).and_then(LanguageTally::dominant) {
+            meta["lang"] = json!(lang);
+        }
         let page_record = PageRecord {
             page_id: page_id.clone(),
             doc_id: doc_id.to_string(),
             page_number: (idx + 1) as u32,
             approx_tokens,
-            meta: json!({
-                "width_px": page.width_px,
-                "height_px": page.height_px,
-                "z": page.z,
-            }),
+            meta,
         };
         pages_writer.write_record(&page_record)?;
         page_lookup.insert(page.z, page_id);
     }
 
+    let mut guards_by_cell: HashMap<(u32, i32, i32), Vec<_>> = HashMap::new();
+    for guard in &document.numguards {
+        if let Some(entity) = &guard.entity {
+            guards_by_cell
+                .entry((guard.z, guard.x as i32, guard.y as i32))
+                .or_default()
+                .push(entity.clone());
+        }
+    }
+
+    let mut pii_by_cell: HashMap<(u32, i32, i32), Vec<PiiKind>> = HashMap::new();
+    for redaction in &document.redactions {
+        let kinds = pii_by_cell
+            .entry((redaction.z, redaction.x, redaction.y))
+            .or_default();
+        if !kinds.contains(&redaction.pii.kind) {
+            kinds.push(redaction.pii.kind);
+        }
+    }
     let ordered_cells = document.ordered_cells();
+    let mut cell_ids = HashMap::new();
+    for (idx, cell) in ordered_cells.iter().enumerate() {
+        cell_ids
+            .entry((cell.z, cell.x, cell.y))
+            .or_insert_with(|| format!("{doc_id}_cell_{:06}", idx + 1));
+    }
+    let mut links_by_cell: HashMap<(u32, i32, i32), Vec<CellLink>> = HashMap::new();
+    for link in &document.links {
+        let mut entry = CellLink {
+            start: link.start,
+            end: link.end,
+            kind: link.kind,
+            url: None,
+            cell_id: None,
+            page_id: None,
+        };
+        match &link.target {
+            LinkTarget::Url(url) => entry.url = Some(url.clone()),
+            LinkTarget::Page(z) => entry.page_id = page_lookup.get(z).cloned(),
+            LinkTarget::Cell { z, x, y } => entry.cell_id = cell_ids.get(&(*z, *x, *y)).cloned(),
+        }
+        if entry.url.is_some() || entry.cell_id.is_some() || entry.page_id.is_some() {
+            links_by_cell.entry(link.source()).or_default().push(entry);
+        }
+    }
+
     for (idx, cell) in ordered_cells.iter().enumerate() {
         let cell_id = format!("{doc_id}_cell_{:06}", idx + 1);
         let text = document
@@ -193,12 +222,25 @@ fn write_index_records(
             .get(&cell.z)
             .cloned()
             .unwrap_or_else(|| format!("{doc_id}_page_{:04}", cell.z + 1));
+        let position = (cell.z, cell.x, cell.y);
         let bbox = Some([
             cell.x as f32,
             cell.y as f32,
             (cell.x as f32) + cell.w as f32,
             (cell.y as f32) + cell.h as f32,
         ]);
+        let mut meta = json!({
+            "rle": cell.rle,
+        });
+        if let Some(lang) = &cell.lang {
+            meta["lang"] = json!(lang);
+        }
+        if let Some(kinds) = pii_by_cell.get(&position) {
+            meta["pii"] = json!(kinds);
+        }
+        if let Some(links) = links_by_cell.get(&position) {
+            meta["links"] = json!(links);
+        }
         let record = IndexCellRecord {
             cell_id,
             doc_id: doc_id.to_string(),
@@ -207,14 +249,30 @@ fn write_index_records(
             text,
             importance: (cell.importance as f32) / 255.0,
             bbox,
-            numguard: None,
-            meta: json!({
-                "rle": cell.rle,
-            }),
+            numguard: guards_by_cell
+                .get(&(cell.z, cell.x, cell.y))
+                .map(|entities| json!(entities)),
+            meta,
         };
         cells_writer.write_record(&record)?;
     }
 
+    if !document.redactions.is_empty() {
+        let redactions_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(index_dir.join("redactions.jsonl"))?;
+        let mut redactions_writer = JsonlWriter::new(BufWriter::new(redactions_file));
+        for redaction in &document.redactions {
+            let mut entry = json!(redaction);
+            entry["doc_id"] = json!(doc_id);
+            if let Some(cell_id) = cell_ids.get(&(redaction.z, redaction.x, redaction.y)) {
+                entry["cell_id"] = json!(cell_id);
+            }
+            redactions_writer.write_record(&entry)?;
+        }
+    }
+
     Ok(())
 }
 
@@ -225,6 +283,7 @@ fn normalize_kind(cell_type: CellType) -> String {
         CellType::Figure => "figure",
         CellType::Footer => "footer",
         CellType::Header => "heading",
+        CellType::Equation => "equation",
     }
     .to_string()
 }
@@ -249,3 +308,190 @@ fn next_doc_id(raw_dir: &Path) -> Result<String> {
     }
     Ok(format!("doc_{:04}", max_id + 1))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::links::LinkKind;
+    use serde_json::Value;
+    use tempfile::tempdir;
+
+    #[test]
+    fn index_cells_carry_their_numguards() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("figures.md");
+        std::fs::write(
+            &input,
+            "Quarterly summary\n\nRevenue reached $1,200 in the second quarter.\n",
+        )
+        .unwrap();
+        let output_dir = dir.path().join("dataset");
+
+        ingest_to_index(&input, &output_dir).unwrap();
+
+        let cells = std::fs::read_to_string(output_dir.join("index/cells.jsonl")).unwrap();
+        let revenue: IndexCellRecord = cells
+            .lines()
+            .map(|line| serde_json::from_str::<IndexCellRecord>(line).unwrap())
+            .find(|cell| cell.text.contains("Revenue"))
+            .unwrap();
+        let bbox = revenue.bbox.unwrap();
+        assert_ne!(bbox[0], bbox[1]);
+        let guards = revenue.numguard.expect("numguard is filled in");
+        assert!(!guards.as_array().unwrap().is_empty());
+    }
+
+    #[test]
+    fn ingest_creates_raw_and_index_files() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("sample.md");
+        std::fs::write(
+            &input,
+            "## Heading\n\n".to_string() + &"Body text ".repeat(50),
+        )
+        .unwrap();
+        let output_dir = dir.path().join("dataset");
+
+        ingest_to_index(&input, &output_dir).unwrap();
+
+        assert!(output_dir.join("raw/3dcf/doc_0001.3dcf").exists());
+        assert!(output_dir.join("raw/3dcf/doc_0001.3dcf.json").exists());
+
+        let docs_path = output_dir.join("index/documents.jsonl");
+        let docs_content = std::fs::read_to_string(&docs_path).unwrap();
+        let first_line = docs_content.lines().next().unwrap();
+        let doc_record: DocumentRecord = serde_json::from_str(first_line).unwrap();
+        assert_eq!(doc_record.doc_id, "doc_0001");
+        assert_eq!(doc_record.source_format, "md");
+
+        let cells_path = output_dir.join("index/cells.jsonl");
+        let cells_content = std::fs::read_to_string(cells_path).unwrap();
+        assert!(!cells_content.is_empty());
+        let first_cell: Value =
+            serde_json::from_str(cells_content.lines().next().unwrap()).unwrap();
+        assert_eq!(
+            first_cell.get("doc_id").unwrap().as_str().unwrap(),
+            "doc_0001"
+        );
+    }
+
+    #[test]
+    fn ingest_records_cell_and_page_languages() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("mixed.md");
+        std::fs::write(
+            &input,
+            "Der Vorstand hat den Jahresabschluss geprüft und festgestellt.\n\n\
+             Die Umsatzerlöse stiegen im Berichtszeitraum deutlich an.\n\n\
+             The board approved the annual accounts without changes.\n",
+        )
+        .unwrap();
+        let output_dir = dir.path().join("dataset");
+        ingest_to_index(&input, &output_dir).unwrap();
+
+        let cells: Vec<IndexCellRecord> =
+            std::fs::read_to_string(output_dir.join("index/cells.jsonl"))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+        let langs: Vec<_> = cells.iter().map(|cell| cell.lang()).collect();
+        assert_eq!(langs, vec![Some("de"), Some("de"), Some("en")]);
+
+        let page: Value = serde_json::from_str(
+            std::fs::read_to_string(output_dir.join("index/pages.jsonl"))
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(page["meta"]["lang"], "de");
+        let doc = Document::load_bin(output_dir.join("raw/3dcf/doc_0001.3dcf")).unwrap();
+        assert_eq!(doc.header.lang.as_deref(), Some("de"));
+    }
+
+    #[test]
+    fn ingest_redacts_pii_and_logs_it() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("contacts.md");
+        std::fs::write(
+            &input,
+            "Contact Jane Doe at jane@example.com for details.\n\nNo personal data here.\n",
+        )
+        .unwrap();
+        let output_dir = dir.path().join("dataset");
+        let opts = IngestOptions {
+            redaction: Some(RedactionConfig {
+                names: vec!["Jane Doe".to_string()],
+                ..RedactionConfig::default()
+            }),
+            ..IngestOptions::default()
+        };
+        ingest_to_index_with_opts(&input, &output_dir, &opts).unwrap();
+
+        let cells: Vec<IndexCellRecord> =
+            std::fs::read_to_string(output_dir.join("index/cells.jsonl"))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+        assert_eq!(cells[0].text, "Contact [PERSON] at [EMAIL] for details.");
+        assert_eq!(cells[0].meta["pii"], serde_json::json!(["person", "email"]));
+        assert!(cells[1].meta.get("pii").is_none());
+
+        let log: Vec<Value> = std::fs::read_to_string(output_dir.join("index/redactions.jsonl"))
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[1]["kind"], "email");
+        assert_eq!(log[1]["cell_id"], cells[0].cell_id.as_str());
+        let raw = std::fs::read_to_string(output_dir.join("raw/3dcf/doc_0001.3dcf.json")).unwrap();
+        assert!(!raw.contains("jane@example.com"));
+    }
+
+    #[test]
+    fn ingest_records_links_and_footnotes() {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("linked.md");
+        std::fs::write(
+            &input,
+            "Results are in the [annual report](https://example.org/ar) and \
+             [the appendix](#appendix).[^src]\n\n\
+             ## Appendix {#appendix}\n\n\
+             Detailed tables follow.\n\n\
+             [^src]: Audited by the board.\n",
+        )
+        .unwrap();
+        let output_dir = dir.path().join("dataset");
+        ingest_to_index(&input, &output_dir).unwrap();
+
+        let cells: Vec<IndexCellRecord> =
+            std::fs::read_to_string(output_dir.join("index/cells.jsonl"))
+                .unwrap()
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+        let target = |link: &CellLink| {
+            cells
+                .iter()
+                .find(|cell| Some(&cell.cell_id) == link.cell_id.as_ref())
+                .map(|cell| cell.text.as_str())
+        };
+        let links = cells[0].links();
+        assert_eq!(links.len(), 3);
+        assert_eq!(
+            &cells[0].text[links[0].start..links[0].end],
+            "annual report"
+        );
+        assert_eq!(links[0].url.as_deref(), Some("https://example.org/ar"));
+        assert_eq!(links[1].kind, LinkKind::CrossRef);
+        assert!(target(&links[1]).unwrap().contains("Appendix"));
+        assert_eq!(links[2].kind, LinkKind::Footnote);
+        assert!(target(&links[2])
+            .unwrap()
+            .ends_with("Audited by the board."));
+    }
+}
@@ -0,0 +1,411 @@
+//! BLAKE3 Merkle root over a document's content and Ed25519 signatures of it.
+//!
+//! Each header, page, cell, dict entry, NumGuard and link is a leaf: the
+//! BLAKE3 hash of its protobuf encoding (cells with absolute coordinates),
+//! prefixed with a section tag. Every section is reduced to a binary Merkle
+//! root and the document root hashes the section roots in a fixed order, so
+//! the value does not depend on the container layout it was read from.
+
+use std::path::Path;
+
+use ed25519_dalek::{Signature, Signer, Verifier};
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+
+use crate::document::{cell_to_proto, fixed_bytes, link_to_proto, numguard_to_proto, Document};
+use crate::error::{DcfError, Result};
+use crate::proto;
+
+/// Environment variable naming the public key `Document::load_bin` verifies
+/// against: a hex string or the path of a `.pub` key file.
+pub const TRUSTED_KEY_ENV: &str = "THREE_DCF_TRUSTED_KEY";
+
+const SIGNATURE_CONTEXT: &[u8] = b"3dcf integrity v1";
+
+const LEAF: u8 = 0;
+const NODE: u8 = 1;
+const EMPTY: u8 = 2;
+const ROOT: u8 = 3;
+
+#[derive(Clone, Copy)]
+#[repr(u8)]
+enum Section {
+    Header = 1,
+    Pages = 2,
+    Cells = 3,
+    Dict = 4,
+    NumGuards = 5,
+    Links = 6,
+}
+
+/// The integrity section stored with a document.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "IntegrityHex", into = "IntegrityHex")]
+pub struct Integrity {
+    pub merkle_root: [u8; 32],
+    /// Signer and signature; both absent for a document that is only sealed.
+    pub signature: Option<([u8; 32], [u8; 64])>,
+}
+
+/// Outcome of a successful [`Document::verify_integrity`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityStatus {
+    /// The Merkle root matches; there is no signature.
+    Sealed,
+    /// The Merkle root matches and is signed by this key.
+    Signed(VerifyingKey),
+}
+
+impl Document {
+    /// BLAKE3 Merkle root over header, pages, cells, dict, NumGuards and links.
+    pub fn merkle_root(&self) -> [u8; 32] {
+        let header = proto::Header {
+            version: self.header.version,
+            grid: self.header.grid.clone(),
+            codeset: self.header.codeset.clone(),
+            lang: self.header.lang.clone(),
+        };
+        let header = vec![leaf(Section::Header, &header)];
+
+        let mut pages = self.pages.clone();
+        pages.sort_by_key(|page| page.z);
+        let pages: Vec<_> = pages
+            .iter()
+            .map(|page| {
+                let proto = proto::PageInfo {
+                    z: page.z,
+                    width_px: page.width_px,
+                    height_px: page.height_px,
+                };
+                leaf(Section::Pages, &proto)
+            })
+            .collect();
+
+        let cells: Vec<_> = self
+            .ordered_cells()
+            .iter()
+            .map(|cell| {
+                leaf(
+                    Section::Cells,
+                    &cell_to_proto(cell, (cell.z as i32, cell.x, cell.y)),
+                )
+            })
+            .collect();
+
+        // Dict, NumGuards and links are sets; their stored order depends on the layout.
+        let mut dict: Vec<_> = self
+            .dict
+            .iter()
+            .map(|(code_id, payload)| {
+                let entry = proto::DictEntry {
+                    code_id: code_id.to_vec().into(),
+                    payload_utf8: payload.clone(),
+                };
+                leaf(Section::Dict, &entry)
+            })
+            .collect();
+        dict.sort_unstable();
+        let mut numguards: Vec<_> = self
+            .numguards
+            .iter()
+            .map(|guard| leaf(Section::NumGuards, &numguard_to_proto(guard)))
+            .collect();
+        numguards.sort_unstable();
+        let mut links: Vec<_> = self
+            .links
+            .iter()
+            .map(|link| leaf(Section::Links, &link_to_proto(link)))
+            .collect();
+        links.sort_unstable();
+
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(&[ROOT]);
+        for (section, leaves) in [
+            (Section::Header, header),
+            (Section::Pages, pages),
+            (Section::Cells, cells),
+            (Section::Dict, dict),
+            (Section::NumGuards, numguards),
+            (Section::Links, links),
+        ] {
+            hasher.update(&merkle(section, leaves));
+        }
+        hasher.finalize().into()
+    }
+
+    /// Records the Merkle root without a signature.
+    pub fn seal(&mut self) {
+        self.integrity = Some(Integrity {
+            merkle_root: self.merkle_root(),
+            signature: None,
+        });
+    }
+
+    /// Records the Merkle root and signs it with `key`.
+    pub fn sign(&mut self, key: &SigningKey) {
+        let merkle_root = self.merkle_root();
+        let signature = key.sign(&signed_message(&merkle_root));
+        self.integrity = Some(Integrity {
+            merkle_root,
+            signature: Some((key.verifying_key().to_bytes(), signature.to_bytes())),
+        });
+    }
+
+    /// Recomputes the Merkle root and checks the signature. With `trusted`,
+    /// the document must be signed by exactly that key.
+    pub fn verify_integrity(&self, trusted: Option<&VerifyingKey>) -> Result<IntegrityStatus> {
+        let integrity = self
+            .integrity
+            .as_ref()
+            .ok_or_else(|| DcfError::Integrity("document has no integrity section".into()))?;
+        if integrity.merkle_root != self.merkle_root() {
+            return Err(DcfError::Integrity(
+                "Merkle root does not match the document content".into(),
+            ));
+        }
+        let Some((public_key, signature)) = &integrity.signature else {
+            return match trusted {
+                Some(_) => Err(DcfError::Integrity("document is not signed".into())),
+                None => Ok(IntegrityStatus::Sealed),
+            };
+        };
+        let signer = VerifyingKey::from_bytes(public_key)
+            .map_err(|_| DcfError::Integrity("invalid signer public key".into()))?;
+        if let Some(trusted) = trusted {
+            if trusted != &signer {
+                return Err(DcfError::Integrity(format!(
+                    "signed by untrusted key {}",
+                    hex::encode(public_key)
+                )));
+            }
+        }
+        signer
+            .verify(
+                &signed_message(&integrity.merkle_root),
+                &Signature::from_bytes(signature),
+            )
+            .map_err(|_| DcfError::Integrity("signature does not verify".into()))?;
+        Ok(IntegrityStatus::Signed(signer))
+    }
+}
+
+impl Integrity {
+    pub(crate) fn to_proto(&self) -> proto::Integrity {
+        let (public_key, signature) = match &self.signature {
+            Some((key, sig)) => (key.to_vec(), sig.to_vec()),
+            None => (Vec::new(), Vec::new()),
+        };
+        proto::Integrity {
+            merkle_root: self.merkle_root.to_vec(),
+            public_key,
+            signature,
+        }
+    }
+
+    pub(crate) fn from_proto(integrity: proto::Integrity) -> Result<Self> {
+        let signature = if integrity.signature.is_empty() && integrity.public_key.is_empty() {
+            None
+        } else {
+            Some((
+                fixed_bytes("integrity public_key", &integrity.public_key)?,
+                fixed_bytes("integrity signature", &integrity.signature)?,
+            ))
+        };
+        Ok(Self {
+            merkle_root: fixed_bytes("integrity merkle_root", &integrity.merkle_root)?,
+            signature,
+        })
+    }
+}
+
+/// A fresh signing key from the operating system's RNG.
+pub fn generate_signing_key() -> SigningKey {
+    SigningKey::generate(&mut rand::rngs::OsRng)
+}
+
+/// Parses a hex-encoded 32-byte Ed25519 secret key.
+pub fn signing_key_from_hex(hex_key: &str) -> Result<SigningKey> {
+    Ok(SigningKey::from_bytes(&key_bytes(hex_key)?))
+}
+
+/// Parses a hex-encoded 32-byte Ed25519 public key.
+pub fn verifying_key_from_hex(hex_key: &str) -> Result<VerifyingKey> {
+    VerifyingKey::from_bytes(&key_bytes(hex_key)?)
+        .map_err(|_| DcfError::Integrity("invalid Ed25519 public key".into()))
+}
+
+/// Reads a key file written by `3dcf keygen`, or takes `spec` as the hex key itself.
+pub fn read_verifying_key(spec: &str) -> Result<VerifyingKey> {
+    if Path::new(spec).is_file() {
+        verifying_key_from_hex(&std::fs::read_to_string(spec)?)
+    } else {
+        verifying_key_from_hex(spec)
+    }
+}
+
+pub fn read_signing_key<P: AsRef<Path>>(path: P) -> Result<SigningKey> {
+    signing_key_from_hex(&std::fs::read_to_string(path)?)
+}
+
+pub(crate) fn trusted_key_from_env() -> Result<Option<VerifyingKey>> {
+    match std::env::var(TRUSTED_KEY_ENV) {
+        Ok(spec) if !spec.trim().is_empty() => read_verifying_key(spec.trim()).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn key_bytes(hex_key: &str) -> Result<[u8; 32]> {
+    hex::decode(hex_key.trim())
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| DcfError::Integrity("keys must be 32 bytes of hex".into()))
+}
+
+fn signed_message(root: &[u8; 32]) -> Vec<u8> {
+    [SIGNATURE_CONTEXT, root.as_slice()].concat()
+}
+
+fn leaf<M: Message>(section: Section, message: &M) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&[LEAF, section as u8]);
+    hasher.update(&message.encode_to_vec());
+    hasher.finalize().into()
+}
+
+fn merkle(section: Section, mut level: Vec<[u8; 32]>) -> [u8; 32] {
+    if level.is_empty() {
+        return blake3::hash(&[EMPTY, section as u8]).into();
+    }
+    while level.len() > 1 {
+        level = level
+            .chunks(2)
+            .map(|pair| match pair {
+                [left, right] => {
+                    let mut hasher = blake3::Hasher::new();
+                    hasher.update(&[NODE]);
+                    hasher.update(left);
+                    hasher.update(right);
+                    hasher.finalize().into()
+                }
+                [odd] => *odd,
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    level[0]
+}
+
+#[derive(Serialize, Deserialize)]
+struct IntegrityHex {
+    merkle_root: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    public_key: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    signature: Option<String>,
+}
+
+impl From<Integrity> for IntegrityHex {
+    fn from(value: Integrity) -> Self {
+        Self {
+            merkle_root: hex::encode(value.merkle_root),
+            public_key: value.signature.map(|(key, _)| hex::encode(key)),
+            signature: value.signature.map(|(_, sig)| hex::encode(sig)),
+        }
+    }
+}
+
+impl TryFrom<IntegrityHex> for Integrity {
+    type Error = String;
+
+    fn try_from(value: IntegrityHex) -> std::result::Result<Self, Self::Error> {
+        fn decode<const N: usize>(
+            hex_value: &str,
+            field: &str,
+        ) -> std::result::Result<[u8; N], String> {
+            hex::decode(hex_value)
+                .ok()
+                .and_then(|bytes| bytes.try_into().ok())
+                .ok_or_else(|| format!("integrity {field} must be {N} bytes of hex"))
+        }
+        let signature = match (value.public_key, value.signature) {
+            (Some(key), Some(sig)) => {
+                Some((decode(&key, "public_key")?, decode(&sig, "signature")?))
+            }
+            (None, None) => None,
+            _ => return Err("integrity public_key and signature go together".into()),
+        };
+        Ok(Self {
+            merkle_root: decode(&value.merkle_root, "merkle_root")?,
+            signature,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Document {
+        Document::fixture(&[
+            &["Page 0", "Revenue was $12,000"],
+            &["Page 1", "Revenue was $12,000"],
+        ])
+    }
+
+    #[test]
+    fn signatures_survive_both_layouts_and_json() {
+        let key = generate_signing_key();
+        let mut doc = report();
+        doc.sign(&key);
+        let trusted = key.verifying_key();
+
+        let seekable = Document::from_bytes(&doc.to_seekable_bytes().unwrap()).unwrap();
+        let blob = Document::from_bytes(&doc.to_bytes().unwrap()).unwrap();
+        let json: Document = serde_json::from_str(&serde_json::to_string(&doc).unwrap()).unwrap();
+        for copy in [seekable, blob, json] {
+            assert_eq!(
+                copy.verify_integrity(Some(&trusted)).unwrap(),
+                IntegrityStatus::Signed(trusted)
+            );
+        }
+    }
+
+    #[test]
+    fn tampering_and_untrusted_keys_are_rejected() {
+        let key = generate_signing_key();
+        let mut doc = report();
+        doc.sign(&key);
+
+        let mut edited = doc.clone();
+        let code = edited.cells[1].code_id;
+        edited.dict.insert(code, "Revenue was $15,000".into());
+        assert!(edited.verify_integrity(None).is_err());
+
+        let mut moved = doc.clone();
+        moved.cells[0].y += 10;
+        assert!(moved.verify_integrity(None).is_err());
+
+        let other = generate_signing_key();
+        assert!(doc.verify_integrity(Some(&other.verifying_key())).is_err());
+
+        let mut sealed = report();
+        sealed.seal();
+        assert_eq!(
+            sealed.verify_integrity(None).unwrap(),
+            IntegrityStatus::Sealed
+        );
+        assert!(sealed.verify_integrity(Some(&key.verifying_key())).is_err());
+        assert!(report().verify_integrity(None).is_err());
+    }
+
+    #[test]
+    fn hex_keys_round_trip() {
+        let key = generate_signing_key();
+        let parsed = signing_key_from_hex(&hex::encode(key.to_bytes())).unwrap();
+        assert_eq!(parsed.verifying_key(), key.verifying_key());
+        let public = verifying_key_from_hex(&hex::encode(key.verifying_key().to_bytes())).unwrap();
+        assert_eq!(public, key.verifying_key());
+        assert!(verifying_key_from_hex("abcd").is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+/// Texts with fewer letters than this are too short to identify on their own.
+const MIN_LETTERS: usize = 12;
+
+/// Result of language identification for one piece of text.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LanguageGuess {
+    /// ISO 639-1 code where one exists (`en`, `de`, `ja`), ISO 639-3 otherwise.
+    pub code: String,
+    pub confidence: f32,
+    /// Whether the detector considers the guess trustworthy for this text length.
+    pub reliable: bool,
+}
+
+/// Identifies the language of `text` with a trigram model on the CPU.
+/// Returns `None` when the text has too few letters to judge.
+pub fn detect_language(text: &str) -> Option<LanguageGuess> {
+    let letters = text.chars().filter(|c| c.is_alphabetic()).count();
+    if letters < MIN_LETTERS {
+        return None;
+    }
+    let info = whatlang::detect(text)?;
+    Some(LanguageGuess {
+        code: iso639_1(info.lang()).to_string(),
+        confidence: info.confidence() as f32,
+        reliable: info.is_reliable(),
+    })
+}
+
+/// Tesseract traineddata name for an ISO 639-1/639-3 code (`de` → `deu`, `zh` → `chi_sim`).
+pub fn tesseract_language(code: &str) -> Option<&'static str> {
+    let lang = Lang::all()
+        .iter()
+        .copied()
+        .find(|lang| iso639_1(*lang) == code || lang.code() == code)?;
+    Some(match lang {
+        Lang::Cmn => "chi_sim",
+        Lang::Pes => "fas",
+        Lang::Nob => "nor",
+        other => other.code(),
+    })
+}
+
+/// Narrows a multi-language OCR setting to the language detected in a first
+/// OCR pass. English stays in the list when configured because mixed pages
+/// (acronyms, product names) are common. Returns `None` when the text gives no
+/// reliable answer or the detected language is not configured.
+pub fn ocr_languages_for(text: &str, configured: &[String]) -> Option<Vec<String>> {
+    let guess = detect_language(text).filter(|guess| guess.reliable)?;
+    let detected = tesseract_language(&guess.code)?;
+    if !configured.iter().any(|lang| lang == detected) {
+        return None;
+    }
+    let mut langs = vec![detected.to_string()];
+    if detected != "eng" && configured.iter().any(|lang| lang == "eng") {
+        langs.push("eng".to_string());
+    }
+    Some(langs)
+}
+
+/// Accumulates language votes weighted by text length.
+#[derive(Debug, Clone, Default)]
+pub struct LanguageTally {
+    weights: HashMap<String, usize>,
+}
+
+impl LanguageTally {
+    pub fn add(&mut self, code: &str, weight: usize) {
+        *self.weights.entry(code.to_string()).or_default() += weight;
+    }
+
+    /// Language with the largest total weight; ties break alphabetically.
+    pub fn dominant(&self) -> Option<String> {
+        self.weights
+            .iter()
+            .max_by(|a, b| a.1.cmp(b.1).then_with(|| b.0.cmp(a.0)))
+            .map(|(code, _)| code.clone())
+    }
+}
+
+fn iso639_1(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Epo => "eo",
+        Lang::Eng => "en",
+        Lang::Rus => "ru",
+        Lang::Cmn => "zh",
+        Lang::Spa => "es",
+        Lang::Por => "pt",
+        Lang::Ita => "it",
+        Lang::Ben => "bn",
+        Lang::Fra => "fr",
+        Lang::Deu => "de",
+        Lang::Ukr => "uk",
+        Lang::Kat => "ka",
+        Lang::Ara => "ar",
+        Lang::Hin => "hi",
+        Lang::Jpn => "ja",
+        Lang::Heb => "he",
+        Lang::Yid => "yi",
+        Lang::Pol => "pl",
+        Lang::Amh => "am",
+        Lang::Jav => "jv",
+        Lang::Kor => "ko",
+        Lang::Nob => "nb",
+        Lang::Dan => "da",
+        Lang::Swe => "sv",
+        Lang::Fin => "fi",
+        Lang::Tur => "tr",
+        Lang::Nld => "nl",
+        Lang::Hun => "hu",
+        Lang::Ces => "cs",
+        Lang::Ell => "el",
+        Lang::Bul => "bg",
+        Lang::Bel => "be",
+        Lang::Mar => "mr",
+        Lang::Kan => "kn",
+        Lang::Ron => "ro",
+        Lang::Slv => "sl",
+        Lang::Hrv => "hr",
+        Lang::Srp => "sr",
+        Lang::Mkd => "mk",
+        Lang::Lit => "lt",
+        Lang::Lav => "lv",
+        Lang::Est => "et",
+        Lang::Tam => "ta",
+        Lang::Vie => "vi",
+        Lang::Urd => "ur",
+        Lang::Tha => "th",
+        Lang::Guj => "gu",
+        Lang::Uzb => "uz",
+        Lang::Pan => "pa",
+        Lang::Aze => "az",
+        Lang::Ind => "id",
+        Lang::Tel => "te",
+        Lang::Pes => "fa",
+        Lang::Mal => "ml",
+        Lang::Ori => "or",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Sin => "si",
+        Lang::Khm => "km",
+        Lang::Tuk => "tk",
+        Lang::Aka => "ak",
+        Lang::Zul => "zu",
+        Lang::Sna => "sn",
+        Lang::Afr => "af",
+        Lang::Lat => "la",
+        Lang::Slk => "sk",
+        Lang::Cat => "ca",
+        Lang::Tgl => "tl",
+        Lang::Hye => "hy",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_english_german_and_japanese() {
+        let cases = [
+            (
+                "The quarterly report shows strong growth in all regions.",
+                "en",
+            ),
+            (
+                "Der Quartalsbericht zeigt ein starkes Wachstum in allen Regionen.",
+                "de",
+            ),
+            (
+                "四半期報告書は、すべての地域で力強い成長を示しています。",
+                "ja",
+            ),
+        ];
+        for (text, expected) in cases {
+            let guess = detect_language(text).expect(text);
+            assert_eq!(guess.code, expected, "{text}");
+        }
+        assert!(detect_language("Q3 2024").is_none());
+    }
+
+    #[test]
+    fn ocr_languages_follow_the_detected_language() {
+        let configured = vec!["eng".to_string(), "deu".to_string(), "jpn".to_string()];
+        assert_eq!(
+            ocr_languages_for(
+                "Die Umsatzerlöse stiegen im Berichtszeitraum deutlich an, vor allem in Europa.",
+                &configured
+            ),
+            Some(vec!["deu".to_string(), "eng".to_string()])
+        );
+        assert_eq!(
+            ocr_languages_for(
+                "Les revenus ont fortement augmenté au cours de la période.",
+                &configured
+            ),
+            None
+        );
+        assert_eq!(tesseract_language("zh"), Some("chi_sim"));
+    }
+
+    #[test]
+    fn tally_picks_the_heaviest_language() {
+        let mut tally = LanguageTally::default();
+        tally.add("de", 40);
+        tally.add("en", 25);
+        tally.add("en", 20);
+        assert_eq!(tally.dominant().as_deref(), Some("en"));
+        assert_eq!(LanguageTally::default().dominant(), None);
+    }
+}
@@ -74,27 +74,46 @@
 //!     .dedup_window(5)              // Dedup across 5 pages
 //!     .hyphenation(HyphenationMode::Preserve)
 //!     .importance_tuning(ImportanceTuning {
-//!         header_boost: 1.5,
-//!         table_boost: 1.2,
+//!         heading_boost: 1.5,
+//!         number_boost: 1.2,
 //!         ..Default::default()
 //!     })
 //!     .build();
 //! # Ok::<(), three_dcf_core::DcfError>(())
 //! ```
 //!
-//! ## Chunking for RAG
+//! ## Learned Importance Scoring
+//!
+//! The heuristic above can be swapped for any [`ImportanceScorer`]. The bundled
+//! [`LinearImportanceModel`] scores position, length, cell type, numeric density,
+//! heading depth, and TF-IDF rarity; its weights are fitted offline from bench gold
+//! data (`3dcf fit-importance`) and loaded from TOML or JSON:
 //!
 //! ```rust,no_run
+//! use std::sync::Arc;
+//! use three_dcf_core::{EncoderBuilder, LinearImportanceModel};
+//!
+//! let model = LinearImportanceModel::load("importance.toml")?;
+//! let encoder = EncoderBuilder::new("reports")?
+//!     .importance_scorer(Arc::new(model.into_scorer()))
+//!     .build();
+//! # Ok::<(), three_dcf_core::DcfError>(())
+//! ```
+//!
+//! ## Chunking for RAG
+//!
+//! ```rust
 //! use three_dcf_core::{Chunker, ChunkConfig, ChunkMode};
+//! # let document = three_dcf_core::Document::new(Default::default());
 //!
 //! let chunker = Chunker::new(ChunkConfig {
 //!     mode: ChunkMode::Semantic,
-//!     target_tokens: 512,
-//!     overlap_tokens: 64,
+//!     min_tokens: 64,
+//!     max_tokens: 512,
 //!     ..Default::default()
 //! });
 //!
-//! let chunks = chunker.chunk(&document);
+//! let chunks = chunker.chunk_document(&document, "report");
 //! ```
 
 #![cfg_attr(docsrs, feature(doc_cfg))]
@@ -111,37 +130,86 @@ pub mod index;
 pub mod prelude;
 
 mod bench;
+mod bundle;
 mod chunk;
+mod citation;
+mod container;
 mod decoder;
+mod diff;
 mod document;
 mod embedding;
 mod encoder;
 mod error;
+mod faithfulness;
+mod figures;
+mod importance;
 mod ingest;
+mod integrity;
+mod language;
+mod links;
+mod math;
 mod metrics;
+mod migrate;
 mod normalization;
 mod numguard;
 mod ocr;
+mod overlay;
+mod pii;
+mod preprocess;
+mod render;
+mod select;
+mod sentence;
 mod serializer;
 mod stats;
+mod validate;
 
 // Re-exports for public API
 pub use bench::{BenchConfig, BenchMode, BenchResult, BenchRunner, CorpusMetrics};
-pub use chunk::{ChunkConfig, ChunkMode, ChunkRecord, Chunker};
+pub use bundle::{Bundle, BundleDocInfo, BundleStats, BUNDLE_EXTENSION};
+pub use chunk::{parent_window, ChunkConfig, ChunkLevel, ChunkMode, ChunkRecord, Chunker, PageBox};
+pub use citation::{cell_id, CellIds, Citation};
+pub use container::DocumentReader;
 pub use decoder::Decoder;
+pub use diff::{
+    CellChange, ChangeKind, DiffCell, DiffOptions, DiffSummary, DocumentDiff, NumberChange,
+};
 pub use document::{
-    hash_payload, CellRecord, CellType, CodeHash, Document, Header, NumGuard, NumGuardAlert,
-    NumGuardIssue, PageInfo,
+    hash_payload, CellRecord, CellType, CodeHash, Document, DropReason, DroppedCell, Header,
+    NumGuard, NumGuardAlert, NumGuardIssue, PageInfo,
 };
-pub use embedding::{EmbeddingRecord, HashEmbedder, HashEmbedderConfig};
-pub use encoder::{EncodeInput, Encoder, EncoderBuilder, EncoderPreset};
+pub use embedding::{Embedder, EmbeddingRecord, HashEmbedder, HashEmbedderConfig};
+pub use encoder::{EncodeInput, Encoder, EncoderBuilder, EncoderConfig, EncoderPreset, PageBuffer};
 pub use error::{DcfError, Result};
+pub use faithfulness::{FaithfulnessPolicy, FaithfulnessReport, NumericClaim, NumericVerifier};
+pub use figures::{FigureConfig, FigureImage, FigureRef, PageFigure, FIGURE_DIR};
+pub use importance::{
+    importance_samples, FeatureWeights, HeuristicScorer, IdfTable, ImportanceContext,
+    ImportanceSample, ImportanceScorer, LineFeatures, LinearImportanceModel, LinearScorer,
+};
 pub use ingest::{ingest_to_index, ingest_to_index_with_opts, IngestOptions};
+pub use integrity::{
+    generate_signing_key, read_signing_key, read_verifying_key, signing_key_from_hex,
+    verifying_key_from_hex, Integrity, IntegrityStatus, SigningKey, VerifyingKey, TRUSTED_KEY_ENV,
+};
+pub use language::{
+    detect_language, ocr_languages_for, tesseract_language, LanguageGuess, LanguageTally,
+};
+pub use links::{Link, LinkKind, LinkTarget};
 pub use metrics::{cer, numeric_stats, wer, Metrics, NumStats, TokenMetrics};
+pub use migrate::{MigrationReport, MigrationStep, FORMAT_VERSION};
 pub use normalization::{HyphenationMode, ImportanceTuning};
-pub use serializer::{TableMode, TextSerializer, TextSerializerConfig};
+pub use numguard::{extract_entities, extract_entity_spans, NumericEntity, NumericKind};
+pub use ocr::{OcrBox, OcrLine, OcrPage, OcrWord};
+pub use pii::{
+    PiiKind, PiiMatch, Redaction, RedactionConfig, RedactionMode, Redactor, PSEUDONYM_KEY_ENV,
+};
+pub use preprocess::{preprocess, preprocess_file, PreprocessConfig, ScanCorrection, ScanFixture};
+pub use select::{SelectTarget, Selector};
+pub use sentence::split_sentences;
+pub use serializer::{ContextFormat, TableMode, TextSerializer, TextSerializerConfig};
 pub use stats::{estimate_tokens, Stats, TokenizerKind};
+pub use validate::{IssueKind, SectionSize, Severity, ValidationIssue, ValidationReport};
 
 // Re-export index types at crate root for convenience
-pub use index::{DocumentRecord, JsonlWriter, PageRecord};
+pub use index::{CellLink, DocumentRecord, JsonlWriter, PageRecord};
 // Note: index::CellRecord conflicts with document::CellRecord; refer to it via `index::CellRecord` explicitly.
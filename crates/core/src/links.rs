@@ -0,0 +1,637 @@
+//! Hyperlinks, cross-references and footnotes kept as spans on cells.
+//!
+//! HTML and Markdown links survive flattening as inline markers, PDF link
+//! annotations are matched to lines by position, and footnote markers in
+//! plain text are paired with the footnote lines at the foot of the page.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use lopdf::{Dictionary, Object, ObjectId};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::figures::{clean_html_text, media_box, parse_attrs, MARKER_DELIM};
+use crate::pii::PiiMatch;
+
+/// Wraps link and anchor markers while HTML is flattened to text.
+const LINK_DELIM: char = '\u{F8FE}';
+
+static LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<a\b([^>]*)>(.*?)</a\s*>").unwrap());
+static OPEN_TAG_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<[a-z][a-z0-9]*\b[^>]*>").unwrap());
+/// pulldown-cmark puts a footnote's label in its own `<sup>` ahead of the body.
+static MD_FOOTNOTE_LABEL_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<sup class="footnote-definition-label">([^<]*)</sup>\s*<p>"#).unwrap()
+});
+static FOOTNOTE_LABEL_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\[?\^?(?:\d{1,3}|[a-z]|[*†‡§]{1,3})\]?$").unwrap());
+static FOOTNOTE_DEF_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:\[\^?([^\]\s]{1,8})\]:?|(\d{1,3}|[⁰¹²³⁴⁵⁶⁷⁸⁹]{1,3}|[*†‡]{1,3}))\s+\S").unwrap()
+});
+/// Plain digits after a word ("Q3", "CO2") are not markers; a marker is
+/// bracketed, follows a caret, or is superscript.
+static FOOTNOTE_REF_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"\[\^?([^\]\s]{1,8})\]|(?:^|[^\p{N}])(\^(\d{1,3}))(?:[^\p{N}]|$)|[\p{L}\p{Pe}.,;:’”"']([⁰¹²³⁴⁵⁶⁷⁸⁹]{1,3}|[*†‡]{1,3})"#,
+    )
+    .unwrap()
+});
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkKind {
+    /// Points outside the document (web page, mail address).
+    Hyperlink,
+    /// Points at another place in the same document.
+    CrossRef,
+    /// Footnote marker pointing at the footnote text.
+    Footnote,
+}
+
+impl LinkKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Hyperlink => "hyperlink",
+            Self::CrossRef => "cross_ref",
+            Self::Footnote => "footnote",
+        }
+    }
+}
+
+impl fmt::Display for LinkKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkTarget {
+    Url(String),
+    /// A page of the same document, by `z`.
+    Page(u32),
+    /// A cell of the same document, by position.
+    Cell {
+        z: u32,
+        x: i32,
+        y: i32,
+    },
+}
+
+impl fmt::Display for LinkTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Url(url) => f.write_str(url),
+            Self::Page(z) => write!(f, "page(z={z})"),
+            Self::Cell { z, x, y } => write!(f, "cell(z={z},x={x},y={y})"),
+        }
+    }
+}
+
+/// A span of a cell's payload that links somewhere.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Link {
+    /// Position of the cell holding the anchor text.
+    pub z: u32,
+    pub x: i32,
+    pub y: i32,
+    /// Byte offsets of the anchor text in the cell payload.
+    pub start: usize,
+    pub end: usize,
+    pub kind: LinkKind,
+    pub target: LinkTarget,
+}
+
+impl Link {
+    pub fn source(&self) -> (u32, i32, i32) {
+        (self.z, self.x, self.y)
+    }
+
+    /// The linked text, when the offsets fit `payload`.
+    pub fn anchor_text<'a>(&self, payload: &'a str) -> Option<&'a str> {
+        payload.get(self.start..self.end)
+    }
+}
+
+/// Where a link found in the source points, before layout.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SourceTarget {
+    Url(String),
+    /// A fragment (`#id`) resolved once every page is laid out.
+    Anchor(String),
+    Page(u32),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct SourceLink {
+    pub kind: LinkKind,
+    pub target: SourceTarget,
+    /// Vertical centre of a PDF link rectangle as a fraction of the page
+    /// height; `None` for links carried by inline markers.
+    pub area: Option<f32>,
+}
+
+/// Links and named anchors of one input, referenced by marker ids.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SourceLinks {
+    pub links: Vec<SourceLink>,
+    pub anchors: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum SpanTarget {
+    /// Index into the page's [`SourceLinks::links`].
+    Source(usize),
+    /// Another line of the same page (a footnote).
+    Line(usize),
+}
+
+/// Byte span of a laid-out line that links somewhere.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct LineSpan {
+    pub start: usize,
+    pub end: usize,
+    pub target: SpanTarget,
+}
+
+impl LineSpan {
+    pub fn overlaps(&self, other: &LineSpan) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+}
+
+/// Link spans and anchor ids taken out of one line.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct LineMarks {
+    pub spans: Vec<LineSpan>,
+    pub anchors: Vec<usize>,
+}
+
+/// A link placed on a cell whose target may name an anchor on a later page.
+#[derive(Debug, Clone)]
+pub(crate) struct PendingLink {
+    pub z: u32,
+    pub x: i32,
+    pub y: i32,
+    pub start: usize,
+    pub end: usize,
+    pub kind: LinkKind,
+    pub target: Result<LinkTarget, String>,
+}
+
+/// Replaces `<a href>` elements with markers around their text and marks the
+/// elements that in-page links point at, so both survive `html2text`.
+pub(crate) fn extract_html_links(html: &str) -> (String, SourceLinks) {
+    let html = MD_FOOTNOTE_LABEL_RE.replace_all(html, "<p>$1 ");
+    let mut sources = SourceLinks::default();
+    let referenced: HashSet<String> = LINK_RE
+        .captures_iter(&html)
+        .filter_map(|caps| parse_attrs(&caps[1]).remove("href"))
+        .filter_map(|href| href.trim().strip_prefix('#').map(str::to_string))
+        .filter(|id| !id.is_empty())
+        .collect();
+    let anchored = if referenced.is_empty() {
+        html.into_owned()
+    } else {
+        OPEN_TAG_RE
+            .replace_all(&html, |caps: &regex::Captures| {
+                let tag = &caps[0];
+                let attrs = parse_attrs(tag);
+                let Some(id) = attrs
+                    .get("id")
+                    .or_else(|| attrs.get("name"))
+                    .filter(|id| referenced.contains(*id) && !sources.anchors.contains(id))
+                else {
+                    return tag.to_string();
+                };
+                let marker = format!("{LINK_DELIM}#{}{LINK_DELIM}", sources.anchors.len());
+                sources.anchors.push(id.clone());
+                format!("{tag}{marker}")
+            })
+            .into_owned()
+    };
+    let linked = LINK_RE.replace_all(&anchored, |caps: &regex::Captures| {
+        let inner = &caps[2];
+        let attrs = parse_attrs(&caps[1]);
+        let label = strip_text(&clean_html_text(inner));
+        let href = attrs.get("href").map_or("", |href| href.trim());
+        if label.is_empty() || inner.contains(MARKER_DELIM) {
+            return inner.to_string();
+        }
+        let (kind, target) = match href.strip_prefix('#') {
+            Some("") => return inner.to_string(),
+            Some(fragment) => {
+                let noteref = FOOTNOTE_LABEL_RE.is_match(&label)
+                    || ["class", "role"].iter().any(|key| {
+                        attrs.get(*key).is_some_and(|value| {
+                            value.contains("footnote") || value.contains("noteref")
+                        })
+                    });
+                let kind = if noteref {
+                    LinkKind::Footnote
+                } else {
+                    LinkKind::CrossRef
+                };
+                (kind, SourceTarget::Anchor(fragment.to_string()))
+            }
+            None => {
+                let scheme = href.split(':').next().unwrap_or("").to_ascii_lowercase();
+                if href.is_empty() || scheme == "javascript" || scheme == "data" {
+                    return inner.to_string();
+                }
+                (LinkKind::Hyperlink, SourceTarget::Url(href.to_string()))
+            }
+        };
+        let id = sources.links.len();
+        sources.links.push(SourceLink {
+            kind,
+            target,
+            area: None,
+        });
+        format!("{LINK_DELIM}{id}{LINK_DELIM}{inner}{LINK_DELIM}/{LINK_DELIM}")
+    });
+    (linked.into_owned(), sources)
+}
+
+/// Removes link markers from `line`, returning the clean text and what the
+/// markers said. `open` carries a link that wraps onto the next line.
+pub(crate) fn strip_markers(line: &str, open: &mut Option<usize>) -> (String, LineMarks) {
+    let mut marks = LineMarks::default();
+    let mut text = String::with_capacity(line.len());
+    let mut start = open.map(|_| 0);
+    for (idx, part) in line.split(LINK_DELIM).enumerate() {
+        if idx % 2 == 0 {
+            text.push_str(part);
+        } else if part == "/" {
+            if let (Some(id), Some(from)) = (open.take(), start.take()) {
+                push_span(&mut marks.spans, from, text.len(), SpanTarget::Source(id));
+            }
+        } else if let Some(anchor) = part.strip_prefix('#') {
+            marks.anchors.extend(anchor.parse::<usize>().ok());
+        } else if let Ok(id) = part.parse::<usize>() {
+            *open = Some(id);
+            start = Some(text.len());
+        }
+    }
+    if let (Some(id), Some(from)) = (*open, start) {
+        push_span(&mut marks.spans, from, text.len(), SpanTarget::Source(id));
+    }
+    let trimmed = text.trim();
+    if trimmed.len() == text.len() {
+        return (text, marks);
+    }
+    let lead = text.len() - text.trim_start().len();
+    for span in &mut marks.spans {
+        span.start = span.start.saturating_sub(lead).min(trimmed.len());
+        span.end = span.end.saturating_sub(lead).min(trimmed.len());
+    }
+    marks.spans.retain(|span| span.start < span.end);
+    (trimmed.to_string(), marks)
+}
+
+fn push_span(spans: &mut Vec<LineSpan>, start: usize, end: usize, target: SpanTarget) {
+    if start < end {
+        spans.push(LineSpan { start, end, target });
+    }
+}
+
+/// Superscript labels key the same footnote as their plain digits.
+fn footnote_label(label: &str) -> String {
+    label
+        .chars()
+        .map(|c| match "⁰¹²³⁴⁵⁶⁷⁸⁹".chars().position(|sup| sup == c) {
+            Some(digit) => char::from(b'0' + digit as u8),
+            None => c,
+        })
+        .collect()
+}
+
+/// `line` without any link markers.
+pub(crate) fn strip_text(line: &str) -> String {
+    line.split(LINK_DELIM).step_by(2).collect()
+}
+
+/// Pairs footnote markers ("revenue²", `revenue[^2]`, "revenue[2]",
+/// "revenue^2") with footnote lines ("2 Unaudited.") in the bottom half of the
+/// same page. A marker only counts when its footnote follows it, which keeps
+/// stray markers unlinked.
+pub(crate) fn footnote_spans(lines: &[&str]) -> Vec<(usize, LineSpan)> {
+    let mut notes: HashMap<String, usize> = HashMap::new();
+    for (idx, line) in lines.iter().enumerate().skip(lines.len() / 2) {
+        if let Some(caps) = FOOTNOTE_DEF_RE.captures(line) {
+            if let Some(label) = caps.get(1).or_else(|| caps.get(2)) {
+                notes.entry(footnote_label(label.as_str())).or_insert(idx);
+            }
+        }
+    }
+    if notes.is_empty() {
+        return Vec::new();
+    }
+    let mut spans = Vec::new();
+    for (idx, line) in lines.iter().enumerate() {
+        for caps in FOOTNOTE_REF_RE.captures_iter(line) {
+            let (label, span) = match (caps.get(1), caps.get(2), caps.get(3), caps.get(4)) {
+                (Some(label), ..) => (label, caps.get(0).unwrap_or(label)),
+                (None, Some(span), Some(label), _) => (label, span),
+                (None, None, None, Some(label)) => (label, label),
+                _ => continue,
+            };
+            let Some(&note) = notes
+                .get(&footnote_label(label.as_str()))
+                .filter(|note| **note > idx)
+            else {
+                continue;
+            };
+            spans.push((
+                idx,
+                LineSpan {
+                    start: span.start(),
+                    end: span.end(),
+                    target: SpanTarget::Line(note),
+                },
+            ));
+        }
+    }
+    spans
+}
+
+/// Moves span offsets from a line onto its redacted form.
+pub(crate) fn shift_spans<'a>(spans: &mut [LineSpan], matches: impl Iterator<Item = &'a PiiMatch>) {
+    let mut matches: Vec<&PiiMatch> = matches.collect();
+    if matches.is_empty() {
+        return;
+    }
+    matches.sort_by_key(|m| m.start);
+    for span in spans {
+        span.start = shifted(span.start, &matches, false);
+        span.end = shifted(span.end, &matches, true);
+    }
+}
+
+/// A position inside a replaced value snaps to the replacement's edge.
+fn shifted(pos: usize, matches: &[&PiiMatch], is_end: bool) -> usize {
+    let mut delta = 0isize;
+    for m in matches {
+        let replacement = m.replacement.len() as isize;
+        if m.end <= pos {
+            delta += replacement - (m.end - m.start) as isize;
+        } else if m.start < pos {
+            let edge = if is_end { replacement } else { 0 };
+            return (m.start as isize + delta + edge).max(0) as usize;
+        }
+    }
+    (pos as isize + delta).max(0) as usize
+}
+
+/// Resolves fragment targets against the anchors found on every page;
+/// links to fragments that never appeared are dropped.
+pub(crate) fn resolve(
+    pending: Vec<PendingLink>,
+    anchors: &HashMap<String, (u32, i32, i32)>,
+) -> Vec<Link> {
+    pending
+        .into_iter()
+        .filter_map(|link| {
+            let target = match link.target {
+                Ok(target) => target,
+                Err(fragment) => {
+                    let &(z, x, y) = anchors.get(&fragment)?;
+                    LinkTarget::Cell { z, x, y }
+                }
+            };
+            Some(Link {
+                z: link.z,
+                x: link.x,
+                y: link.y,
+                start: link.start,
+                end: link.end,
+                kind: link.kind,
+                target,
+            })
+        })
+        .collect()
+}
+
+/// Link annotations of one PDF page; `pages` maps page objects to their `z`.
+pub(crate) fn pdf_page_links(
+    doc: &lopdf::Document,
+    page_id: ObjectId,
+    pages: &HashMap<ObjectId, u32>,
+) -> Vec<SourceLink> {
+    let Ok(annots) = doc
+        .get_dictionary(page_id)
+        .and_then(|page| page.get_deref(b"Annots", doc))
+        .and_then(Object::as_array)
+    else {
+        return Vec::new();
+    };
+    let [_, my0, _, my1] = media_box(doc, page_id).unwrap_or([0.0, 0.0, 612.0, 792.0]);
+    let height = (my1 - my0).abs().max(1.0);
+    let mut links = Vec::new();
+    for annot in annots {
+        let annot = match annot {
+            Object::Reference(id) => doc.get_dictionary(*id),
+            other => other.as_dict(),
+        };
+        let Ok(annot) = annot else {
+            continue;
+        };
+        if annot.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link".as_slice()) {
+            continue;
+        }
+        let Some(target) = pdf_link_target(doc, annot, pages) else {
+            continue;
+        };
+        let centre = annot
+            .get_deref(b"Rect", doc)
+            .and_then(Object::as_array)
+            .ok()
+            .map(|rect| {
+                rect.iter()
+                    .filter_map(|o| o.as_float().ok())
+                    .collect::<Vec<_>>()
+            })
+            .filter(|values| values.len() == 4)
+            .map_or(0.0, |v| {
+                ((my1 - (v[1] + v[3]) / 2.0) / height).clamp(0.0, 1.0)
+            });
+        let kind = match target {
+            SourceTarget::Url(_) => LinkKind::Hyperlink,
+            _ => LinkKind::CrossRef,
+        };
+        links.push(SourceLink {
+            kind,
+            target,
+            area: Some(centre),
+        });
+    }
+    links
+}
+
+fn pdf_link_target(
+    doc: &lopdf::Document,
+    annot: &Dictionary,
+    pages: &HashMap<ObjectId, u32>,
+) -> Option<SourceTarget> {
+    let Ok(action) = annot.get_deref(b"A", doc).and_then(Object::as_dict) else {
+        return pdf_destination(annot.get_deref(b"Dest", doc).ok()?, pages);
+    };
+    match action.get(b"S").and_then(Object::as_name).ok()? {
+        b"URI" => {
+            let uri = action
+                .get_deref(b"URI", doc)
+                .and_then(Object::as_str)
+                .ok()?;
+            let uri = String::from_utf8_lossy(uri).trim().to_string();
+            (!uri.is_empty()).then_some(SourceTarget::Url(uri))
+        }
+        b"GoTo" => pdf_destination(action.get_deref(b"D", doc).ok()?, pages),
+        _ => None,
+    }
+}
+
+/// Explicit destinations only; named destinations are not looked up.
+fn pdf_destination(dest: &Object, pages: &HashMap<ObjectId, u32>) -> Option<SourceTarget> {
+    let page = dest.as_array().ok()?.first()?.as_reference().ok()?;
+    pages.get(&page).map(|z| SourceTarget::Page(*z))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pii::PiiKind;
+    use lopdf::dictionary;
+
+    #[test]
+    fn html_links_become_spans_after_flattening() {
+        let html = r##"<p>See <a href="https://example.org/r">the report</a> and
+<a href="#s2">section two</a>.<sup><a href="#fn1">1</a></sup></p>
+<h2 id="s2">Section two</h2>
+<p id="fn1">1 Audited figures.</p>
+<a href="javascript:void(0)">noop</a>"##;
+        let (marked, sources) = extract_html_links(html);
+        assert_eq!(sources.anchors, vec!["s2", "fn1"]);
+        assert_eq!(sources.links.len(), 3);
+        assert_eq!(
+            sources.links[0].target,
+            SourceTarget::Url("https://example.org/r".to_string())
+        );
+        assert_eq!(sources.links[1].kind, LinkKind::CrossRef);
+        assert_eq!(sources.links[2].kind, LinkKind::Footnote);
+
+        let flat = html2text::from_read(marked.as_bytes(), 80);
+        let mut open = None;
+        let (text, marks) = strip_markers(flat.lines().next().unwrap(), &mut open);
+        assert_eq!(
+            &text[marks.spans[0].start..marks.spans[0].end],
+            "the report"
+        );
+        assert!(!text.contains(LINK_DELIM) && !text.contains("noop"));
+        assert!(strip_text(&flat).contains("noop"));
+    }
+
+    #[test]
+    fn wrapped_links_continue_on_the_next_line() {
+        let mut open = None;
+        let (first, marks) = strip_markers("read \u{F8FE}0\u{F8FE}the annual", &mut open);
+        assert_eq!(&first[marks.spans[0].start..], "the annual");
+        assert_eq!(open, Some(0));
+        let (second, marks) = strip_markers("report\u{F8FE}/\u{F8FE} now", &mut open);
+        assert_eq!(second, "report now");
+        assert_eq!((marks.spans[0].start, marks.spans[0].end), (0, 6));
+        assert_eq!(open, None);
+        let (anchor, marks) = strip_markers("\u{F8FE}#3\u{F8FE} Heading", &mut open);
+        assert_eq!((anchor.as_str(), marks.anchors), ("Heading", vec![3]));
+    }
+
+    #[test]
+    fn footnote_markers_pair_with_page_foot() {
+        let lines = [
+            "Revenue grew strongly¹ while costs fell.",
+            "Version 2.1 shipped[^b] in May.",
+            "Margins held[3] and cash^4 too.",
+            "Q2 sales and CO3 output rose 4.",
+            "Body text continues here.",
+            "Filler line.",
+            "1 Unaudited.",
+            "[^b]: Internal build.",
+            "2 Restated.",
+            "3 Adjusted.",
+            "⁴ Excluding leases.",
+        ];
+        let found: Vec<(usize, &str, SpanTarget)> = footnote_spans(&lines)
+            .into_iter()
+            .map(|(idx, span)| (idx, &lines[idx][span.start..span.end], span.target))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (0, "¹", SpanTarget::Line(6)),
+                (1, "[^b]", SpanTarget::Line(7)),
+                (2, "[3]", SpanTarget::Line(9)),
+                (2, "^4", SpanTarget::Line(10)),
+            ]
+        );
+    }
+
+    #[test]
+    fn spans_follow_redaction() {
+        let mut spans = vec![LineSpan {
+            start: 27,
+            end: 31,
+            target: SpanTarget::Source(0),
+        }];
+        let email = PiiMatch {
+            kind: PiiKind::Email,
+            start: 5,
+            end: 22,
+            replacement: "[EMAIL]".to_string(),
+            fingerprint: String::new(),
+        };
+        shift_spans(&mut spans, [&email].into_iter());
+        assert_eq!((spans[0].start, spans[0].end), (17, 21));
+    }
+
+    #[test]
+    fn pdf_annotations_resolve_uris_and_pages() {
+        let mut doc = lopdf::Document::with_version("1.5");
+        let pages_id = doc.new_object_id();
+        let second = doc.add_object(dictionary! { "Type" => "Page", "Parent" => pages_id });
+        let uri = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![72.into(), 692.into(), 300.into(), 712.into()],
+            "A" => dictionary! {
+                "S" => "URI",
+                "URI" => Object::string_literal("https://example.org"),
+            },
+        };
+        let goto = dictionary! {
+            "Type" => "Annot",
+            "Subtype" => "Link",
+            "Rect" => vec![72.into(), 92.into(), 300.into(), 112.into()],
+            "Dest" => vec![second.into(), "Fit".into()],
+        };
+        let uri_id = doc.add_object(uri);
+        let first = doc.add_object(dictionary! {
+            "Type" => "Page",
+            "Parent" => pages_id,
+            "MediaBox" => vec![0.into(), 0.into(), 612.into(), 792.into()],
+            "Annots" => vec![uri_id.into(), goto.into()],
+        });
+        let pages = HashMap::from([(first, 0), (second, 1)]);
+
+        let links = pdf_page_links(&doc, first, &pages);
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            links[0].target,
+            SourceTarget::Url("https://example.org".to_string())
+        );
+        assert!(links[0].area.unwrap() < 0.15);
+        assert_eq!(links[1].target, SourceTarget::Page(1));
+        assert_eq!(links[1].kind, LinkKind::CrossRef);
+        assert!(links[1].area.unwrap() > 0.85);
+    }
+}
@@ -0,0 +1,461 @@
+//! Equations kept as LaTeX source through text flattening and normalization.
+//!
+//! TeX, Markdown (`$...$`, `$$...$$`, `\(...\)`, `\[...\]`) and HTML
+//! (MathML, MathJax scripts and delimiters) formulas are swapped for markers
+//! before the input is flattened, so neither HTML rendering nor NFKC touches
+//! them. Display formulas become Equation cells; inline ones are restored as
+//! `$...$` in the surrounding text. Math recovered from PDFs has no source,
+//! so lines that look like formulas only skip NFKC and are typed Equation.
+
+use once_cell::sync::Lazy;
+use regex::{Captures, Regex};
+
+use crate::figures::{clean_html_text, parse_attrs, unescape_entities};
+
+/// Wraps formula markers while the input is flattened to text.
+const MATH_DELIM: char = '\u{F8FD}';
+
+/// Environments whose whole source is kept; single equations keep only their body.
+const TEX_ENVIRONMENTS: [&str; 16] = [
+    "equation",
+    "equation*",
+    "displaymath",
+    "math",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "flalign",
+    "flalign*",
+    "alignat",
+    "alignat*",
+];
+
+static MARKDOWN_MATH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)(?P<keep>```.*?```|~~~.*?~~~|`[^`\n]+`|\\\$)|\$\$(?P<dd>.+?)\$\$|\\\[(?P<bd>.+?)\\\]|\\\((?P<bi>.+?)\\\)|\$(?P<di>[^\s$](?:[^$\n]*?[^\s$\\])?)\$",
+    )
+    .unwrap()
+});
+static HTML_MATH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?is)(?P<keep><pre\b.*?</pre\s*>|<code\b.*?</code\s*>|<script\b.*?</script\s*>|<style\b.*?</style\s*>|<textarea\b.*?</textarea\s*>|\\\$)|\$\$(?P<dd>.+?)\$\$|\\\[(?P<bd>.+?)\\\]|\\\((?P<bi>.+?)\\\)",
+    )
+    .unwrap()
+});
+static TEX_MATH_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r"(?s)(?P<keep>\\[$%]|%[^\n]*|\\begin\{verbatim\}.*?\\end\{verbatim\})|\$\$(?P<dd>.+?)\$\$|\\\[(?P<bd>.+?)\\\]|\\\((?P<bi>.+?)\\\)|\$(?P<di>[^$]+?)\$",
+    )
+    .unwrap()
+});
+static MATHML_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"(?is)<math\b([^>]*)>(.*?)</math\s*>").unwrap());
+static TEX_ANNOTATION_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<annotation\b[^>]*encoding\s*=\s*["']application/x-tex["'][^>]*>(.*?)</annotation\s*>"#)
+        .unwrap()
+});
+static MATH_SCRIPT_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r#"(?is)<script\b[^>]*type\s*=\s*["']math/tex(; *mode=display)?["'][^>]*>(.*?)</script\s*>"#)
+        .unwrap()
+});
+static INLINE_MARKER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new("\u{F8FD}([0-9]+)\u{F8FD}").unwrap());
+
+/// A formula lifted out of the input, referenced by its marker index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Formula {
+    pub tex: String,
+    pub display: bool,
+}
+
+/// Replaces Markdown math with markers, skipping code spans and fences.
+/// Display math on its own line becomes its own block at the line's indent,
+/// so it stays inside a list item; inside a table row or a sentence it is
+/// kept inline, since blank lines would split the row or the list.
+pub(crate) fn protect_markdown(markdown: &str) -> (String, Vec<Formula>) {
+    let mut formulas = Vec::new();
+    let out = protect_delimited(
+        &MARKDOWN_MATH_RE,
+        markdown,
+        &mut formulas,
+        true,
+        |marker, indent| format!("\n\n{indent}{marker}\n\n"),
+    );
+    (out, formulas)
+}
+
+/// Replaces MathML, MathJax scripts and TeX delimiters in HTML with markers.
+pub(crate) fn protect_html(html: &str) -> (String, Vec<Formula>) {
+    let mut formulas = Vec::new();
+    let html = MATHML_RE.replace_all(html, |caps: &Captures| {
+        let attrs = parse_attrs(&caps[1]);
+        let display = attrs.get("display").is_some_and(|v| v == "block")
+            || attrs.get("mode").is_some_and(|v| v == "display");
+        let tex = match TEX_ANNOTATION_RE.captures(&caps[2]) {
+            Some(annotation) => unescape_entities(annotation[1].trim()),
+            None => clean_html_text(&caps[2]),
+        };
+        html_marker(push(&mut formulas, tex, display))
+    });
+    let html = MATH_SCRIPT_RE.replace_all(&html, |caps: &Captures| {
+        let tex = unescape_entities(caps[2].trim());
+        html_marker(push(&mut formulas, tex, caps.get(1).is_some()))
+    });
+    let out = protect_delimited(&HTML_MATH_RE, &html, &mut formulas, false, |marker, _| {
+        format!("<p>{marker}</p>")
+    });
+    (out, formulas)
+}
+
+/// Replaces math environments and delimiters in LaTeX source with markers.
+pub(crate) fn protect_tex(src: &str) -> (String, Vec<Formula>) {
+    let mut formulas = Vec::new();
+    let mut out = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some(pos) = rest.find("\\begin{") {
+        let after = &rest[pos + "\\begin{".len()..];
+        let name = after.split('}').next().unwrap_or_default();
+        let end_tag = format!("\\end{{{name}}}");
+        let end = TEX_ENVIRONMENTS
+            .contains(&name)
+            .then(|| after.find(&end_tag))
+            .flatten();
+        let Some(end) = end else {
+            out.push_str(&rest[..pos + "\\begin{".len()]);
+            rest = after;
+            continue;
+        };
+        out.push_str(&rest[..pos]);
+        let body = &after[name.len() + 1..end];
+        let tex = if matches!(name, "equation" | "equation*" | "displaymath" | "math") {
+            body.trim().to_string()
+        } else {
+            rest[pos..pos + "\\begin{".len() + end + end_tag.len()].to_string()
+        };
+        let display = name != "math";
+        let marker = push(&mut formulas, tex, display);
+        if display {
+            out.push_str(&format!("\n{marker}\n"));
+        } else {
+            out.push_str(&marker);
+        }
+        rest = &after[end + end_tag.len()..];
+    }
+    out.push_str(rest);
+    let out = protect_delimited(&TEX_MATH_RE, &out, &mut formulas, false, |marker, _| {
+        format!("\n{marker}\n")
+    });
+    (out, formulas)
+}
+
+/// Puts inline formulas back as `$...$`; display markers are left alone.
+pub(crate) fn restore_inline(line: &str, formulas: &[Formula]) -> String {
+    if !line.contains(MATH_DELIM) {
+        return line.to_string();
+    }
+    INLINE_MARKER_RE
+        .replace_all(line, |caps: &Captures| {
+            match caps[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| formulas.get(idx))
+            {
+                Some(formula) => format!("${}$", formula.tex),
+                None => String::new(),
+            }
+        })
+        .into_owned()
+}
+
+/// Index of the formula when `line` is a display marker.
+pub(crate) fn parse_display(line: &str) -> Option<usize> {
+    line.trim()
+        .strip_prefix(MATH_DELIM)?
+        .strip_suffix(MATH_DELIM)?
+        .strip_prefix("math:")?
+        .parse()
+        .ok()
+}
+
+/// Line with every formula written out as TeX, for plain-text export.
+pub(crate) fn render_text(line: &str, formulas: &[Formula]) -> String {
+    match parse_display(line).and_then(|idx| formulas.get(idx)) {
+        Some(formula) => format!("$${}$$", formula.tex),
+        None => restore_inline(line, formulas),
+    }
+}
+
+/// Lines of Unicode math, as PDFs render formulas: operators, math letters or
+/// Greek, super/subscripts, and hardly any words. Arrows alone do not make a
+/// formula ("A → B" lists), Greek words are prose, not variables, and a single
+/// symbol in front of a figure ("≈ 4.2 bn", "± 0.5") is still a figure.
+pub(crate) fn looks_like_math(line: &str) -> bool {
+    let mut strong = 0usize;
+    let mut symbols = 0usize;
+    let mut operators = 0usize;
+    let mut letters = 0usize;
+    for word in line.split_whitespace() {
+        let greek_word = word.chars().filter(|c| is_greek(*c)).count() > 2;
+        for ch in word.chars() {
+            if is_arrow(ch) {
+                strong += 1;
+            } else if is_math_operator(ch) {
+                operators += 1;
+                if !ch.is_ascii() {
+                    symbols += 1;
+                }
+            } else if is_math_letter(ch) || (is_greek(ch) && !greek_word) {
+                strong += 1;
+            } else if ch.is_alphabetic() {
+                letters += 1;
+            }
+        }
+    }
+    let words = line
+        .split_whitespace()
+        .filter(|word| word.chars().filter(|c| c.is_alphabetic()).count() >= 4)
+        .count();
+    if symbols > 1 {
+        strong += symbols;
+    }
+    strong > 0 && operators > 0 && words <= 2 && (strong + operators) * 2 >= letters
+}
+
+fn is_math_operator(ch: char) -> bool {
+    matches!(
+        ch,
+        '=' | '+' | '<' | '>' | '^' | '±' | '×' | '÷' | '¬' | '·'
+    ) || matches!(ch as u32, 0x2200..=0x22FF | 0x2A00..=0x2AFF | 0x27C0..=0x27EF | 0x2980..=0x29FF)
+}
+
+fn is_arrow(ch: char) -> bool {
+    matches!(ch as u32, 0x2190..=0x21FF | 0x27F0..=0x27FF | 0x2900..=0x297F)
+}
+
+fn is_greek(ch: char) -> bool {
+    matches!(ch as u32, 0x0391..=0x03C9)
+}
+
+/// Super/subscripts, mathematical alphanumerics, and the letterlike symbols
+/// used in formulas (ℝ, ℓ, ℏ, ℵ), but not ™, ℃ or №.
+fn is_math_letter(ch: char) -> bool {
+    matches!(ch, '¹' | '²' | '³')
+        || matches!(
+            ch as u32,
+            0x2070..=0x209F
+                | 0x2102
+                | 0x210A..=0x2113
+                | 0x2115
+                | 0x2118..=0x211D
+                | 0x2124
+                | 0x2128
+                | 0x212C..=0x212D
+                | 0x212F..=0x2131
+                | 0x2133..=0x2138
+                | 0x213C..=0x2149
+                | 0x1D400..=0x1D7FF
+        )
+}
+
+fn protect_delimited(
+    re: &Regex,
+    text: &str,
+    formulas: &mut Vec<Formula>,
+    display_needs_own_line: bool,
+    display_marker: impl Fn(&str, &str) -> String,
+) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut last = 0;
+    for caps in re.captures_iter(text) {
+        let whole = caps.get(0).expect("match");
+        out.push_str(&text[last..whole.start()]);
+        last = whole.end();
+        if caps.name("keep").is_some() {
+            out.push_str(whole.as_str());
+            continue;
+        }
+        // `$5 and $10` are prices, not math.
+        if caps.name("di").is_some()
+            && text[whole.end()..]
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_digit())
+        {
+            out.push_str(whole.as_str());
+            continue;
+        }
+        let (tex, display) = match (
+            caps.name("dd").or(caps.name("bd")),
+            caps.name("bi").or(caps.name("di")),
+        ) {
+            (Some(tex), _) => (tex.as_str(), true),
+            (None, Some(tex)) => (tex.as_str(), false),
+            (None, None) => unreachable!("math pattern without a body"),
+        };
+        let line_start = text[..whole.start()].rfind('\n').map_or(0, |pos| pos + 1);
+        let indent = &text[line_start..whole.start()];
+        let rest_of_line = text[whole.end()..].split('\n').next().unwrap_or_default();
+        let display = display
+            && (!display_needs_own_line
+                || (indent.trim().is_empty() && rest_of_line.trim().is_empty()));
+        let tex = unescape_entities(tex.trim());
+        if tex.is_empty() {
+            out.push_str(whole.as_str());
+            continue;
+        }
+        let marker = push(formulas, tex, display);
+        if display {
+            out.push_str(&display_marker(&marker, indent));
+        } else {
+            out.push_str(&marker);
+        }
+    }
+    out.push_str(&text[last..]);
+    out
+}
+
+fn push(formulas: &mut Vec<Formula>, tex: String, display: bool) -> String {
+    formulas.push(Formula { tex, display });
+    let idx = formulas.len() - 1;
+    if display {
+        format!("{MATH_DELIM}math:{idx}{MATH_DELIM}")
+    } else {
+        format!("{MATH_DELIM}{idx}{MATH_DELIM}")
+    }
+}
+
+fn html_marker(marker: String) -> String {
+    if parse_display(&marker).is_some() {
+        format!("<p>{marker}</p>")
+    } else {
+        marker
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn restored(text: &str, formulas: &[Formula]) -> Vec<String> {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| render_text(line, formulas))
+            .collect()
+    }
+
+    #[test]
+    fn markdown_math_is_lifted_outside_code() {
+        let md = "Energy $E = mc^2$ costs $5 and $10.\n\n$$\\int_0^1 x\\,dx$$\n\n`$not math$`";
+        let (text, formulas) = protect_markdown(md);
+        assert_eq!(
+            formulas,
+            vec![
+                Formula {
+                    tex: "E = mc^2".into(),
+                    display: false
+                },
+                Formula {
+                    tex: "\\int_0^1 x\\,dx".into(),
+                    display: true
+                },
+            ]
+        );
+        assert_eq!(
+            restored(&text, &formulas),
+            vec![
+                "Energy $E = mc^2$ costs $5 and $10.",
+                "$$\\int_0^1 x\\,dx$$",
+                "`$not math$`",
+            ]
+        );
+    }
+
+    #[test]
+    fn markdown_display_math_keeps_lists_and_tables() {
+        let md = "1. First\n\n   $$a + b$$\n\n2. Second\n\n| x | $$c$$ |\n| - | - |\n";
+        let (text, formulas) = protect_markdown(md);
+        assert_eq!(
+            formulas.iter().map(|f| f.display).collect::<Vec<_>>(),
+            vec![true, false]
+        );
+        assert!(text.contains("\n   \u{F8FD}math:0\u{F8FD}\n"), "{text:?}");
+        assert!(
+            text.contains("| x | \u{F8FD}1\u{F8FD} |\n| - | - |"),
+            "{text:?}"
+        );
+        let mut html = String::new();
+        pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(&text));
+        assert_eq!(html.matches("<ol>").count(), 1, "{html}");
+    }
+
+    #[test]
+    fn html_math_prefers_the_tex_annotation() {
+        let html = concat!(
+            r#"<p>Let <math><semantics><mi>x</mi><annotation encoding="application/x-tex">x &lt; 1</annotation></semantics></math>.</p>"#,
+            r#"<math display="block"><mi>a</mi><mo>+</mo><mi>b</mi></math>"#,
+            r#"<script type="math/tex; mode=display">\sum_i a_i</script>"#,
+            r"<p>\(y\) and <code>\(kept\)</code></p>",
+        );
+        let (html, formulas) = protect_html(html);
+        let tex: Vec<(&str, bool)> = formulas
+            .iter()
+            .map(|f| (f.tex.as_str(), f.display))
+            .collect();
+        assert_eq!(
+            tex,
+            vec![
+                ("x < 1", false),
+                ("a + b", true),
+                ("\\sum_i a_i", true),
+                ("y", false)
+            ]
+        );
+        assert!(html.contains(r"<code>\(kept\)</code>"));
+    }
+
+    #[test]
+    fn tex_environments_keep_their_source() {
+        let src = "Intro $a^2$ and 50\\% off % a $comment$\n\\begin{equation}\n  e^{i\\pi} + 1 = 0\n\\end{equation}\n\\begin{align}\nx &= 1 \\\\\ny &= 2\n\\end{align}\n\\begin{itemize}\\item no\\end{itemize}";
+        let (text, formulas) = protect_tex(src);
+        assert_eq!(formulas.len(), 3);
+        assert_eq!(formulas[0].tex, "e^{i\\pi} + 1 = 0");
+        assert!(formulas[1].tex.starts_with("\\begin{align}"));
+        assert!(formulas[1].tex.ends_with("\\end{align}"));
+        assert_eq!(formulas[2].tex, "a^2");
+        assert!(text.contains("\\begin{itemize}"));
+        assert!(text.contains("% a $comment$"));
+        assert_eq!(
+            restore_inline(text.lines().next().unwrap(), &formulas),
+            "Intro $a^2$ and 50\\% off % a $comment$"
+        );
+    }
+
+    #[test]
+    fn unicode_math_lines_are_detected() {
+        assert!(looks_like_math("∑ᵢ αᵢ² ≤ 1"));
+        assert!(looks_like_math("𝑓(𝑥) = ∫ 𝑔(𝑡) 𝑑𝑡"));
+        assert!(!looks_like_math("Revenue grew 12% to $4.2M."));
+        assert!(!looks_like_math(
+            "The parameter α controls the learning rate = decay"
+        ));
+    }
+
+    #[test]
+    fn greek_prose_and_arrow_lists_are_not_math() {
+        assert!(!looks_like_math("Η αγορά → ανάπτυξη"));
+        assert!(!looks_like_math("Ο δείκτης αυξήθηκε + 3"));
+        assert!(!looks_like_math("A → B"));
+        assert!(!looks_like_math("• Input → Output"));
+        assert!(!looks_like_math("Acme™ + partners"));
+        assert!(!looks_like_math("≈ 4.2 bn"));
+        assert!(!looks_like_math("Tolerance ± 0.5 mm"));
+        assert!(looks_like_math("∫ f ≥ 0"));
+        assert!(looks_like_math("Δx = α + β"));
+        assert!(looks_like_math("f: x → x² + 1"));
+    }
+}
@@ -14,9 +14,35 @@ pub struct Metrics {
     pub raw_tokens_estimate: Option<u32>,
     pub compressed_tokens_estimate: Option<u32>,
     pub compression_factor: Option<f32>,
+    /// Mean OCR word confidence per page (0–100), `None` for pages with a text
+    /// layer. Empty when no page went through OCR.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ocr_page_confidence: Vec<Option<f32>>,
+    /// OCR words discarded by the minimum-confidence filter.
+    #[serde(default)]
+    pub ocr_words_dropped: u32,
+    /// Figure cells kept in the document.
+    #[serde(default)]
+    pub figures: u32,
+    /// PII values replaced by the redactor.
+    #[serde(default)]
+    pub redactions: u32,
+    /// Hyperlinks, cross-references and footnotes kept on cells.
+    #[serde(default)]
+    pub links: u32,
 }
 
 impl Metrics {
+    /// Mean OCR confidence over the pages that were OCR'd.
+    pub fn mean_ocr_confidence(&self) -> Option<f32> {
+        let scores: Vec<f32> = self.ocr_page_confidence.iter().flatten().copied().collect();
+        if scores.is_empty() {
+            None
+        } else {
+            Some(scores.iter().sum::<f32>() / scores.len() as f32)
+        }
+    }
+
     pub fn with_token_metrics(mut self, raw: Option<u32>, compressed: Option<u32>) -> Self {
         self.record_tokens(raw, compressed);
         self
@@ -146,11 +172,11 @@ fn levenshtein_words(pred: &[&str], gold: &[&str]) -> usize {
         return m;
     }
     let mut dp = vec![vec![0usize; n + 1]; m + 1];
-    for i in 0..=m {
-        dp[i][0] = i;
+    for (i, row) in dp.iter_mut().enumerate() {
+        row[0] = i;
     }
-    for j in 0..=n {
-        dp[0][j] = j;
+    for (j, cell) in dp[0].iter_mut().enumerate() {
+        *cell = j;
     }
     for i in 1..=m {
         for j in 1..=n {
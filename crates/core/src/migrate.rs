@@ -0,0 +1,183 @@
+//! In-memory upgrades for documents written by older format versions.
+//!
+//! | version | change |
+//! |---------|--------|
+//! | 1       | initial layout; NumGuards are bare digit hashes |
+//! | 2       | NumGuards carry typed, normalized values (`NumGuard.value`); `links`; optional `integrity` section; files written as the seekable container |
+//!
+//! Version 1 documents have no links or integrity section to recover, and
+//! the seekable layout is a property of the file rather than the message, so
+//! the only in-memory step is typing NumGuards; saving rewrites the layout.
+//!
+//! Every decode path runs [`Document::migrate`], so callers always see the
+//! current version; the upgrade is only persisted when the document is saved
+//! again (`3dcf migrate`).
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::document::Document;
+use crate::error::{DcfError, Result};
+use crate::numguard;
+
+/// Version written by this build and the newest one it reads.
+pub const FORMAT_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationStep {
+    pub from: u32,
+    pub to: u32,
+    pub description: &'static str,
+    /// Records the step rewrote.
+    pub changed: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MigrationReport {
+    pub from: u32,
+    pub to: u32,
+    pub steps: Vec<MigrationStep>,
+}
+
+impl MigrationReport {
+    pub fn is_noop(&self) -> bool {
+        self.steps.is_empty()
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_noop() {
+            return write!(f, "version {} (current)", self.to);
+        }
+        write!(f, "version {} -> {}", self.from, self.to)?;
+        for step in &self.steps {
+            write!(
+                f,
+                "\n  v{} -> v{}: {} ({} changed)",
+                step.from, step.to, step.description, step.changed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Rejects documents newer than this build; version 0 (unset) reads as 1.
+pub(crate) fn check_version(version: u32) -> Result<u32> {
+    if version > FORMAT_VERSION {
+        return Err(DcfError::UnsupportedVersion {
+            found: version,
+            supported: FORMAT_VERSION,
+        });
+    }
+    Ok(version.max(1))
+}
+
+impl Document {
+    /// Upgrades the document to [`FORMAT_VERSION`]. When anything was
+    /// upgraded the report is also kept in `Document::migration`.
+    pub fn migrate(&mut self) -> Result<MigrationReport> {
+        let from = check_version(self.header.version)?;
+        let mut steps = Vec::new();
+        if from < 2 {
+            steps.push(MigrationStep {
+                from: 1,
+                to: 2,
+                description: "typed NumGuard values",
+                changed: type_numguards(self),
+            });
+        }
+        self.header.version = FORMAT_VERSION;
+        let report = MigrationReport {
+            from,
+            to: FORMAT_VERSION,
+            steps,
+        };
+        if !report.is_noop() {
+            self.migration = Some(report.clone());
+        }
+        Ok(report)
+    }
+}
+
+/// Version 1 guards hash the digits of one number in their cell. Re-extracting
+/// the cell's entities and matching the hash recovers the typed value; guards
+/// whose number is no longer in the payload stay untyped so the digit-hash
+/// check still reports them.
+fn type_numguards(doc: &mut Document) -> usize {
+    let cells: HashMap<_, _> = doc
+        .cells
+        .iter()
+        .map(|cell| ((cell.z, cell.x, cell.y), cell.code_id))
+        .collect();
+    let mut typed = 0;
+    for guard in doc.numguards.iter_mut().filter(|g| g.entity.is_none()) {
+        let Some(payload) = cells
+            .get(&(guard.z, guard.x as i32, guard.y as i32))
+            .and_then(|code_id| doc.dict.get(code_id))
+        else {
+            continue;
+        };
+        let matched = numguard::extract_guards(payload, guard.z, guard.x, guard.y)
+            .into_iter()
+            .find(|candidate| candidate.sha1 == guard.sha1);
+        if let Some(candidate) = matched {
+            // Legacy units are what unit whitelists were written against.
+            if guard.units.is_empty() {
+                guard.units = candidate.units;
+            }
+            guard.entity = candidate.entity;
+            typed += 1;
+        }
+    }
+    typed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{Header, NumGuard};
+
+    #[test]
+    fn version_one_guards_gain_typed_values() {
+        let text = "Revenue grew 12% to $1,250";
+        let mut doc = Document::fixture(&[&[text]]);
+        doc.header.version = 1;
+        let mut legacy: Vec<NumGuard> = numguard::extract_guards(text, 0, 64, 64);
+        for guard in &mut legacy {
+            guard.entity = None;
+        }
+        let stale = {
+            let mut guard = legacy[0].clone();
+            guard.sha1 = [7; 20];
+            guard
+        };
+        doc.numguards = legacy;
+        doc.numguards.push(stale);
+
+        let report = doc.migrate().unwrap();
+        assert_eq!((report.from, report.to), (1, FORMAT_VERSION));
+        assert_eq!(report.steps[0].changed, 2);
+        assert_eq!(doc.header.version, FORMAT_VERSION);
+        assert_eq!(doc.migration.as_ref(), Some(&report));
+        assert!(doc.numguards[..2].iter().all(|g| g.entity.is_some()));
+        assert!(doc.numguards[2].entity.is_none());
+
+        let again = doc.clone().migrate().unwrap();
+        assert!(again.is_noop());
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let mut doc = Document::new(Header {
+            version: FORMAT_VERSION + 1,
+            ..Header::default()
+        });
+        assert!(matches!(
+            doc.migrate(),
+            Err(DcfError::UnsupportedVersion { found, .. }) if found == FORMAT_VERSION + 1
+        ));
+    }
+}
@@ -3,6 +3,7 @@ use regex::Regex;
 use unicode_normalization::UnicodeNormalization;
 
 use crate::document::CellType;
+use crate::math::looks_like_math;
 
 #[derive(Debug, Clone, Copy)]
 pub struct ImportanceTuning {
@@ -31,10 +32,15 @@ pub enum HyphenationMode {
 
 pub fn normalize_line(line: &str) -> String {
     let trimmed = line.trim_matches(|c: char| c.is_control() || c.is_whitespace());
-    let nfkc = trimmed.nfkc().collect::<String>();
-    let mut result = String::with_capacity(nfkc.len());
+    // NFKC folds math letters and superscripts (`𝑥²` → `x2`), so formulas skip it.
+    let folded = if looks_like_math(trimmed) {
+        trimmed.to_string()
+    } else {
+        trimmed.nfkc().collect::<String>()
+    };
+    let mut result = String::with_capacity(folded.len());
     let mut prev_space = false;
-    for ch in nfkc.chars() {
+    for ch in folded.chars() {
         if ch.is_control() {
             continue;
         }
@@ -74,9 +80,8 @@ fn merge_hyphenation(lines: &[String]) -> Vec<String> {
             combined.push_str(line.trim_start());
             combined
         };
-        let trimmed = current.trim_end().to_string();
-        if trimmed.ends_with('-') && trimmed.len() > 1 {
-            carry = trimmed.trim_end_matches('-').to_string();
+        if ends_with_break(&current) {
+            carry = current.trim_end().trim_end_matches('-').to_string();
             continue;
         }
         out.push(current);
@@ -88,8 +93,17 @@ fn merge_hyphenation(lines: &[String]) -> Vec<String> {
     out
 }
 
+/// Whether `line` ends in a hyphen that [`HyphenationMode::Merge`] joins with
+/// the next line.
+pub(crate) fn ends_with_break(line: &str) -> bool {
+    let trimmed = line.trim_end();
+    trimmed.ends_with('-') && trimmed.len() > 1
+}
+
 pub fn classify_cell_type(line: &str) -> CellType {
-    if looks_like_table(line) {
+    if looks_like_math(line) {
+        CellType::Equation
+    } else if looks_like_table(line) {
         CellType::Table
     } else if looks_like_header(line) {
         CellType::Header
@@ -206,4 +220,11 @@ mod tests {
         assert!(looks_like_table_with_tolerance("Q1      Q2      Q3", 24));
         assert!(!looks_like_table_with_tolerance("Short line", 32));
     }
+
+    #[test]
+    fn math_lines_skip_nfkc() {
+        assert_eq!(normalize_line("  𝑥² + 𝑦²  =  𝑧² "), "𝑥² + 𝑦² = 𝑧²");
+        assert_eq!(normalize_line("ﬁle x²"), "file x2");
+        assert_eq!(classify_cell_type("𝑥² + 𝑦² = 𝑧²"), CellType::Equation);
+    }
 }
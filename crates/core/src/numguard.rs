@@ -1,38 +1,240 @@
+use std::ops::Range;
+
 use once_cell::sync::Lazy;
-use regex::Regex;
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
 use sha1::{Digest, Sha1};
 
 use crate::document::NumGuard;
+use crate::proto;
+
+/// What a numeric mention measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NumericKind {
+    Plain,
+    Currency,
+    Percent,
+    Unit,
+    Date,
+}
+
+/// A typed, normalized numeric mention.
+///
+/// `value` is always in canonical form so different spellings compare equal:
+/// currencies are fully scaled (`$1.2M` → `1200000`), percentages are fractions
+/// (`5%` → `0.05`), measurements are converted to a base unit (`12 cm` → `0.12 m`),
+/// and dates are days since 1970-01-01.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NumericEntity {
+    pub kind: NumericKind,
+    pub value: f64,
+    /// Power of ten applied by a magnitude word or suffix (`k` = 3, `M` = 6, `bn` = 9).
+    #[serde(default)]
+    pub scale: i32,
+    /// ISO 4217 code for currencies, `%` for percentages, base unit for measurements.
+    #[serde(default)]
+    pub unit: String,
+    /// ISO-8601 calendar date for [`NumericKind::Date`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    /// Source spelling as it appeared in the text.
+    #[serde(default)]
+    pub raw: String,
+}
+
+impl NumericEntity {
+    /// Strict comparison: same kind, same unit, value within `1e-6` relative error.
+    pub fn equivalent(&self, other: &NumericEntity) -> bool {
+        self.kind == other.kind && self.unit == other.unit && values_close(self.value, other.value)
+    }
+
+    /// Lenient comparison used for generated text: a plain number may stand in for a
+    /// currency or percentage (`0.05` matches `5%`), but dates only match dates.
+    pub fn matches(&self, other: &NumericEntity, rel_tol: f64) -> bool {
+        self.compatible(other) && within(self.value, other.value, rel_tol.max(1e-6))
+    }
+
+    /// Like [`NumericEntity::matches`], but `rel_tol` is taken relative to this
+    /// value only, so a loose tolerance does not widen with a larger `other`.
+    pub fn approximates(&self, other: &NumericEntity, rel_tol: f64) -> bool {
+        self.compatible(other)
+            && (self.value - other.value).abs() <= rel_tol.max(1e-6) * self.value.abs()
+    }
+
+    fn compatible(&self, other: &NumericEntity) -> bool {
+        match (self.kind, other.kind) {
+            (a, b) if a == b => self.unit == other.unit,
+            (NumericKind::Date, _) | (_, NumericKind::Date) => false,
+            (NumericKind::Plain, _) | (_, NumericKind::Plain) => true,
+            _ => false,
+        }
+    }
+
+    /// Relative error implied by the precision of `raw`: half a unit of the last
+    /// stated digit, so `1.2M` tolerates `0.05 / 1.2`. Capped at 10% so a single
+    /// digit ("1 million") does not cover half its value. Dates are always exact.
+    pub fn rounding_tolerance(&self) -> f64 {
+        if self.kind == NumericKind::Date {
+            return 0.0;
+        }
+        let Some(num) = NUM_DIGITS_RE.find(&self.raw) else {
+            return 0.0;
+        };
+        let cleaned = num.as_str().replace(['\u{a0}', '\u{202f}'], ",");
+        let magnitude = parse_decimal(&cleaned).unwrap_or(0.0).abs();
+        if magnitude == 0.0 {
+            return 0.0;
+        }
+        let fraction_digits = decimal_separator(&cleaned)
+            .and_then(|sep| cleaned.rfind(sep).map(|idx| cleaned.len() - idx - 1))
+            .unwrap_or(0);
+        (0.5 * 10f64.powi(-(fraction_digits as i32)) / magnitude).min(MAX_ROUNDING_TOLERANCE)
+    }
+
+    /// Calendar year of a [`NumericKind::Date`].
+    pub fn year(&self) -> Option<i32> {
+        self.date.as_deref()?.get(..4)?.parse().ok()
+    }
+
+    pub(crate) fn to_proto(&self) -> proto::NumValue {
+        let kind = match self.kind {
+            NumericKind::Plain => proto::NumKind::Plain,
+            NumericKind::Currency => proto::NumKind::Currency,
+            NumericKind::Percent => proto::NumKind::Percent,
+            NumericKind::Unit => proto::NumKind::Unit,
+            NumericKind::Date => proto::NumKind::Date,
+        };
+        proto::NumValue {
+            kind: kind as i32,
+            value: self.value,
+            scale: self.scale,
+            unit: self.unit.clone(),
+            date: self.date.clone().unwrap_or_default(),
+            raw: self.raw.clone(),
+        }
+    }
+
+    pub(crate) fn from_proto(value: proto::NumValue) -> Option<Self> {
+        let kind = match proto::NumKind::try_from(value.kind).ok()? {
+            proto::NumKind::Unspecified => return None,
+            proto::NumKind::Plain => NumericKind::Plain,
+            proto::NumKind::Currency => NumericKind::Currency,
+            proto::NumKind::Percent => NumericKind::Percent,
+            proto::NumKind::Unit => NumericKind::Unit,
+            proto::NumKind::Date => NumericKind::Date,
+        };
+        Some(Self {
+            kind,
+            value: value.value,
+            scale: value.scale,
+            unit: value.unit,
+            date: (!value.date.is_empty()).then_some(value.date),
+            raw: value.raw,
+        })
+    }
+}
+
+/// Upper bound of [`NumericEntity::rounding_tolerance`].
+const MAX_ROUNDING_TOLERANCE: f64 = 0.1;
+
+const MONTHS: &str = "jan(?:uary)?|feb(?:ruary)?|mar(?:ch)?|apr(?:il)?|may|jun(?:e)?|jul(?:y)?|aug(?:ust)?|sep(?:t(?:ember)?)?|oct(?:ober)?|nov(?:ember)?|dec(?:ember)?";
+
+static DATE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(&format!(
+        r"(?ix)
+        (?P<iso>\b(?P<iy>\d{{4}})-(?P<im>\d{{1,2}})-(?P<id>\d{{1,2}})\b)
+        | (?P<slash>\b(?P<sa>\d{{1,2}})/(?P<sb>\d{{1,2}})/(?P<sy>\d{{4}})\b)
+        | (?P<dot>\b(?P<dd>\d{{1,2}})\.(?P<dm>\d{{1,2}})\.(?P<dy>\d{{4}})\b)
+        | (?P<mdy>\b(?P<mm>{MONTHS})\.?\s+(?P<md>\d{{1,2}})(?:st|nd|rd|th)?,?\s+(?P<my>\d{{4}})\b)
+        | (?P<dmy>\b(?P<xd>\d{{1,2}})(?:st|nd|rd|th)?\s+(?P<xm>{MONTHS})\.?,?\s+(?P<xy>\d{{4}})\b)
+        | (?P<cjk>(?P<jy>\d{{4}})年\s*(?P<jm>\d{{1,2}})月\s*(?P<jd>\d{{1,2}})日)
+        "
+    ))
+    .expect("valid regex")
+});
 
 static NUM_RE: Lazy<Regex> = Lazy::new(|| {
     Regex::new(
-        r"(?P<num>\d{1,3}(?:[,\s]\d{3})*(?:\.\d+)?)\s*(?P<unit>%|mmhg|mm|cm|mg|kg|usd|eur|bpm)?",
+        r"(?x)
+        (?P<pre>(?i:us\$|[$€£¥]|\b(?:usd|eur|gbp|jpy|chf|cad|aud|cny)\b))?\s*
+        (?P<sign>[-+−])?
+        (?P<num>\d{1,3}(?:[,.\x{a0}\x{202f}]\d{3})+\d*(?:[.,]\d+)?|\d+(?:[.,]\d+)?|[.,]\d+)
+        (?:\s*(?P<scale>(?i:thousand|million|billion|trillion|mln|mio|mrd|mn|bn|tn)\b|[kKMBT]\b|m\b))?
+        (?:\s*(?P<post>%|(?i:percent|pct|usd|eur|gbp|jpy|chf|cad|aud|cny|euros?|dollars?|mmhg|bpm|km|cm|mm|mg|kg|ml|g|l|m)\b))?
+        ",
     )
     .expect("valid regex")
 });
 
-pub fn extract_guards(line: &str, z: u32, x: u32, y: u32) -> Vec<NumGuard> {
-    let mut guards = Vec::new();
-    for caps in NUM_RE.captures_iter(&line.to_lowercase()) {
-        let mut digits = caps
-            .name("num")
-            .map(|m| m.as_str())
-            .unwrap_or("")
-            .to_string();
-        digits.retain(|c| c.is_ascii_digit());
-        if digits.is_empty() {
+static NUM_DIGITS_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"\d{1,3}(?:[,.\x{a0}\x{202f}]\d{3})+\d*(?:[.,]\d+)?|\d+(?:[.,]\d+)?|[.,]\d+")
+        .expect("valid regex")
+});
+
+/// Extracts every typed numeric mention from `text`, left to right.
+pub fn extract_entities(text: &str) -> Vec<NumericEntity> {
+    extract_entity_spans(text)
+        .into_iter()
+        .map(|(_, entity)| entity)
+        .collect()
+}
+
+/// Like [`extract_entities`], but also returns the byte range of each mention.
+pub fn extract_entity_spans(text: &str) -> Vec<(Range<usize>, NumericEntity)> {
+    let mut spans: Vec<(Range<usize>, NumericEntity)> = Vec::new();
+    for caps in DATE_RE.captures_iter(text) {
+        let whole = caps.get(0).expect("match");
+        if let Some(entity) = parse_date(&caps) {
+            spans.push((whole.range(), entity));
+        }
+    }
+    for caps in NUM_RE.captures_iter(text) {
+        let whole = caps.get(0).expect("match");
+        let num = caps.name("num").expect("num group");
+        if spans
+            .iter()
+            .any(|(range, _)| num.start() < range.end && num.end() > range.start)
+        {
             continue;
         }
-        let units = caps.name("unit").map(|m| m.as_str()).unwrap_or("");
-        guards.push(NumGuard {
-            z,
-            x,
-            y,
-            units: units.to_string(),
-            sha1: sha1_from_digits(&digits),
-        });
+        if let Some((entity, end)) = parse_number(&caps) {
+            spans.push((trimmed_range(text, whole.start()..end), entity));
+        }
     }
-    guards
+    spans.sort_by_key(|(range, _)| range.start);
+    spans
+}
+
+fn trimmed_range(text: &str, range: Range<usize>) -> Range<usize> {
+    let slice = &text[range.clone()];
+    let start = range.start + (slice.len() - slice.trim_start().len());
+    let end = range.end - (slice.len() - slice.trim_end().len());
+    start..end
+}
+
+pub fn extract_guards(line: &str, z: u32, x: u32, y: u32) -> Vec<NumGuard> {
+    extract_entities(line)
+        .into_iter()
+        .filter_map(|entity| {
+            let digits = entity
+                .raw
+                .chars()
+                .filter(|c| c.is_ascii_digit())
+                .collect::<String>();
+            if digits.is_empty() {
+                return None;
+            }
+            Some(NumGuard {
+                z,
+                x,
+                y,
+                units: guard_units(&entity),
+                sha1: sha1_from_digits(&digits),
+                entity: Some(entity),
+            })
+        })
+        .collect()
 }
 
 pub fn hash_digits_from_payload(payload: &str) -> Option<[u8; 20]> {
@@ -54,3 +256,364 @@ fn sha1_from_digits(digits: &str) -> [u8; 20] {
     sha.copy_from_slice(&hash[..]);
     sha
 }
+
+/// Lowercase unit label kept on the guard for unit whitelists: the unit as
+/// written (`cm`, not the `m` it normalizes to), the ISO code for currencies.
+fn guard_units(entity: &NumericEntity) -> String {
+    match entity.kind {
+        NumericKind::Plain | NumericKind::Date => String::new(),
+        NumericKind::Unit => entity
+            .raw
+            .rsplit(|c: char| !c.is_alphabetic())
+            .next()
+            .unwrap_or_default()
+            .to_lowercase(),
+        _ => entity.unit.to_lowercase(),
+    }
+}
+
+/// Parses one `NUM_RE` match; also returns where the mention ends, which is
+/// before the suffix when a single-letter scale does not apply.
+fn parse_number(caps: &Captures<'_>) -> Option<(NumericEntity, usize)> {
+    let whole = caps.get(0)?;
+    let num = caps.name("num")?;
+    let mut end = whole.end();
+    let mut value = parse_decimal(num.as_str())?;
+    if matches!(caps.name("sign").map(|m| m.as_str()), Some("-") | Some("−")) {
+        value = -value;
+    }
+    let currency = caps
+        .name("pre")
+        .and_then(|m| currency_code(m.as_str()))
+        .or_else(|| caps.name("post").and_then(|m| currency_code(m.as_str())));
+    let mut scale = 0;
+    let mut unit_word = caps.name("post").map(|m| m.as_str().to_lowercase());
+    if let Some(suffix) = caps.name("scale") {
+        let word = suffix.as_str();
+        if word == "m" && currency.is_none() {
+            // A bare lowercase `m` is a metre unless money is involved.
+            unit_word = Some("m".to_string());
+        } else if word.len() == 1
+            && currency.is_none()
+            && !short_scale(num.as_str(), suffix.start() == num.end(), word)
+        {
+            // "Section 3 B" is not three billion.
+            end = num.end();
+            unit_word = None;
+        } else {
+            scale = scale_exponent(word)?;
+        }
+    }
+    let raw = whole.as_str()[..end - whole.start()].trim().to_string();
+    let entity = number_entity(value, scale, currency, unit_word.as_deref(), raw)?;
+    Some((entity, end))
+}
+
+/// Single-letter scales without a currency only count when written onto the
+/// number as in `120k` or `3.5M`; a bare `3M` or `3 B` is more often a label.
+fn short_scale(num: &str, attached: bool, word: &str) -> bool {
+    attached && (matches!(word, "k" | "K") || num.contains(['.', ',']))
+}
+
+fn number_entity(
+    value: f64,
+    scale: i32,
+    currency: Option<&'static str>,
+    unit_word: Option<&str>,
+    raw: String,
+) -> Option<NumericEntity> {
+    let scaled = value * 10f64.powi(scale);
+    if let Some(code) = currency {
+        return Some(NumericEntity {
+            kind: NumericKind::Currency,
+            value: scaled,
+            scale,
+            unit: code.to_string(),
+            date: None,
+            raw,
+        });
+    }
+    match unit_word {
+        Some("%") | Some("percent") | Some("pct") => Some(NumericEntity {
+            kind: NumericKind::Percent,
+            value: scaled / 100.0,
+            scale,
+            unit: "%".to_string(),
+            date: None,
+            raw,
+        }),
+        Some(word) => {
+            let (base, factor) = canonical_unit(word)?;
+            Some(NumericEntity {
+                kind: NumericKind::Unit,
+                value: scaled * factor,
+                scale,
+                unit: base.to_string(),
+                date: None,
+                raw,
+            })
+        }
+        None => Some(NumericEntity {
+            kind: NumericKind::Plain,
+            value: scaled,
+            scale,
+            unit: String::new(),
+            date: None,
+            raw,
+        }),
+    }
+}
+
+/// Parses `1,200,000.50`, `1.200.000,50`, `3,5`, and `.75` into a float.
+fn parse_decimal(raw: &str) -> Option<f64> {
+    let cleaned = raw.replace(['\u{a0}', '\u{202f}'], ",");
+    let decimal_sep = decimal_separator(&cleaned);
+    let mut normalized = String::with_capacity(cleaned.len());
+    for ch in cleaned.chars() {
+        if ch.is_ascii_digit() {
+            normalized.push(ch);
+        } else if Some(ch) == decimal_sep {
+            normalized.push('.');
+        }
+    }
+    normalized.parse::<f64>().ok()
+}
+
+/// Decides which of `,`/`.` (if any) is the decimal separator in a digit run.
+fn decimal_separator(cleaned: &str) -> Option<char> {
+    let last_comma = cleaned.rfind(',');
+    let last_dot = cleaned.rfind('.');
+    match (last_comma, last_dot) {
+        (Some(c), Some(d)) => Some(if c > d { ',' } else { '.' }),
+        (Some(c), None) => {
+            let groups = cleaned.matches(',').count();
+            let tail = cleaned.len() - c - 1;
+            (groups == 1 && tail != 3).then_some(',')
+        }
+        (None, Some(d)) => {
+            let groups = cleaned.matches('.').count();
+            let tail = cleaned.len() - d - 1;
+            (groups == 1 || tail != 3).then_some('.')
+        }
+        (None, None) => None,
+    }
+}
+
+fn currency_code(token: &str) -> Option<&'static str> {
+    match token.trim().to_lowercase().as_str() {
+        "$" | "us$" | "usd" | "dollar" | "dollars" => Some("USD"),
+        "€" | "eur" | "euro" | "euros" => Some("EUR"),
+        "£" | "gbp" => Some("GBP"),
+        "¥" | "jpy" => Some("JPY"),
+        "chf" => Some("CHF"),
+        "cad" => Some("CAD"),
+        "aud" => Some("AUD"),
+        "cny" => Some("CNY"),
+        _ => None,
+    }
+}
+
+fn scale_exponent(word: &str) -> Option<i32> {
+    match word {
+        "k" | "K" => Some(3),
+        "M" => Some(6),
+        "B" => Some(9),
+        "T" => Some(12),
+        "m" => Some(6),
+        other => match other.to_lowercase().as_str() {
+            "thousand" => Some(3),
+            "million" | "mln" | "mio" | "mn" => Some(6),
+            "billion" | "mrd" | "bn" => Some(9),
+            "trillion" | "tn" => Some(12),
+            _ => None,
+        },
+    }
+}
+
+fn canonical_unit(word: &str) -> Option<(&'static str, f64)> {
+    match word {
+        "km" => Some(("m", 1000.0)),
+        "m" => Some(("m", 1.0)),
+        "cm" => Some(("m", 0.01)),
+        "mm" => Some(("m", 0.001)),
+        "kg" => Some(("kg", 1.0)),
+        "g" => Some(("kg", 0.001)),
+        "mg" => Some(("kg", 1e-6)),
+        "l" => Some(("l", 1.0)),
+        "ml" => Some(("l", 0.001)),
+        "mmhg" => Some(("mmhg", 1.0)),
+        "bpm" => Some(("bpm", 1.0)),
+        _ => None,
+    }
+}
+
+fn parse_date(caps: &Captures<'_>) -> Option<NumericEntity> {
+    let num = |name: &str| caps.name(name).and_then(|m| m.as_str().parse::<u32>().ok());
+    let (year, month, day) = if caps.name("iso").is_some() {
+        (num("iy")?, num("im")?, num("id")?)
+    } else if caps.name("slash").is_some() {
+        let (a, b) = (num("sa")?, num("sb")?);
+        // US month-first unless the first field cannot be a month.
+        if a > 12 {
+            (num("sy")?, b, a)
+        } else {
+            (num("sy")?, a, b)
+        }
+    } else if caps.name("dot").is_some() {
+        (num("dy")?, num("dm")?, num("dd")?)
+    } else if caps.name("mdy").is_some() {
+        (
+            num("my")?,
+            month_number(caps.name("mm")?.as_str())?,
+            num("md")?,
+        )
+    } else if caps.name("dmy").is_some() {
+        (
+            num("xy")?,
+            month_number(caps.name("xm")?.as_str())?,
+            num("xd")?,
+        )
+    } else {
+        (num("jy")?, num("jm")?, num("jd")?)
+    };
+    if !(1..=12).contains(&month) || day == 0 || day > days_in_month(year, month) {
+        return None;
+    }
+    Some(NumericEntity {
+        kind: NumericKind::Date,
+        value: days_from_civil(year as i64, month, day) as f64,
+        scale: 0,
+        unit: String::new(),
+        date: Some(format!("{year:04}-{month:02}-{day:02}")),
+        raw: caps.get(0)?.as_str().to_string(),
+    })
+}
+
+fn month_number(name: &str) -> Option<u32> {
+    let lower = name.to_lowercase();
+    let idx = [
+        "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+    ]
+    .iter()
+    .position(|m| lower.starts_with(m))?;
+    Some(idx as u32 + 1)
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if (year % 4 == 0 && year % 100 != 0) || year % 400 == 0 => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let y = if month <= 2 { year - 1 } else { year };
+    let era = (if y >= 0 { y } else { y - 399 }) / 400;
+    let yoe = y - era * 400;
+    let m = month as i64;
+    let doy = (153 * (if m > 2 { m - 3 } else { m + 9 }) + 2) / 5 + day as i64 - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn values_close(a: f64, b: f64) -> bool {
+    within(a, b, 1e-6)
+}
+
+fn within(a: f64, b: f64, rel_tol: f64) -> bool {
+    let scale = a.abs().max(b.abs()).max(1e-12);
+    (a - b).abs() <= rel_tol * scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn single(text: &str) -> NumericEntity {
+        let mut all = extract_entities(text);
+        assert_eq!(all.len(), 1, "{text}: {all:?}");
+        all.remove(0)
+    }
+
+    #[test]
+    fn currencies_normalize_across_spellings() {
+        let short = single("$1.2M");
+        let long = single("1,200,000 USD");
+        assert_eq!(short.kind, NumericKind::Currency);
+        assert_eq!(short.scale, 6);
+        assert!(short.equivalent(&long));
+        assert_eq!(single("€3,5 Mio").value, 3_500_000.0);
+    }
+
+    #[test]
+    fn percentages_become_fractions() {
+        let pct = single("5%");
+        assert_eq!(pct.kind, NumericKind::Percent);
+        assert!(pct.matches(&single("0.05"), 0.0));
+        assert!(!pct.equivalent(&single("0.05")));
+    }
+
+    #[test]
+    fn units_convert_to_base() {
+        assert!(single("12 cm").equivalent(&single("120 mm")));
+        assert_eq!(single("80 mmHg").unit, "mmhg");
+    }
+
+    #[test]
+    fn dates_parse_in_several_layouts() {
+        let iso = single("2024-03-15");
+        assert_eq!(iso.date.as_deref(), Some("2024-03-15"));
+        assert!(iso.equivalent(&single("March 15, 2024")));
+        assert!(iso.equivalent(&single("15.03.2024")));
+        assert!(iso.equivalent(&single("2024年3月15日")));
+        assert_eq!(single("1970-01-02").value, 1.0);
+    }
+
+    #[test]
+    fn european_and_us_grouping() {
+        assert_eq!(parse_decimal("1.200.000,50"), Some(1_200_000.5));
+        assert_eq!(parse_decimal("1,200,000.50"), Some(1_200_000.5));
+        assert_eq!(parse_decimal("3,5"), Some(3.5));
+    }
+
+    #[test]
+    fn decimals_are_not_split_into_groups() {
+        assert_eq!(single("ratio 2.71829").value, 2.71829);
+        assert_eq!(single("1,234.5678").value, 1234.5678);
+        assert_eq!(single("1.234,5678").value, 1234.5678);
+        assert_eq!(single("1,200,000").value, 1_200_000.0);
+    }
+
+    #[test]
+    fn single_letter_scales_need_a_currency_or_a_suffix() {
+        let label = single("Section 3 B");
+        assert_eq!((label.kind, label.value), (NumericKind::Plain, 3.0));
+        assert_eq!(label.raw, "3");
+        assert_eq!(single("Table 4M").value, 4.0);
+        assert_eq!(single("$3 B").value, 3e9);
+        assert_eq!(single("120k users").value, 120_000.0);
+        assert_eq!(single("3.5M downloads").value, 3_500_000.0);
+        let spans = extract_entity_spans("see Section 3 B below");
+        assert_eq!(spans[0].0, 12..13);
+    }
+
+    #[test]
+    fn guard_units_keep_the_written_unit() {
+        let guards = extract_guards("12 cm and 80 mmHg", 0, 0, 0);
+        let units: Vec<_> = guards.iter().map(|g| g.units.as_str()).collect();
+        assert_eq!(units, ["cm", "mmhg"]);
+        assert_eq!(guards[0].entity.as_ref().unwrap().unit, "m");
+    }
+
+    #[test]
+    fn guards_carry_typed_entities() {
+        let guards = extract_guards("Revenue rose 12% to $4.5bn", 0, 0, 0);
+        assert_eq!(guards.len(), 2);
+        assert_eq!(guards[0].units, "%");
+        assert_eq!(guards[1].units, "usd");
+        assert_eq!(guards[1].entity.as_ref().unwrap().value, 4.5e9);
+    }
+}
@@ -1,4 +1,8 @@
+use std::collections::BTreeMap;
+
 use image::DynamicImage;
+use once_cell::sync::Lazy;
+use regex::Regex;
 
 use crate::error::{DcfError, Result};
 
@@ -10,8 +14,287 @@ use {
     tempfile::NamedTempFile,
 };
 
+/// Axis-aligned box in image pixels.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct OcrBox {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl OcrBox {
+    pub fn right(&self) -> u32 {
+        self.left + self.width
+    }
+
+    pub fn bottom(&self) -> u32 {
+        self.top + self.height
+    }
+
+    pub fn union(&self, other: &OcrBox) -> OcrBox {
+        let left = self.left.min(other.left);
+        let top = self.top.min(other.top);
+        OcrBox {
+            left,
+            top,
+            width: self.right().max(other.right()) - left,
+            height: self.bottom().max(other.bottom()) - top,
+        }
+    }
+}
+
+/// A single recognized word with its Tesseract layout position.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrWord {
+    pub text: String,
+    pub bbox: OcrBox,
+    /// Tesseract word confidence, 0–100.
+    pub confidence: f32,
+    pub block: u32,
+    pub par: u32,
+    pub line: u32,
+}
+
+/// Words of one text line that passed the confidence filter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcrLine {
+    pub text: String,
+    pub bbox: OcrBox,
+    /// Mean confidence of the kept words, 0–100.
+    pub confidence: f32,
+    pub block: u32,
+    /// Words on this line that fell below the confidence threshold.
+    pub dropped_words: usize,
+}
+
+/// Structured OCR output for one page image.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OcrPage {
+    pub width: u32,
+    pub height: u32,
+    pub words: Vec<OcrWord>,
+}
+
+impl OcrPage {
+    /// Parses Tesseract TSV output (`tesseract img out tsv`).
+    pub fn from_tsv(tsv: &str) -> Result<Self> {
+        let mut page = OcrPage::default();
+        for (idx, row) in tsv.lines().enumerate() {
+            if row.trim().is_empty() || row.starts_with("level") {
+                continue;
+            }
+            let cols: Vec<&str> = row.splitn(12, '\t').collect();
+            if cols.len() < 11 {
+                return Err(DcfError::Other(format!(
+                    "malformed tesseract tsv row {}: expected at least 11 columns",
+                    idx + 1
+                )));
+            }
+            let num = |col: usize| -> Result<u32> {
+                cols[col].trim().parse::<u32>().map_err(|_| {
+                    DcfError::Other(format!(
+                        "malformed tesseract tsv row {}: bad integer '{}'",
+                        idx + 1,
+                        cols[col]
+                    ))
+                })
+            };
+            let bbox = OcrBox {
+                left: num(6)?,
+                top: num(7)?,
+                width: num(8)?,
+                height: num(9)?,
+            };
+            match num(0)? {
+                1 => {
+                    page.width = page.width.max(bbox.right());
+                    page.height = page.height.max(bbox.bottom());
+                }
+                5 => {
+                    let text = cols.get(11).map(|t| t.trim()).unwrap_or_default();
+                    let confidence = cols[10].trim().parse::<f32>().unwrap_or(-1.0);
+                    if text.is_empty() || confidence < 0.0 {
+                        continue;
+                    }
+                    page.words.push(OcrWord {
+                        text: text.to_string(),
+                        bbox,
+                        confidence,
+                        block: num(2)?,
+                        par: num(3)?,
+                        line: num(4)?,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(page)
+    }
+
+    /// Parses Tesseract hOCR output (`tesseract img out hocr`).
+    pub fn from_hocr(hocr: &str) -> Result<Self> {
+        let mut page = OcrPage::default();
+        let (mut block, mut par, mut line) = (0u32, 0u32, 0u32);
+        for caps in HOCR_TAG_RE.captures_iter(hocr) {
+            let tag = caps.get(0).expect("match");
+            let class = &caps["class"];
+            let title = TITLE_RE
+                .captures(tag.as_str())
+                .and_then(|t| t.get(1).or_else(|| t.get(2)))
+                .map(|m| m.as_str().to_string())
+                .unwrap_or_default();
+            let bbox = parse_hocr_bbox(&title);
+            match class {
+                "ocr_page" => {
+                    if let Some(bbox) = bbox {
+                        page.width = bbox.right();
+                        page.height = bbox.bottom();
+                    }
+                }
+                "ocr_carea" => block += 1,
+                "ocr_par" => par += 1,
+                "ocrx_word" => {
+                    let rest = &hocr[tag.end()..];
+                    let inner = rest.find("</span>").map(|end| &rest[..end]).unwrap_or("");
+                    let text = unescape_html(&STRIP_TAGS_RE.replace_all(inner, ""));
+                    let text = text.trim();
+                    let Some(bbox) = bbox else {
+                        continue;
+                    };
+                    if text.is_empty() {
+                        continue;
+                    }
+                    let confidence = WCONF_RE
+                        .captures(&title)
+                        .and_then(|c| c[1].parse::<f32>().ok())
+                        .unwrap_or(0.0);
+                    page.words.push(OcrWord {
+                        text: text.to_string(),
+                        bbox,
+                        confidence,
+                        block,
+                        par,
+                        line,
+                    });
+                }
+                // ocr_line and its typed siblings (ocr_header, ocr_caption, ...)
+                _ => line += 1,
+            }
+        }
+        if page.words.is_empty() && !hocr.contains("ocr_page") {
+            return Err(DcfError::Other(
+                "input does not look like tesseract hOCR".to_string(),
+            ));
+        }
+        Ok(page)
+    }
+
+    /// Mean word confidence over the whole page, before any filtering.
+    pub fn mean_confidence(&self) -> Option<f32> {
+        if self.words.is_empty() {
+            return None;
+        }
+        let sum: f32 = self.words.iter().map(|w| w.confidence).sum();
+        Some(sum / self.words.len() as f32)
+    }
+
+    /// Groups words into lines in reading order, dropping words whose confidence
+    /// is below `min_confidence`. Lines left without words are omitted.
+    pub fn lines(&self, min_confidence: f32) -> Vec<OcrLine> {
+        let mut grouped: BTreeMap<(u32, u32, u32), Vec<&OcrWord>> = BTreeMap::new();
+        for word in &self.words {
+            grouped
+                .entry((word.block, word.par, word.line))
+                .or_default()
+                .push(word);
+        }
+        let mut lines = Vec::with_capacity(grouped.len());
+        for ((block, _, _), words) in grouped {
+            let kept: Vec<&OcrWord> = words
+                .iter()
+                .copied()
+                .filter(|w| w.confidence >= min_confidence)
+                .collect();
+            let dropped_words = words.len() - kept.len();
+            let Some(first) = kept.first() else {
+                continue;
+            };
+            let bbox = kept
+                .iter()
+                .skip(1)
+                .fold(first.bbox, |acc, w| acc.union(&w.bbox));
+            let text = kept
+                .iter()
+                .map(|w| w.text.as_str())
+                .collect::<Vec<_>>()
+                .join(" ");
+            let confidence = kept.iter().map(|w| w.confidence).sum::<f32>() / kept.len() as f32;
+            lines.push(OcrLine {
+                text,
+                bbox,
+                confidence,
+                block,
+                dropped_words,
+            });
+        }
+        lines
+    }
+
+    /// Plain text with one line per OCR line and a blank line between blocks.
+    pub fn text(&self) -> String {
+        let mut out = String::new();
+        let mut prev_block = None;
+        for line in self.lines(0.0) {
+            if prev_block.is_some_and(|b| b != line.block) {
+                out.push('\n');
+            }
+            out.push_str(&line.text);
+            out.push('\n');
+            prev_block = Some(line.block);
+        }
+        out
+    }
+}
+
+static HOCR_TAG_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(
+        r#"<(?:div|p|span)\b[^>]*\bclass=['"](?P<class>ocr_page|ocr_carea|ocr_par|ocr_line|ocr_header|ocr_caption|ocr_textfloat|ocrx_word)['"][^>]*>"#,
+    )
+    .expect("valid regex")
+});
+static TITLE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"\btitle=(?:'([^']*)'|"([^"]*)")"#).expect("valid regex"));
+static BBOX_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\bbbox (\d+) (\d+) (\d+) (\d+)").expect("valid regex"));
+static WCONF_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"\bx_wconf (-?\d+(?:\.\d+)?)").expect("valid regex"));
+static STRIP_TAGS_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"<[^>]*>").expect("valid regex"));
+
+/// hOCR boxes are `x0 y0 x1 y1`.
+fn parse_hocr_bbox(title: &str) -> Option<OcrBox> {
+    let caps = BBOX_RE.captures(title)?;
+    let coord = |i: usize| caps[i].parse::<u32>().ok();
+    let (x0, y0, x1, y1) = (coord(1)?, coord(2)?, coord(3)?, coord(4)?);
+    Some(OcrBox {
+        left: x0,
+        top: y0,
+        width: x1.saturating_sub(x0),
+        height: y1.saturating_sub(y0),
+    })
+}
+
+fn unescape_html(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
 #[cfg(feature = "ocr")]
-pub fn image_to_text(image: &DynamicImage, languages: &[String]) -> Result<String> {
+pub fn recognize(image: &DynamicImage, languages: &[String]) -> Result<OcrPage> {
     let lang = if languages.is_empty() {
         "eng".to_string()
     } else {
@@ -46,15 +329,98 @@ pub fn image_to_text(image: &DynamicImage, languages: &[String]) -> Result<Strin
             "failed to load image into tesseract".to_string(),
         ));
     }
-    let text = tess
-        .get_utf8_text()
+    let tsv = tess
+        .get_tsv_text(0)
         .map_err(|e| DcfError::Other(format!("tesseract failed: {e}")))?;
     let _ = temp_path.close();
-    Ok(text)
+    let mut page = OcrPage::from_tsv(&tsv)?;
+    if page.width == 0 || page.height == 0 {
+        page.width = image.width();
+        page.height = image.height();
+    }
+    Ok(page)
 }
 
 #[cfg(not(feature = "ocr"))]
 #[allow(dead_code)]
-pub fn image_to_text(_image: &DynamicImage, _languages: &[String]) -> Result<String> {
+pub fn recognize(_image: &DynamicImage, _languages: &[String]) -> Result<OcrPage> {
     Err(DcfError::OcrSupportDisabled)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TSV: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t800\t600\t-1\t
+2\t1\t1\t0\t0\t0\t40\t50\t300\t60\t-1\t
+4\t1\t1\t1\t1\t0\t40\t50\t300\t20\t-1\t
+5\t1\t1\t1\t1\t1\t40\t50\t120\t20\t96.5\tRevenue
+5\t1\t1\t1\t1\t2\t170\t52\t80\t18\t91\t$4.2M
+4\t1\t1\t1\t2\t0\t40\t80\t300\t20\t-1\t
+5\t1\t1\t1\t2\t1\t40\t80\t60\t20\t12.0\t~#
+5\t1\t1\t1\t2\t2\t110\t80\t90\t20\t88\tgrowth
+";
+
+    #[test]
+    fn tsv_groups_words_into_lines() {
+        let page = OcrPage::from_tsv(TSV).unwrap();
+        assert_eq!((page.width, page.height), (800, 600));
+        assert_eq!(page.words.len(), 4);
+        let lines = page.lines(0.0);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "Revenue $4.2M");
+        assert_eq!(
+            lines[0].bbox,
+            OcrBox {
+                left: 40,
+                top: 50,
+                width: 210,
+                height: 20
+            }
+        );
+        assert!((lines[0].confidence - 93.75).abs() < 1e-3);
+        assert!((page.mean_confidence().unwrap() - 71.875).abs() < 1e-3);
+    }
+
+    #[test]
+    fn short_tsv_rows_are_rejected() {
+        let err = OcrPage::from_tsv("5\t1\t1\t1\t1\t1\t10\t10\t20\t8")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("expected at least 11 columns"), "{err}");
+        // The text column may be missing on rows without a word.
+        assert!(OcrPage::from_tsv("1\t1\t0\t0\t0\t0\t0\t0\t800\t600\t-1").is_ok());
+    }
+
+    #[test]
+    fn low_confidence_words_are_filtered() {
+        let page = OcrPage::from_tsv(TSV).unwrap();
+        let lines = page.lines(60.0);
+        assert_eq!(lines[1].text, "growth");
+        assert_eq!(lines[1].dropped_words, 1);
+        assert_eq!(lines[1].bbox.left, 110);
+        assert!(page.lines(99.0).is_empty());
+    }
+
+    #[test]
+    fn hocr_matches_tsv_layout() {
+        let hocr = r#"<div class='ocr_page' id='page_1' title='image "scan.png"; bbox 0 0 800 600; ppageno 0'>
+<div class='ocr_carea' id='block_1_1' title="bbox 40 50 340 110">
+<p class='ocr_par' id='par_1_1' lang='eng' title="bbox 40 50 340 110">
+<span class='ocr_line' id='line_1_1' title="bbox 40 50 250 70; baseline 0 -4">
+<span class='ocrx_word' id='word_1_1' title='bbox 40 50 160 70; x_wconf 96'>Revenue</span>
+<span class='ocrx_word' id='word_1_2' title='bbox 170 52 250 70; x_wconf 91'><strong>R&amp;D</strong></span>
+</span>
+</p>
+</div>
+</div>"#;
+        let page = OcrPage::from_hocr(hocr).unwrap();
+        assert_eq!((page.width, page.height), (800, 600));
+        let lines = page.lines(0.0);
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "Revenue R&D");
+        assert_eq!(lines[0].bbox.width, 210);
+        assert!(OcrPage::from_hocr("<html></html>").is_err());
+    }
+}
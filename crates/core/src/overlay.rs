@@ -0,0 +1,412 @@
+//! Debug overlays that draw a page's cell boxes, for tuning the encoder.
+//!
+//! Boxes are coloured by [`CellType`] and get more opaque with importance.
+//! Cells the encoder dropped ([`Document::dropped`], kept when the encoder
+//! was built with `keep_dropped`) are hatched with a
+//! dashed outline. SVG boxes carry a `<title>` with the position, importance,
+//! `code_id` and a payload preview, shown as a hover tooltip.
+
+use std::fmt::Write as _;
+use std::io::Cursor;
+
+use image::{ImageFormat, Rgba, RgbaImage};
+
+use crate::document::{CellRecord, CellType, Document, DropReason, PageInfo};
+use crate::error::{DcfError, Result};
+
+const PREVIEW_CHARS: usize = 80;
+const LEGEND_HEIGHT: u32 = 28;
+const CELL_TYPES: [CellType; 6] = [
+    CellType::Text,
+    CellType::Header,
+    CellType::Table,
+    CellType::Figure,
+    CellType::Footer,
+    CellType::Equation,
+];
+
+struct OverlayBox<'a> {
+    cell: &'a CellRecord,
+    payload: &'a str,
+    dropped: Option<DropReason>,
+}
+
+impl Document {
+    /// SVG overlay of page `z`, with a colour legend below the page.
+    pub fn overlay_svg(&self, z: u32) -> Result<String> {
+        let (page, boxes) = self.overlay_boxes(z)?;
+        let (width, height) = canvas_size(&page, &boxes);
+        let mut svg = String::new();
+        let _ = writeln!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{total}\" \
+             viewBox=\"0 0 {width} {total}\" font-family=\"sans-serif\" font-size=\"12\">",
+            total = height + LEGEND_HEIGHT
+        );
+        svg.push_str("<defs>\n");
+        for cell_type in CELL_TYPES {
+            let _ = writeln!(
+                svg,
+                "<pattern id=\"hatch-{name}\" width=\"6\" height=\"6\" patternUnits=\"userSpaceOnUse\" \
+                 patternTransform=\"rotate(45)\"><rect width=\"2\" height=\"6\" fill=\"{color}\"/></pattern>",
+                name = type_name(cell_type),
+                color = hex_color(cell_type)
+            );
+        }
+        svg.push_str("</defs>\n");
+        let _ = writeln!(
+            svg,
+            "<rect width=\"{width}\" height=\"{height}\" fill=\"#ffffff\"/>\n\
+             <rect class=\"page\" width=\"{}\" height=\"{}\" fill=\"none\" stroke=\"#333333\"/>",
+            page.width_px, page.height_px
+        );
+        for b in &boxes {
+            let cell = b.cell;
+            let color = hex_color(cell.cell_type);
+            let (fill, extra) = match b.dropped {
+                Some(_) => (
+                    format!("url(#hatch-{})", type_name(cell.cell_type)),
+                    " fill-opacity=\"0.8\" stroke-dasharray=\"4 2\"".to_string(),
+                ),
+                None => (
+                    color.to_string(),
+                    format!(" fill-opacity=\"{:.2}\"", importance_alpha(cell.importance)),
+                ),
+            };
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{fill}\" stroke=\"{color}\"{extra}>\
+                 <title>{}</title></rect>",
+                cell.x,
+                cell.y,
+                cell.w,
+                cell.h,
+                escape_xml(&tooltip(b))
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<g class=\"legend\" transform=\"translate(8,{})\">",
+            height + 8
+        );
+        for (idx, cell_type) in CELL_TYPES.iter().enumerate() {
+            let x = idx * 100;
+            let _ = writeln!(
+                svg,
+                "<rect x=\"{x}\" width=\"12\" height=\"12\" fill=\"{color}\" fill-opacity=\"0.6\" stroke=\"{color}\"/>\
+                 <text x=\"{}\" y=\"10\">{}</text>",
+                x + 16,
+                type_name(*cell_type),
+                color = hex_color(*cell_type)
+            );
+        }
+        let _ = writeln!(
+            svg,
+            "<rect x=\"600\" width=\"12\" height=\"12\" fill=\"url(#hatch-text)\" stroke=\"{}\" stroke-dasharray=\"4 2\"/>\
+             <text x=\"616\" y=\"10\">dropped</text>\n</g>\n</svg>",
+            hex_color(CellType::Text)
+        );
+        Ok(svg)
+    }
+
+    /// PNG rendering of the same overlay (without tooltips or legend),
+    /// scaled by `scale`.
+    pub fn overlay_png(&self, z: u32, scale: f32) -> Result<Vec<u8>> {
+        if !(scale > 0.0 && scale.is_finite()) {
+            return Err(DcfError::Other(format!("invalid overlay scale {scale}")));
+        }
+        let (page, boxes) = self.overlay_boxes(z)?;
+        let (width, height) = canvas_size(&page, &boxes);
+        let px = |v: f32| (v * scale).round().max(0.0) as u32;
+        let mut image = RgbaImage::from_pixel(
+            px(width as f32).max(1),
+            px(height as f32).max(1),
+            Rgba([255; 4]),
+        );
+        let border = Rgba([51, 51, 51, 255]);
+        stroke_rect(
+            &mut image,
+            (0, 0, px(page.width_px as f32), px(page.height_px as f32)),
+            border,
+            false,
+        );
+        for b in &boxes {
+            let cell = b.cell;
+            let rect = (
+                px(cell.x as f32),
+                px(cell.y as f32),
+                px(cell.w as f32).max(1),
+                px(cell.h as f32).max(1),
+            );
+            let color = rgb_color(cell.cell_type);
+            match b.dropped {
+                Some(_) => {
+                    fill_rect(&mut image, rect, color, 0.8, |x, y| (x + y) % 6 < 2);
+                    stroke_rect(&mut image, rect, opaque(color), true);
+                }
+                None => {
+                    let alpha = importance_alpha(cell.importance);
+                    fill_rect(&mut image, rect, color, alpha, |_, _| true);
+                    stroke_rect(&mut image, rect, opaque(color), false);
+                }
+            }
+        }
+        let mut bytes = Vec::new();
+        image
+            .write_to(&mut Cursor::new(&mut bytes), ImageFormat::Png)
+            .map_err(|e| DcfError::Other(format!("png encode failed: {e}")))?;
+        Ok(bytes)
+    }
+
+    fn overlay_boxes(&self, z: u32) -> Result<(PageInfo, Vec<OverlayBox<'_>>)> {
+        let page = self
+            .pages
+            .iter()
+            .find(|page| page.z == z)
+            .cloned()
+            .ok_or_else(|| DcfError::Other(format!("document has no page {z}")))?;
+        let mut boxes: Vec<OverlayBox<'_>> = self
+            .cells
+            .iter()
+            .filter(|cell| cell.z == z)
+            .map(|cell| OverlayBox {
+                cell,
+                payload: self.payload_for(&cell.code_id).unwrap_or_default(),
+                dropped: None,
+            })
+            .chain(
+                self.dropped
+                    .iter()
+                    .filter(|dropped| dropped.cell.z == z)
+                    .map(|dropped| OverlayBox {
+                        cell: &dropped.cell,
+                        payload: &dropped.payload,
+                        dropped: Some(dropped.reason),
+                    }),
+            )
+            .collect();
+        boxes.sort_by_key(|b| (b.cell.y, b.cell.x));
+        Ok((page, boxes))
+    }
+}
+
+/// The page, grown to fit cells that run past it (text inputs flow past the
+/// nominal page height).
+fn canvas_size(page: &PageInfo, boxes: &[OverlayBox<'_>]) -> (u32, u32) {
+    boxes
+        .iter()
+        .fold((page.width_px, page.height_px), |(w, h), b| {
+            let right = (b.cell.x.max(0) as u32).saturating_add(b.cell.w);
+            let bottom = (b.cell.y.max(0) as u32).saturating_add(b.cell.h);
+            (w.max(right), h.max(bottom))
+        })
+}
+
+fn tooltip(b: &OverlayBox<'_>) -> String {
+    let cell = b.cell;
+    let mut preview: String = b.payload.chars().take(PREVIEW_CHARS).collect();
+    if b.payload.chars().count() > PREVIEW_CHARS {
+        preview.push_str("...");
+    }
+    let status = match b.dropped {
+        Some(DropReason::Budget) => "dropped by budget ",
+        Some(DropReason::Footer) => "dropped as footer ",
+        Some(DropReason::Duplicate) => "dropped as duplicate ",
+        None => "",
+    };
+    format!(
+        "{status}{} z={} ({},{}) {}x{} importance={} code={}\n{}",
+        type_name(cell.cell_type).to_uppercase(),
+        cell.z,
+        cell.x,
+        cell.y,
+        cell.w,
+        cell.h,
+        cell.importance,
+        hex::encode(&cell.code_id[..8]),
+        preview.replace('\n', " ")
+    )
+}
+
+fn importance_alpha(importance: u8) -> f32 {
+    0.15 + 0.6 * f32::from(importance) / 255.0
+}
+
+fn type_name(cell_type: CellType) -> &'static str {
+    match cell_type {
+        CellType::Text => "text",
+        CellType::Header => "header",
+        CellType::Table => "table",
+        CellType::Figure => "figure",
+        CellType::Footer => "footer",
+        CellType::Equation => "equation",
+    }
+}
+
+fn rgb_color(cell_type: CellType) -> [u8; 3] {
+    match cell_type {
+        CellType::Text => [0x4e, 0x79, 0xa7],
+        CellType::Header => [0xf2, 0x8e, 0x2b],
+        CellType::Table => [0x59, 0xa1, 0x4f],
+        CellType::Figure => [0xb0, 0x7a, 0xa1],
+        CellType::Footer => [0x9d, 0x76, 0x60],
+        CellType::Equation => [0x76, 0xb7, 0xb2],
+    }
+}
+
+fn hex_color(cell_type: CellType) -> String {
+    format!("#{}", hex::encode(rgb_color(cell_type)))
+}
+
+fn opaque([r, g, b]: [u8; 3]) -> Rgba<u8> {
+    Rgba([r, g, b, 255])
+}
+
+type Rect = (u32, u32, u32, u32);
+
+fn fill_rect(
+    image: &mut RgbaImage,
+    (x0, y0, w, h): Rect,
+    color: [u8; 3],
+    alpha: f32,
+    mask: impl Fn(u32, u32) -> bool,
+) {
+    for y in y0..(y0 + h).min(image.height()) {
+        for x in x0..(x0 + w).min(image.width()) {
+            if !mask(x, y) {
+                continue;
+            }
+            let pixel = image.get_pixel_mut(x, y);
+            for channel in 0..3 {
+                let base = f32::from(pixel[channel]);
+                pixel[channel] = (base + (f32::from(color[channel]) - base) * alpha).round() as u8;
+            }
+        }
+    }
+}
+
+fn stroke_rect(image: &mut RgbaImage, (x0, y0, w, h): Rect, color: Rgba<u8>, dashed: bool) {
+    let (x1, y1) = (x0 + w.saturating_sub(1), y0 + h.saturating_sub(1));
+    let mut plot = |x: u32, y: u32, step: u32| {
+        if (!dashed || step % 6 < 4) && x < image.width() && y < image.height() {
+            image.put_pixel(x, y, color);
+        }
+    };
+    for x in x0..=x1 {
+        plot(x, y0, x - x0);
+        plot(x, y1, x - x0);
+    }
+    for y in y0..=y1 {
+        plot(x0, y, y - y0);
+        plot(x1, y, y - y0);
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{hash_payload, DroppedCell, Header};
+
+    fn cell(y: i32, cell_type: CellType, importance: u8, text: &str) -> (CellRecord, String) {
+        (
+            CellRecord {
+                z: 0,
+                x: 10,
+                y,
+                w: 100,
+                h: 20,
+                code_id: hash_payload(text),
+                rle: 0,
+                cell_type,
+                importance,
+                ocr_confidence: None,
+                lang: None,
+            },
+            text.to_string(),
+        )
+    }
+
+    fn document() -> Document {
+        let mut doc = Document::new(Header::default());
+        doc.add_page(PageInfo {
+            z: 0,
+            width_px: 200,
+            height_px: 100,
+        });
+        let (header, payload) = cell(10, CellType::Header, 255, "REVENUE <2024>");
+        doc.push_cell(header, payload);
+        let (table, payload) = cell(40, CellType::Table, 160, "a | b");
+        doc.push_cell(table, payload);
+        let (footer, payload) = cell(130, CellType::Footer, 20, "Page 1 of 9");
+        doc.dropped.push(DroppedCell {
+            cell: footer,
+            payload,
+            reason: DropReason::Footer,
+        });
+        doc
+    }
+
+    #[test]
+    fn svg_marks_types_importance_and_dropped_cells() {
+        let svg = document().overlay_svg(0).unwrap();
+        assert!(svg.starts_with("<svg"));
+        // The canvas grows to fit the dropped footer below the page.
+        assert!(svg.contains("height=\"178\""));
+        assert!(svg.contains("fill=\"#f28e2b\" stroke=\"#f28e2b\" fill-opacity=\"0.75\""));
+        assert!(svg.contains("<title>HEADER z=0 (10,10) 100x20 importance=255 code="));
+        assert!(svg.contains("REVENUE &lt;2024&gt;</title>"));
+        assert!(svg.contains("fill=\"url(#hatch-footer)\""));
+        assert!(svg.contains("<title>dropped as footer FOOTER z=0 (10,130)"));
+        assert!(document().overlay_svg(3).is_err());
+    }
+
+    #[test]
+    fn png_scales_the_canvas() {
+        let bytes = document().overlay_png(0, 0.5).unwrap();
+        let image = image::load_from_memory(&bytes).unwrap().to_rgba8();
+        assert_eq!(image.dimensions(), (100, 75));
+        assert_eq!(image.get_pixel(99, 74), &Rgba([255; 4]));
+        assert_ne!(image.get_pixel(30, 10), &Rgba([255; 4]));
+    }
+
+    #[test]
+    fn encoder_records_dropped_cells() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("report.txt");
+        std::fs::write(
+            &path,
+            "QUARTERLY RESULTS\nRevenue grew 12% to $1.2M\nCosts were flat\nMinor note\nPage 1 of 3\n",
+        )
+        .unwrap();
+        let encoder = crate::Encoder::builder("reports")
+            .unwrap()
+            .budget(Some(3))
+            .drop_footers(true)
+            .keep_dropped(true)
+            .build();
+        let (doc, _) = encoder.encode_path(&path).unwrap();
+        let reasons: Vec<_> = doc.dropped.iter().map(|d| d.reason).collect();
+        assert_eq!(doc.cells.len() + doc.dropped.len(), 5);
+        assert!(reasons.contains(&DropReason::Budget));
+        assert!(doc.dropped.iter().all(|d| !d.payload.is_empty()));
+        let svg = doc.overlay_svg(0).unwrap();
+        assert_eq!(
+            svg.matches("stroke-dasharray=\"4 2\">").count(),
+            doc.dropped.len()
+        );
+    }
+}
@@ -13,12 +13,13 @@ pub use crate::encoder::{EncodeInput, Encoder, EncoderBuilder, EncoderPreset};
 pub use crate::document::{CellRecord, CellType, CodeHash, Document, Header, PageInfo};
 
 // Serialization
-pub use crate::serializer::{TableMode, TextSerializer, TextSerializerConfig};
+pub use crate::serializer::{ContextFormat, TableMode, TextSerializer, TextSerializerConfig};
 
 // Chunking for RAG
-pub use crate::chunk::{ChunkConfig, ChunkMode, ChunkRecord, Chunker};
+pub use crate::chunk::{ChunkConfig, ChunkLevel, ChunkMode, ChunkRecord, Chunker};
 
 // Configuration types
+pub use crate::importance::{ImportanceScorer, LinearImportanceModel};
 pub use crate::normalization::{HyphenationMode, ImportanceTuning};
 
 // Error handling
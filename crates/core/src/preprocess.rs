@@ -0,0 +1,569 @@
+use std::path::Path;
+
+use image::{imageops, DynamicImage, GrayImage, Luma};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{DcfError, Result};
+
+const INK_THRESHOLD: u8 = 128;
+const MAX_DESKEW_SAMPLES: usize = 150_000;
+
+/// Image clean-up applied to scans before they reach Tesseract.
+///
+/// Steps run in a fixed order: orientation (90/180/270 turns), deskew,
+/// adaptive thresholding, despeckle. Everything is off by default; the
+/// `scans` preset turns all of them on.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PreprocessConfig {
+    pub orientation: bool,
+    pub deskew: bool,
+    /// Largest skew angle searched, in degrees either way.
+    pub max_skew_degrees: f32,
+    pub binarize: bool,
+    /// Side of the square window used for the local mean, in pixels.
+    pub threshold_window: u32,
+    /// A pixel is ink when it is this fraction darker than its local mean.
+    pub threshold_bias: f32,
+    pub despeckle: bool,
+    /// Connected ink blobs up to this many pixels are treated as noise.
+    pub speck_max_pixels: u32,
+}
+
+impl Default for PreprocessConfig {
+    fn default() -> Self {
+        Self {
+            orientation: false,
+            deskew: false,
+            max_skew_degrees: 10.0,
+            binarize: false,
+            threshold_window: 31,
+            threshold_bias: 0.15,
+            despeckle: false,
+            speck_max_pixels: 4,
+        }
+    }
+}
+
+impl PreprocessConfig {
+    /// Every step enabled with default parameters.
+    pub fn all() -> Self {
+        Self {
+            orientation: true,
+            deskew: true,
+            binarize: true,
+            despeckle: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.orientation || self.deskew || self.binarize || self.despeckle
+    }
+
+    /// Builds a config from step names (`orientation`, `deskew`, `binarize`,
+    /// `despeckle`, or `all` / `none`), keeping default parameters.
+    pub fn from_steps<I, S>(steps: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut config = Self::default();
+        for step in steps {
+            match step.as_ref().trim().to_ascii_lowercase().as_str() {
+                "" | "none" => {}
+                "all" => config = Self::all(),
+                "orientation" | "rotate" => config.orientation = true,
+                "deskew" => config.deskew = true,
+                "binarize" | "threshold" => config.binarize = true,
+                "despeckle" | "denoise" => config.despeckle = true,
+                other => {
+                    return Err(DcfError::Other(format!(
+                        "unknown preprocess step '{other}' (expected orientation, deskew, binarize, despeckle, all, none)"
+                    )))
+                }
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// What preprocessing changed on one image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct ScanCorrection {
+    /// Clockwise turn applied to make text upright: 0, 90, 180, or 270.
+    pub rotation: u16,
+    /// Rotation applied to level text lines, in degrees (clockwise positive).
+    pub skew_degrees: f32,
+    pub specks_removed: usize,
+}
+
+/// Runs the enabled steps and returns a grayscale image ready for OCR.
+pub fn preprocess(image: &DynamicImage, config: &PreprocessConfig) -> (GrayImage, ScanCorrection) {
+    let mut gray = image.to_luma8();
+    let mut correction = ScanCorrection::default();
+    if config.orientation {
+        let rotation = detect_orientation(&ink_mask(&gray, config), config.max_skew_degrees);
+        gray = match rotation {
+            90 => imageops::rotate90(&gray),
+            180 => imageops::rotate180(&gray),
+            270 => imageops::rotate270(&gray),
+            _ => gray,
+        };
+        correction.rotation = rotation;
+    }
+    if config.deskew {
+        let angle = detect_skew(&ink_mask(&gray, config), config.max_skew_degrees);
+        if angle.abs() >= 0.05 {
+            gray = rotate_about_center(&gray, angle);
+        }
+        correction.skew_degrees = angle;
+    }
+    if config.binarize {
+        gray = adaptive_threshold(&gray, config.threshold_window, config.threshold_bias);
+    }
+    if config.despeckle {
+        correction.specks_removed = despeckle(&mut gray, config);
+    }
+    (gray, correction)
+}
+
+/// Loads an image, preprocesses it, and writes the result (format from the extension).
+pub fn preprocess_file(
+    input: &Path,
+    output: &Path,
+    config: &PreprocessConfig,
+) -> Result<ScanCorrection> {
+    let image = image::open(input)
+        .map_err(|e| DcfError::Other(format!("failed to open image {}: {e}", input.display())))?;
+    let (prepared, correction) = preprocess(&image, config);
+    prepared
+        .save(output)
+        .map_err(|e| DcfError::Other(format!("failed to write {}: {e}", output.display())))?;
+    Ok(correction)
+}
+
+/// Bradley–Roth adaptive threshold: ink is any pixel darker than
+/// `(1 - bias)` times the mean of the `window`×`window` square around it.
+pub fn adaptive_threshold(image: &GrayImage, window: u32, bias: f32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let integral = integral_image(image);
+    let half = (window.max(3) / 2) as i64;
+    let stride = width as usize + 1;
+    let mut out = GrayImage::new(width, height);
+    for y in 0..height as i64 {
+        let y0 = (y - half).max(0) as usize;
+        let y1 = (y + half + 1).min(height as i64) as usize;
+        for x in 0..width as i64 {
+            let x0 = (x - half).max(0) as usize;
+            let x1 = (x + half + 1).min(width as i64) as usize;
+            let sum = integral[y1 * stride + x1] + integral[y0 * stride + x0]
+                - integral[y0 * stride + x1]
+                - integral[y1 * stride + x0];
+            let count = ((x1 - x0) * (y1 - y0)) as f64;
+            let value = image.get_pixel(x as u32, y as u32)[0] as f64;
+            let ink = value * count <= sum as f64 * (1.0 - bias as f64);
+            out.put_pixel(x as u32, y as u32, Luma([if ink { 0 } else { 255 }]));
+        }
+    }
+    out
+}
+
+fn integral_image(image: &GrayImage) -> Vec<u64> {
+    let (width, height) = image.dimensions();
+    let stride = width as usize + 1;
+    let mut integral = vec![0u64; stride * (height as usize + 1)];
+    for y in 0..height as usize {
+        let mut row_sum = 0u64;
+        for x in 0..width as usize {
+            row_sum += image.get_pixel(x as u32, y as u32)[0] as u64;
+            integral[(y + 1) * stride + x + 1] = integral[y * stride + x + 1] + row_sum;
+        }
+    }
+    integral
+}
+
+/// Binary ink mask used by the geometric steps; always adaptive so uneven
+/// illumination does not read as text.
+fn ink_mask(image: &GrayImage, config: &PreprocessConfig) -> GrayImage {
+    adaptive_threshold(image, config.threshold_window, config.threshold_bias)
+}
+
+fn is_ink(mask: &GrayImage, x: u32, y: u32) -> bool {
+    mask.get_pixel(x, y)[0] < INK_THRESHOLD
+}
+
+/// Clockwise turn (0/90/180/270) that makes text lines horizontal and upright.
+/// Both axes are scored at their best skew angle so a tilted page does not
+/// look like a turned one.
+pub fn detect_orientation(mask: &GrayImage, max_skew_degrees: f32) -> u16 {
+    let turned = imageops::rotate90(mask);
+    let horizontal = best_angle(
+        &ink_points(mask),
+        mask.dimensions(),
+        max_skew_degrees,
+        false,
+    );
+    let vertical = best_angle(
+        &ink_points(&turned),
+        turned.dimensions(),
+        max_skew_degrees,
+        false,
+    );
+    let (base, candidate, angle) = if vertical.1 > horizontal.1 {
+        (90, turned, vertical.0)
+    } else {
+        (0, mask.clone(), horizontal.0)
+    };
+    let level = if angle.abs() >= 0.05 {
+        rotate_about_center(&candidate, angle as f32)
+    } else {
+        candidate
+    };
+    if is_upside_down(&level) {
+        base + 180
+    } else {
+        base
+    }
+}
+
+fn profiles(mask: &GrayImage) -> (Vec<f64>, Vec<f64>) {
+    let (width, height) = mask.dimensions();
+    let mut rows = vec![0f64; height as usize];
+    let mut cols = vec![0f64; width as usize];
+    for (x, y, px) in mask.enumerate_pixels() {
+        if px[0] < INK_THRESHOLD {
+            rows[y as usize] += 1.0;
+            cols[x as usize] += 1.0;
+        }
+    }
+    (rows, cols)
+}
+
+/// Latin-like scripts have more ascender ink above the x-height band than
+/// descender ink below the baseline; flipped text shows the opposite.
+fn is_upside_down(mask: &GrayImage) -> bool {
+    let (rows, _) = profiles(mask);
+    let peak = rows.iter().cloned().fold(0.0, f64::max);
+    if peak == 0.0 {
+        return false;
+    }
+    let mut above = 0.0;
+    let mut below = 0.0;
+    let mut y = 0;
+    while y < rows.len() {
+        if rows[y] <= peak * 0.02 {
+            y += 1;
+            continue;
+        }
+        let start = y;
+        while y < rows.len() && rows[y] > peak * 0.02 {
+            y += 1;
+        }
+        let band = &rows[start..y];
+        let band_peak = band.iter().cloned().fold(0.0, f64::max);
+        let core: Vec<usize> = (0..band.len())
+            .filter(|&i| band[i] >= band_peak * 0.5)
+            .collect();
+        let (Some(&first), Some(&last)) = (core.first(), core.last()) else {
+            continue;
+        };
+        above += band[..first].iter().sum::<f64>();
+        below += band[last + 1..].iter().sum::<f64>();
+    }
+    below > above * 1.1
+}
+
+/// Finds the rotation (degrees, clockwise positive) that maximizes the
+/// sharpness of the horizontal projection profile.
+pub fn detect_skew(mask: &GrayImage, max_degrees: f32) -> f32 {
+    let (angle, _) = best_angle(&ink_points(mask), mask.dimensions(), max_degrees, true);
+    ((angle * 10.0).round() / 10.0) as f32
+}
+
+/// Ink pixel coordinates relative to the image center, subsampled for speed.
+fn ink_points(mask: &GrayImage) -> Vec<(f64, f64)> {
+    let (width, height) = mask.dimensions();
+    let mut points = Vec::new();
+    for (x, y, px) in mask.enumerate_pixels() {
+        if px[0] < INK_THRESHOLD {
+            points.push((
+                x as f64 - width as f64 / 2.0,
+                y as f64 - height as f64 / 2.0,
+            ));
+        }
+    }
+    if points.len() > MAX_DESKEW_SAMPLES {
+        let step = points.len().div_ceil(MAX_DESKEW_SAMPLES);
+        points = points.into_iter().step_by(step).collect();
+    }
+    points
+}
+
+/// Searches `[-max, max]` in half-degree steps (then tenths when `refine`)
+/// and returns the best angle with its normalized profile sharpness. Text
+/// lines give sharp edges across the line direction and a smooth profile
+/// along it.
+fn best_angle(
+    points: &[(f64, f64)],
+    dims: (u32, u32),
+    max_degrees: f32,
+    refine: bool,
+) -> (f64, f64) {
+    if points.len() < 32 {
+        return (0.0, 0.0);
+    }
+    let bins = (dims.0.max(dims.1) as usize) * 2;
+    let score = |angle: f64| -> f64 {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let mut profile = vec![0f64; bins];
+        for &(x, y) in points {
+            let rotated = x * sin + y * cos + bins as f64 / 2.0;
+            if rotated >= 0.0 && (rotated as usize) < bins {
+                profile[rotated as usize] += 1.0;
+            }
+        }
+        let energy: f64 = profile.iter().map(|v| v * v).sum();
+        let jumps: f64 = profile.windows(2).map(|w| (w[1] - w[0]).powi(2)).sum();
+        jumps / energy.max(1.0)
+    };
+    let max = max_degrees.abs().max(0.5) as f64;
+    let mut best = (0.0, score(0.0));
+    let search = |from: f64, to: f64, step: f64, best: &mut (f64, f64)| {
+        let mut angle = from;
+        while angle <= to + 1e-9 {
+            let value = score(angle);
+            if value > best.1 {
+                *best = (angle, value);
+            }
+            angle += step;
+        }
+    };
+    search(-max, max, 0.5, &mut best);
+    if refine {
+        let coarse = best.0;
+        search(coarse - 0.5, coarse + 0.5, 0.1, &mut best);
+    }
+    best
+}
+
+/// Rotates clockwise by `degrees` around the center, filling with white.
+pub fn rotate_about_center(image: &GrayImage, degrees: f32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let (sin, cos) = (degrees as f64).to_radians().sin_cos();
+    let cx = width as f64 / 2.0;
+    let cy = height as f64 / 2.0;
+    let mut out = GrayImage::from_pixel(width, height, Luma([255]));
+    for y in 0..height {
+        for x in 0..width {
+            let dx = x as f64 + 0.5 - cx;
+            let dy = y as f64 + 0.5 - cy;
+            let sx = dx * cos + dy * sin + cx;
+            let sy = -dx * sin + dy * cos + cy;
+            if sx >= 0.0 && sy >= 0.0 && sx < width as f64 && sy < height as f64 {
+                out.put_pixel(x, y, *image.get_pixel(sx as u32, sy as u32));
+            }
+        }
+    }
+    out
+}
+
+/// Whitens 8-connected ink blobs no larger than `speck_max_pixels`.
+fn despeckle(image: &mut GrayImage, config: &PreprocessConfig) -> usize {
+    let mask = if config.binarize {
+        image.clone()
+    } else {
+        ink_mask(image, config)
+    };
+    let (width, height) = mask.dimensions();
+    let mut seen = vec![false; (width * height) as usize];
+    let mut removed = 0;
+    let mut stack = Vec::new();
+    let mut blob = Vec::new();
+    for start_y in 0..height {
+        for start_x in 0..width {
+            let idx = (start_y * width + start_x) as usize;
+            if seen[idx] || !is_ink(&mask, start_x, start_y) {
+                continue;
+            }
+            seen[idx] = true;
+            stack.push((start_x, start_y));
+            blob.clear();
+            while let Some((x, y)) = stack.pop() {
+                blob.push((x, y));
+                for ny in y.saturating_sub(1)..=(y + 1).min(height - 1) {
+                    for nx in x.saturating_sub(1)..=(x + 1).min(width - 1) {
+                        let nidx = (ny * width + nx) as usize;
+                        if !seen[nidx] && is_ink(&mask, nx, ny) {
+                            seen[nidx] = true;
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+            }
+            if blob.len() <= config.speck_max_pixels as usize {
+                removed += 1;
+                for &(x, y) in &blob {
+                    image.put_pixel(x, y, Luma([255]));
+                }
+            }
+        }
+    }
+    removed
+}
+
+/// Synthetic scanned page for benches and tests: rows of glyph-shaped blobs
+/// (with ascenders and descenders) on an unevenly lit background, then
+/// skewed, turned, and sprinkled with specks.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScanFixture {
+    pub width: u32,
+    pub height: u32,
+    /// Clockwise turn applied to the upright page: 0, 90, 180, or 270.
+    pub rotation: u16,
+    /// Clockwise skew applied before the turn, in degrees.
+    pub skew_degrees: f32,
+    pub specks: usize,
+    pub seed: u64,
+}
+
+impl Default for ScanFixture {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 1000,
+            rotation: 0,
+            skew_degrees: 0.0,
+            specks: 0,
+            seed: 7,
+        }
+    }
+}
+
+impl ScanFixture {
+    pub fn render(&self) -> GrayImage {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let (width, height) = (self.width, self.height);
+        let mut page = GrayImage::from_fn(width, height, |x, _| {
+            // Illumination falls off towards the right edge like a fax.
+            Luma([(245.0 - 60.0 * x as f32 / width as f32) as u8])
+        });
+        let margin = width / 10;
+        let x_height = 12;
+        let mut baseline = margin + 20;
+        while baseline + 20 < height - margin {
+            let mut x = margin;
+            while x + 20 < width - margin {
+                let word_len = rng.gen_range(2..9);
+                for _ in 0..word_len {
+                    let glyph_w = rng.gen_range(6..11);
+                    fill(&mut page, x, baseline - x_height, glyph_w, x_height, 30);
+                    if rng.gen_bool(0.4) {
+                        fill(&mut page, x, baseline - x_height - 8, 2, 8, 30);
+                    }
+                    if rng.gen_bool(0.15) {
+                        fill(&mut page, x + glyph_w - 2, baseline, 2, 6, 30);
+                    }
+                    x += glyph_w + 2;
+                }
+                x += rng.gen_range(8..14);
+            }
+            baseline += 40;
+        }
+        let mut page = rotate_about_center(&page, self.skew_degrees);
+        page = match self.rotation {
+            90 => imageops::rotate90(&page),
+            180 => imageops::rotate180(&page),
+            270 => imageops::rotate270(&page),
+            _ => page,
+        };
+        let (w, h) = page.dimensions();
+        for _ in 0..self.specks {
+            let (x, y) = (rng.gen_range(0..w), rng.gen_range(0..h));
+            page.put_pixel(x, y, Luma([20]));
+        }
+        page
+    }
+
+    pub fn write_png(&self, path: &Path) -> Result<()> {
+        self.render()
+            .save_with_format(path, image::ImageFormat::Png)
+            .map_err(|e| DcfError::Other(format!("failed to write {}: {e}", path.display())))
+    }
+}
+
+fn fill(image: &mut GrayImage, x: u32, y: u32, w: u32, h: u32, value: u8) {
+    for yy in y..(y + h).min(image.height()) {
+        for xx in x..(x + w).min(image.width()) {
+            image.put_pixel(xx, yy, Luma([value]));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mask_of(fixture: &ScanFixture) -> GrayImage {
+        adaptive_threshold(&fixture.render(), 31, 0.15)
+    }
+
+    #[test]
+    fn deskew_recovers_applied_skew() {
+        for skew in [-4.0f32, 2.5, 6.0] {
+            let fixture = ScanFixture {
+                skew_degrees: skew,
+                ..ScanFixture::default()
+            };
+            let angle = detect_skew(&mask_of(&fixture), 10.0);
+            assert!((angle + skew).abs() <= 0.3, "skew {skew} detected {angle}");
+        }
+    }
+
+    #[test]
+    fn orientation_undoes_quarter_turns() {
+        for rotation in [0u16, 90, 180, 270] {
+            let fixture = ScanFixture {
+                rotation,
+                ..ScanFixture::default()
+            };
+            assert_eq!(
+                detect_orientation(&mask_of(&fixture), 10.0),
+                (360 - rotation) % 360,
+                "fixture turned {rotation}"
+            );
+        }
+    }
+
+    #[test]
+    fn full_pipeline_straightens_and_cleans() {
+        let fixture = ScanFixture {
+            rotation: 180,
+            skew_degrees: 3.0,
+            specks: 200,
+            ..ScanFixture::default()
+        };
+        let image = DynamicImage::ImageLuma8(fixture.render());
+        let (clean, correction) = preprocess(&image, &PreprocessConfig::all());
+        assert_eq!(correction.rotation, 180);
+        // A half turn keeps the tilt, so undoing it still takes -3°.
+        assert!((correction.skew_degrees + 3.0).abs() <= 0.3);
+        assert!(correction.specks_removed >= 150);
+        assert!(clean.pixels().all(|p| p[0] == 0 || p[0] == 255));
+    }
+
+    #[test]
+    fn steps_parse_from_names() {
+        let config = PreprocessConfig::from_steps(["deskew", "despeckle"]).unwrap();
+        assert!(config.deskew && config.despeckle);
+        assert!(!config.orientation && !config.binarize);
+        assert_eq!(
+            PreprocessConfig::from_steps(["all"]).unwrap(),
+            PreprocessConfig::all()
+        );
+        assert!(!PreprocessConfig::from_steps(["none"]).unwrap().is_enabled());
+        assert!(PreprocessConfig::from_steps(["sharpen"]).is_err());
+    }
+}
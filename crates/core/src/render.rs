@@ -0,0 +1,624 @@
+//! Markdown and HTML renderings of decoded cells.
+//!
+//! Cells are grouped into blocks in reading order: headings, paragraphs,
+//! lists, tables, figures, equations and footers. Every page becomes its own
+//! block of output, separated by a thematic break (`---`) in Markdown and a
+//! `<section class="page">` per page in HTML. Hyperlinks become Markdown
+//! links and `<a href>` elements.
+
+use std::collections::BTreeSet;
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+
+use crate::document::{CellRecord, CellType, Document};
+use crate::figures::FigureRef;
+use crate::links::LinkTarget;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Markup {
+    Markdown,
+    Html,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Block {
+    Heading(u8, String),
+    Paragraph(String),
+    /// Items of one list; ordered lists carry the number of their first item.
+    List(Option<u32>, Vec<String>),
+    Table(Vec<Vec<String>>),
+    Figure(FigureRef),
+    Equation(String),
+    Footer(String),
+}
+
+static MD_HEADING_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(#{1,6})\s+(.+?)\s*#*\s*$").unwrap());
+static LIST_ITEM_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?:([*+\-•])|(\d{1,3})[.)])\s+(.+)$").unwrap());
+pub(crate) static SECTION_NUMBER_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(\d+(?:\.\d+)*)\.?\s").unwrap());
+static COLUMN_GAP_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\t|\s{2,}").unwrap());
+
+/// Wraps hyperlink anchor text while blocks are built:
+/// `LINK_OPEN <index into Document::links> LINK_TEXT <anchor> LINK_CLOSE`.
+const LINK_OPEN: char = '\u{F8FA}';
+const LINK_TEXT: char = '\u{F8FB}';
+const LINK_CLOSE: char = '\u{F8FC}';
+static LINK_MARK_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new("\u{F8FA}([0-9]+)\u{F8FB}(.*?)\u{F8FC}").unwrap());
+
+/// Renders `cells` (already in reading order) for the pages in `pages`.
+pub(crate) fn render(
+    document: &Document,
+    pages: &[u32],
+    cells: &[CellRecord],
+    markup: Markup,
+) -> String {
+    let levels = HeadingLevels::new(document);
+    let rendered: Vec<String> = pages
+        .iter()
+        .map(|&z| {
+            let page_cells: Vec<&CellRecord> = cells.iter().filter(|cell| cell.z == z).collect();
+            let blocks = build_blocks(document, &page_cells, &levels);
+            match markup {
+                Markup::Markdown => blocks
+                    .iter()
+                    .map(block_to_markdown)
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+                Markup::Html => page_to_html(z, &blocks),
+            }
+        })
+        .collect();
+    match markup {
+        Markup::Markdown => {
+            let mut out = rendered.join("\n\n---\n\n");
+            out.push('\n');
+            write_links(document, &out, markup)
+        }
+        Markup::Html => {
+            let lang = document
+                .header
+                .lang
+                .as_deref()
+                .map(|lang| format!(" lang=\"{}\"", escape_html(lang)))
+                .unwrap_or_default();
+            let out = format!("<article{lang}>\n{}</article>\n", rendered.concat());
+            write_links(document, &out, markup)
+        }
+    }
+}
+
+/// `payload` with the anchor text of each hyperlink of `cell` marked.
+fn mark_links(document: &Document, cell: &CellRecord, payload: &str) -> String {
+    let mut spans: Vec<(usize, usize, usize)> = document
+        .links
+        .iter()
+        .enumerate()
+        .filter(|(_, link)| matches!(link.target, LinkTarget::Url(_)))
+        .filter(|(_, link)| link.source() == (cell.z, cell.x, cell.y))
+        .filter(|(_, link)| link.start < link.end && payload.get(link.start..link.end).is_some())
+        .map(|(idx, link)| (link.start, link.end, idx))
+        .collect();
+    if spans.is_empty() {
+        return payload.to_string();
+    }
+    spans.sort_unstable();
+    let mut out = String::with_capacity(payload.len() + spans.len() * 8);
+    let mut cursor = 0;
+    for (start, end, idx) in spans {
+        if start < cursor {
+            continue;
+        }
+        out.push_str(&payload[cursor..start]);
+        out.push(LINK_OPEN);
+        out.push_str(&idx.to_string());
+        out.push(LINK_TEXT);
+        out.push_str(&payload[start..end]);
+        out.push(LINK_CLOSE);
+        cursor = end;
+    }
+    out.push_str(&payload[cursor..]);
+    out
+}
+
+/// Replaces the link marks left by [`mark_links`] with the markup's links.
+fn write_links(document: &Document, rendered: &str, markup: Markup) -> String {
+    if !rendered.contains(LINK_OPEN) {
+        return rendered.to_string();
+    }
+    LINK_MARK_RE
+        .replace_all(rendered, |caps: &regex::Captures| {
+            let text = &caps[2];
+            let url = caps[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| document.links.get(idx))
+                .and_then(|link| match &link.target {
+                    LinkTarget::Url(url) => Some(url.as_str()),
+                    _ => None,
+                });
+            match (url, markup) {
+                (None, _) => text.to_string(),
+                (Some(url), Markup::Markdown) if url.contains([' ', '(', ')']) => {
+                    format!("[{text}](<{url}>)")
+                }
+                (Some(url), Markup::Markdown) => format!("[{text}]({url})"),
+                (Some(url), Markup::Html) => {
+                    format!("<a href=\"{}\">{text}</a>", escape_html(url))
+                }
+            }
+        })
+        .into_owned()
+}
+
+/// Every page of the document plus any `z` that only appears on cells.
+pub(crate) fn all_pages(document: &Document) -> Vec<u32> {
+    let pages: BTreeSet<u32> = document
+        .pages
+        .iter()
+        .map(|page| page.z)
+        .chain(document.cells.iter().map(|cell| cell.z))
+        .collect();
+    pages.into_iter().collect()
+}
+
+/// Levels for `HEADER` cells without Markdown markers: section numbers give
+/// the depth (`2.1 Scope` is level 2), otherwise taller lines rank higher.
+pub(crate) struct HeadingLevels {
+    heights: Vec<u32>,
+}
+
+impl HeadingLevels {
+    pub(crate) fn new(document: &Document) -> Self {
+        let heights: BTreeSet<u32> = document
+            .cells
+            .iter()
+            .filter(|cell| cell.cell_type == CellType::Header)
+            .map(|cell| cell.h)
+            .collect();
+        Self {
+            heights: heights.into_iter().rev().collect(),
+        }
+    }
+
+    /// Level and text when the cell is a heading: a Markdown `#` line of any
+    /// type, or a `HEADER` cell.
+    pub(crate) fn heading(&self, cell: &CellRecord, text: &str) -> Option<(u8, String)> {
+        if let Some(caps) = MD_HEADING_RE.captures(text) {
+            return Some((caps[1].len() as u8, caps[2].to_string()));
+        }
+        (cell.cell_type == CellType::Header).then(|| (self.level(cell, text), text.to_string()))
+    }
+
+    fn level(&self, cell: &CellRecord, text: &str) -> u8 {
+        if let Some(caps) = SECTION_NUMBER_RE.captures(text) {
+            return (caps[1].split('.').count()).min(6) as u8;
+        }
+        let rank = self.heights.iter().position(|h| *h == cell.h).unwrap_or(0);
+        (rank + 1).min(6) as u8
+    }
+}
+
+fn build_blocks(document: &Document, cells: &[&CellRecord], levels: &HeadingLevels) -> Vec<Block> {
+    let mut blocks: Vec<Block> = Vec::new();
+    for cell in cells {
+        let Some(payload) = document.payload_for(&cell.code_id) else {
+            continue;
+        };
+        let marked = mark_links(document, cell, payload);
+        let text = marked.trim();
+        if text.is_empty() {
+            continue;
+        }
+        if let Some((level, heading)) = levels.heading(cell, text) {
+            blocks.push(Block::Heading(level, heading));
+            continue;
+        }
+        match cell.cell_type {
+            CellType::Figure => match FigureRef::parse(payload) {
+                Some(figure) => blocks.push(Block::Figure(figure)),
+                None => blocks.push(Block::Paragraph(text.into())),
+            },
+            CellType::Equation => blocks.push(Block::Equation(text.into())),
+            CellType::Footer => blocks.push(Block::Footer(text.into())),
+            CellType::Table => {
+                let rows = table_rows(text, table_width(&blocks));
+                push_table_rows(&mut blocks, rows)
+            }
+            CellType::Text if is_box_rule(text) => {}
+            CellType::Text if text.contains('│') => {
+                let rows = table_rows(text, table_width(&blocks));
+                push_table_rows(&mut blocks, rows)
+            }
+            // `HEADER` cells were turned into headings above.
+            CellType::Text | CellType::Header => push_text(&mut blocks, text),
+        }
+    }
+    // A "table" of single values is just a run of lines.
+    blocks
+        .into_iter()
+        .flat_map(|block| match block {
+            Block::Table(rows) if rows.iter().all(|row| row.len() <= 1) => rows
+                .into_iter()
+                .filter_map(|row| row.into_iter().next())
+                .map(Block::Paragraph)
+                .collect(),
+            other => vec![other],
+        })
+        .collect()
+}
+
+/// Width of the table the next rows would join, so a table whose flattened
+/// rows spill over into further cells splits them the same way.
+fn table_width(blocks: &[Block]) -> Option<usize> {
+    match blocks.last() {
+        Some(Block::Table(rows)) => rows.first().map(Vec::len),
+        _ => None,
+    }
+}
+
+fn push_table_rows(blocks: &mut Vec<Block>, rows: Vec<Vec<String>>) {
+    if rows.is_empty() {
+        return;
+    }
+    if let Some(Block::Table(existing)) = blocks.last_mut() {
+        existing.extend(rows);
+    } else {
+        blocks.push(Block::Table(rows));
+    }
+}
+
+/// Lists gather consecutive items; a line that starts in lower case after a
+/// line without closing punctuation continues the previous paragraph or list
+/// item, which undoes the line wrapping of PDF and text inputs.
+fn push_text(blocks: &mut Vec<Block>, text: &str) {
+    if let Some(caps) = LIST_ITEM_RE.captures(text) {
+        let start = caps.get(2).and_then(|n| n.as_str().parse::<u32>().ok());
+        let item = caps[3].to_string();
+        match blocks.last_mut() {
+            Some(Block::List(kind, items)) if kind.is_some() == start.is_some() => items.push(item),
+            _ => blocks.push(Block::List(start, vec![item])),
+        }
+        return;
+    }
+    let previous = match blocks.last_mut() {
+        Some(Block::Paragraph(previous)) => Some(previous),
+        Some(Block::List(_, items)) => items.last_mut(),
+        _ => None,
+    };
+    if let Some(previous) = previous {
+        let open = !previous.ends_with(['.', '!', '?', ':', ';']);
+        if open && text.starts_with(|c: char| c.is_lowercase()) {
+            previous.push(' ');
+            previous.push_str(text);
+            return;
+        }
+    }
+    blocks.push(Block::Paragraph(text.into()));
+}
+
+/// Splits a table payload into rows of cells. Pipe tables (including rows
+/// flattened onto one line), box-drawing tables, tab-separated rows and
+/// columns separated by runs of spaces are recognised; separator rows are
+/// dropped. `width` is the row width already known from earlier lines.
+fn table_rows(payload: &str, mut width: Option<usize>) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    for line in payload.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        if is_box_rule(line) {
+            continue;
+        }
+        if line.contains('|') || line.contains('│') {
+            rows.extend(split_pipe_rows(line, &mut width));
+        } else {
+            rows.push(
+                COLUMN_GAP_RE
+                    .split(line)
+                    .map(|cell| cell.trim().to_string())
+                    .collect(),
+            );
+        }
+    }
+    rows.retain(|row| !is_separator_row(row));
+    rows
+}
+
+fn split_pipe_rows(line: &str, known_width: &mut Option<usize>) -> Vec<Vec<String>> {
+    let inner = line
+        .trim_start_matches(['|', '│'])
+        .trim_end_matches(['|', '│']);
+    let cells: Vec<String> = inner
+        .split(['|', '│'])
+        .map(|cell| cell.trim().to_string())
+        .collect();
+    // `| a | b | |---|---| | 1 | 2 |`: rows joined on one line are separated
+    // by an empty cell, and the separator row (here or on an earlier line)
+    // tells how wide a row is. The last row may be cut short.
+    let separators = cells
+        .iter()
+        .skip_while(|cell| !is_separator_cell(cell))
+        .take_while(|cell| is_separator_cell(cell))
+        .count();
+    if separators > 0 {
+        *known_width = Some(separators);
+    }
+    let width = known_width.unwrap_or(0);
+    if width > 0 && cells.len() > width {
+        let chunks: Vec<&[String]> = cells.chunks(width + 1).collect();
+        let aligned = chunks
+            .iter()
+            .all(|chunk| chunk.len() <= width || chunk[width].is_empty());
+        if aligned {
+            return chunks
+                .into_iter()
+                .map(|chunk| chunk[..chunk.len().min(width)].to_vec())
+                .collect();
+        }
+    }
+    vec![cells]
+}
+
+fn is_separator_cell(cell: &str) -> bool {
+    let core = cell.trim_matches(':');
+    core.len() >= 3 && core.chars().all(|c| c == '-')
+}
+
+fn is_separator_row(row: &[String]) -> bool {
+    row.iter()
+        .all(|cell| cell.is_empty() || is_separator_cell(cell))
+}
+
+/// Horizontal rules of box-drawing tables, e.g. `───────┼──`.
+fn is_box_rule(line: &str) -> bool {
+    line.chars().any(|c| c == '─')
+        && line
+            .chars()
+            .all(|c| c.is_whitespace() || ('\u{2500}'..='\u{257F}').contains(&c))
+}
+
+fn block_to_markdown(block: &Block) -> String {
+    match block {
+        Block::Heading(level, text) => format!("{} {text}", "#".repeat(*level as usize)),
+        Block::Paragraph(text) | Block::Footer(text) => text.clone(),
+        Block::List(start, items) => items
+            .iter()
+            .enumerate()
+            .map(|(idx, item)| match start {
+                Some(start) => format!("{}. {item}", *start as usize + idx),
+                None => format!("- {item}"),
+            })
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Block::Table(rows) => {
+            let width = rows.iter().map(Vec::len).max().unwrap_or(1);
+            let line = |row: &[String]| {
+                let cells: Vec<String> = (0..width)
+                    .map(|idx| {
+                        row.get(idx)
+                            .map_or(String::new(), |c| c.replace('|', "\\|"))
+                    })
+                    .collect();
+                format!("| {} |", cells.join(" | "))
+            };
+            let mut out = vec![line(&rows[0]), format!("|{}", " --- |".repeat(width))];
+            out.extend(rows[1..].iter().map(|row| line(row)));
+            out.join("\n")
+        }
+        Block::Figure(figure) => FigureRef {
+            text: None,
+            ..figure.clone()
+        }
+        .to_payload(),
+        Block::Equation(tex) => format!("$$\n{tex}\n$$"),
+    }
+}
+
+fn page_to_html(z: u32, blocks: &[Block]) -> String {
+    let mut out = format!("<section class=\"page\" data-z=\"{z}\">\n");
+    for block in blocks {
+        out.push_str(&block_to_html(block));
+        out.push('\n');
+    }
+    out.push_str("</section>\n");
+    out
+}
+
+fn block_to_html(block: &Block) -> String {
+    match block {
+        Block::Heading(level, text) => format!("<h{level}>{}</h{level}>", escape_html(text)),
+        Block::Paragraph(text) => format!("<p>{}</p>", escape_html(text)),
+        Block::Footer(text) => format!("<footer>{}</footer>", escape_html(text)),
+        Block::List(start, items) => {
+            let items: String = items
+                .iter()
+                .map(|item| format!("<li>{}</li>", escape_html(item)))
+                .collect();
+            match start {
+                None => format!("<ul>{items}</ul>"),
+                Some(1) => format!("<ol>{items}</ol>"),
+                Some(start) => format!("<ol start=\"{start}\">{items}</ol>"),
+            }
+        }
+        Block::Table(rows) => {
+            let width = rows.iter().map(Vec::len).max().unwrap_or(1);
+            let row = |cells: &[String], tag: &str| {
+                let cells: String = (0..width)
+                    .map(|idx| {
+                        let text = cells.get(idx).map_or("", String::as_str);
+                        format!("<{tag}>{}</{tag}>", escape_html(text))
+                    })
+                    .collect();
+                format!("<tr>{cells}</tr>")
+            };
+            let body: String = rows[1..].iter().map(|cells| row(cells, "td")).collect();
+            format!(
+                "<table>\n<thead>{}</thead>\n<tbody>{body}</tbody>\n</table>",
+                row(&rows[0], "th")
+            )
+        }
+        Block::Figure(figure) => {
+            let caption = figure.caption.as_deref().unwrap_or_default();
+            let mut out = format!(
+                "<figure><img src=\"{}\" alt=\"{}\">",
+                escape_html(&figure.path),
+                escape_html(caption)
+            );
+            if !caption.is_empty() {
+                out.push_str(&format!(
+                    "<figcaption>{}</figcaption>",
+                    escape_html(caption)
+                ));
+            }
+            out.push_str("</figure>");
+            out
+        }
+        Block::Equation(tex) => format!("<div class=\"math\">\\[{}\\]</div>", escape_html(tex)),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            _ => out.push(ch),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Header;
+
+    fn document(pages: &[&[(CellType, u32, &str)]]) -> Document {
+        let mut doc = Document::new(Header::default());
+        for (z, lines) in pages.iter().enumerate() {
+            for (idx, (cell_type, h, text)) in lines.iter().enumerate() {
+                doc.push_fixture_cell(z as u32, 64 + idx as i32 * 40, *cell_type, text)
+                    .h = *h;
+            }
+        }
+        doc
+    }
+
+    fn markdown(doc: &Document) -> String {
+        render(doc, &all_pages(doc), &doc.ordered_cells(), Markup::Markdown)
+    }
+
+    #[test]
+    fn headings_lists_and_wrapped_paragraphs() {
+        let doc = document(&[&[
+            (CellType::Header, 32, "ANNUAL REPORT"),
+            (CellType::Text, 24, "## Highlights"),
+            (CellType::Text, 24, "* Revenue: $12.4M"),
+            (CellType::Text, 24, "* Net income: $2.2M"),
+            (CellType::Header, 24, "2.1 SCOPE OF WORK"),
+            (
+                CellType::Text,
+                24,
+                "Demand remained strong across retail and",
+            ),
+            (CellType::Text, 24, "industrial channels."),
+            (CellType::Text, 24, "A new paragraph."),
+            (CellType::Text, 24, "2. Second step of the"),
+            (CellType::Text, 24, "pipeline"),
+            (CellType::Text, 24, "3. Third step"),
+        ]]);
+        assert_eq!(
+            markdown(&doc),
+            "# ANNUAL REPORT\n\n## Highlights\n\n- Revenue: $12.4M\n- Net income: $2.2M\n\n\
+             ## 2.1 SCOPE OF WORK\n\nDemand remained strong across retail and industrial channels.\n\n\
+             A new paragraph.\n\n2. Second step of the pipeline\n3. Third step\n"
+        );
+        let html = render(&doc, &all_pages(&doc), &doc.ordered_cells(), Markup::Html);
+        assert!(html.contains(
+            "<ol start=\"2\"><li>Second step of the pipeline</li><li>Third step</li></ol>"
+        ));
+    }
+
+    #[test]
+    fn tables_figures_and_page_breaks() {
+        let doc = document(&[
+            &[
+                (
+                    CellType::Table,
+                    24,
+                    "| Item | Q1 | |---|---| | Revenue | 10 | | Cost | 4 |",
+                ),
+                (
+                    CellType::Figure,
+                    24,
+                    "![Revenue chart](figures/abc.png)\nQ1 Q2",
+                ),
+            ],
+            &[
+                (CellType::Text, 24, "───────┬──"),
+                (CellType::Text, 24, "Item │Q1"),
+                (CellType::Text, 24, "───────┼──"),
+                (CellType::Text, 24, "R&D │<5"),
+                (CellType::Equation, 24, "E = mc^2"),
+            ],
+        ]);
+        assert_eq!(
+            markdown(&doc),
+            "| Item | Q1 |\n| --- | --- |\n| Revenue | 10 |\n| Cost | 4 |\n\n\
+             ![Revenue chart](figures/abc.png)\n\n---\n\n\
+             | Item | Q1 |\n| --- | --- |\n| R&D | <5 |\n\n$$\nE = mc^2\n$$\n"
+        );
+        let html = render(&doc, &all_pages(&doc), &doc.ordered_cells(), Markup::Html);
+        assert!(html.starts_with("<article>\n<section class=\"page\" data-z=\"0\">"));
+        assert!(html.contains("<thead><tr><th>Item</th><th>Q1</th></tr></thead>"));
+        assert!(html.contains("<tr><td>R&amp;D</td><td>&lt;5</td></tr>"));
+        assert!(html.contains(
+            "<figure><img src=\"figures/abc.png\" alt=\"Revenue chart\"><figcaption>Revenue chart</figcaption></figure>"
+        ));
+        assert!(html.contains("<section class=\"page\" data-z=\"1\">"));
+    }
+
+    #[test]
+    fn hyperlinks_become_links() {
+        use crate::links::{Link, LinkKind};
+
+        let mut doc = document(&[&[(CellType::Text, 24, "Read the R&D guide now.")]]);
+        doc.links.push(Link {
+            z: 0,
+            x: 64,
+            y: 64,
+            start: 9,
+            end: 18,
+            kind: LinkKind::Hyperlink,
+            target: LinkTarget::Url("https://example.org/guide?a=1&b=2".into()),
+        });
+        assert_eq!(
+            markdown(&doc),
+            "Read the [R&D guide](https://example.org/guide?a=1&b=2) now.\n"
+        );
+        let html = render(&doc, &all_pages(&doc), &doc.ordered_cells(), Markup::Html);
+        assert!(html.contains(
+            "<p>Read the <a href=\"https://example.org/guide?a=1&amp;b=2\">R&amp;D guide</a> now.</p>"
+        ));
+    }
+
+    #[test]
+    fn flattened_rows_split_across_table_cells() {
+        let doc = document(&[&[
+            (
+                CellType::Table,
+                24,
+                "| Item | Q1 | |---|---| | Revenue | 10 | | Cost |",
+            ),
+            (CellType::Table, 24, "| R&D | 5 | | Tax | 2 |"),
+        ]]);
+        assert_eq!(
+            markdown(&doc),
+            "| Item | Q1 |\n| --- | --- |\n| Revenue | 10 |\n| Cost |  |\n| R&D | 5 |\n| Tax | 2 |\n"
+        );
+    }
+}
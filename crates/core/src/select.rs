@@ -0,0 +1,1004 @@
+//! Cell selector expressions shared by decode, context, chunking, search and
+//! the bindings.
+//!
+//! ```text
+//! page=2..5 and type=table|figure
+//! section~"^Results" and not text~"(?i)draft"
+//! (importance>=180 or numguard=currency) lang=en
+//! z=3,x=120..240,y=40..100
+//! ```
+//!
+//! An expression is a list of predicates joined by `and` (also `&&`, `,` or
+//! plain juxtaposition), `or` (`||`) and `not` (`!`), with parentheses for
+//! grouping; `not` binds tightest and `or` loosest. Predicates are
+//! `key op value`:
+//!
+//! | key | ops | matches |
+//! |-----|-----|---------|
+//! | `page` / `z` | `=` `<` `<=` `>` `>=` | page number |
+//! | `x`, `y` | `=` `<` `<=` `>` `>=` | cells whose box overlaps the range |
+//! | `type` | `=` | cell type |
+//! | `importance` | `=` `<` `<=` `>` `>=` | importance (0–255); `min_importance=N` is `importance>=N` |
+//! | `text` | `=` `~` | payload: case-insensitive substring, or regex |
+//! | `section` | `=` `~` | heading path: any heading equal (ignoring case and section numbers), or regex over `A > B > C` |
+//! | `numguard` | bare, `=` | cells with a NumGuard, optionally of the given kinds |
+//! | `lang` | `=` | cell language (or the document's) |
+//! | `doc` / `doc_id` | `=` `~` | document id, where the caller knows one |
+//!
+//! Numeric values are `N`, `A..B`, `A..` or `..B`; `|` separates
+//! alternatives (`page=1|4..6`, `type=table|figure`). Values containing
+//! spaces, commas or parentheses are quoted with `"` or `'`; a backslash only
+//! escapes the quote character, so regexes keep their own escapes.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::chunk::ChunkRecord;
+use crate::document::{CellRecord, CellType, Document};
+use crate::embedding::EmbeddingRecord;
+use crate::error::{DcfError, Result};
+use crate::numguard::NumericKind;
+use crate::render::{HeadingLevels, SECTION_NUMBER_RE};
+
+/// A parsed selector expression. Parse with [`str::parse`] / [`Selector::parse`].
+#[derive(Debug, Clone)]
+pub struct Selector {
+    source: String,
+    expr: Expr,
+}
+
+/// What a selector sees of a cell or a chunk. Predicates on fields the target
+/// cannot provide (`None`) do not match.
+#[derive(Debug, Clone, Copy)]
+pub struct SelectTarget<'a> {
+    pub doc: Option<&'a str>,
+    /// First and last page covered.
+    pub pages: (u32, u32),
+    /// `(x0, y0, x1, y1)` in page pixels.
+    pub bbox: Option<(i32, i32, i32, i32)>,
+    pub cell_type: CellType,
+    pub importance: u8,
+    pub text: &'a str,
+    /// Enclosing headings, outermost first.
+    pub section: Option<&'a [String]>,
+    /// Kinds of the NumGuards anchored on the target.
+    pub numguards: Option<&'a [NumericKind]>,
+    pub lang: Option<&'a str>,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    All,
+    Pred(Pred),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum Pred {
+    Number(NumField, Vec<(i64, i64)>),
+    Type(Vec<CellType>),
+    Text(TextMatch),
+    Section(TextMatch),
+    NumGuard(Vec<NumericKind>),
+    Lang(Vec<String>),
+    Doc(TextMatch),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum NumField {
+    Page,
+    X,
+    Y,
+    Importance,
+}
+
+#[derive(Debug, Clone)]
+enum TextMatch {
+    Equals(Vec<String>),
+    Regex(Regex),
+}
+
+impl Selector {
+    pub fn parse(source: &str) -> Result<Self> {
+        let tokens = lex(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = if parser.tokens.is_empty() {
+            Expr::All
+        } else {
+            let expr = parser.or()?;
+            if let Some(token) = parser.tokens.get(parser.pos) {
+                return Err(invalid(format!("unexpected {}", token.describe())));
+            }
+            expr
+        };
+        Ok(Self {
+            source: source.trim().to_string(),
+            expr,
+        })
+    }
+
+    /// Parses a `--filters` value: a selector expression, or the older
+    /// `doc_id=..,type=..,min_importance=..` list. That form treated repeated
+    /// `doc_id` and `type` keys as alternatives, so `type=text,type=table`
+    /// reads as `(type=text or type=table)` rather than an empty `and`; a
+    /// repeated `min_importance` keeps its last value.
+    pub fn parse_filters(source: &str) -> Result<Self> {
+        match legacy_filters(source) {
+            Some(expr) => Self::parse(&expr),
+            None => Self::parse(source),
+        }
+    }
+
+    /// A selector that matches everything.
+    pub fn all() -> Self {
+        Self {
+            source: String::new(),
+            expr: Expr::All,
+        }
+    }
+
+    pub fn matches(&self, target: &SelectTarget<'_>) -> bool {
+        self.expr.matches(target)
+    }
+
+    /// The page every match must be on, when the expression pins one
+    /// (`page=3 and ...`), so callers can load just that page. `None` when
+    /// the expression also tests sections, whose paths need the headings
+    /// of earlier pages.
+    pub fn single_page(&self) -> Option<u32> {
+        if self.uses_sections() {
+            return None;
+        }
+        self.expr.single_page()
+    }
+
+    fn uses_sections(&self) -> bool {
+        self.expr.any_pred(&|pred| matches!(pred, Pred::Section(_)))
+    }
+}
+
+impl FromStr for Selector {
+    type Err = DcfError;
+
+    fn from_str(value: &str) -> Result<Self> {
+        Self::parse(value)
+    }
+}
+
+impl fmt::Display for Selector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Expr {
+    fn matches(&self, target: &SelectTarget<'_>) -> bool {
+        match self {
+            Expr::All => true,
+            Expr::Pred(pred) => pred.matches(target),
+            Expr::Not(inner) => !inner.matches(target),
+            Expr::And(items) => items.iter().all(|item| item.matches(target)),
+            Expr::Or(items) => items.iter().any(|item| item.matches(target)),
+        }
+    }
+
+    fn single_page(&self) -> Option<u32> {
+        match self {
+            Expr::Pred(Pred::Number(NumField::Page, ranges)) => match ranges.as_slice() {
+                [(lo, hi)] if lo == hi => u32::try_from(*lo).ok(),
+                _ => None,
+            },
+            Expr::And(items) => items.iter().find_map(Expr::single_page),
+            _ => None,
+        }
+    }
+
+    fn any_pred(&self, test: &dyn Fn(&Pred) -> bool) -> bool {
+        match self {
+            Expr::All => false,
+            Expr::Pred(pred) => test(pred),
+            Expr::Not(inner) => inner.any_pred(test),
+            Expr::And(items) | Expr::Or(items) => items.iter().any(|item| item.any_pred(test)),
+        }
+    }
+}
+
+impl Pred {
+    fn matches(&self, target: &SelectTarget<'_>) -> bool {
+        match self {
+            Pred::Number(field, ranges) => {
+                let (lo, hi) = match field {
+                    NumField::Page => (target.pages.0 as i64, target.pages.1 as i64),
+                    NumField::Importance => (target.importance as i64, target.importance as i64),
+                    NumField::X => match target.bbox {
+                        Some((x0, _, x1, _)) => (x0 as i64, x1 as i64),
+                        None => return false,
+                    },
+                    NumField::Y => match target.bbox {
+                        Some((_, y0, _, y1)) => (y0 as i64, y1 as i64),
+                        None => return false,
+                    },
+                };
+                ranges.iter().any(|(a, b)| lo <= *b && hi >= *a)
+            }
+            Pred::Type(types) => types.contains(&target.cell_type),
+            Pred::Text(TextMatch::Equals(needles)) => {
+                let haystack = target.text.to_lowercase();
+                needles.iter().any(|needle| haystack.contains(needle))
+            }
+            Pred::Text(TextMatch::Regex(re)) => re.is_match(target.text),
+            Pred::Section(matcher) => match target.section {
+                Some(path) => match matcher {
+                    TextMatch::Equals(names) => path.iter().any(|heading| {
+                        let heading = heading.trim().to_lowercase();
+                        let bare = SECTION_NUMBER_RE.replace(&heading, "");
+                        names.iter().any(|name| *name == heading || *name == bare)
+                    }),
+                    TextMatch::Regex(re) => re.is_match(&path.join(" > ")),
+                },
+                None => false,
+            },
+            Pred::NumGuard(kinds) => match target.numguards {
+                Some(found) if kinds.is_empty() => !found.is_empty(),
+                Some(found) => found.iter().any(|kind| kinds.contains(kind)),
+                None => false,
+            },
+            Pred::Lang(langs) => target
+                .lang
+                .is_some_and(|lang| langs.contains(&lang.to_lowercase())),
+            Pred::Doc(matcher) => match (target.doc, matcher) {
+                (Some(doc), TextMatch::Equals(ids)) => ids.iter().any(|id| id == doc),
+                (Some(doc), TextMatch::Regex(re)) => re.is_match(doc),
+                (None, _) => false,
+            },
+        }
+    }
+}
+
+impl Document {
+    /// Cells matching `selector`, in reading order.
+    pub fn select_cells(&self, selector: &Selector) -> Vec<CellRecord> {
+        let sections = selector.uses_sections().then(|| section_paths(self));
+        let mut guards: HashMap<(u32, i32, i32), Vec<NumericKind>> = HashMap::new();
+        for guard in &self.numguards {
+            let kind = guard
+                .entity
+                .as_ref()
+                .map_or(NumericKind::Plain, |entity| entity.kind);
+            guards
+                .entry((guard.z, guard.y as i32, guard.x as i32))
+                .or_default()
+                .push(kind);
+        }
+        self.ordered_cells()
+            .into_iter()
+            .filter(|cell| {
+                let key = cell.key();
+                let target = SelectTarget {
+                    doc: None,
+                    pages: (cell.z, cell.z),
+                    bbox: Some((
+                        cell.x,
+                        cell.y,
+                        cell.x + cell.w as i32,
+                        cell.y + cell.h as i32,
+                    )),
+                    cell_type: cell.cell_type,
+                    importance: cell.importance,
+                    text: self.payload_for(&cell.code_id).unwrap_or_default(),
+                    section: sections
+                        .as_ref()
+                        .map(|paths| paths.get(&key).map_or(&[][..], Vec::as_slice)),
+                    numguards: Some(guards.get(&key).map_or(&[][..], Vec::as_slice)),
+                    lang: cell.lang.as_deref().or(self.header.lang.as_deref()),
+                };
+                selector.matches(&target)
+            })
+            .collect()
+    }
+
+    /// Keeps only the cells matching `selector`, together with their
+    /// payloads, NumGuards and links. Pages are left as they are.
+    pub fn retain_selected(&mut self, selector: &Selector) {
+        let keep: HashSet<(u32, i32, i32)> = self
+            .select_cells(selector)
+            .iter()
+            .map(CellRecord::key)
+            .collect();
+        self.cells.retain(|cell| keep.contains(&cell.key()));
+        self.numguards
+            .retain(|guard| keep.contains(&(guard.z, guard.y as i32, guard.x as i32)));
+        self.retain_dict_for_cells();
+        self.retain_links_for_cells();
+    }
+}
+
+impl ChunkRecord {
+    /// The chunk as seen by a [`Selector`]: its page span, dominant type,
+    /// mean importance and breadcrumb, when recorded. Chunks carry no box,
+    /// NumGuards or language.
+    pub fn select_target(&self) -> SelectTarget<'_> {
+        SelectTarget {
+            section: (!self.breadcrumb.is_empty()).then_some(self.breadcrumb.as_slice()),
+            ..chunk_target(
+                &self.doc,
+                (self.z_start, self.z_end),
+                self.dominant_type,
+                self.importance_mean,
+                &self.text,
+            )
+        }
+    }
+}
+
+impl EmbeddingRecord {
+    /// Same view as [`ChunkRecord::select_target`].
+    pub fn select_target(&self) -> SelectTarget<'_> {
+        SelectTarget {
+            section: (!self.breadcrumb.is_empty()).then_some(self.breadcrumb.as_slice()),
+            ..chunk_target(
+                &self.doc,
+                (self.z_start, self.z_end),
+                self.dominant_type,
+                self.importance_mean,
+                &self.text,
+            )
+        }
+    }
+}
+
+/// `importance_mean` is stored as a fraction of 255.
+fn chunk_target<'a>(
+    doc: &'a str,
+    pages: (u32, u32),
+    cell_type: CellType,
+    importance_mean: f32,
+    text: &'a str,
+) -> SelectTarget<'a> {
+    SelectTarget {
+        doc: Some(doc),
+        pages,
+        bbox: None,
+        cell_type,
+        importance: (importance_mean * 255.0).round().clamp(0.0, 255.0) as u8,
+        text,
+        section: None,
+        numguards: None,
+        lang: None,
+    }
+}
+
+/// Heading path of every cell, keyed by [`CellRecord::key`]. A heading's own
+/// path ends with itself.
+pub(crate) fn section_paths(document: &Document) -> HashMap<(u32, i32, i32), Vec<String>> {
+    let levels = HeadingLevels::new(document);
+    let mut stack: Vec<(u8, String)> = Vec::new();
+    let mut paths = HashMap::new();
+    for cell in document.ordered_cells() {
+        let text = document
+            .payload_for(&cell.code_id)
+            .unwrap_or_default()
+            .trim();
+        if let Some((level, heading)) = levels.heading(&cell, text) {
+            while stack.last().is_some_and(|(top, _)| *top >= level) {
+                stack.pop();
+            }
+            stack.push((level, heading));
+        }
+        paths.insert(
+            cell.key(),
+            stack.iter().map(|(_, heading)| heading.clone()).collect(),
+        );
+    }
+    paths
+}
+
+fn invalid(message: impl fmt::Display) -> DcfError {
+    DcfError::Other(format!("invalid selector: {message}"))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    Not,
+    And,
+    Or,
+    Pred {
+        key: String,
+        op: Option<String>,
+        value: String,
+    },
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Open => "'('".to_string(),
+            Token::Close => "')'".to_string(),
+            Token::Not => "'not'".to_string(),
+            Token::And => "'and'".to_string(),
+            Token::Or => "'or'".to_string(),
+            Token::Pred { key, .. } => format!("predicate '{key}'"),
+        }
+    }
+}
+
+fn lex(source: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::And);
+                i += 1;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '&' | '|' if chars.get(i + 1) == Some(&c) => {
+                tokens.push(if c == '&' { Token::And } else { Token::Or });
+                i += 2;
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let key: String = chars[start..i].iter().collect::<String>().to_lowercase();
+                let mut j = i;
+                while j < chars.len() && chars[j].is_whitespace() {
+                    j += 1;
+                }
+                let op = ["<=", ">=", "=", "~", "<", ">"]
+                    .into_iter()
+                    .find(|op| chars[j..].starts_with(&op.chars().collect::<Vec<_>>()));
+                let Some(op) = op else {
+                    tokens.push(match key.as_str() {
+                        "and" => Token::And,
+                        "or" => Token::Or,
+                        "not" => Token::Not,
+                        _ => Token::Pred {
+                            key,
+                            op: None,
+                            value: String::new(),
+                        },
+                    });
+                    continue;
+                };
+                i = j + op.len();
+                while i < chars.len() && chars[i].is_whitespace() {
+                    i += 1;
+                }
+                let value = match chars.get(i) {
+                    Some(&quote) if quote == '"' || quote == '\'' => {
+                        let mut value = String::new();
+                        i += 1;
+                        loop {
+                            match chars.get(i) {
+                                None => {
+                                    return Err(invalid(format!("unterminated quote after {key}")))
+                                }
+                                Some(&'\\') if chars.get(i + 1) == Some(&quote) => {
+                                    value.push(quote);
+                                    i += 2;
+                                }
+                                Some(&ch) if ch == quote => {
+                                    i += 1;
+                                    break;
+                                }
+                                Some(&ch) => {
+                                    value.push(ch);
+                                    i += 1;
+                                }
+                            }
+                        }
+                        value
+                    }
+                    _ => {
+                        let start = i;
+                        while i < chars.len()
+                            && !chars[i].is_whitespace()
+                            && !matches!(chars[i], ',' | '(' | ')')
+                        {
+                            i += 1;
+                        }
+                        chars[start..i].iter().collect()
+                    }
+                };
+                tokens.push(Token::Pred {
+                    key,
+                    op: Some(op.to_string()),
+                    value,
+                });
+            }
+            other => return Err(invalid(format!("unexpected '{other}'"))),
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut items = vec![self.and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            items.push(self.and()?);
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::Or(items)
+        })
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut items = vec![self.unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.pos += 1;
+                    items.push(self.unary()?);
+                }
+                // Juxtaposed operands are joined with `and`.
+                Some(Token::Open | Token::Not | Token::Pred { .. }) => items.push(self.unary()?),
+                _ => break,
+            }
+        }
+        Ok(if items.len() == 1 {
+            items.remove(0)
+        } else {
+            Expr::And(items)
+        })
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| invalid("expression ends early"))?;
+        self.pos += 1;
+        match token {
+            Token::Not => Ok(Expr::Not(Box::new(self.unary()?))),
+            Token::Open => {
+                let inner = self.or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(invalid("missing ')'"));
+                }
+                self.pos += 1;
+                Ok(inner)
+            }
+            Token::Pred { key, op, value } => {
+                Ok(Expr::Pred(predicate(&key, op.as_deref(), &value)?))
+            }
+            other => Err(invalid(format!("unexpected {}", other.describe()))),
+        }
+    }
+}
+
+fn predicate(key: &str, op: Option<&str>, value: &str) -> Result<Pred> {
+    let Some(op) = op else {
+        return match key {
+            "numguard" => Ok(Pred::NumGuard(Vec::new())),
+            _ => Err(invalid(format!("'{key}' needs a value, e.g. {key}=..."))),
+        };
+    };
+    let field = match key {
+        "page" | "z" => Some(NumField::Page),
+        "x" => Some(NumField::X),
+        "y" => Some(NumField::Y),
+        "importance" => Some(NumField::Importance),
+        _ => None,
+    };
+    if let Some(field) = field {
+        return Ok(Pred::Number(field, numeric_ranges(key, op, value)?));
+    }
+    match (key, op) {
+        ("min_importance", "=") => Ok(Pred::Number(
+            NumField::Importance,
+            numeric_ranges(key, ">=", value)?,
+        )),
+        ("type", "=") => alternatives(value)
+            .map(|name| {
+                cell_type(name).ok_or_else(|| {
+                    invalid(format!(
+                        "unknown cell type '{name}' (expected text, table, figure, footer, header or equation)"
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Pred::Type),
+        ("text", _) => text_match(op, value, true).map(Pred::Text),
+        ("section", _) => text_match(op, value, true).map(Pred::Section),
+        ("doc" | "doc_id", _) => text_match(op, value, false).map(Pred::Doc),
+        ("numguard", "=") => alternatives(value)
+            .map(|name| {
+                numeric_kind(name).ok_or_else(|| {
+                    invalid(format!(
+                        "unknown NumGuard kind '{name}' (expected plain, currency, percent, unit or date)"
+                    ))
+                })
+            })
+            .collect::<Result<Vec<_>>>()
+            .map(Pred::NumGuard),
+        ("lang", "=") => Ok(Pred::Lang(
+            alternatives(value).map(str::to_lowercase).collect(),
+        )),
+        ("type" | "min_importance" | "numguard" | "lang", _) => {
+            Err(invalid(format!("'{key}' only supports '='")))
+        }
+        _ => Err(invalid(format!(
+            "unknown key '{key}' (expected page, x, y, type, importance, text, section, numguard, lang or doc)"
+        ))),
+    }
+}
+
+/// Rewrites a legacy `--filters` list that repeats a key; anything else is
+/// left to the selector parser.
+fn legacy_filters(source: &str) -> Option<String> {
+    let mut keys: Vec<(&str, Vec<&str>)> = Vec::new();
+    for part in source
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+    {
+        let (key, value) = part.split_once('=')?;
+        let plain = !value.is_empty()
+            && !value.contains(|c: char| c.is_whitespace() || "\"'()|=<>~!&".contains(c));
+        if !plain || !matches!(key, "doc_id" | "type" | "min_importance") {
+            return None;
+        }
+        match keys.iter_mut().find(|(seen, _)| *seen == key) {
+            Some((_, values)) => values.push(value),
+            None => keys.push((key, vec![value])),
+        }
+    }
+    if keys.iter().all(|(_, values)| values.len() == 1) {
+        return None;
+    }
+    let predicates: Vec<_> = keys
+        .into_iter()
+        .map(|(key, values)| match values.as_slice() {
+            [value] => format!("{key}={value}"),
+            [.., last] if key == "min_importance" => format!("{key}={last}"),
+            _ => {
+                let any: Vec<_> = values
+                    .iter()
+                    .map(|value| format!("{key}={value}"))
+                    .collect();
+                format!("({})", any.join(" or "))
+            }
+        })
+        .collect();
+    Some(predicates.join(" and "))
+}
+
+fn alternatives(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split('|')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
+
+fn numeric_ranges(key: &str, op: &str, value: &str) -> Result<Vec<(i64, i64)>> {
+    let number = |raw: &str| {
+        raw.trim()
+            .parse::<i64>()
+            .map_err(|_| invalid(format!("'{raw}' is not a number in {key}{op}{value}")))
+    };
+    match op {
+        "=" => {
+            let ranges = alternatives(value)
+                .map(|part| match part.split_once("..") {
+                    Some((a, b)) => {
+                        let lo = if a.trim().is_empty() {
+                            i64::MIN
+                        } else {
+                            number(a)?
+                        };
+                        let hi = if b.trim().is_empty() {
+                            i64::MAX
+                        } else {
+                            number(b)?
+                        };
+                        Ok((lo.min(hi), lo.max(hi)))
+                    }
+                    None => number(part).map(|n| (n, n)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if ranges.is_empty() {
+                return Err(invalid(format!("{key}= needs a value")));
+            }
+            Ok(ranges)
+        }
+        "<" => Ok(vec![(i64::MIN, number(value)?.saturating_sub(1))]),
+        "<=" => Ok(vec![(i64::MIN, number(value)?)]),
+        ">" => Ok(vec![(number(value)?.saturating_add(1), i64::MAX)]),
+        ">=" => Ok(vec![(number(value)?, i64::MAX)]),
+        _ => Err(invalid(format!("'{key}' does not support '{op}'"))),
+    }
+}
+
+fn text_match(op: &str, value: &str, ignore_case: bool) -> Result<TextMatch> {
+    match op {
+        "=" => Ok(TextMatch::Equals(
+            alternatives(value)
+                .map(|part| {
+                    if ignore_case {
+                        part.to_lowercase()
+                    } else {
+                        part.to_string()
+                    }
+                })
+                .collect(),
+        )),
+        "~" => RegexBuilder::new(value)
+            .build()
+            .map(TextMatch::Regex)
+            .map_err(|err| invalid(format!("bad regex '{value}': {err}"))),
+        _ => Err(invalid(format!("'{op}' only applies to numeric keys"))),
+    }
+}
+
+fn cell_type(name: &str) -> Option<CellType> {
+    match name.to_lowercase().as_str() {
+        "text" => Some(CellType::Text),
+        "table" => Some(CellType::Table),
+        "figure" => Some(CellType::Figure),
+        "footer" => Some(CellType::Footer),
+        "header" | "heading" => Some(CellType::Header),
+        "equation" => Some(CellType::Equation),
+        _ => None,
+    }
+}
+
+fn numeric_kind(name: &str) -> Option<NumericKind> {
+    match name.to_lowercase().as_str() {
+        "plain" => Some(NumericKind::Plain),
+        "currency" => Some(NumericKind::Currency),
+        "percent" | "percentage" => Some(NumericKind::Percent),
+        "unit" => Some(NumericKind::Unit),
+        "date" => Some(NumericKind::Date),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::Header;
+
+    fn document() -> Document {
+        let mut doc = Document::new(Header::default());
+        let cells = [
+            (0, 40, CellType::Header, 200, "1 Overview"),
+            (0, 80, CellType::Text, 120, "Draft notes on the plan."),
+            (1, 40, CellType::Header, 200, "2 Results"),
+            (1, 80, CellType::Header, 160, "2.1 Revenue"),
+            (
+                1,
+                120,
+                CellType::Table,
+                180,
+                "Quarter | Revenue\nQ1 | $4.2M",
+            ),
+            (2, 40, CellType::Text, 90, "Headcount was 1,240."),
+        ];
+        for (z, y, cell_type, importance, text) in cells {
+            doc.push_fixture_cell(z, y, cell_type, text).importance = importance;
+        }
+        doc
+    }
+
+    fn texts(doc: &Document, expr: &str) -> Vec<String> {
+        let selector: Selector = expr.parse().unwrap();
+        doc.select_cells(&selector)
+            .iter()
+            .map(|cell| doc.payload_for(&cell.code_id).unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn predicates_and_combinators() {
+        let doc = document();
+        assert_eq!(texts(&doc, "").len(), 6);
+        assert_eq!(texts(&doc, "page=1..2 type=table|text").len(), 2);
+        assert_eq!(
+            texts(&doc, "page>=1 and importance>170"),
+            ["2 Results", "Quarter | Revenue\nQ1 | $4.2M"]
+        );
+        assert_eq!(
+            texts(&doc, "min_importance=200"),
+            ["1 Overview", "2 Results"]
+        );
+        assert_eq!(texts(&doc, r#"text~"\d,\d{3}""#), ["Headcount was 1,240."]);
+        assert_eq!(
+            texts(&doc, "text=draft || numguard=currency"),
+            ["Draft notes on the plan.", "Quarter | Revenue\nQ1 | $4.2M"]
+        );
+        assert_eq!(
+            texts(&doc, "numguard and not page=0..1"),
+            ["Headcount was 1,240."]
+        );
+        assert_eq!(
+            texts(&doc, "!(type=header) && (page=0 or page=2)"),
+            ["Draft notes on the plan.", "Headcount was 1,240."]
+        );
+        // The old `decode --select` bbox form still selects a region.
+        assert_eq!(
+            texts(&doc, "z=1,x=0..100,y=110..130"),
+            ["Quarter | Revenue\nQ1 | $4.2M"]
+        );
+    }
+
+    #[test]
+    fn sections_follow_heading_levels() {
+        let doc = document();
+        assert_eq!(
+            texts(&doc, "section=results type=table"),
+            ["Quarter | Revenue\nQ1 | $4.2M"]
+        );
+        assert_eq!(
+            texts(&doc, r#"section~"Results > 2\.1""#),
+            [
+                "2.1 Revenue",
+                "Quarter | Revenue\nQ1 | $4.2M",
+                "Headcount was 1,240."
+            ]
+        );
+        assert_eq!(texts(&doc, "section='1 Overview'").len(), 2);
+    }
+
+    #[test]
+    fn retain_selected_drops_payloads_and_guards() {
+        let mut doc = document();
+        doc.retain_selected(&"page=2".parse().unwrap());
+        assert_eq!(doc.cells.len(), 1);
+        assert_eq!(doc.dict.len(), 1);
+        assert_eq!(doc.numguards.len(), 1);
+        assert_eq!(
+            Selector::parse("page=2 and type=text")
+                .unwrap()
+                .single_page(),
+            Some(2)
+        );
+        assert_eq!(
+            Selector::parse("page=2 or type=text")
+                .unwrap()
+                .single_page(),
+            None
+        );
+    }
+
+    #[test]
+    fn section_selectors_need_the_earlier_pages() {
+        let selector = Selector::parse("page=2 and section=revenue").unwrap();
+        assert_eq!(selector.single_page(), None);
+        // The page-2 cell sits under headings from page 1.
+        let mut doc = document();
+        assert_eq!(doc.select_cells(&selector).len(), 1);
+        doc.retain_selected(&"page=2".parse().unwrap());
+        assert!(doc.select_cells(&selector).is_empty());
+    }
+
+    #[test]
+    fn targets_without_a_field_do_not_match_it() {
+        let selector: Selector = "doc=a.3dcf and section~. ".parse().unwrap();
+        let target = SelectTarget {
+            doc: Some("a.3dcf"),
+            pages: (0, 3),
+            bbox: None,
+            cell_type: CellType::Text,
+            importance: 100,
+            text: "chunk",
+            section: None,
+            numguards: None,
+            lang: None,
+        };
+        assert!(!selector.matches(&target));
+        assert!("doc=a.3dcf page=2 type=text"
+            .parse::<Selector>()
+            .unwrap()
+            .matches(&target));
+    }
+
+    #[test]
+    fn embedding_records_match_sections_by_breadcrumb() {
+        let record: crate::EmbeddingRecord = serde_json::from_value(serde_json::json!({
+            "chunk_id": "a",
+            "doc": "a.3dcf",
+            "chunk_index": 0,
+            "breadcrumb": ["2 Results", "2.1 Revenue"],
+            "embedding": [1.0],
+            "text": "Revenue rose.",
+        }))
+        .unwrap();
+        let selector = Selector::parse_filters("section=revenue").unwrap();
+        assert!(selector.matches(&record.select_target()));
+        let bare = crate::EmbeddingRecord {
+            breadcrumb: Vec::new(),
+            ..record
+        };
+        assert!(!selector.matches(&bare.select_target()));
+    }
+
+    #[test]
+    fn errors_name_the_problem() {
+        for (expr, needle) in [
+            ("page=x", "not a number"),
+            ("type=chart", "unknown cell type"),
+            ("colour=red", "unknown key"),
+            ("text~\"(\"", "bad regex"),
+            ("(page=1", "missing ')'"),
+            ("page=1 and", "ends early"),
+            ("type>2", "only supports"),
+            ("text='open", "unterminated"),
+        ] {
+            let err = Selector::parse(expr).unwrap_err().to_string();
+            assert!(err.contains(needle), "{expr}: {err}");
+        }
+    }
+
+    #[test]
+    fn legacy_filters_or_repeated_keys() {
+        let doc = document();
+        let count = |expr: &str| {
+            doc.select_cells(&Selector::parse_filters(expr).unwrap())
+                .len()
+        };
+        assert_eq!(count("type=TEXT,type=TABLE"), 3);
+        assert_eq!(count("type=TEXT,type=TABLE,min_importance=100"), 2);
+        assert_eq!(count("min_importance=250,min_importance=150"), 4);
+        assert_eq!(count("type=text,page=1"), 0);
+        assert_eq!(count("page=1..2 type=table|text"), 2);
+
+        let selector = Selector::parse_filters("doc_id=b.3dcf,doc_id=a.3dcf,type=TEXT").unwrap();
+        let target = SelectTarget {
+            doc: Some("a.3dcf"),
+            pages: (0, 0),
+            bbox: None,
+            cell_type: CellType::Text,
+            importance: 100,
+            text: "chunk",
+            section: None,
+            numguards: None,
+            lang: None,
+        };
+        assert!(selector.matches(&target));
+    }
+
+    #[test]
+    fn bounds_at_the_ends_of_i64_do_not_overflow() {
+        let doc = document();
+        assert!(texts(&doc, "page<-9223372036854775808").is_empty());
+        assert!(texts(&doc, "importance>9223372036854775807").is_empty());
+        assert_eq!(texts(&doc, "page<=9223372036854775807").len(), 6);
+    }
+}
@@ -0,0 +1,198 @@
+use std::ops::Range;
+
+use unicode_segmentation::UnicodeSegmentation;
+
+/// Abbreviations (lowercase, without the final period) that UAX #29 would
+/// otherwise treat as the end of a sentence when a capital or digit follows.
+const ABBREVIATIONS: &[&str] = &[
+    // English
+    "al", "approx", "cf", "ch", "dept", "dr", "e.g", "eq", "fig", "figs", "i.e", "jr", "mr", "mrs",
+    "ms", "nos", "p", "pp", "prof", "ref", "sec", "sr", "vol", "vs", // German
+    "abb", "abs", "bzw", "ca", "ggf", "hr", "nr", "tab", "vgl", "z.b",
+    // French, Spanish, Italian
+    "av", "mme", "p.ex", "pág", "sig", "sra", "sres",
+];
+
+/// Abbreviations that are also words ("The answer is no."), so they only
+/// count when a number follows: "No. 5", "S. 12", "est. 1990".
+const NUMBERED_ABBREVIATIONS: &[&str] = &["est", "m", "no", "s", "st"];
+
+/// Splits `text` into sentences with Unicode sentence boundaries (UAX #29),
+/// keeping abbreviations such as "Dr." or "z.B." and runs of initials such
+/// as "J. R." attached to the following word. Each sentence keeps its trailing
+/// whitespace, so the pieces concatenate back to `text`.
+pub fn split_sentences(text: &str) -> Vec<&str> {
+    sentence_ranges(text)
+        .into_iter()
+        .map(|range| &text[range])
+        .collect()
+}
+
+pub(crate) fn sentence_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    for (start, sentence) in text.split_sentence_bound_indices() {
+        let end = start + sentence.len();
+        match ranges.last_mut() {
+            Some(last) if ends_with_abbreviation(&text[last.clone()], sentence) => last.end = end,
+            _ => ranges.push(start..end),
+        }
+    }
+    ranges
+}
+
+/// Splits `text` between words into pieces whose `cost` stays within `limit`,
+/// for sentences too long to keep whole.
+pub(crate) fn word_ranges(
+    text: &str,
+    limit: usize,
+    cost: impl Fn(&str) -> usize,
+) -> Vec<Range<usize>> {
+    let mut ranges: Vec<Range<usize>> = Vec::new();
+    let mut used = 0usize;
+    for (start, word) in text.split_word_bound_indices() {
+        let word_cost = cost(word);
+        let end = start + word.len();
+        match ranges.last_mut() {
+            Some(last) if used + word_cost <= limit || word.trim().is_empty() => {
+                last.end = end;
+                used += word_cost;
+            }
+            _ => {
+                ranges.push(start..end);
+                used = word_cost;
+            }
+        }
+    }
+    ranges
+}
+
+fn ends_with_abbreviation(sentence: &str, next: &str) -> bool {
+    let trimmed = sentence.trim_end();
+    // A line break ends the sentence whatever precedes it.
+    if trimmed.len() < sentence.len() && sentence[trimmed.len()..].contains('\n') {
+        return false;
+    }
+    let Some(stem) = trimmed.strip_suffix('.') else {
+        return false;
+    };
+    let word = stem
+        .rsplit(|c: char| c.is_whitespace() || c == '(')
+        .next()
+        .unwrap_or_default();
+    if is_initial(word) {
+        // Runs of initials: "J. R. R. Tolkien", but not "Plan A." or
+        // "vitamin C." at the end of a sentence.
+        let before = stem[..stem.len() - word.len()].trim_end();
+        let previous = before
+            .strip_suffix('.')
+            .and_then(|rest| rest.rsplit(char::is_whitespace).next());
+        let following = next.split_whitespace().next().unwrap_or_default();
+        if previous.is_some_and(is_initial) || following.strip_suffix('.').is_some_and(is_initial) {
+            return true;
+        }
+    }
+    let word = word.to_lowercase();
+    if NUMBERED_ABBREVIATIONS.contains(&word.as_str()) {
+        return next.starts_with(|c: char| c.is_ascii_digit());
+    }
+    ABBREVIATIONS.contains(&word.as_str())
+}
+
+/// A single uppercase letter, as in the initial "J".
+fn is_initial(word: &str) -> bool {
+    let mut chars = word.chars();
+    matches!((chars.next(), chars.next()), (Some(first), None) if first.is_uppercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_abbreviations_and_initials_inside_sentences() {
+        let text = "Dr. Smith reviewed Fig. 3 with J. R. Tolkien. Results follow.";
+        assert_eq!(
+            split_sentences(text),
+            vec![
+                "Dr. Smith reviewed Fig. 3 with J. R. Tolkien. ",
+                "Results follow."
+            ]
+        );
+        assert_eq!(
+            split_sentences("Siehe z.B. Abb. 4 und Nr. 7. Danach kommt Teil B."),
+            vec!["Siehe z.B. Abb. 4 und Nr. 7. ", "Danach kommt Teil B."]
+        );
+    }
+
+    #[test]
+    fn single_capitals_end_sentences_outside_runs_of_initials() {
+        assert_eq!(
+            split_sentences("We chose Plan A. The budget allows it."),
+            vec!["We chose Plan A. ", "The budget allows it."]
+        );
+        assert_eq!(
+            split_sentences("Take vitamin C. Rest well. Letters by T. S. Eliot arrived."),
+            vec![
+                "Take vitamin C. ",
+                "Rest well. ",
+                "Letters by T. S. Eliot arrived."
+            ]
+        );
+    }
+
+    #[test]
+    fn word_abbreviations_need_a_number() {
+        assert_eq!(
+            split_sentences("The answer is no. Next step is review."),
+            vec!["The answer is no. ", "Next step is review."]
+        );
+        assert_eq!(
+            split_sentences("The gap was 5 m. It closed in 3 s. Done."),
+            vec!["The gap was 5 m. ", "It closed in 3 s. ", "Done."]
+        );
+        assert_eq!(
+            split_sentences("See No. 12 Main Street. Est. 1990 Company. Siehe S. 4 Tabelle."),
+            vec![
+                "See No. 12 Main Street. ",
+                "Est. 1990 Company. ",
+                "Siehe S. 4 Tabelle."
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_multilingual_text() {
+        assert_eq!(
+            split_sentences("東京は晴れです。明日は雨でしょう。"),
+            vec!["東京は晴れです。", "明日は雨でしょう。"]
+        );
+        assert_eq!(
+            split_sentences("Привет, мир! Как дела? Всё хорошо."),
+            vec!["Привет, мир! ", "Как дела? ", "Всё хорошо."]
+        );
+        assert_eq!(
+            split_sentences("यह पहला वाक्य है। यह दूसरा है।"),
+            vec!["यह पहला वाक्य है। ", "यह दूसरा है।"]
+        );
+        assert_eq!(
+            split_sentences("¿Dónde está la Sra. García? Está en casa."),
+            vec!["¿Dónde está la Sra. García? ", "Está en casa."]
+        );
+    }
+
+    #[test]
+    fn pieces_concatenate_back_to_the_text() {
+        let text = "First line\nSecond line. Third, with e.g. an aside.\n\nFourth";
+        assert_eq!(split_sentences(text).concat(), text);
+    }
+
+    #[test]
+    fn word_ranges_stay_within_the_limit() {
+        let text = "one two three four five six";
+        let pieces: Vec<_> = word_ranges(text, 2, |word| usize::from(!word.trim().is_empty()))
+            .into_iter()
+            .map(|range| &text[range])
+            .collect();
+        assert_eq!(pieces, vec!["one two ", "three four ", "five six"]);
+    }
+}
@@ -2,24 +2,42 @@ use std::fs::File;
 use std::io::Write;
 use std::path::Path;
 
-use crate::document::{CellType, Document};
+use serde::Serialize;
+
+use crate::citation::CellIds;
+use crate::document::{CellRecord, CellType, Document};
 use crate::error::Result;
+use crate::render::HeadingLevels;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum TableMode {
+    #[default]
     Auto,
     Csv,
     Dims,
 }
 
-impl Default for TableMode {
-    fn default() -> Self {
-        TableMode::Auto
-    }
+/// Layout of the serialized context. Every format except `Ctx3d` carries the
+/// full payload of each cell and its short ID (see [`CellIds`]), which models
+/// can cite and [`Document::parse_citations`] maps back to cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ContextFormat {
+    /// `(z=..,x=..,y=..,code=..,...) "preview"` lines inside `<ctx3d>`.
+    #[default]
+    Ctx3d,
+    /// `<page>` elements holding `<cell id=".." type="..">` elements.
+    Xml,
+    /// One compact JSON object with a `cells` array.
+    Json,
+    /// Headings as `#` lines, other cells as paragraphs, IDs in brackets.
+    Markdown,
+    /// `id: text`, one cell per line.
+    Minimal,
 }
 
 #[derive(Debug, Clone)]
 pub struct TextSerializerConfig {
+    pub format: ContextFormat,
     pub include_header: bool,
     pub include_grammar: bool,
     pub max_preview_chars: usize,
@@ -31,6 +49,7 @@ pub struct TextSerializerConfig {
 impl Default for TextSerializerConfig {
     fn default() -> Self {
         Self {
+            format: ContextFormat::Ctx3d,
             include_header: true,
             include_grammar: true,
             max_preview_chars: 64,
@@ -46,6 +65,12 @@ pub struct TextSerializer {
     config: TextSerializerConfig,
 }
 
+impl Default for TextSerializer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TextSerializer {
     pub fn new() -> Self {
         Self {
@@ -58,6 +83,16 @@ impl TextSerializer {
     }
 
     pub fn to_string(&self, document: &Document) -> Result<String> {
+        match self.config.format {
+            ContextFormat::Ctx3d => self.to_ctx3d(document),
+            ContextFormat::Xml => Ok(self.to_xml(document)),
+            ContextFormat::Json => self.to_json(document),
+            ContextFormat::Markdown => Ok(self.to_markdown(document)),
+            ContextFormat::Minimal => Ok(self.to_minimal(document)),
+        }
+    }
+
+    fn to_ctx3d(&self, document: &Document) -> Result<String> {
         let mut out = String::new();
         if self.config.include_header {
             let preset = self
@@ -81,13 +116,14 @@ impl TextSerializer {
                 .payload_for(&cell.code_id)
                 .map(|payload| match cell.cell_type {
                     CellType::Table => render_table_preview(payload, &self.config),
+                    CellType::Equation => equation_preview(payload),
                     _ => preview(payload, self.config.max_preview_chars),
                 })
                 .unwrap_or_else(|| "<missing>".to_string());
             let code_short = &code_hex[..16];
             let preview_escaped = escape_preview(&preview);
             out.push_str(&format!(
-                "(z={z},x={x},y={y},w={w},h={h},code={code},rle={rle},imp={imp},type={typ}) \"{preview}\"\n",
+                "(z={z},x={x},y={y},w={w},h={h},code={code},rle={rle},imp={imp},type={typ}) \"{preview}\"{links}\n",
                 z = cell.z,
                 x = cell.x,
                 y = cell.y,
@@ -97,7 +133,8 @@ impl TextSerializer {
                 rle = cell.rle,
                 imp = cell.importance,
                 typ = format!("{:?}", cell.cell_type).to_uppercase(),
-                preview = preview_escaped
+                preview = preview_escaped,
+                links = render_links(document, &cell)
             ));
         }
         if self.config.include_grammar {
@@ -109,6 +146,150 @@ impl TextSerializer {
         Ok(out)
     }
 
+    fn to_xml(&self, document: &Document) -> String {
+        let ids = document.cell_ids();
+        let mut out = String::from("<context");
+        if self.config.include_header {
+            out.push_str(&format!(
+                " grid=\"{}\" codeset=\"{}\" preset=\"{}\" budget=\"{}\"",
+                escape_xml(&document.header.grid),
+                escape_xml(&document.header.codeset),
+                escape_xml(self.preset_label()),
+                escape_xml(self.budget_label())
+            ));
+        }
+        out.push_str(">\n");
+        if self.config.include_grammar {
+            if let Some(example) = example_id(document, &ids) {
+                out.push_str(&format!("<cite>{}</cite>\n", citation_hint(&example)));
+            }
+        }
+        for (z, cells) in cells_by_page(document) {
+            out.push_str(&format!("<page z=\"{z}\">\n"));
+            for cell in cells {
+                out.push_str(&format!(
+                    "<cell id=\"{}\" type=\"{}\">{}</cell>\n",
+                    ids.id(&cell),
+                    type_name(cell.cell_type),
+                    escape_xml(payload(document, &cell))
+                ));
+            }
+            out.push_str("</page>\n");
+        }
+        out.push_str("</context>\n");
+        out
+    }
+
+    fn to_json(&self, document: &Document) -> Result<String> {
+        #[derive(Serialize)]
+        struct JsonContext<'a> {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            header: Option<JsonHeader<'a>>,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            cite: Option<String>,
+            cells: Vec<JsonCell<'a>>,
+        }
+        #[derive(Serialize)]
+        struct JsonHeader<'a> {
+            grid: &'a str,
+            codeset: &'a str,
+            preset: &'a str,
+            budget: &'a str,
+        }
+        #[derive(Serialize)]
+        struct JsonCell<'a> {
+            id: String,
+            page: u32,
+            #[serde(rename = "type")]
+            kind: &'static str,
+            text: &'a str,
+        }
+        let ids = document.cell_ids();
+        let context = JsonContext {
+            header: self.config.include_header.then(|| JsonHeader {
+                grid: &document.header.grid,
+                codeset: &document.header.codeset,
+                preset: self.preset_label(),
+                budget: self.budget_label(),
+            }),
+            cite: example_id(document, &ids)
+                .filter(|_| self.config.include_grammar)
+                .map(|example| citation_hint(&example)),
+            cells: document
+                .ordered_cells()
+                .iter()
+                .map(|cell| JsonCell {
+                    id: ids.id(cell),
+                    page: cell.z,
+                    kind: type_name(cell.cell_type),
+                    text: payload(document, cell),
+                })
+                .collect(),
+        };
+        let mut out = serde_json::to_string(&context)?;
+        out.push('\n');
+        Ok(out)
+    }
+
+    fn to_markdown(&self, document: &Document) -> String {
+        let ids = document.cell_ids();
+        let levels = HeadingLevels::new(document);
+        let pages: Vec<String> = cells_by_page(document)
+            .into_iter()
+            .map(|(_, cells)| {
+                cells
+                    .iter()
+                    .map(|cell| {
+                        let id = ids.id(cell);
+                        let text = payload(document, cell).trim();
+                        match levels.heading(cell, text) {
+                            Some((level, heading)) => {
+                                format!("{} {heading} [{id}]", "#".repeat(level as usize))
+                            }
+                            None if text.contains('\n') => format!("{text}\n[{id}]"),
+                            None => format!("{text} [{id}]"),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            })
+            .collect();
+        let mut out = pages.join("\n\n---\n\n");
+        out.push('\n');
+        if self.config.include_grammar {
+            if let Some(example) = example_id(document, &ids) {
+                out.push_str(&format!("\n{}\n", citation_hint(&example)));
+            }
+        }
+        out
+    }
+
+    fn to_minimal(&self, document: &Document) -> String {
+        let ids = document.cell_ids();
+        let mut out = String::new();
+        for cell in document.ordered_cells() {
+            let text = payload(document, &cell)
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" ");
+            out.push_str(&format!("{}: {text}\n", ids.id(&cell)));
+        }
+        if self.config.include_grammar {
+            if let Some(example) = example_id(document, &ids) {
+                out.push_str(&format!("\n{}\n", citation_hint(&example)));
+            }
+        }
+        out
+    }
+
+    fn preset_label(&self) -> &str {
+        self.config.preset_label.as_deref().unwrap_or("unknown")
+    }
+
+    fn budget_label(&self) -> &str {
+        self.config.budget_label.as_deref().unwrap_or("auto")
+    }
+
     pub fn write_textual<P: AsRef<Path>>(&self, document: &Document, path: P) -> Result<()> {
         let txt = self.to_string(document)?;
         let mut file = File::create(path)?;
@@ -117,6 +298,68 @@ impl TextSerializer {
     }
 }
 
+fn cells_by_page(document: &Document) -> Vec<(u32, Vec<CellRecord>)> {
+    let mut pages: Vec<(u32, Vec<CellRecord>)> = Vec::new();
+    for cell in document.ordered_cells() {
+        match pages.last_mut() {
+            Some((z, cells)) if *z == cell.z => cells.push(cell),
+            _ => pages.push((cell.z, vec![cell])),
+        }
+    }
+    pages
+}
+
+fn payload<'a>(document: &'a Document, cell: &CellRecord) -> &'a str {
+    document.payload_for(&cell.code_id).unwrap_or_default()
+}
+
+fn example_id(document: &Document, ids: &CellIds) -> Option<String> {
+    document.ordered_cells().first().map(|cell| ids.id(cell))
+}
+
+fn citation_hint(example: &str) -> String {
+    format!("Cite the cells you use by id in square brackets, e.g. [{example}].")
+}
+
+fn type_name(cell_type: CellType) -> &'static str {
+    match cell_type {
+        CellType::Text => "text",
+        CellType::Table => "table",
+        CellType::Figure => "figure",
+        CellType::Footer => "footer",
+        CellType::Header => "header",
+        CellType::Equation => "equation",
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// ` links=[footnote "1"->cell(z=0,x=64,y=310), ...]`, or nothing for unlinked cells.
+fn render_links(document: &Document, cell: &CellRecord) -> String {
+    let payload = document.payload_for(&cell.code_id).unwrap_or_default();
+    let rendered: Vec<String> = document
+        .links_for(cell)
+        .map(|link| {
+            format!(
+                "{} \"{}\"->{}",
+                link.kind,
+                escape_preview(link.anchor_text(payload).unwrap_or_default()),
+                link.target
+            )
+        })
+        .collect();
+    if rendered.is_empty() {
+        String::new()
+    } else {
+        format!(" links=[{}]", rendered.join(", "))
+    }
+}
+
 fn preview(payload: &str, limit: usize) -> String {
     if payload.len() <= limit {
         return payload.to_string();
@@ -126,6 +369,11 @@ fn preview(payload: &str, limit: usize) -> String {
     truncated
 }
 
+/// Formulas are never truncated; line breaks in the source are TeX whitespace.
+fn equation_preview(payload: &str) -> String {
+    payload.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
 fn estimate_table_columns(payload: &str) -> usize {
     if payload.contains('|') {
         payload
@@ -166,8 +414,7 @@ fn csv_preview(payload: &str, limit: usize) -> String {
     let mut rows = Vec::new();
     for line in payload.lines().filter(|l| !l.trim().is_empty()) {
         let normalized = line
-            .replace('|', ",")
-            .replace('\t', ",")
+            .replace(['|', '\t'], ",")
             .split(',')
             .map(|cell| cell.trim())
             .filter(|cell| !cell.is_empty())
@@ -201,6 +448,7 @@ fn escape_preview(payload: &str) -> String {
 mod tests {
     use super::*;
     use crate::document::{CellRecord, CellType, Document, Header, PageInfo};
+    use crate::links::{Link, LinkKind, LinkTarget};
 
     fn sample_document() -> Document {
         let mut doc = Document::new(Header::default());
@@ -221,6 +469,8 @@ mod tests {
             rle: 0,
             cell_type: CellType::Text,
             importance: 100,
+            ocr_confidence: None,
+            lang: None,
         });
 
         let table_code = [2u8; 32];
@@ -238,6 +488,8 @@ mod tests {
             rle: 0,
             cell_type: CellType::Table,
             importance: 120,
+            ocr_confidence: None,
+            lang: None,
         });
 
         doc
@@ -272,4 +524,93 @@ mod tests {
         let rendered = serializer.to_string(&doc).unwrap();
         insta::assert_snapshot!(rendered);
     }
+
+    #[test]
+    fn links_follow_the_cell_preview() {
+        let mut doc = sample_document();
+        doc.links.push(Link {
+            z: 0,
+            x: 10,
+            y: 20,
+            start: 6,
+            end: 11,
+            kind: LinkKind::Hyperlink,
+            target: LinkTarget::Url("https://example.org".to_string()),
+        });
+        let rendered = TextSerializer::new().to_string(&doc).unwrap();
+        assert!(
+            rendered.contains("\"Hello world\" links=[hyperlink \"world\"->https://example.org]\n")
+        );
+        assert!(rendered.contains("type=TABLE) \"[csv"));
+    }
+
+    #[test]
+    fn equations_are_emitted_verbatim() {
+        let mut doc = sample_document();
+        let tex = "\\begin{align}\nf(x) &= \\sum_{k=0}^{\\infty} \\frac{f^{(k)}(a)}{k!} (x-a)^k \\\\\ng(x) &= 0\n\\end{align}";
+        let code = [3u8; 32];
+        doc.dict.insert(code, tex.to_string());
+        doc.cells.push(CellRecord {
+            z: 0,
+            x: 10,
+            y: 120,
+            w: 700,
+            h: 24,
+            code_id: code,
+            rle: 0,
+            cell_type: CellType::Equation,
+            importance: 100,
+            ocr_confidence: None,
+            lang: None,
+        });
+        let rendered = TextSerializer::new().to_string(&doc).unwrap();
+        assert!(rendered.contains(
+            "type=EQUATION) \"\\begin{align} f(x) &= \\sum_{k=0}^{\\infty} \\frac{f^{(k)}(a)}{k!} (x-a)^k \\\\ g(x) &= 0 \\end{align}\"\n"
+        ));
+    }
+
+    fn render_format(doc: &Document, format: ContextFormat) -> String {
+        TextSerializer::with_config(TextSerializerConfig {
+            format,
+            ..Default::default()
+        })
+        .to_string(doc)
+        .unwrap()
+    }
+
+    #[test]
+    fn snapshot_context_formats() {
+        let doc = sample_document();
+        for (name, format) in [
+            ("xml", ContextFormat::Xml),
+            ("json", ContextFormat::Json),
+            ("markdown", ContextFormat::Markdown),
+            ("minimal", ContextFormat::Minimal),
+        ] {
+            insta::assert_snapshot!(format!("context_{name}"), render_format(&doc, format));
+        }
+    }
+
+    #[test]
+    fn cited_ids_resolve_in_every_format() {
+        let doc = sample_document();
+        let ids = doc.cell_ids();
+        let table_id = ids.id(&doc.cells[1]);
+        for format in [
+            ContextFormat::Xml,
+            ContextFormat::Json,
+            ContextFormat::Markdown,
+            ContextFormat::Minimal,
+        ] {
+            let rendered = render_format(&doc, format);
+            assert!(rendered.contains(&table_id), "{format:?}");
+            // Citations of the context itself resolve to its cells.
+            assert!(doc
+                .parse_citations(&rendered)
+                .iter()
+                .all(|citation| citation.cell.is_some()));
+        }
+        let citations = doc.parse_citations(&format!("Q2 revenue was 12 [{table_id}]."));
+        assert_eq!(citations[0].cell.as_ref(), Some(&doc.cells[1]));
+    }
 }
@@ -104,7 +104,7 @@ fn load_custom_tokenizer(path: &Path) -> Result<tiktoken_rs::CoreBPE> {
         encoder.insert(decode_token_key(&token), rank);
     }
     let mut special_tokens: FxHashMap<String, usize> = FxHashMap::default();
-    special_tokens.extend(spec.special_tokens.into_iter());
+    special_tokens.extend(spec.special_tokens);
     tiktoken_rs::CoreBPE::new(encoder, special_tokens, &spec.pat_str)
         .map_err(|e| DcfError::Tokenizer(format!("failed to build tokenizer: {e}")))
 }
@@ -0,0 +1,541 @@
+//! Structural checks and size accounting for decoded documents.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use prost::Message;
+use serde::Serialize;
+
+use crate::document::{hash_payload, CellRecord, Document};
+use crate::links::LinkTarget;
+use crate::proto;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueKind {
+    /// A cell's `code_id` has no dict entry.
+    MissingPayload,
+    /// A dict payload does not hash to its `code_id`.
+    PayloadHashMismatch,
+    /// A dict entry no cell refers to.
+    OrphanPayload,
+    /// A cell on a `z` with no page entry.
+    MissingPage,
+    DuplicatePage,
+    /// A cell starting left of or above its page, or running past its right or bottom edge.
+    OutOfPage,
+    /// Two cells on the same page whose boxes intersect.
+    Overlap,
+    /// An `rle` run that is not followed by that many cells with the same `code_id`.
+    RleMismatch,
+    /// A NumGuard whose position matches no cell.
+    OrphanNumGuard,
+    /// A link whose anchor cell or target cell/page does not exist.
+    DanglingLink,
+    /// The stored Merkle root does not match the content.
+    IntegrityMismatch,
+}
+
+impl IssueKind {
+    pub fn severity(self) -> Severity {
+        match self {
+            IssueKind::OrphanPayload | IssueKind::OutOfPage | IssueKind::Overlap => {
+                Severity::Warning
+            }
+            IssueKind::DanglingLink => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            IssueKind::MissingPayload => "missing_payload",
+            IssueKind::PayloadHashMismatch => "payload_hash_mismatch",
+            IssueKind::OrphanPayload => "orphan_payload",
+            IssueKind::MissingPage => "missing_page",
+            IssueKind::DuplicatePage => "duplicate_page",
+            IssueKind::OutOfPage => "out_of_page",
+            IssueKind::Overlap => "overlap",
+            IssueKind::RleMismatch => "rle_mismatch",
+            IssueKind::OrphanNumGuard => "orphan_numguard",
+            IssueKind::DanglingLink => "dangling_link",
+            IssueKind::IntegrityMismatch => "integrity_mismatch",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ValidationIssue {
+    pub kind: IssueKind,
+    pub severity: Severity,
+    /// Page of the offending record, when it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub z: Option<u32>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+}
+
+impl ValidationReport {
+    pub fn errors(&self) -> usize {
+        self.count(Severity::Error)
+    }
+
+    pub fn warnings(&self) -> usize {
+        self.count(Severity::Warning)
+    }
+
+    /// True when there are no errors; warnings are allowed.
+    pub fn is_valid(&self) -> bool {
+        self.errors() == 0
+    }
+
+    /// Issue counts per kind, in the order kinds first appear.
+    pub fn counts(&self) -> Vec<(IssueKind, usize)> {
+        let mut counts: Vec<(IssueKind, usize)> = Vec::new();
+        for issue in &self.issues {
+            match counts.iter_mut().find(|(kind, _)| *kind == issue.kind) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((issue.kind, 1)),
+            }
+        }
+        counts
+    }
+
+    fn count(&self, severity: Severity) -> usize {
+        self.issues
+            .iter()
+            .filter(|issue| issue.severity == severity)
+            .count()
+    }
+
+    fn push(&mut self, kind: IssueKind, z: Option<u32>, message: String) {
+        self.issues.push(ValidationIssue {
+            kind,
+            severity: kind.severity(),
+            z,
+            message,
+        });
+    }
+}
+
+impl fmt::Display for ValidationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} errors, {} warnings", self.errors(), self.warnings())?;
+        for issue in &self.issues {
+            let level = match issue.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            };
+            write!(f, "\n  {level} {}: {}", issue.kind.as_str(), issue.message)?;
+        }
+        Ok(())
+    }
+}
+
+/// Encoded size of one section of the protobuf container.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SectionSize {
+    pub section: &'static str,
+    pub records: usize,
+    /// Protobuf bytes before compression.
+    pub encoded_bytes: usize,
+    /// The section alone, compressed with zstd level 3.
+    pub compressed_bytes: usize,
+}
+
+impl Document {
+    /// Checks dict completeness, cell geometry, RLE runs, NumGuard and link
+    /// anchors, duplicate pages and, when present, the Merkle root.
+    pub fn validate(&self) -> ValidationReport {
+        let mut report = ValidationReport::default();
+        let cells = self.ordered_cells();
+
+        let mut pages: HashMap<u32, (u32, u32)> = HashMap::new();
+        for page in &self.pages {
+            if pages
+                .insert(page.z, (page.width_px, page.height_px))
+                .is_some()
+            {
+                report.push(
+                    IssueKind::DuplicatePage,
+                    Some(page.z),
+                    format!("page z={} is listed more than once", page.z),
+                );
+            }
+        }
+
+        let mut referenced = HashSet::new();
+        let mut missing_pages = HashSet::new();
+        for cell in &cells {
+            referenced.insert(cell.code_id);
+            if !self.dict.contains_key(&cell.code_id) {
+                report.push(
+                    IssueKind::MissingPayload,
+                    Some(cell.z),
+                    format!(
+                        "{} has no payload for code_id {}",
+                        position(cell),
+                        short(&cell.code_id)
+                    ),
+                );
+            }
+            match pages.get(&cell.z) {
+                None => {
+                    if missing_pages.insert(cell.z) {
+                        report.push(
+                            IssueKind::MissingPage,
+                            Some(cell.z),
+                            format!("cells on z={} but no such page", cell.z),
+                        );
+                    }
+                }
+                Some(&(width, height)) => {
+                    if cell.x < 0
+                        || cell.y < 0
+                        || i64::from(cell.x) + i64::from(cell.w) > i64::from(width)
+                        || i64::from(cell.y) + i64::from(cell.h) > i64::from(height)
+                    {
+                        report.push(
+                            IssueKind::OutOfPage,
+                            Some(cell.z),
+                            format!(
+                                "{} ({}x{}) lies outside the {width}x{height}px page",
+                                position(cell),
+                                cell.w,
+                                cell.h
+                            ),
+                        );
+                    }
+                }
+            }
+        }
+
+        for (code_id, payload) in &self.dict {
+            if hash_payload(payload) != *code_id {
+                report.push(
+                    IssueKind::PayloadHashMismatch,
+                    None,
+                    format!("payload for code_id {} does not hash to it", short(code_id)),
+                );
+            }
+            if !referenced.contains(code_id) {
+                report.push(
+                    IssueKind::OrphanPayload,
+                    None,
+                    format!("code_id {} is not used by any cell", short(code_id)),
+                );
+            }
+        }
+
+        check_overlaps(&cells, &mut report);
+        check_rle(&cells, &mut report);
+
+        let positions: HashSet<(u32, i32, i32)> =
+            cells.iter().map(|cell| (cell.z, cell.x, cell.y)).collect();
+        for guard in &self.numguards {
+            if !positions.contains(&(guard.z, guard.x as i32, guard.y as i32)) {
+                report.push(
+                    IssueKind::OrphanNumGuard,
+                    Some(guard.z),
+                    format!(
+                        "NumGuard at z={} ({},{}) has no cell",
+                        guard.z, guard.x, guard.y
+                    ),
+                );
+            }
+        }
+        for link in &self.links {
+            let anchor = positions.contains(&(link.z, link.x, link.y));
+            let target = match &link.target {
+                LinkTarget::Url(_) => true,
+                LinkTarget::Page(z) => pages.contains_key(z),
+                LinkTarget::Cell { z, x, y } => positions.contains(&(*z, *x, *y)),
+            };
+            if !anchor || !target {
+                report.push(
+                    IssueKind::DanglingLink,
+                    Some(link.z),
+                    format!(
+                        "link at z={} ({},{}) has a missing {}",
+                        link.z,
+                        link.x,
+                        link.y,
+                        if anchor { "target" } else { "anchor cell" }
+                    ),
+                );
+            }
+        }
+
+        if let Some(integrity) = &self.integrity {
+            if integrity.merkle_root != self.merkle_root() {
+                report.push(
+                    IssueKind::IntegrityMismatch,
+                    None,
+                    "stored Merkle root does not match the content".to_string(),
+                );
+            }
+        }
+        report
+    }
+
+    /// Size of each container section, encoded on its own.
+    pub fn section_sizes(&self) -> Vec<SectionSize> {
+        let full = self.to_proto();
+        let mut sections = Vec::new();
+        let mut measure = |section: &'static str, records: usize, part: proto::Document| {
+            let bytes = part.encode_to_vec();
+            let compressed = zstd::encode_all(&bytes[..], 3)
+                .map(|out| out.len())
+                .unwrap_or(bytes.len());
+            sections.push(SectionSize {
+                section,
+                records,
+                encoded_bytes: bytes.len(),
+                compressed_bytes: compressed,
+            });
+        };
+        measure(
+            "header",
+            1,
+            proto::Document {
+                header: full.header,
+                ..Default::default()
+            },
+        );
+        measure(
+            "pages",
+            full.pages.len(),
+            proto::Document {
+                pages: full.pages,
+                ..Default::default()
+            },
+        );
+        measure(
+            "cells",
+            full.cells.len(),
+            proto::Document {
+                cells: full.cells,
+                ..Default::default()
+            },
+        );
+        measure(
+            "dict",
+            full.dict.len(),
+            proto::Document {
+                dict: full.dict,
+                ..Default::default()
+            },
+        );
+        measure(
+            "numguards",
+            full.numguards.len(),
+            proto::Document {
+                numguards: full.numguards,
+                ..Default::default()
+            },
+        );
+        measure(
+            "links",
+            full.links.len(),
+            proto::Document {
+                links: full.links,
+                ..Default::default()
+            },
+        );
+        measure(
+            "integrity",
+            usize::from(full.integrity.is_some()),
+            proto::Document {
+                integrity: full.integrity,
+                ..Default::default()
+            },
+        );
+        sections
+    }
+}
+
+/// Sweeps each page top to bottom so only vertically close cells are compared.
+fn check_overlaps(cells: &[CellRecord], report: &mut ValidationReport) {
+    let mut by_page: HashMap<u32, Vec<&CellRecord>> = HashMap::new();
+    for cell in cells {
+        by_page.entry(cell.z).or_default().push(cell);
+    }
+    let mut pages: Vec<_> = by_page.into_iter().collect();
+    pages.sort_by_key(|(z, _)| *z);
+    for (_, mut page) in pages {
+        page.sort_by_key(|cell| (cell.y, cell.x));
+        for (i, a) in page.iter().enumerate() {
+            let bottom = i64::from(a.y) + i64::from(a.h);
+            for b in &page[i + 1..] {
+                if i64::from(b.y) >= bottom {
+                    break;
+                }
+                let overlaps_x = i64::from(b.x) < i64::from(a.x) + i64::from(a.w)
+                    && i64::from(a.x) < i64::from(b.x) + i64::from(b.w);
+                let has_area = a.w > 0 && a.h > 0 && b.w > 0 && b.h > 0;
+                if overlaps_x && has_area {
+                    report.push(
+                        IssueKind::Overlap,
+                        Some(a.z),
+                        format!("{} overlaps {}", position(a), position(b)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn check_rle(cells: &[CellRecord], report: &mut ValidationReport) {
+    let mut covered_until = 0usize;
+    for (i, cell) in cells.iter().enumerate() {
+        if cell.rle == 0 {
+            continue;
+        }
+        let run = cell.rle as usize;
+        let consistent = i >= covered_until
+            && cells.len() > i + run
+            && cells[i + 1..=i + run]
+                .iter()
+                .all(|next| next.code_id == cell.code_id && next.rle == 0);
+        if !consistent {
+            report.push(
+                IssueKind::RleMismatch,
+                Some(cell.z),
+                format!(
+                    "{} claims a run of {} more identical cells",
+                    position(cell),
+                    run
+                ),
+            );
+        }
+        covered_until = covered_until.max(i + run + 1);
+    }
+}
+
+fn position(cell: &CellRecord) -> String {
+    format!("cell z={} ({},{})", cell.z, cell.x, cell.y)
+}
+
+fn short(code_id: &[u8; 32]) -> String {
+    hex::encode(&code_id[..6])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::document::{CellType, Header, PageInfo};
+
+    fn cell(z: u32, x: i32, y: i32, text: &str) -> (CellRecord, String) {
+        (
+            CellRecord {
+                z,
+                x,
+                y,
+                w: 400,
+                h: 24,
+                code_id: hash_payload(text),
+                rle: 0,
+                cell_type: CellType::Text,
+                importance: 100,
+                ocr_confidence: None,
+                lang: None,
+            },
+            text.to_string(),
+        )
+    }
+
+    fn clean() -> Document {
+        let mut doc = Document::new(Header::default());
+        doc.add_page(PageInfo {
+            z: 0,
+            width_px: 1024,
+            height_px: 1400,
+        });
+        for (idx, text) in ["Annual report", "Revenue was $12,000", "Outlook"]
+            .iter()
+            .enumerate()
+        {
+            let (record, payload) = cell(0, 64, 64 + idx as i32 * 30, text);
+            doc.push_cell(record, payload);
+        }
+        for guard in crate::numguard::extract_guards("Revenue was $12,000", 0, 64, 94) {
+            doc.add_numguard(guard);
+        }
+        doc
+    }
+
+    #[test]
+    fn clean_documents_have_no_issues() {
+        let report = clean().validate();
+        assert!(report.issues.is_empty(), "{report}");
+        let sizes = clean().section_sizes();
+        assert_eq!(sizes.len(), 7);
+        assert_eq!(
+            sizes.iter().find(|s| s.section == "cells").unwrap().records,
+            3
+        );
+    }
+
+    #[test]
+    fn cells_below_the_page_are_out_of_page() {
+        let mut doc = clean();
+        let (footer, payload) = cell(0, 64, 1390, "Page 1 of 1");
+        doc.push_cell(footer, payload);
+        let report = doc.validate();
+        assert_eq!(report.issues.len(), 1, "{report}");
+        assert_eq!(report.issues[0].kind, IssueKind::OutOfPage);
+        assert!(report.issues[0].message.contains("1024x1400px"), "{report}");
+    }
+
+    #[test]
+    fn reports_each_kind_of_damage() {
+        let mut doc = clean();
+        doc.add_page(PageInfo {
+            z: 0,
+            width_px: 1024,
+            height_px: 1400,
+        });
+        let (overlapping, payload) = cell(0, 300, 70, "Boxed note");
+        doc.push_cell(overlapping, payload);
+        let (outside, payload) = cell(0, 900, 400, "Margin note");
+        doc.push_cell(outside, payload);
+        let (stray, payload) = cell(3, 64, 64, "Lost page");
+        doc.push_cell(stray, payload);
+        doc.dict.insert(hash_payload("unused"), "unused".into());
+        let first = doc.cells[0].code_id;
+        doc.dict.shift_remove(&first);
+        let second = doc.cells[1].code_id;
+        doc.dict.insert(second, "Revenue was $99,000".into());
+        doc.cells[2].rle = 2;
+        doc.numguards[0].y = 500;
+
+        let report = doc.validate();
+        let kinds: Vec<_> = report.counts().into_iter().map(|(kind, _)| kind).collect();
+        for kind in [
+            IssueKind::DuplicatePage,
+            IssueKind::MissingPayload,
+            IssueKind::MissingPage,
+            IssueKind::OutOfPage,
+            IssueKind::PayloadHashMismatch,
+            IssueKind::OrphanPayload,
+            IssueKind::Overlap,
+            IssueKind::RleMismatch,
+            IssueKind::OrphanNumGuard,
+        ] {
+            assert!(kinds.contains(&kind), "{kind:?} not reported:\n{report}");
+        }
+        assert!(!report.is_valid());
+        assert!(report.warnings() >= 3);
+    }
+}
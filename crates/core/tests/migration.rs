@@ -0,0 +1,142 @@
+use std::path::{Path, PathBuf};
+
+use prost::Message;
+use three_dcf_core::{proto, DcfError, Document, FORMAT_VERSION};
+
+fn fixtures() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/v1")
+}
+
+fn load(path: &Path) -> Document {
+    let doc = if path.extension().is_some_and(|ext| ext == "json") {
+        Document::load_json(path)
+    } else {
+        Document::load_bin(path)
+    };
+    doc.unwrap_or_else(|err| panic!("{}: {err}", path.display()))
+}
+
+#[test]
+fn version_one_fixtures_are_upgraded_on_load() {
+    for name in ["quarterly_note", "sample_report"] {
+        let expected = std::fs::read_to_string(fixtures().join(format!("{name}.decoded.txt")))
+            .expect("decoded fixture");
+        for file in [format!("{name}.3dcf"), format!("{name}.3dcf.json")] {
+            let doc = load(&fixtures().join(&file));
+            let report = doc.migration.as_ref().expect("fixture is version 1");
+            assert_eq!((report.from, report.to), (1, FORMAT_VERSION), "{file}");
+            assert_eq!(doc.header.version, FORMAT_VERSION);
+            assert_eq!(
+                doc.decode_to_text().trim_end(),
+                expected.trim_end(),
+                "{file}"
+            );
+            assert!(!doc.numguards.is_empty());
+            assert!(
+                doc.numguards.iter().all(|guard| guard.entity.is_some()),
+                "{file}: every legacy guard should gain a typed value"
+            );
+            assert!(
+                doc.numguard_mismatches_with_units(None).is_empty(),
+                "{file}"
+            );
+        }
+    }
+}
+
+#[test]
+fn migration_keeps_the_legacy_units() {
+    let raw: serde_json::Value = serde_json::from_str(
+        &std::fs::read_to_string(fixtures().join("quarterly_note.3dcf.json")).unwrap(),
+    )
+    .unwrap();
+    let legacy: Vec<_> = raw["numguards"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|guard| guard["units"].as_str().unwrap().to_string())
+        .collect();
+    let doc = load(&fixtures().join("quarterly_note.3dcf.json"));
+    assert_eq!(legacy.len(), doc.numguards.len());
+    for (before, guard) in legacy.iter().zip(&doc.numguards) {
+        if !before.is_empty() {
+            assert_eq!(&guard.units, before);
+        }
+    }
+}
+
+#[test]
+fn migrated_documents_are_saved_at_the_current_version() {
+    let doc = load(&fixtures().join("quarterly_note.3dcf"));
+    for bytes in [doc.to_bytes().unwrap(), doc.to_seekable_bytes().unwrap()] {
+        let reloaded = Document::from_bytes(&bytes).unwrap();
+        assert!(reloaded.migration.is_none());
+        assert_eq!(reloaded.header.version, FORMAT_VERSION);
+        assert_eq!(reloaded.numguards, doc.numguards);
+    }
+}
+
+fn encode(doc: &proto::Document) -> Vec<u8> {
+    zstd::encode_all(&doc.encode_to_vec()[..], 3).unwrap()
+}
+
+fn valid_proto() -> proto::Document {
+    let doc = load(&fixtures().join("sample_report.3dcf"));
+    doc.to_proto()
+}
+
+#[test]
+fn malformed_containers_return_typed_errors() {
+    let mut newer = valid_proto();
+    newer.header.as_mut().unwrap().version = FORMAT_VERSION + 1;
+    assert!(matches!(
+        Document::from_bytes(&encode(&newer)),
+        Err(DcfError::UnsupportedVersion { found, supported })
+            if found == FORMAT_VERSION + 1 && supported == FORMAT_VERSION
+    ));
+
+    let mut short_hash = valid_proto();
+    short_hash.cells[0].code_id.truncate(7);
+    assert!(matches!(
+        Document::from_bytes(&encode(&short_hash)),
+        Err(DcfError::InvalidLength {
+            field: "cell code_id",
+            expected: 32,
+            found: 7
+        })
+    ));
+
+    let mut short_sha = valid_proto();
+    short_sha.numguards[0].sha1.clear();
+    assert!(matches!(
+        Document::from_bytes(&encode(&short_sha)),
+        Err(DcfError::InvalidLength {
+            field: "NumGuard sha1",
+            ..
+        })
+    ));
+
+    let mut unknown_type = valid_proto();
+    unknown_type.cells[0].r#type = 42;
+    assert!(matches!(
+        Document::from_bytes(&encode(&unknown_type)),
+        Err(DcfError::UnknownEnumValue {
+            field: "cell type",
+            value: 42
+        })
+    ));
+
+    let mut truncated = seekable_fixture();
+    truncated.truncate(truncated.len() - 3);
+    assert!(matches!(
+        Document::from_bytes(&truncated),
+        Err(DcfError::CorruptContainer(_))
+    ));
+    assert!(Document::from_bytes(b"not a container").is_err());
+}
+
+fn seekable_fixture() -> Vec<u8> {
+    load(&fixtures().join("sample_report.3dcf"))
+        .to_seekable_bytes()
+        .unwrap()
+}
@@ -22,6 +22,8 @@ proptest! {
                 rle: 0,
                 cell_type: spec.cell_type,
                 importance: spec.importance,
+                ocr_confidence: None,
+                lang: None,
             });
         }
 
@@ -41,11 +41,87 @@ fn sample_document() -> Document {
             rle: 0,
             cell_type: CellType::Text,
             importance: 100,
+            ocr_confidence: None,
+            lang: None,
         });
     }
     doc
 }
 
+#[test]
+fn html_links_survive_roundtrip() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("links.html");
+    std::fs::write(
+        &path,
+        r##"<p>Read the <a href="https://example.org/guide">guide</a> or jump to <a href="#faq">the FAQ</a>.</p>
+<h2 id="faq">FAQ</h2><p>Answers.</p>"##,
+    )
+    .expect("write");
+    let encoder = three_dcf_core::Encoder::from_preset("reports").expect("preset");
+    let (doc, metrics) = encoder.encode_path(&path).expect("encode");
+    assert_eq!(metrics.links, 2);
+    let first = &doc.ordered_cells()[0];
+    let payload = doc.payload_for(&first.code_id).expect("payload");
+    assert!(!payload.contains("[1]"));
+    let anchors: Vec<_> = doc
+        .links_for(first)
+        .filter_map(|link| link.anchor_text(payload))
+        .collect();
+    assert_eq!(anchors, vec!["guide", "the FAQ"]);
+
+    let decoded = Document::from_bytes(&doc.to_bytes().expect("serialize")).expect("decode");
+    assert_eq!(doc.links, decoded.links);
+    let three_dcf_core::LinkTarget::Cell { z, x, y } = decoded.links[1].target else {
+        panic!("cross-reference should point at a cell");
+    };
+    let heading = decoded
+        .cells
+        .iter()
+        .find(|c| (c.z, c.x, c.y) == (z, x, y))
+        .expect("cell");
+    assert!(decoded
+        .payload_for(&heading.code_id)
+        .expect("payload")
+        .contains("FAQ"));
+}
+
+#[test]
+fn long_text_flows_fit_their_page() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("long.txt");
+    let text: String = (0..200)
+        .map(|idx| format!("Paragraph {idx} keeps the flow going.\n"))
+        .collect();
+    std::fs::write(&path, text).expect("write");
+    let encoder = three_dcf_core::Encoder::from_preset("reports").expect("preset");
+    let (doc, _) = encoder.encode_path(&path).expect("encode");
+    assert!(doc.pages[0].height_px > 1400);
+    let report = doc.validate();
+    assert!(report.issues.is_empty(), "{report}");
+}
+
+#[test]
+fn dropped_cells_are_only_kept_on_request() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("notes.txt");
+    std::fs::write(&path, "First line.\nSecond line.\nThird line.\n").expect("write");
+    let encode = |keep: bool| {
+        three_dcf_core::EncoderBuilder::new("reports")
+            .expect("preset")
+            .budget(Some(1))
+            .keep_dropped(keep)
+            .build()
+            .encode_path(&path)
+            .expect("encode")
+            .0
+    };
+    assert!(encode(false).dropped.is_empty());
+    let kept = encode(true);
+    assert_eq!(kept.dropped.len(), 2);
+    assert!(kept.dropped.iter().all(|d| !d.payload.is_empty()));
+}
+
 #[test]
 fn bbox_decode_matches_subset() {
     let doc = sample_document();
@@ -55,3 +131,172 @@ fn bbox_decode_matches_subset() {
     assert!(text_block.contains("Revenue"));
     assert!(!text_block.contains("Net Income"));
 }
+
+#[test]
+fn typed_numguards_survive_roundtrip() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("numbers.txt");
+    std::fs::write(&path, "Revenue rose 12% to $4.5bn on 2024-03-15\n").expect("write");
+    let encoder = three_dcf_core::Encoder::from_preset("reports").expect("preset");
+    let (doc, _) = encoder.encode_path(&path).expect("encode");
+    assert_eq!(doc.numguards.len(), 3);
+    assert!(doc.numguard_mismatches().is_empty());
+
+    let decoded = Document::from_bytes(&doc.to_bytes().expect("serialize")).expect("decode");
+    assert_eq!(doc.numguards, decoded.numguards);
+    assert!(decoded.numguard_mismatches().is_empty());
+}
+
+#[test]
+fn ocr_layout_and_confidence_survive_roundtrip() {
+    use three_dcf_core::{EncodeInput, Encoder, OcrPage, PageBuffer};
+
+    let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t900\t700\t-1\t
+5\t1\t1\t1\t1\t1\t40\t50\t120\t20\t95\tRevenue
+5\t1\t1\t1\t1\t2\t170\t50\t80\t20\t89\t$4.2M
+5\t1\t1\t1\t2\t1\t40\t90\t60\t20\t8\t~#%
+5\t1\t2\t1\t1\t1\t40\t300\t200\t24\t77\tOutlook
+";
+    let encoder = Encoder::builder("scans")
+        .expect("preset")
+        .ocr_min_confidence(50.0)
+        .build();
+    let page = PageBuffer::from_ocr(0, OcrPage::from_tsv(tsv).expect("tsv"), encoder.config());
+    let (doc, metrics) = encoder
+        .encode(EncodeInput { pages: vec![page] })
+        .expect("encode");
+
+    assert_eq!(doc.pages[0].width_px, 900);
+    let cells = doc.ordered_cells();
+    assert_eq!(cells.len(), 2);
+    assert_eq!((cells[0].x, cells[0].y, cells[0].w), (40, 50, 210));
+    assert_eq!(cells[0].ocr_confidence, Some(92));
+    assert_eq!(metrics.ocr_words_dropped, 1);
+    let page_confidence = metrics.ocr_page_confidence[0].expect("ocr page");
+    assert!((page_confidence - 67.25).abs() < 1e-3);
+
+    let decoded = Document::from_bytes(&doc.to_bytes().expect("serialize")).expect("decode");
+    assert_eq!(doc.ordered_cells(), decoded.ordered_cells());
+}
+
+#[test]
+fn ocr_lines_merge_hyphenated_words() {
+    use three_dcf_core::{EncodeInput, Encoder, HyphenationMode, OcrPage, PageBuffer};
+
+    let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t900\t700\t-1\t
+5\t1\t1\t1\t1\t1\t40\t50\t120\t20\t90\tQuarterly
+5\t1\t1\t1\t1\t2\t170\t50\t90\t20\t90\tperfor-
+5\t1\t1\t1\t2\t1\t40\t80\t70\t20\t80\tmance
+5\t1\t1\t1\t2\t2\t120\t80\t60\t20\t80\timproved
+";
+    let encode = |mode| {
+        let encoder = Encoder::builder("scans")
+            .expect("preset")
+            .hyphenation(mode)
+            .build();
+        let page = PageBuffer::from_ocr(0, OcrPage::from_tsv(tsv).expect("tsv"), encoder.config());
+        let (doc, _) = encoder
+            .encode(EncodeInput { pages: vec![page] })
+            .expect("encode");
+        doc
+    };
+
+    let merged = encode(HyphenationMode::Merge);
+    let cells = merged.ordered_cells();
+    assert_eq!(cells.len(), 1);
+    assert_eq!(
+        merged.payload_for(&cells[0].code_id),
+        Some("Quarterly performance improved")
+    );
+    assert_eq!(
+        (cells[0].x, cells[0].y, cells[0].w, cells[0].h),
+        (40, 50, 220, 50)
+    );
+    assert_eq!(cells[0].ocr_confidence, Some(85));
+
+    let preserved = encode(HyphenationMode::Preserve);
+    assert_eq!(preserved.ordered_cells().len(), 2);
+}
+
+#[test]
+fn markdown_images_become_figure_cells_with_sidecars() {
+    use three_dcf_core::{Encoder, FigureRef};
+
+    let dir = tempfile::tempdir().expect("tempdir");
+    let image = image::RgbImage::from_pixel(120, 60, image::Rgb([30, 90, 160]));
+    image.save(dir.path().join("chart.png")).expect("png");
+    let source = dir.path().join("report.md");
+    std::fs::write(
+        &source,
+        "# Results\n\nRevenue grew.\n\n![Figure 1: Revenue 2023](chart.png)\n\nCosts fell.\n",
+    )
+    .expect("markdown");
+
+    let encoder = Encoder::builder("reports")
+        .expect("preset")
+        .extract_figures(true)
+        .build();
+    let (doc, metrics) = encoder.encode_path(&source).expect("encode");
+    assert_eq!(metrics.figures, 1);
+
+    let cells = doc.ordered_cells();
+    let texts: Vec<&str> = cells
+        .iter()
+        .map(|cell| doc.payload_for(&cell.code_id).unwrap())
+        .collect();
+    let figure_at = cells
+        .iter()
+        .position(|cell| cell.cell_type == CellType::Figure)
+        .expect("figure cell");
+    assert_eq!(texts[figure_at - 1], "Revenue grew.");
+    assert_eq!(texts[figure_at + 1], "Costs fell.");
+    assert_eq!(cells[figure_at].h, 60);
+
+    let figures = doc.figures();
+    let reference: &FigureRef = &figures[0].1;
+    assert_eq!(reference.caption.as_deref(), Some("Figure 1: Revenue 2023"));
+    assert!(reference.path.starts_with("figures/") && reference.path.ends_with(".png"));
+
+    let out = dir.path().join("out");
+    std::fs::create_dir_all(&out).expect("out dir");
+    doc.save_bin(out.join("report.3dcf")).expect("save");
+    assert!(out.join(&reference.path).is_file());
+
+    let loaded = Document::load_bin(out.join("report.3dcf")).expect("load");
+    assert_eq!(loaded.figures()[0].1, *reference);
+    assert!(loaded.figure_files.is_empty());
+}
+
+#[test]
+fn markdown_math_becomes_equation_cells() {
+    let dir = tempfile::tempdir().expect("tempdir");
+    let path = dir.path().join("math.md");
+    std::fs::write(
+        &path,
+        "Euler showed that $e^{i\\pi} + 1 = 0$ holds.\n\n$$\\int_0^\\infty e^{-x^2}\\,dx = \\frac{\\sqrt{\\pi}}{2}$$\n\nThe area is 𝑥² wide.\n",
+    )
+    .expect("write");
+    let encoder = three_dcf_core::Encoder::from_preset("reports").expect("preset");
+    let (doc, _) = encoder.encode_path(&path).expect("encode");
+    let cells = doc.ordered_cells();
+    let texts: Vec<&str> = cells
+        .iter()
+        .map(|cell| doc.payload_for(&cell.code_id).unwrap())
+        .collect();
+    assert_eq!(texts[0], "Euler showed that $e^{i\\pi} + 1 = 0$ holds.");
+    assert_eq!(cells[0].cell_type, CellType::Text);
+    assert_eq!(
+        texts[1],
+        "\\int_0^\\infty e^{-x^2}\\,dx = \\frac{\\sqrt{\\pi}}{2}"
+    );
+    assert_eq!(cells[1].cell_type, CellType::Equation);
+    assert!(doc
+        .numguards
+        .iter()
+        .all(|guard| guard.y as i32 != cells[1].y));
+
+    let decoded = Document::from_bytes(&doc.to_bytes().expect("serialize")).expect("decode");
+    assert_eq!(decoded.ordered_cells()[1].cell_type, CellType::Equation);
+}
@@ -0,0 +1,78 @@
+use std::path::PathBuf;
+
+use three_dcf_core::{preprocess, PreprocessConfig, ScanFixture};
+
+fn scans_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../datasets/scans")
+}
+
+fn manifest() -> Vec<(String, ScanFixture)> {
+    let manifest =
+        std::fs::read_to_string(scans_dir().join("manifest.jsonl")).expect("scan manifest");
+    manifest
+        .lines()
+        .map(|line| {
+            let entry: serde_json::Value = serde_json::from_str(line).expect("manifest entry");
+            let file = entry["file"].as_str().expect("file").to_string();
+            (file, serde_json::from_value(entry).expect("fixture"))
+        })
+        .collect()
+}
+
+#[test]
+fn preprocessing_undoes_the_scan_fixture_distortions() {
+    let fixtures = manifest();
+    assert_eq!(fixtures.len(), 4);
+    for (file, fixture) in fixtures {
+        let image = image::open(scans_dir().join(&file)).expect("scan png");
+        let (stored, rendered) = (image.to_luma8(), fixture.render());
+        assert_eq!(
+            stored.dimensions(),
+            rendered.dimensions(),
+            "{file} is stale"
+        );
+        let differing = stored
+            .pixels()
+            .zip(rendered.pixels())
+            .filter(|(a, b)| a[0].abs_diff(b[0]) > 32)
+            .count();
+        assert!(
+            differing * 100 <= stored.len(),
+            "{file} is stale: {differing} pixels differ"
+        );
+
+        let (clean, correction) = preprocess(&image, &PreprocessConfig::all());
+        assert_eq!(
+            correction.rotation,
+            (360 - fixture.rotation) % 360,
+            "{file}"
+        );
+        assert!(
+            (correction.skew_degrees + fixture.skew_degrees).abs() <= 0.3,
+            "{file}: skew {} corrected by {}",
+            fixture.skew_degrees,
+            correction.skew_degrees
+        );
+        assert!(correction.specks_removed * 2 >= fixture.specks, "{file}");
+        assert!(clean.pixels().all(|p| p[0] == 0 || p[0] == 255));
+    }
+}
+
+#[cfg(not(feature = "ocr"))]
+#[test]
+fn bench_skips_scans_when_ocr_is_disabled() {
+    use three_dcf_core::{BenchConfig, BenchMode, BenchRunner, TokenizerKind};
+
+    let runner = BenchRunner::new(BenchConfig {
+        mode: BenchMode::Encode,
+        root: scans_dir(),
+        gold_root: None,
+        output: None,
+        preset: "scans".to_string(),
+        tokenizer: TokenizerKind::Cl100k,
+        budgets: Vec::new(),
+    })
+    .expect("runner");
+    let metrics = runner.run().expect("bench run");
+    assert!(metrics.results.is_empty());
+}
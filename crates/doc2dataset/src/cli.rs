@@ -28,6 +28,12 @@ pub enum Command {
         force_ocr: bool,
         #[arg(long, default_value = "eng")]
         ocr_langs: String,
+        #[arg(long, default_value_t = false)]
+        redact_pii: bool,
+        #[arg(long)]
+        redact_mode: Option<String>,
+        #[arg(long)]
+        pii_gazetteer: Option<String>,
     },
     Tasks {
         dataset_root: String,
@@ -1,6 +1,7 @@
 use anyhow::{anyhow, Result};
 use serde::Deserialize;
 use std::env;
+use three_dcf_core::{FaithfulnessPolicy, RedactionConfig};
 use three_dcf_llm::LlmProvider;
 
 #[derive(Debug, Clone)]
@@ -11,6 +12,7 @@ pub struct Doc2DatasetConfig {
     pub llm_delay_ms: u64,
     pub qa_max_per_doc: usize,
     pub summary_max_per_doc: usize,
+    pub numeric_check: FaithfulnessPolicy,
 }
 
 impl Doc2DatasetConfig {
@@ -34,6 +36,10 @@ impl Doc2DatasetConfig {
             .ok()
             .and_then(|v| v.parse().ok())
             .unwrap_or(DEFAULT_SUMMARY_MAX_PER_DOC);
+        let numeric_check = match env::var("DOC2DATASET_NUMERIC_CHECK") {
+            Ok(value) => value.parse().map_err(|err| anyhow!("{err}"))?,
+            Err(_) => FaithfulnessPolicy::Flag,
+        };
         Ok(Self {
             provider,
             model,
@@ -41,8 +47,14 @@ impl Doc2DatasetConfig {
             llm_delay_ms,
             qa_max_per_doc,
             summary_max_per_doc,
+            numeric_check,
         })
     }
+
+    /// `DOC2DATASET_LANG=auto` asks for samples in the language of their source cells.
+    pub fn uses_source_lang(&self) -> bool {
+        self.lang.eq_ignore_ascii_case(AUTO_LANG)
+    }
 }
 
 fn default_model(provider: LlmProvider) -> &'static str {
@@ -111,6 +123,9 @@ pub struct IngestConfig {
     pub force_ocr: bool,
     #[serde(default = "default_ocr_langs")]
     pub ocr_langs: Vec<String>,
+    /// PII redaction during ingest; omitted means no redaction.
+    #[serde(default)]
+    pub redaction: Option<RedactionConfig>,
 }
 
 fn default_preset() -> String {
@@ -133,3 +148,6 @@ pub struct RunConfig {
 }
 pub const DEFAULT_QA_MAX_PER_DOC: usize = 4;
 pub const DEFAULT_SUMMARY_MAX_PER_DOC: usize = 3;
+pub const AUTO_LANG: &str = "auto";
+/// Used with `auto` when the source cells carry no language.
+pub const FALLBACK_LANG: &str = "en";
@@ -175,6 +175,44 @@ fn render_table(headers: &[String], rows: &[Vec<String>], out: &mut String) {
     out.push('\n');
 }
 
+fn sorted_keys(map: &Map<String, Value>) -> Vec<String> {
+    let mut keys: Vec<String> = map.keys().cloned().collect();
+    keys.sort();
+    keys
+}
+
+fn write_heading(level: usize, title: &str, out: &mut String) {
+    let capped = level.min(6);
+    let hashes = "#".repeat(capped.max(1));
+    out.push_str(&format!("{} {}\n\n", hashes, title));
+}
+
+fn format_scalar(value: &Value) -> String {
+    match value {
+        Value::Null => "null".to_string(),
+        Value::Bool(v) => v.to_string(),
+        Value::Number(num) => num.to_string(),
+        Value::String(s) => s.trim().to_string(),
+        Value::Array(_) => "[...]".to_string(),
+        Value::Object(_) => "{...}".to_string(),
+    }
+}
+
+fn format_scalar_label(idx: usize, value: &Value) -> String {
+    match value {
+        Value::Object(_) | Value::Array(_) => format!("item {}", idx + 1),
+        other => format_scalar(other),
+    }
+}
+
+fn sanitize_cell(value: &str) -> String {
+    value
+        .replace('|', "\\|")
+        .replace('\n', " ")
+        .trim()
+        .to_string()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -222,41 +260,3 @@ mod tests {
         assert!(markdown.contains("key"));
     }
 }
-
-fn sorted_keys(map: &Map<String, Value>) -> Vec<String> {
-    let mut keys: Vec<String> = map.keys().cloned().collect();
-    keys.sort();
-    keys
-}
-
-fn write_heading(level: usize, title: &str, out: &mut String) {
-    let capped = level.min(6);
-    let hashes = "#".repeat(capped.max(1));
-    out.push_str(&format!("{} {}\n\n", hashes, title));
-}
-
-fn format_scalar(value: &Value) -> String {
-    match value {
-        Value::Null => "null".to_string(),
-        Value::Bool(v) => v.to_string(),
-        Value::Number(num) => num.to_string(),
-        Value::String(s) => s.trim().to_string(),
-        Value::Array(_) => "[...]".to_string(),
-        Value::Object(_) => "{...}".to_string(),
-    }
-}
-
-fn format_scalar_label(idx: usize, value: &Value) -> String {
-    match value {
-        Value::Object(_) | Value::Array(_) => format!("item {}", idx + 1),
-        other => format_scalar(other),
-    }
-}
-
-fn sanitize_cell(value: &str) -> String {
-    value
-        .replace('|', "\\|")
-        .replace('\n', " ")
-        .trim()
-        .to_string()
-}
@@ -14,12 +14,33 @@ pub fn convert_tex(path: &Path) -> Result<String> {
     let mut list_depth = 0usize;
     let mut table_rows: Vec<Vec<String>> = Vec::new();
     let mut in_table = false;
+    let mut math: Option<(String, Vec<String>)> = None;
     for raw_line in raw.lines() {
         let line = strip_comments(raw_line);
         let trimmed = line.trim();
+        if let Some((env, body)) = math.as_mut() {
+            match trimmed.find(&end_marker(env)) {
+                Some(end) => {
+                    body.push(trimmed[..end].to_string());
+                    let (env, body) = math.take().unwrap_or_default();
+                    flush_math(&env, &body, &mut out);
+                }
+                None => body.push(trimmed.to_string()),
+            }
+            continue;
+        }
         if trimmed.is_empty() {
             continue;
         }
+        if let Some((env, rest)) = begin_math(trimmed) {
+            flush_paragraph(&mut paragraph, &mut out);
+            flush_table(&mut table_rows, &mut in_table, &mut out);
+            match rest.find(&end_marker(env)) {
+                Some(end) => flush_math(env, &[rest[..end].to_string()], &mut out),
+                None => math = Some((env.to_string(), vec![rest.to_string()])),
+            }
+            continue;
+        }
         if let Some(title) = extract_command(trimmed, "\\section") {
             flush_paragraph(&mut paragraph, &mut out);
             flush_table(&mut table_rows, &mut in_table, &mut out);
@@ -44,9 +65,7 @@ pub fn convert_tex(path: &Path) -> Result<String> {
             continue;
         }
         if trimmed.starts_with("\\end{itemize}") || trimmed.starts_with("\\end{enumerate}") {
-            if list_depth > 0 {
-                list_depth -= 1;
-            }
+            list_depth = list_depth.saturating_sub(1);
             continue;
         }
         if trimmed.starts_with("\\item") {
@@ -70,7 +89,7 @@ pub fn convert_tex(path: &Path) -> Result<String> {
             for row in trimmed.split("\\\\") {
                 let cells: Vec<String> = row
                     .split('&')
-                    .map(|cell| clean_tex_fragment(cell))
+                    .map(clean_tex_fragment)
                     .filter(|cell| !cell.is_empty())
                     .collect();
                 if !cells.is_empty() {
@@ -79,9 +98,12 @@ pub fn convert_tex(path: &Path) -> Result<String> {
             }
             continue;
         }
-        paragraph.push_str(trimmed);
+        paragraph.push_str(&inline_math(trimmed));
         paragraph.push(' ');
     }
+    if let Some((env, body)) = math.take() {
+        flush_math(&env, &body, &mut out);
+    }
     flush_paragraph(&mut paragraph, &mut out);
     flush_table(&mut table_rows, &mut in_table, &mut out);
     Ok(out)
@@ -96,6 +118,71 @@ fn flush_paragraph(buffer: &mut String, out: &mut String) {
     buffer.clear();
 }
 
+/// Math environments kept as LaTeX; single equations keep only their body.
+const MATH_ENVIRONMENTS: [&str; 15] = [
+    "equation",
+    "equation*",
+    "displaymath",
+    "align",
+    "align*",
+    "gather",
+    "gather*",
+    "multline",
+    "multline*",
+    "eqnarray",
+    "eqnarray*",
+    "flalign",
+    "flalign*",
+    "alignat",
+    "alignat*",
+];
+
+/// Environment name and the rest of the line when `line` opens display math.
+fn begin_math(line: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = line.strip_prefix("\\[") {
+        return Some(("[", rest));
+    }
+    let rest = line.strip_prefix("\\begin{")?;
+    let (env, rest) = rest.split_once('}')?;
+    MATH_ENVIRONMENTS.contains(&env).then_some((env, rest))
+}
+
+fn end_marker(env: &str) -> String {
+    if env == "[" {
+        "\\]".to_string()
+    } else {
+        format!("\\end{{{env}}}")
+    }
+}
+
+/// Writes a display formula as a `$$` block, the form the Markdown encoder
+/// turns into an Equation cell.
+fn flush_math(env: &str, body: &[String], out: &mut String) {
+    let body: Vec<&str> = body
+        .iter()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty())
+        .collect();
+    if body.is_empty() {
+        return;
+    }
+    out.push_str("$$\n");
+    if matches!(env, "[" | "equation" | "equation*" | "displaymath") {
+        out.push_str(&body.join("\n"));
+    } else {
+        out.push_str(&format!(
+            "\\begin{{{env}}}\n{}\n\\end{{{env}}}",
+            body.join("\n")
+        ));
+    }
+    out.push_str("\n$$\n\n");
+}
+
+/// `\(...\)` becomes `$...$` so inline math reaches the encoder intact.
+fn inline_math(line: &str) -> String {
+    line.replace("\\(", "$").replace("\\)", "$")
+}
+
 fn flush_table(rows: &mut Vec<Vec<String>>, in_table: &mut bool, out: &mut String) {
     if !*in_table {
         return;
@@ -150,8 +237,8 @@ fn flush_table(rows: &mut Vec<Vec<String>>, in_table: &mut bool, out: &mut Strin
 }
 
 fn extract_command(line: &str, command: &str) -> Option<String> {
-    if line.starts_with(command) {
-        let rest = line[command.len()..].trim();
+    if let Some(rest) = line.strip_prefix(command) {
+        let rest = rest.trim();
         if let Some(stripped) = rest.strip_prefix('{') {
             if let Some(end) = stripped.find('}') {
                 return Some(clean_tex_fragment(&stripped[..end]));
@@ -161,21 +248,34 @@ fn extract_command(line: &str, command: &str) -> Option<String> {
     None
 }
 
+/// Drops braces and backslashes outside `$...$`; inline math is kept as written.
 fn clean_tex_fragment(value: &str) -> String {
-    value
-        .replace('{', "")
-        .replace('}', "")
-        .replace("\\", "")
+    inline_math(value)
+        .split('$')
+        .enumerate()
+        .map(|(idx, part)| {
+            if idx % 2 == 1 {
+                part.to_string()
+            } else {
+                part.replace(['{', '}'], "").replace("\\", "")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("$")
         .trim()
         .to_string()
 }
 
 fn strip_comments(line: &str) -> &str {
-    if let Some(idx) = line.find('%') {
-        &line[..idx]
-    } else {
-        line
+    let mut escaped = false;
+    for (idx, ch) in line.char_indices() {
+        match ch {
+            '%' if !escaped => return &line[..idx],
+            '\\' => escaped = !escaped,
+            _ => escaped = false,
+        }
     }
+    line
 }
 
 #[cfg(test)]
@@ -195,4 +295,19 @@ mod tests {
         assert!(markdown.contains("## Intro"));
         assert!(markdown.contains("Bullet"));
     }
+
+    #[test]
+    fn tex_conversion_keeps_math_source() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(
+            b"Let \\(x_i\\) be 50\\% of $\\sum_i a_i$. % note\n\\begin{equation}\n  E = mc^2 \\label{eq:e}\n\\end{equation}\n\\begin{align}\na &= 1 \\\\\nb &= 2\n\\end{align}\n\\begin{itemize}\n\\item Case $n > 1$\n\\end{itemize}",
+        )
+        .unwrap();
+        let markdown = convert_tex(file.path()).unwrap();
+        assert!(markdown.contains("Let $x_i$ be 50\\% of $\\sum_i a_i$."));
+        assert!(!markdown.contains("note"));
+        assert!(markdown.contains("$$\nE = mc^2 \\label{eq:e}\n$$"));
+        assert!(markdown.contains("$$\n\\begin{align}\na &= 1 \\\\\nb &= 2\n\\end{align}\n$$"));
+        assert!(markdown.contains("- Case $n > 1$"));
+    }
 }
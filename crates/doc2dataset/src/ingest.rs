@@ -2,13 +2,14 @@ use std::path::{Path, PathBuf};
 
 use anyhow::{anyhow, Context, Result};
 use glob::Pattern;
-use three_dcf_core::{ingest_to_index_with_opts, IngestOptions};
+use three_dcf_core::{ingest_to_index_with_opts, IngestOptions, RedactionConfig, RedactionMode};
 use walkdir::WalkDir;
 
 use crate::convert;
 use crate::logging;
 use crate::model::{FileFormat, RawDocument, SourceType};
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     input: String,
     output: String,
@@ -17,6 +18,7 @@ pub fn run(
     enable_ocr: bool,
     force_ocr: bool,
     ocr_langs: String,
+    redaction: Option<RedactionConfig>,
 ) -> Result<()> {
     run_with(
         input,
@@ -26,10 +28,31 @@ pub fn run(
         enable_ocr,
         force_ocr,
         ocr_langs,
-        |doc, out, opts| ingest_to_index_with_opts(doc, out, opts),
+        redaction,
+        ingest_to_index_with_opts,
     )
 }
 
+/// Builds the redaction settings for `ingest --redact-pii`; `None` leaves PII untouched.
+pub fn redaction_from_flags(
+    redact_pii: bool,
+    mode: Option<&str>,
+    gazetteer: Option<&str>,
+) -> Result<Option<RedactionConfig>> {
+    if !redact_pii {
+        return Ok(None);
+    }
+    let mode = match mode {
+        Some(value) => value.parse::<RedactionMode>()?,
+        None => RedactionMode::default(),
+    };
+    Ok(Some(RedactionConfig {
+        gazetteer: gazetteer.map(PathBuf::from),
+        ..RedactionConfig::with_mode(mode)
+    }))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn run_with<F>(
     input: String,
     output: String,
@@ -38,6 +61,7 @@ fn run_with<F>(
     enable_ocr: bool,
     force_ocr: bool,
     ocr_langs: String,
+    redaction: Option<RedactionConfig>,
     ingest_fn: F,
 ) -> Result<()>
 where
@@ -80,6 +104,7 @@ where
             lang_list
         },
         source_override: None,
+        redaction,
     };
     for doc in docs {
         println!(
@@ -229,6 +254,7 @@ mod tests {
             true,
             true,
             "eng,pol".to_string(),
+            Some(RedactionConfig::with_mode(RedactionMode::Pseudonymize)),
             |_, _, opts| {
                 recorded.borrow_mut().push(opts.clone());
                 Ok(())
@@ -244,6 +270,10 @@ mod tests {
             opts[0].ocr_languages,
             vec!["eng".to_string(), "pol".to_string()]
         );
+        assert_eq!(
+            opts[0].redaction.as_ref().map(|config| config.mode),
+            Some(RedactionMode::Pseudonymize)
+        );
     }
 
     #[test]
@@ -263,6 +293,7 @@ mod tests {
             false,
             false,
             "eng".to_string(),
+            None,
             |path, _, _| {
                 recorded.borrow_mut().push(path.to_path_buf());
                 Ok(())
@@ -31,8 +31,22 @@ fn main() -> Result<()> {
             enable_ocr,
             force_ocr,
             ocr_langs,
+            redact_pii,
+            redact_mode,
+            pii_gazetteer,
         } => ingest::run(
-            input, output, pattern, preset, enable_ocr, force_ocr, ocr_langs,
+            input,
+            output,
+            pattern,
+            preset,
+            enable_ocr,
+            force_ocr,
+            ocr_langs,
+            ingest::redaction_from_flags(
+                redact_pii,
+                redact_mode.as_deref(),
+                pii_gazetteer.as_deref(),
+            )?,
         ),
         Command::Tasks {
             dataset_root,
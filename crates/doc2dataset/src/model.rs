@@ -41,6 +41,7 @@ pub enum FileFormat {
     Toml,
     Log,
     Rtf,
+    Docx,
     Image,
     Unknown,
 }
@@ -72,12 +73,8 @@ impl FileFormat {
             Some("toml") => FileFormat::Toml,
             Some("log") => FileFormat::Log,
             Some("rtf") => FileFormat::Rtf,
-            Some(ext)
-                if matches!(
-                    ext,
-                    "png" | "jpg" | "jpeg" | "gif" | "tif" | "tiff" | "bmp" | "webp"
-                ) =>
-            {
+            Some("docx") => FileFormat::Docx,
+            Some("png" | "jpg" | "jpeg" | "gif" | "tif" | "tiff" | "bmp" | "webp") => {
                 FileFormat::Image
             }
             _ => {
@@ -116,6 +113,7 @@ impl FileFormat {
             FileFormat::Toml => "toml",
             FileFormat::Log => "log",
             FileFormat::Rtf => "rtf",
+            FileFormat::Docx => "docx",
             FileFormat::Image => "image",
             FileFormat::Unknown => "unknown",
         }
@@ -172,6 +170,13 @@ pub struct TaskMetricsEntry {
     pub samples: usize,
     pub prompt_tokens: u64,
     pub completion_tokens: u64,
+    /// Mean numeric faithfulness over every checked response, kept or dropped.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub numeric_faithfulness: Option<f32>,
+    #[serde(default)]
+    pub unsupported_numbers: u64,
+    #[serde(default)]
+    pub dropped_unfaithful: usize,
 }
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
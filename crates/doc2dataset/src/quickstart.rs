@@ -14,6 +14,7 @@ pub fn run(input: String) -> Result<()> {
         false,
         false,
         "eng".to_string(),
+        None,
     )?;
     tasks::run(
         output.to_string_lossy().into_owned(),
@@ -5,6 +5,8 @@ use anyhow::{anyhow, Context, Result};
 use serde_yaml::from_str;
 
 use crate::cli::ExportCommand;
+use three_dcf_core::RedactionConfig;
+
 use crate::config::RunConfig;
 use crate::{export, ingest, tasks};
 
@@ -21,7 +23,16 @@ fn run_pipeline<FIngest, FTasks, FExport>(
     export_fn: FExport,
 ) -> Result<()>
 where
-    FIngest: Fn(String, String, String, String, bool, bool, String) -> Result<()>,
+    FIngest: Fn(
+        String,
+        String,
+        String,
+        String,
+        bool,
+        bool,
+        String,
+        Option<RedactionConfig>,
+    ) -> Result<()>,
     FTasks: Fn(String, String) -> Result<()>,
     FExport: Fn(ExportCommand) -> Result<()>,
 {
@@ -44,6 +55,7 @@ where
             cfg.ingest.enable_ocr,
             cfg.ingest.force_ocr,
             cfg.ingest.ocr_langs.join(","),
+            cfg.ingest.redaction.clone(),
         )?;
     }
     if !cfg.tasks.is_empty() {
@@ -110,6 +122,7 @@ mod tests {
                 enable_ocr: true,
                 force_ocr: false,
                 ocr_langs: vec!["eng".to_string(), "deu".to_string()],
+                redaction: Some(RedactionConfig::default()),
             },
         };
         cfg.sources.push(crate::config::SourceConfig {
@@ -121,7 +134,7 @@ mod tests {
         let export_calls: RefCell<Vec<String>> = RefCell::new(Vec::new());
         run_pipeline(
             cfg,
-            |input, output, pattern, preset, enable, force, langs| {
+            |input, output, pattern, preset, enable, force, langs, redaction| {
                 ingest_calls
                     .borrow_mut()
                     .push((input.clone(), preset.clone()));
@@ -130,6 +143,7 @@ mod tests {
                 assert!(enable);
                 assert!(!force);
                 assert_eq!(langs, "eng,deu");
+                assert!(redaction.is_some());
                 Ok(())
             },
             |dataset, tasks| {
@@ -175,12 +189,13 @@ mod tests {
                 enable_ocr: false,
                 force_ocr: false,
                 ocr_langs: vec!["eng".to_string()],
+                redaction: None,
             },
         };
         let task_calls: RefCell<Vec<String>> = RefCell::new(Vec::new());
         run_pipeline(
             cfg,
-            |_, _, _, _, _, _, _| Ok(()),
+            |_, _, _, _, _, _, _, _| Ok(()),
             |dataset, _| {
                 task_calls.borrow_mut().push(dataset.clone());
                 Ok(())
@@ -6,12 +6,13 @@ use std::thread;
 use std::time::{Duration, Instant};
 
 use anyhow::{anyhow, Context, Result};
-use serde_json::json;
+use serde_json::{json, Value};
 use three_dcf_core::index::{CellRecord as IndexCellRecord, JsonlWriter};
+use three_dcf_core::{FaithfulnessPolicy, FaithfulnessReport, LanguageTally, NumericVerifier};
 use three_dcf_llm::{LlmClient, LlmRequest, LlmResponse};
 use tokio::runtime::Runtime;
 
-use crate::config::Doc2DatasetConfig;
+use crate::config::{Doc2DatasetConfig, FALLBACK_LANG};
 #[cfg(test)]
 use crate::config::{DEFAULT_QA_MAX_PER_DOC, DEFAULT_SUMMARY_MAX_PER_DOC};
 use crate::logging;
@@ -63,7 +64,7 @@ pub fn run(dataset_root: String, tasks: String) -> Result<()> {
         );
         let metrics = generate_qa(&root, &index, &config, &llm_runner)?;
         report.qa = Some(metrics);
-        logging::stage("rag", "building RAG view from QA samples".to_string());
+        logging::stage("rag", "building RAG view from QA samples");
         build_rag_samples(&root, &index)?;
     }
     if requested.contains(&TaskKind::Summary) {
@@ -113,6 +114,7 @@ fn generate_qa(
     let file = File::create(&qa_path).context("failed to create qa.jsonl")?;
     let mut writer = JsonlWriter::new(BufWriter::new(file));
     let mut metrics = TaskMetricsEntry::default();
+    let mut tally = FaithfulnessTally::default();
     let mut sample_counter = 0usize;
     let doc_ids = sorted_doc_ids(index);
     let total_docs = doc_ids.len();
@@ -140,36 +142,64 @@ fn generate_qa(
                 continue;
             }
             let mut context_text = text.to_string();
-            let mut cell_ids = vec![cell.cell_id.clone()];
+            let mut context_cells = vec![cell];
             if context_text.len() < QA_MAX_CONTEXT_CHARS && idx + 1 < cells.len() {
                 let next = &cells[idx + 1];
                 if is_textual_cell(&next.kind) && !next.text.trim().is_empty() {
                     context_text.push_str("\n\n");
                     context_text.push_str(next.text.trim());
-                    cell_ids.push(next.cell_id.clone());
+                    context_cells.push(next);
                 }
             }
+            let linked = linked_cells(index, &context_cells);
+            let followed_links = linked.len();
+            for target in linked {
+                context_text.push_str("\n\n");
+                context_text.push_str(target.text.trim());
+                context_cells.push(target);
+            }
+            let cell_ids: Vec<String> = context_cells
+                .iter()
+                .map(|cell| cell.cell_id.clone())
+                .collect();
             logging::verbose(format!(
                 "[qa] doc={} cells={} context_chars={}",
                 doc_id,
                 cell_ids.join(","),
                 context_text.len()
             ));
+            let lang = sample_lang(config, context_cells.iter().copied());
             let prompt = format!(
                 "Here is a fragment of a document:\n{}\n\nGenerate a helpful question and a precise answer following the format:\nQuestion: ...\nAnswer: ...\nLanguage: {}.",
                 context_text,
-                config.lang
+                lang
             );
             let call_started = Instant::now();
             let response =
                 call_llm_with_retry(invoke, Some(QA_SYSTEM_PROMPT), &prompt, &doc_id, "qa")?;
             throttle_llm(config.llm_delay_ms);
             if let Some((question, answer)) = parse_qa_response(&response.content) {
+                metrics.prompt_tokens += response.prompt_tokens as u64;
+                metrics.completion_tokens += response.completion_tokens as u64;
+                let report = check_numbers(config, &answer, context_cells.iter().copied());
+                if !tally.record(config, report.as_ref(), &mut metrics) {
+                    logging::verbose(format!(
+                        "[qa] doc={} dropped answer with unsupported numbers",
+                        doc_id
+                    ));
+                    idx += 1;
+                    continue;
+                }
                 sample_counter += 1;
                 created += 1;
                 metrics.samples += 1;
-                metrics.prompt_tokens += response.prompt_tokens as u64;
-                metrics.completion_tokens += response.completion_tokens as u64;
+                let mut meta = json!({
+                    "context_chars": context_text.len(),
+                });
+                if followed_links > 0 {
+                    meta["followed_links"] = json!(followed_links);
+                }
+                annotate_faithfulness(&mut meta, report.as_ref());
                 let sample = QaSample {
                     sample_id: format!("qa_{sample_counter:06}"),
                     task: "qa".to_string(),
@@ -177,10 +207,8 @@ fn generate_qa(
                     cell_ids,
                     question,
                     answer,
-                    lang: config.lang.clone(),
-                    meta: json!({
-                        "context_chars": context_text.len(),
-                    }),
+                    lang,
+                    meta,
                 };
                 writer.write_record(&sample)?;
                 logging::stage(
@@ -203,6 +231,7 @@ fn generate_qa(
             ),
         );
     }
+    tally.finish(&mut metrics);
     Ok(metrics)
 }
 
@@ -216,6 +245,7 @@ fn generate_summary(
     let file = File::create(&summary_path).context("failed to create summary.jsonl")?;
     let mut writer = JsonlWriter::new(BufWriter::new(file));
     let mut metrics = TaskMetricsEntry::default();
+    let mut tally = FaithfulnessTally::default();
     let mut sample_counter = 0usize;
     let doc_ids = sorted_doc_ids(index);
     let total_docs = doc_ids.len();
@@ -239,9 +269,16 @@ fn generate_summary(
                 continue;
             }
             let truncated = clamp_text(&section.text, SUMMARY_MAX_CONTEXT_CHARS);
+            let lang = sample_lang(
+                config,
+                section
+                    .cell_ids
+                    .iter()
+                    .filter_map(|cell_id| index.lookup_cell(cell_id)),
+            );
             let prompt = format!(
                 "Write a concise summary of the document section. Language: {}.\n\nHeading: {}\n\n{}",
-                config.lang,
+                lang,
                 section.title.as_deref().unwrap_or("(untitled)"),
                 truncated
             );
@@ -264,11 +301,31 @@ fn generate_summary(
             if summary.is_empty() {
                 continue;
             }
+            metrics.prompt_tokens += response.prompt_tokens as u64;
+            metrics.completion_tokens += response.completion_tokens as u64;
+            let report = check_numbers(
+                config,
+                summary,
+                section
+                    .cell_ids
+                    .iter()
+                    .filter_map(|cell_id| index.lookup_cell(cell_id)),
+            );
+            if !tally.record(config, report.as_ref(), &mut metrics) {
+                logging::verbose(format!(
+                    "[summary] doc={} dropped summary with unsupported numbers",
+                    doc_id
+                ));
+                continue;
+            }
             sample_counter += 1;
             created += 1;
             metrics.samples += 1;
-            metrics.prompt_tokens += response.prompt_tokens as u64;
-            metrics.completion_tokens += response.completion_tokens as u64;
+            let mut meta = json!({
+                "context_chars": section.text.chars().count(),
+                "truncated": section.text.chars().count() > SUMMARY_MAX_CONTEXT_CHARS,
+            });
+            annotate_faithfulness(&mut meta, report.as_ref());
             let sample = SummarySample {
                 sample_id: format!("summary_{sample_counter:06}"),
                 task: "summary".to_string(),
@@ -276,11 +333,8 @@ fn generate_summary(
                 cell_ids: section.cell_ids,
                 title: section.title,
                 summary: summary.to_string(),
-                lang: config.lang.clone(),
-                meta: json!({
-                    "context_chars": section.text.chars().count(),
-                    "truncated": section.text.chars().count() > SUMMARY_MAX_CONTEXT_CHARS,
-                }),
+                lang,
+                meta,
             };
             writer.write_record(&sample)?;
             logging::stage(
@@ -301,15 +355,129 @@ fn generate_summary(
             ),
         );
     }
+    tally.finish(&mut metrics);
     Ok(metrics)
 }
 
+/// Language to generate in: the configured one, or with `DOC2DATASET_LANG=auto`
+/// the dominant language of the source cells.
+fn sample_lang<'a>(
+    config: &Doc2DatasetConfig,
+    cells: impl IntoIterator<Item = &'a IndexCellRecord>,
+) -> String {
+    if !config.uses_source_lang() {
+        return config.lang.clone();
+    }
+    let mut tally = LanguageTally::default();
+    for cell in cells {
+        if let Some(lang) = cell.lang() {
+            tally.add(lang, cell.text.chars().count());
+        }
+    }
+    tally
+        .dominant()
+        .unwrap_or_else(|| FALLBACK_LANG.to_string())
+}
+
+/// Checks `text` against the numbers its context cells vouch for.
+fn check_numbers<'a>(
+    config: &Doc2DatasetConfig,
+    text: &str,
+    cells: impl IntoIterator<Item = &'a IndexCellRecord>,
+) -> Option<FaithfulnessReport> {
+    if config.numeric_check == FaithfulnessPolicy::Off {
+        return None;
+    }
+    let sources: Vec<_> = cells
+        .into_iter()
+        .flat_map(IndexCellRecord::numeric_entities)
+        .collect();
+    Some(NumericVerifier::new().verify(text, &sources))
+}
+
+fn annotate_faithfulness(meta: &mut Value, report: Option<&FaithfulnessReport>) {
+    let (Some(report), Some(map)) = (report, meta.as_object_mut()) else {
+        return;
+    };
+    map.insert("numeric_faithfulness".to_string(), json!(report.score()));
+    map.insert(
+        "unsupported_numbers".to_string(),
+        json!(report.unsupported_raw()),
+    );
+}
+
+#[derive(Default)]
+struct FaithfulnessTally {
+    score_sum: f64,
+    checked: usize,
+}
+
+impl FaithfulnessTally {
+    /// Records a checked response and returns whether the sample should be kept.
+    fn record(
+        &mut self,
+        config: &Doc2DatasetConfig,
+        report: Option<&FaithfulnessReport>,
+        metrics: &mut TaskMetricsEntry,
+    ) -> bool {
+        let Some(report) = report else {
+            return true;
+        };
+        self.score_sum += report.score() as f64;
+        self.checked += 1;
+        metrics.unsupported_numbers += report.unsupported().count() as u64;
+        if config.numeric_check == FaithfulnessPolicy::Drop && !report.is_faithful() {
+            metrics.dropped_unfaithful += 1;
+            return false;
+        }
+        true
+    }
+
+    fn finish(self, metrics: &mut TaskMetricsEntry) {
+        if self.checked > 0 {
+            metrics.numeric_faithfulness = Some((self.score_sum / self.checked as f64) as f32);
+        }
+    }
+}
+
 fn sorted_doc_ids(index: &DatasetIndex) -> Vec<String> {
     let mut doc_ids: Vec<String> = index.documents.keys().cloned().collect();
     doc_ids.sort();
     doc_ids
 }
 
+/// Footnotes and cross-referenced cells the context points at, so answers can
+/// draw on them; hyperlinks leave the document and are not followed.
+fn linked_cells<'a>(
+    index: &'a DatasetIndex,
+    context: &[&IndexCellRecord],
+) -> Vec<&'a IndexCellRecord> {
+    let mut seen: HashSet<&str> = context.iter().map(|cell| cell.cell_id.as_str()).collect();
+    let mut budget = QA_MAX_CONTEXT_CHARS.saturating_sub(
+        context
+            .iter()
+            .map(|cell| cell.text.trim().len())
+            .sum::<usize>(),
+    );
+    let mut linked = Vec::new();
+    for link in context.iter().flat_map(|cell| cell.links()) {
+        let Some(target) = link
+            .cell_id
+            .as_deref()
+            .and_then(|cell_id| index.lookup_cell(cell_id))
+        else {
+            continue;
+        };
+        let text = target.text.trim();
+        if text.is_empty() || text.len() > budget || !seen.insert(target.cell_id.as_str()) {
+            continue;
+        }
+        budget -= text.len();
+        linked.push(target);
+    }
+    linked
+}
+
 fn is_textual_cell(kind: &str) -> bool {
     matches!(kind, "text" | "heading")
 }
@@ -320,9 +488,21 @@ fn parse_qa_response(raw: &str) -> Option<(String, String)> {
     for line in raw.lines() {
         let lower = line.to_lowercase();
         if lower.starts_with("question") {
-            question = Some(line.splitn(2, ':').nth(1).unwrap_or("").trim().to_string());
+            question = Some(
+                line.split_once(':')
+                    .map(|x| x.1)
+                    .unwrap_or("")
+                    .trim()
+                    .to_string(),
+            );
         } else if lower.starts_with("answer") {
-            answer = Some(line.splitn(2, ':').nth(1).unwrap_or("").trim().to_string());
+            answer = Some(
+                line.split_once(':')
+                    .map(|x| x.1)
+                    .unwrap_or("")
+                    .trim()
+                    .to_string(),
+            );
         }
     }
     if question.as_ref().map(|s| s.is_empty()).unwrap_or(true)
@@ -366,11 +546,7 @@ fn build_sections(cells: &[IndexCellRecord]) -> Vec<Section> {
             continue;
         }
         if cell.kind == "text" {
-            if current.cell_ids.is_empty() {
-                current.cell_ids.push(cell.cell_id.clone());
-            } else {
-                current.cell_ids.push(cell.cell_id.clone());
-            }
+            current.cell_ids.push(cell.cell_id.clone());
             if !cell.text.trim().is_empty() {
                 if !current.text.is_empty() {
                     current.text.push_str("\n\n");
@@ -590,6 +766,7 @@ mod tests {
             llm_delay_ms: 0,
             qa_max_per_doc: DEFAULT_QA_MAX_PER_DOC,
             summary_max_per_doc: DEFAULT_SUMMARY_MAX_PER_DOC,
+            numeric_check: FaithfulnessPolicy::Flag,
         };
         std::fs::create_dir_all(root.join("samples")).unwrap();
         let response = LlmResponse {
@@ -607,6 +784,51 @@ mod tests {
         assert_eq!(sample.answer, "A");
     }
 
+    #[test]
+    fn generate_qa_follows_footnote_links() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let mut body = long_cell("doc", "doc_cell_1", "Revenue grew strongly1 ".repeat(5));
+        body.meta = json!({
+            "links": [{ "start": 21, "end": 22, "kind": "footnote", "cell_id": "doc_cell_9" }]
+        });
+        let filler = long_cell("doc", "doc_cell_2", "Unrelated body text ".repeat(5));
+        let note = long_cell(
+            "doc",
+            "doc_cell_9",
+            "1 Excludes the divested unit.".to_string(),
+        );
+        let index = sample_index("doc", vec![body, filler, note]);
+        let config = Doc2DatasetConfig {
+            provider: LlmProvider::OpenAi,
+            model: "stub".into(),
+            lang: "en".into(),
+            llm_delay_ms: 0,
+            qa_max_per_doc: 1,
+            summary_max_per_doc: DEFAULT_SUMMARY_MAX_PER_DOC,
+            numeric_check: FaithfulnessPolicy::Flag,
+        };
+        std::fs::create_dir_all(root.join("samples")).unwrap();
+        let prompts = std::cell::RefCell::new(Vec::new());
+        let invoke = |_: Option<&str>, prompt: &str| {
+            prompts.borrow_mut().push(prompt.to_string());
+            Ok(LlmResponse {
+                content: "Question: Q\nAnswer: A".to_string(),
+                prompt_tokens: 1,
+                completion_tokens: 1,
+            })
+        };
+        generate_qa(root, &index, &config, &invoke).unwrap();
+        assert!(prompts.borrow()[0].contains("Excludes the divested unit."));
+        let contents = std::fs::read_to_string(root.join("samples/qa.jsonl")).unwrap();
+        let sample: QaSample = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(
+            sample.cell_ids,
+            vec!["doc_cell_1", "doc_cell_2", "doc_cell_9"]
+        );
+        assert_eq!(sample.meta["followed_links"], 1);
+    }
+
     #[test]
     fn generate_summary_writes_samples_with_stub_llm() {
         let dir = tempdir().unwrap();
@@ -623,6 +845,7 @@ mod tests {
             llm_delay_ms: 0,
             qa_max_per_doc: DEFAULT_QA_MAX_PER_DOC,
             summary_max_per_doc: DEFAULT_SUMMARY_MAX_PER_DOC,
+            numeric_check: FaithfulnessPolicy::Flag,
         };
         std::fs::create_dir_all(root.join("samples")).unwrap();
         let response = LlmResponse {
@@ -639,6 +862,44 @@ mod tests {
         assert_eq!(sample.summary, "Brief summary");
     }
 
+    #[test]
+    fn generate_qa_drops_answers_with_unsupported_numbers() {
+        let dir = tempdir().unwrap();
+        let root = dir.path();
+        let text = format!(
+            "{} Revenue grew to $4.2 million in 2023.",
+            "Context ".repeat(12)
+        );
+        let index = sample_index("doc", vec![long_cell("doc", "doc_cell_1", text)]);
+        let mut config = Doc2DatasetConfig {
+            provider: LlmProvider::OpenAi,
+            model: "stub".into(),
+            lang: "en".into(),
+            llm_delay_ms: 0,
+            qa_max_per_doc: DEFAULT_QA_MAX_PER_DOC,
+            summary_max_per_doc: DEFAULT_SUMMARY_MAX_PER_DOC,
+            numeric_check: FaithfulnessPolicy::Flag,
+        };
+        std::fs::create_dir_all(root.join("samples")).unwrap();
+        let response = LlmResponse {
+            content: "Question: Revenue?\nAnswer: $4.2M in 2023, up 17%".to_string(),
+            prompt_tokens: 10,
+            completion_tokens: 5,
+        };
+        let metrics = generate_qa(root, &index, &config, &|_, _| Ok(response.clone())).unwrap();
+        assert_eq!(metrics.samples, 1);
+        assert_eq!(metrics.unsupported_numbers, 1);
+        let contents = std::fs::read_to_string(root.join("samples/qa.jsonl")).unwrap();
+        let sample: QaSample = serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(sample.meta["unsupported_numbers"], json!(["17%"]));
+
+        config.numeric_check = FaithfulnessPolicy::Drop;
+        let metrics = generate_qa(root, &index, &config, &|_, _| Ok(response.clone())).unwrap();
+        assert_eq!(metrics.samples, 0);
+        assert_eq!(metrics.dropped_unfaithful, 1);
+        assert!((metrics.numeric_faithfulness.unwrap() - 2.0 / 3.0).abs() < 1e-6);
+    }
+
     #[test]
     fn build_rag_samples_creates_file() {
         let dir = tempdir().unwrap();
@@ -669,6 +930,28 @@ mod tests {
         assert!(rag.context.contains("Context"));
     }
 
+    #[test]
+    fn auto_lang_follows_source_cells() {
+        let mut config = Doc2DatasetConfig {
+            provider: LlmProvider::OpenAi,
+            model: "stub".into(),
+            lang: "auto".into(),
+            llm_delay_ms: 0,
+            qa_max_per_doc: DEFAULT_QA_MAX_PER_DOC,
+            summary_max_per_doc: DEFAULT_SUMMARY_MAX_PER_DOC,
+            numeric_check: FaithfulnessPolicy::Flag,
+        };
+        let mut german = long_cell("doc", "c1", "Der Bericht zeigt Wachstum.".repeat(3));
+        german.meta = json!({ "lang": "de" });
+        let mut english = long_cell("doc", "c2", "Short note".to_string());
+        english.meta = json!({ "lang": "en" });
+        let unknown = long_cell("doc", "c3", "42".to_string());
+        assert_eq!(sample_lang(&config, [&german, &english, &unknown]), "de");
+        assert_eq!(sample_lang(&config, [&unknown]), FALLBACK_LANG);
+        config.lang = "ru".into();
+        assert_eq!(sample_lang(&config, [&german]), "ru");
+    }
+
     fn sample_index(doc_id: &str, cells: Vec<IndexCellRecord>) -> DatasetIndex {
         let mut index = DatasetIndex::default();
         let doc = DocumentRecord {
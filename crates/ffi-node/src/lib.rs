@@ -4,7 +4,7 @@ use napi::bindgen_prelude::*;
 use napi_derive::napi;
 use thiserror::Error;
 use three_dcf_core::{
-    estimate_tokens, CellRecord, Decoder, Document, Encoder, Metrics, Stats as CoreStats,
+    estimate_tokens, CellRecord, Decoder, Document, Encoder, Metrics, Selector, Stats as CoreStats,
     TextSerializer, TextSerializerConfig, TokenizerKind,
 };
 
@@ -70,8 +70,15 @@ fn tokenizer_from(spec: Option<&str>) -> Result<TokenizerKind, FfiError> {
     }
 }
 
-fn decode_internal(document_path: &Path, page: Option<u32>) -> Result<String, FfiError> {
-    let document = Document::load_bin(document_path)?;
+fn decode_internal(
+    document_path: &Path,
+    page: Option<u32>,
+    select: Option<&str>,
+) -> Result<String, FfiError> {
+    let mut document = Document::load_bin(document_path)?;
+    if let Some(expr) = select {
+        document.retain_selected(&Selector::parse(expr)?);
+    }
     if let Some(z) = page {
         Ok(document.decode_page_to_text(z))
     } else {
@@ -190,8 +197,40 @@ pub fn encode_file(
 }
 
 #[napi]
-pub fn decode_text(document: String, page: Option<u32>) -> napi::Result<String> {
-    decode_internal(Path::new(&document), page).map_err(Error::from)
+pub fn decode_text(
+    document: String,
+    page: Option<u32>,
+    select: Option<String>,
+) -> napi::Result<String> {
+    decode_internal(Path::new(&document), page, select.as_deref()).map_err(Error::from)
+}
+
+/// Cells of a `.3dcf` matching a selector expression, in reading order.
+#[napi]
+pub fn select_cells(document: String, selector: String) -> napi::Result<Vec<EncodedCell>> {
+    let doc = Document::load_bin(Path::new(&document))
+        .map_err(FfiError::from)
+        .map_err(Error::from)?;
+    let selector = Selector::parse(&selector)
+        .map_err(FfiError::from)
+        .map_err(Error::from)?;
+    let mut rows = Vec::new();
+    for cell in doc.select_cells(&selector) {
+        if let Some(payload) = doc.payload_for(&cell.code_id) {
+            rows.push(EncodedCell {
+                text: payload.to_string(),
+                importance: cell.importance,
+                page: cell.z,
+                bbox: CellBbox {
+                    x: cell.x,
+                    y: cell.y,
+                    w: cell.w,
+                    h: cell.h,
+                },
+            });
+        }
+    }
+    Ok(rows)
 }
 
 #[napi]
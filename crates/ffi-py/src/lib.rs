@@ -5,7 +5,7 @@ use pyo3::prelude::*;
 use pyo3::types::PyDict;
 use thiserror::Error;
 use three_dcf_core::{
-    estimate_tokens, CellRecord, Decoder, Document, Encoder, Metrics, Stats as CoreStats,
+    estimate_tokens, CellRecord, Decoder, Document, Encoder, Metrics, Selector, Stats as CoreStats,
     TextSerializer, TextSerializerConfig, TokenizerKind,
 };
 
@@ -69,8 +69,15 @@ fn tokenizer_from(spec: Option<&str>) -> Result<TokenizerKind, FfiError> {
     }
 }
 
-fn decode_internal(document: &Path, page: Option<u32>) -> Result<String, FfiError> {
-    let document = Document::load_bin(document)?;
+fn decode_internal(
+    document: &Path,
+    page: Option<u32>,
+    select: Option<&str>,
+) -> Result<String, FfiError> {
+    let mut document = Document::load_bin(document)?;
+    if let Some(expr) = select {
+        document.retain_selected(&Selector::parse(expr)?);
+    }
     if let Some(z) = page {
         Ok(document.decode_page_to_text(z))
     } else {
@@ -205,8 +212,32 @@ fn encode(
 }
 
 #[pyfunction]
-fn decode_text(document: &str, page: Option<u32>) -> PyResult<String> {
-    decode_internal(Path::new(document), page).map_err(PyErr::from)
+fn decode_text(document: &str, page: Option<u32>, select: Option<&str>) -> PyResult<String> {
+    decode_internal(Path::new(document), page, select).map_err(PyErr::from)
+}
+
+/// Cells of a `.3dcf` matching a selector expression, in reading order.
+#[pyfunction]
+fn select_cells(py: Python<'_>, document: &str, selector: &str) -> PyResult<Vec<PyCell>> {
+    let doc = Document::load_bin(Path::new(document))
+        .map_err(FfiError::from)
+        .map_err(PyErr::from)?;
+    let selector = Selector::parse(selector)
+        .map_err(FfiError::from)
+        .map_err(PyErr::from)?;
+    let mut cells = Vec::new();
+    for cell in doc.select_cells(&selector) {
+        if let Some(payload) = doc.payload_for(&cell.code_id) {
+            let bbox = cell_bbox_dict(py, &cell)?;
+            cells.push(PyCell {
+                text: payload.to_string(),
+                importance: cell.importance,
+                page: cell.z,
+                bbox,
+            });
+        }
+    }
+    Ok(cells)
 }
 
 #[pyfunction]
@@ -317,6 +348,7 @@ fn three_dcf_py(_py: Python, m: &PyModule) -> PyResult<()> {
     m.add_function(wrap_pyfunction!(stats, m)?)?;
     m.add_function(wrap_pyfunction!(encode_to_context, m)?)?;
     m.add_function(wrap_pyfunction!(encode_to_cells, m)?)?;
+    m.add_function(wrap_pyfunction!(select_cells, m)?)?;
     m.add_class::<StatsResult>()?;
     m.add_class::<PyContextResult>()?;
     m.add_class::<PyCell>()?;
@@ -26,6 +26,7 @@ impl LlmProvider {
         }
     }
 
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(value: &str) -> Option<Self> {
         match value.to_lowercase().as_str() {
             "openai" => Some(LlmProvider::OpenAi),
@@ -6,8 +6,13 @@ pub mod sensitivity;
 pub mod store;
 
 pub use embedding::{EmbeddingBackend, EmbeddingClient};
-pub use pipeline::{execute_rag_query, RagAnswer, RagMetrics, RagQuery, RagUsedCell};
+pub use pipeline::{
+    execute_rag_query, RagAnswer, RagMetrics, RagQuery, RagUsedCell, UNVERIFIED_MARKER,
+};
 pub use pricing::{PricingConfig, PricingEntry, PricingRate};
-pub use sensitivity::{normalize_level, sensitivity_rank};
+pub use sensitivity::{
+    normalize_level, sensitivity_rank, RuleScope, SensitivityClassifier, SensitivityRule,
+    SensitivityRules,
+};
 pub use store::{CellInsert, DocumentInsert, RagPolicy, RagStore, ScoredCell, SearchFilters};
 pub use three_dcf_llm::{LlmClient, LlmProvider, LlmRequest, LlmResponse};
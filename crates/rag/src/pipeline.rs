@@ -3,8 +3,14 @@ use crate::encryption::decrypt_text;
 use crate::store::{RagPolicy, RagStore, ScoredCell, SearchFilters};
 use crate::{LlmClient, LlmRequest, LlmResponse};
 use anyhow::{anyhow, Result};
+use std::collections::HashSet;
 use std::path::Path;
-use three_dcf_core::{estimate_tokens, TokenizerKind};
+use three_dcf_core::{
+    estimate_tokens, parent_window, FaithfulnessPolicy, NumericVerifier, TokenizerKind,
+};
+
+/// Replaces numbers the used cells do not support under [`FaithfulnessPolicy::Drop`].
+pub const UNVERIFIED_MARKER: &str = "[unverified]";
 
 pub struct RagQuery {
     pub collection: String,
@@ -14,6 +20,11 @@ pub struct RagQuery {
     pub policy: RagPolicy,
     pub tokenizer: TokenizerKind,
     pub tokenizer_name: String,
+    /// How numbers in the answer that no used cell supports are handled.
+    pub numeric_check: FaithfulnessPolicy,
+    /// Replace each hit with its section, cut to this many tokens around the
+    /// hit. Hits whose section is already in the context are dropped.
+    pub expand_parent_tokens: Option<usize>,
 }
 
 pub struct RagAnswer {
@@ -39,6 +50,10 @@ pub struct RagMetrics {
     pub raw_tokens_estimate: u32,
     pub compressed_tokens_estimate: u32,
     pub compression_factor: f32,
+    /// Share of numbers in the answer found in the used cells (`1.0` when none).
+    pub numeric_faithfulness: f32,
+    /// Numbers in the answer that no used cell supports, as written by the model.
+    pub unsupported_numbers: Vec<String>,
 }
 
 pub fn execute_rag_query(
@@ -60,9 +75,19 @@ pub fn execute_rag_query(
     }
     let mut used_cells = Vec::new();
     let mut context = String::new();
+    let mut expanded = HashSet::new();
     context.push_str("You are a helpful assistant. Answer only with the information in CONTEXT.\n\n=== CONTEXT START ===\n");
     for cell in &cells {
-        if let Some(text) = resolve_cell_text(cell, decrypt_identity)? {
+        let text = match (query.expand_parent_tokens, &cell.parent_id) {
+            (Some(budget), Some(parent_id)) => {
+                if !expanded.insert((cell.document_id, parent_id.clone())) {
+                    continue;
+                }
+                expand_to_section(store, cell, parent_id, budget, &filters, decrypt_identity)?
+            }
+            _ => resolve_cell_text(cell, decrypt_identity)?,
+        };
+        if let Some(text) = text {
             context.push_str(&format!(
                 "[DOC: {}, page {}]\n{}\n\n",
                 cell.document_source, cell.page, text
@@ -92,6 +117,18 @@ pub fn execute_rag_query(
         &query.tokenizer,
     )? as u32;
     let compressed_tokens = tokenizer.encode_with_special_tokens(prompt.as_str()).len() as u32;
+    let mut answer = response.content.clone();
+    let (numeric_faithfulness, unsupported_numbers) = match query.numeric_check {
+        FaithfulnessPolicy::Off => (1.0, Vec::new()),
+        policy => {
+            let report = NumericVerifier::new()
+                .verify_against_text(&answer, used_cells.iter().map(|cell| cell.text.as_str()));
+            if policy == FaithfulnessPolicy::Drop {
+                answer = report.redact(&answer, UNVERIFIED_MARKER);
+            }
+            (report.score(), report.unsupported_raw())
+        }
+    };
     let metrics = RagMetrics {
         prompt_tokens: response.prompt_tokens,
         completion_tokens: response.completion_tokens,
@@ -102,9 +139,11 @@ pub fn execute_rag_query(
         } else {
             raw_tokens as f32 / compressed_tokens as f32
         },
+        numeric_faithfulness,
+        unsupported_numbers,
     };
     Ok(RagAnswer {
-        answer: response.content.clone(),
+        answer,
         used_cells,
         metrics,
         response,
@@ -112,6 +151,27 @@ pub fn execute_rag_query(
     })
 }
 
+/// The hit's section, read in order and cut to `budget` tokens around the hit.
+fn expand_to_section(
+    store: &RagStore,
+    hit: &ScoredCell,
+    parent_id: &str,
+    budget: usize,
+    filters: &SearchFilters,
+    identity: Option<&Path>,
+) -> Result<Option<String>> {
+    let Some(focus) = resolve_cell_text(hit, identity)? else {
+        return Ok(None);
+    };
+    let mut section = Vec::new();
+    for cell in store.section_cells(hit.document_id, parent_id, filters)? {
+        if let Some(text) = resolve_cell_text(&cell, identity)? {
+            section.push(text);
+        }
+    }
+    Ok(Some(parent_window(&section.join("\n"), &focus, budget)))
+}
+
 fn resolve_cell_text(cell: &ScoredCell, identity: Option<&Path>) -> Result<Option<String>> {
     if let Some(text) = &cell.text {
         return Ok(Some(text.clone()));
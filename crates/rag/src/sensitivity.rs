@@ -1,5 +1,11 @@
+use anyhow::{anyhow, Context, Result};
 use once_cell::sync::Lazy;
-use std::collections::HashMap;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use three_dcf_core::{CellRecord, CellType, Document, PiiKind, RedactionConfig, Redactor};
 
 static LEVELS: [&str; 4] = ["public", "internal", "confidential", "restricted"];
 
@@ -32,3 +38,426 @@ pub fn allowed(level: &str, threshold: &str) -> bool {
 pub fn levels() -> &'static [&'static str] {
     &LEVELS
 }
+
+/// Level of every cell that no rule raises.
+pub const DEFAULT_LEVEL: &str = "public";
+
+/// How far a matching rule reaches.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleScope {
+    /// Only the matching cell.
+    #[default]
+    Cell,
+    /// Every cell on the matching cell's page.
+    Page,
+    /// Every cell in the document.
+    Document,
+    /// Every cell in the document when the matching cell is a running header
+    /// or footer, i.e. its text (ignoring digits) recurs on another page;
+    /// otherwise the matching cell's page.
+    Running,
+}
+
+/// Raises cells to `level` when one of `keywords` (case-insensitive, whole
+/// words) or `pattern` (regex) matches.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SensitivityRule {
+    pub level: String,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// Cell types the rule looks at (`text`, `table`, `header`, `footer`, `figure`); empty means all.
+    #[serde(default)]
+    pub cell_types: Vec<String>,
+    #[serde(default)]
+    pub scope: RuleScope,
+}
+
+/// Sensitivity rules, loaded from TOML or JSON.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SensitivityRules {
+    pub default: String,
+    /// Keep the built-in header/footer markers and PII levels.
+    pub builtin: bool,
+    pub rules: Vec<SensitivityRule>,
+    /// Level per PII kind (`email = "internal"`); overrides the built-in levels.
+    pub pii: HashMap<String, String>,
+    /// Person names that count as PII.
+    pub names: Vec<String>,
+    pub gazetteer: Option<PathBuf>,
+}
+
+impl Default for SensitivityRules {
+    fn default() -> Self {
+        Self {
+            default: DEFAULT_LEVEL.to_string(),
+            builtin: true,
+            rules: Vec::new(),
+            pii: HashMap::new(),
+            names: Vec::new(),
+            gazetteer: None,
+        }
+    }
+}
+
+impl SensitivityRules {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let raw = fs::read_to_string(path)
+            .with_context(|| format!("failed to read sensitivity rules {}", path.display()))?;
+        if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+            Ok(serde_json::from_str(&raw)?)
+        } else {
+            toml::from_str(&raw)
+                .with_context(|| format!("invalid sensitivity rules {}", path.display()))
+        }
+    }
+}
+
+/// "Confidential" stamps live in running headers and footers and mark the
+/// whole document. A one-off mention (a prose line the normalizer took for a
+/// footer) only marks its page.
+fn builtin_rules() -> Vec<SensitivityRule> {
+    let marker = |level: &str, keywords: &[&str]| SensitivityRule {
+        level: level.to_string(),
+        keywords: keywords.iter().map(|k| k.to_string()).collect(),
+        pattern: None,
+        cell_types: vec!["header".to_string(), "footer".to_string()],
+        scope: RuleScope::Running,
+    };
+    vec![
+        marker(
+            "restricted",
+            &["strictly confidential", "restricted", "top secret"],
+        ),
+        marker("confidential", &["confidential"]),
+        marker(
+            "internal",
+            &["internal use only", "internal only", "for internal use"],
+        ),
+    ]
+}
+
+fn builtin_pii_level(kind: PiiKind) -> &'static str {
+    match kind {
+        PiiKind::NationalId | PiiKind::CreditCard | PiiKind::Iban => "restricted",
+        _ => "confidential",
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CompiledRule {
+    rank: u8,
+    matcher: Regex,
+    cell_types: Vec<CellType>,
+    scope: RuleScope,
+}
+
+impl CompiledRule {
+    fn new(rule: &SensitivityRule) -> Result<Self> {
+        let mut alternatives: Vec<String> = rule
+            .keywords
+            .iter()
+            .map(|keyword| keyword.trim())
+            .filter(|keyword| !keyword.is_empty())
+            .map(|keyword| format!(r"(?i:\b{}\b)", regex::escape(keyword).replace(' ', r"\s+")))
+            .collect();
+        if let Some(pattern) = &rule.pattern {
+            alternatives.push(format!("(?:{pattern})"));
+        }
+        if alternatives.is_empty() {
+            return Err(anyhow!(
+                "sensitivity rule for '{}' needs keywords or a pattern",
+                rule.level
+            ));
+        }
+        let matcher = Regex::new(&alternatives.join("|"))
+            .with_context(|| format!("invalid sensitivity pattern for '{}'", rule.level))?;
+        let cell_types = rule
+            .cell_types
+            .iter()
+            .map(|name| parse_cell_type(name))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            rank: checked_rank(&rule.level)?,
+            matcher,
+            cell_types,
+            scope: rule.scope,
+        })
+    }
+
+    fn matches(&self, cell_type: CellType, text: &str) -> bool {
+        (self.cell_types.is_empty() || self.cell_types.contains(&cell_type))
+            && self.matcher.is_match(text)
+    }
+}
+
+/// Assigns a sensitivity level to every cell from keyword/regex rules,
+/// header/footer markers, and PII found in (or already redacted from) the cell.
+#[derive(Debug, Clone)]
+pub struct SensitivityClassifier {
+    default_rank: u8,
+    rules: Vec<CompiledRule>,
+    pii: HashMap<PiiKind, u8>,
+    /// `None` when no PII kind carries a level.
+    detector: Option<Redactor>,
+}
+
+impl SensitivityClassifier {
+    pub fn new(config: SensitivityRules) -> Result<Self> {
+        let mut rules = Vec::new();
+        if config.builtin {
+            rules.extend(builtin_rules());
+        }
+        rules.extend(config.rules.iter().cloned());
+        let rules = rules
+            .iter()
+            .map(CompiledRule::new)
+            .collect::<Result<Vec<_>>>()?;
+        let mut pii = HashMap::new();
+        if config.builtin {
+            for kind in PiiKind::ALL {
+                pii.insert(kind, sensitivity_rank(builtin_pii_level(kind)));
+            }
+        }
+        for (kind, level) in &config.pii {
+            pii.insert(kind.parse::<PiiKind>()?, checked_rank(level)?);
+        }
+        let detector = if pii.is_empty() {
+            None
+        } else {
+            Some(Redactor::new(RedactionConfig {
+                kinds: pii.keys().copied().collect(),
+                names: config.names.clone(),
+                gazetteer: config.gazetteer.clone(),
+                ..RedactionConfig::default()
+            })?)
+        };
+        Ok(Self {
+            default_rank: checked_rank(&config.default)?,
+            rules,
+            pii,
+            detector,
+        })
+    }
+
+    /// Levels for `doc.ordered_cells()`, in the same order.
+    pub fn classify_document(&self, doc: &Document) -> Vec<String> {
+        let cells = doc.ordered_cells();
+        let mut redacted: HashMap<(u32, i32, i32), Vec<PiiKind>> = HashMap::new();
+        for redaction in &doc.redactions {
+            redacted
+                .entry((redaction.z, redaction.x, redaction.y))
+                .or_default()
+                .push(redaction.pii.kind);
+        }
+        let running = running_margins(doc, &cells);
+        let mut ranks = vec![self.default_rank; cells.len()];
+        let mut page_floor: HashMap<u32, u8> = HashMap::new();
+        let mut document_floor = self.default_rank;
+        for (rank, cell) in ranks.iter_mut().zip(&cells) {
+            let text = doc.payload_for(&cell.code_id).unwrap_or("");
+            for rule in self
+                .rules
+                .iter()
+                .filter(|rule| rule.matches(cell.cell_type, text))
+            {
+                let scope = match rule.scope {
+                    RuleScope::Running if running.contains(&margin_key(text)) => {
+                        RuleScope::Document
+                    }
+                    RuleScope::Running => RuleScope::Page,
+                    scope => scope,
+                };
+                match scope {
+                    RuleScope::Cell => *rank = (*rank).max(rule.rank),
+                    RuleScope::Page | RuleScope::Running => {
+                        let floor = page_floor.entry(cell.z).or_insert(rule.rank);
+                        *floor = (*floor).max(rule.rank);
+                    }
+                    RuleScope::Document => document_floor = document_floor.max(rule.rank),
+                }
+            }
+            let found = self
+                .detector
+                .iter()
+                .flat_map(|detector| detector.detect(text))
+                .map(|pii| pii.kind);
+            let earlier = redacted
+                .get(&(cell.z, cell.x, cell.y))
+                .into_iter()
+                .flatten()
+                .copied();
+            for kind in found.chain(earlier) {
+                if let Some(level) = self.pii.get(&kind) {
+                    *rank = (*rank).max(*level);
+                }
+            }
+        }
+        cells
+            .iter()
+            .zip(ranks)
+            .map(|(cell, rank)| {
+                let page = page_floor.get(&cell.z).copied().unwrap_or(0);
+                LEVELS[rank.max(page).max(document_floor) as usize].to_string()
+            })
+            .collect()
+    }
+}
+
+/// Header and footer texts that appear on more than one page.
+fn running_margins(doc: &Document, cells: &[CellRecord]) -> HashSet<String> {
+    let mut pages: HashMap<String, HashSet<u32>> = HashMap::new();
+    for cell in cells
+        .iter()
+        .filter(|cell| matches!(cell.cell_type, CellType::Header | CellType::Footer))
+    {
+        let text = doc.payload_for(&cell.code_id).unwrap_or("");
+        pages.entry(margin_key(text)).or_default().insert(cell.z);
+    }
+    pages
+        .into_iter()
+        .filter(|(_, pages)| pages.len() > 1)
+        .map(|(key, _)| key)
+        .collect()
+}
+
+/// Page numbers change from page to page; the rest of a running line does not.
+fn margin_key(text: &str) -> String {
+    text.split_whitespace()
+        .map(|word| word.replace(|c: char| c.is_ascii_digit(), ""))
+        .filter(|word| !word.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+fn checked_rank(level: &str) -> Result<u8> {
+    let lower = level.trim().to_lowercase();
+    RANKS.get(lower.as_str()).copied().ok_or_else(|| {
+        anyhow!(
+            "unknown sensitivity level '{level}' (expected {})",
+            LEVELS.join(", ")
+        )
+    })
+}
+
+fn parse_cell_type(name: &str) -> Result<CellType> {
+    match name.trim().to_lowercase().as_str() {
+        "text" => Ok(CellType::Text),
+        "table" => Ok(CellType::Table),
+        "figure" => Ok(CellType::Figure),
+        "footer" => Ok(CellType::Footer),
+        "header" | "heading" => Ok(CellType::Header),
+        "equation" => Ok(CellType::Equation),
+        other => Err(anyhow!("unknown cell type '{other}' in sensitivity rule")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use three_dcf_core::{hash_payload, Header};
+
+    fn document(cells: &[(u32, CellType, &str)]) -> Document {
+        let mut doc = Document::new(Header::default());
+        for (idx, (z, cell_type, text)) in cells.iter().enumerate() {
+            let code_id = hash_payload(text);
+            doc.cells.push(CellRecord {
+                z: *z,
+                x: 64,
+                y: 64 + idx as i32 * 30,
+                w: 800,
+                h: 24,
+                code_id,
+                rle: 0,
+                cell_type: *cell_type,
+                importance: 100,
+                ocr_confidence: None,
+                lang: None,
+            });
+            doc.dict.insert(code_id, text.to_string());
+        }
+        doc
+    }
+
+    #[test]
+    fn rules_markers_and_pii_raise_levels() {
+        let rules: SensitivityRules = toml::from_str(
+            r#"
+            [[rules]]
+            level = "restricted"
+            keywords = ["salary band"]
+
+            [[rules]]
+            level = "internal"
+            pattern = "(?i)roadmap"
+            scope = "page"
+            "#,
+        )
+        .unwrap();
+        let classifier = SensitivityClassifier::new(rules).unwrap();
+        let doc = document(&[
+            (0, CellType::Text, "Welcome to the handbook."),
+            (0, CellType::Text, "Questions go to hr@example.com."),
+            (1, CellType::Text, "Product roadmap for next year."),
+            (1, CellType::Text, "Each Salary Band is reviewed yearly."),
+            (2, CellType::Text, "Glossary."),
+        ]);
+        assert_eq!(
+            classifier.classify_document(&doc),
+            vec!["public", "confidential", "internal", "restricted", "public"]
+        );
+
+        let stamped = document(&[
+            (0, CellType::Text, "Welcome to the handbook."),
+            (0, CellType::Footer, "Confidential - page 1"),
+            (1, CellType::Text, "Glossary."),
+            (1, CellType::Footer, "Confidential - page 2"),
+        ]);
+        assert_eq!(
+            classifier.classify_document(&stamped),
+            vec!["confidential"; 4]
+        );
+    }
+
+    #[test]
+    fn one_off_markers_do_not_raise_the_document() {
+        let classifier = SensitivityClassifier::new(SensitivityRules::default()).unwrap();
+        let doc = document(&[
+            (0, CellType::Text, "Welcome to the handbook."),
+            (1, CellType::Footer, "See the confidential annex, page 9."),
+            (1, CellType::Text, "Annex overview."),
+            (2, CellType::Text, "Glossary."),
+        ]);
+        assert_eq!(
+            classifier.classify_document(&doc),
+            vec!["public", "confidential", "confidential", "public"]
+        );
+    }
+
+    #[test]
+    fn invalid_rules_are_rejected() {
+        let unknown_level = SensitivityRules {
+            rules: vec![SensitivityRule {
+                level: "secret".to_string(),
+                keywords: vec!["x".to_string()],
+                pattern: None,
+                cell_types: Vec::new(),
+                scope: RuleScope::Cell,
+            }],
+            ..SensitivityRules::default()
+        };
+        assert!(SensitivityClassifier::new(unknown_level).is_err());
+        let mut pii = HashMap::new();
+        pii.insert("passport".to_string(), "restricted".to_string());
+        assert!(SensitivityClassifier::new(SensitivityRules {
+            pii,
+            ..SensitivityRules::default()
+        })
+        .is_err());
+    }
+}
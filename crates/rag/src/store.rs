@@ -56,6 +56,7 @@ impl RagStore {
                 bbox_y INTEGER,
                 bbox_w INTEGER,
                 bbox_h INTEGER,
+                parent_id TEXT,
                 created_at TEXT DEFAULT CURRENT_TIMESTAMP,
                 FOREIGN KEY(document_id) REFERENCES documents(id)
             );
@@ -63,6 +64,13 @@ impl RagStore {
             CREATE INDEX IF NOT EXISTS idx_cells_document ON cells(document_id);
             "#,
         )?;
+        // Stores created before sections were recorded lack `parent_id`.
+        let has_parent: bool = conn
+            .prepare("SELECT 1 FROM pragma_table_info('cells') WHERE name = 'parent_id'")?
+            .exists([])?;
+        if !has_parent {
+            conn.execute("ALTER TABLE cells ADD COLUMN parent_id TEXT", [])?;
+        }
         Ok(())
     }
 
@@ -95,7 +103,7 @@ impl RagStore {
         for cell in cells {
             let embedding_blob = cast_slice::<f32, u8>(&cell.embedding);
             tx.execute(
-                "INSERT INTO cells (document_id, page, importance, sensitivity, text, text_encrypted, encryption, embedding, bbox_x, bbox_y, bbox_w, bbox_h) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                "INSERT INTO cells (document_id, page, importance, sensitivity, text, text_encrypted, encryption, embedding, bbox_x, bbox_y, bbox_w, bbox_h, parent_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
                 rusqlite::params![
                     document_id,
                     cell.page,
@@ -108,7 +116,8 @@ impl RagStore {
                     cell.bbox_x,
                     cell.bbox_y,
                     cell.bbox_w,
-                    cell.bbox_h
+                    cell.bbox_h,
+                    cell.parent_id
                 ],
             )?;
         }
@@ -139,7 +148,8 @@ impl RagStore {
                 cells.bbox_x,
                 cells.bbox_y,
                 cells.bbox_w,
-                cells.bbox_h
+                cells.bbox_h,
+                cells.parent_id
             FROM cells
             JOIN documents ON cells.document_id = documents.id
             JOIN collections ON documents.collection_id = collections.id
@@ -178,6 +188,7 @@ impl RagStore {
                 bbox_y: row.get(11)?,
                 bbox_w: row.get(12)?,
                 bbox_h: row.get(13)?,
+                parent_id: row.get(14)?,
                 score,
             });
         }
@@ -191,6 +202,69 @@ impl RagStore {
         }
         Ok(hits)
     }
+
+    /// Cells of one document section in insertion (reading) order, subject
+    /// to the same sensitivity and policy checks as [`RagStore::search_cells`].
+    /// Scores are zero.
+    pub fn section_cells(
+        &self,
+        document_id: i64,
+        parent_id: &str,
+        filters: &SearchFilters,
+    ) -> Result<Vec<ScoredCell>> {
+        let conn = self.connection()?;
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT
+                cells.id,
+                documents.source_path,
+                cells.page,
+                cells.importance,
+                cells.sensitivity,
+                cells.text,
+                cells.text_encrypted,
+                cells.encryption,
+                cells.bbox_x,
+                cells.bbox_y,
+                cells.bbox_w,
+                cells.bbox_h
+            FROM cells
+            JOIN documents ON cells.document_id = documents.id
+            WHERE cells.document_id = ?1 AND cells.parent_id = ?2
+            ORDER BY cells.id
+            "#,
+        )?;
+        let mut rows = stmt.query(params![document_id, parent_id])?;
+        let mut cells = Vec::new();
+        while let Some(row) = rows.next()? {
+            let sensitivity: String = row.get(4)?;
+            if !allowed(&sensitivity, &filters.sensitivity_threshold) {
+                continue;
+            }
+            let text_encrypted: Option<Vec<u8>> = row.get(6)?;
+            if filters.policy == RagPolicy::External && text_encrypted.is_some() {
+                continue;
+            }
+            cells.push(ScoredCell {
+                cell_id: row.get(0)?,
+                document_id,
+                document_source: row.get(1)?,
+                page: row.get(2)?,
+                importance: row.get::<_, i64>(3)? as u8,
+                sensitivity,
+                text: row.get(5)?,
+                text_encrypted,
+                encryption: row.get(7)?,
+                bbox_x: row.get(8)?,
+                bbox_y: row.get(9)?,
+                bbox_w: row.get(10)?,
+                bbox_h: row.get(11)?,
+                parent_id: Some(parent_id.to_string()),
+                score: 0.0,
+            });
+        }
+        Ok(cells)
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -219,6 +293,8 @@ pub struct CellInsert {
     pub bbox_y: i32,
     pub bbox_w: u32,
     pub bbox_h: u32,
+    /// Section (hierarchical parent chunk id) the cell belongs to.
+    pub parent_id: Option<String>,
 }
 
 #[derive(Debug, Clone)]
@@ -253,21 +329,17 @@ pub struct ScoredCell {
     pub bbox_y: i32,
     pub bbox_w: u32,
     pub bbox_h: u32,
+    pub parent_id: Option<String>,
     pub score: f32,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
 pub enum RagPolicy {
+    #[default]
     External,
     Internal,
 }
 
-impl Default for RagPolicy {
-    fn default() -> Self {
-        RagPolicy::External
-    }
-}
-
 fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     let mut dot = 0.0f32;
     let mut a_norm = 0.0f32;
@@ -282,3 +354,99 @@ fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
     }
     dot / (a_norm.sqrt() * b_norm.sqrt())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(text: &str, sensitivity: &str, parent_id: Option<&str>) -> CellInsert {
+        CellInsert {
+            page: 0,
+            importance: 128,
+            sensitivity: sensitivity.to_string(),
+            text: Some(text.to_string()),
+            text_encrypted: None,
+            encryption: None,
+            embedding: vec![1.0, 0.0],
+            bbox_x: 0,
+            bbox_y: 0,
+            bbox_w: 10,
+            bbox_h: 10,
+            parent_id: parent_id.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn section_cells_follow_reading_order_and_sensitivity() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RagStore::open(dir.path().join("rag.db")).unwrap();
+        let collection = store.ensure_collection("docs").unwrap();
+        let doc = store
+            .add_document(
+                collection,
+                &DocumentInsert {
+                    source_path: "report.pdf".to_string(),
+                    dcf_path: None,
+                    title: None,
+                },
+            )
+            .unwrap();
+        store
+            .add_cells(
+                doc.id,
+                &[
+                    cell("Budget", "public", Some("s1")),
+                    cell("Grew by 4%.", "public", Some("s1")),
+                    cell("Salary table", "restricted", Some("s1")),
+                    cell("Travel", "public", Some("s2")),
+                ],
+            )
+            .unwrap();
+        let hits = store
+            .search_cells("docs", &[1.0, 0.0], &SearchFilters::default())
+            .unwrap();
+        let budget = hits
+            .iter()
+            .find(|hit| hit.text.as_deref() == Some("Budget"))
+            .unwrap();
+        assert_eq!(budget.parent_id.as_deref(), Some("s1"));
+        let section = store
+            .section_cells(doc.id, "s1", &SearchFilters::default())
+            .unwrap();
+        let texts: Vec<_> = section.iter().filter_map(|c| c.text.as_deref()).collect();
+        assert_eq!(texts, vec!["Budget", "Grew by 4%."]);
+    }
+
+    #[test]
+    fn opening_an_older_store_adds_the_parent_column() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rag.db");
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch(
+                "CREATE TABLE cells (id INTEGER PRIMARY KEY, document_id INTEGER NOT NULL, \
+                 page INTEGER NOT NULL, importance INTEGER NOT NULL, \
+                 sensitivity TEXT NOT NULL DEFAULT 'public', text TEXT, text_encrypted BLOB, \
+                 encryption TEXT, embedding BLOB NOT NULL, bbox_x INTEGER, bbox_y INTEGER, \
+                 bbox_w INTEGER, bbox_h INTEGER, created_at TEXT);",
+            )
+            .unwrap();
+        RagStore::open(&path).unwrap();
+        // Opening twice must not try to add the column again.
+        let store = RagStore::open(&path).unwrap();
+        let collection = store.ensure_collection("docs").unwrap();
+        let doc = store
+            .add_document(
+                collection,
+                &DocumentInsert {
+                    source_path: "old.pdf".to_string(),
+                    dcf_path: None,
+                    title: None,
+                },
+            )
+            .unwrap();
+        store
+            .add_cells(doc.id, &[cell("Text", "public", Some("s1"))])
+            .unwrap();
+    }
+}
@@ -14,12 +14,13 @@ use tokio::task;
 use tracing::{error, info};
 
 use three_dcf_core::{
-    estimate_tokens, Encoder, Metrics, TableMode, TextSerializer, TextSerializerConfig,
-    TokenizerKind,
+    estimate_tokens, ChunkConfig, Chunker, Encoder, FaithfulnessPolicy, Metrics, RedactionConfig,
+    RedactionMode, Redactor, TableMode, TextSerializer, TextSerializerConfig, TokenizerKind,
 };
 use three_dcf_rag::{
     encryption, execute_rag_query, normalize_level, CellInsert, DocumentInsert, EmbeddingClient,
-    LlmClient, LlmProvider, PricingConfig, RagPolicy, RagQuery, RagStore,
+    LlmClient, LlmProvider, PricingConfig, RagPolicy, RagQuery, RagStore, SensitivityClassifier,
+    SensitivityRules,
 };
 
 #[derive(Clone)]
@@ -91,6 +92,8 @@ struct RagDocumentParams {
     budget: Option<usize>,
     sensitivity: Option<String>,
     encrypt: Option<bool>,
+    redact_pii: Option<bool>,
+    redact_mode: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -99,6 +102,7 @@ struct RagDocumentResponse {
     document_id: i64,
     pages: usize,
     cells_indexed: usize,
+    pii_redacted: usize,
 }
 
 #[derive(Debug, Deserialize)]
@@ -111,6 +115,8 @@ struct RagQueryRequest {
     sensitivity_threshold: Option<String>,
     policy: Option<String>,
     tokenizer: Option<String>,
+    numeric_check: Option<String>,
+    expand_parent_tokens: Option<usize>,
 }
 
 #[derive(Debug, Serialize)]
@@ -137,6 +143,8 @@ struct RagQueryMetrics {
     raw_tokens_estimate: u32,
     compressed_tokens_estimate: u32,
     compression_factor: f32,
+    numeric_faithfulness: f32,
+    unsupported_numbers: Vec<String>,
     estimated_saving_usd: Option<f64>,
 }
 
@@ -214,7 +222,7 @@ fn run_encode(
 ) -> Result<EncodeResponse, AppError> {
     let mut tmp = NamedTempFile::new().map_err(AppError::internal)?;
     std::io::Write::write_all(&mut tmp, bytes).map_err(AppError::internal)?;
-    let encoder = build_service_encoder(preset, budget)?;
+    let encoder = build_service_encoder(preset, budget, None)?;
     let (doc, mut metrics, raw_text) = encoder
         .encode_path_with_plaintext(tmp.path())
         .map_err(AppError::internal)?;
@@ -234,14 +242,45 @@ fn run_encode(
     })
 }
 
-fn build_service_encoder(preset: &str, budget: Option<usize>) -> Result<Encoder, AppError> {
+fn build_service_encoder(
+    preset: &str,
+    budget: Option<usize>,
+    redactor: Option<Redactor>,
+) -> Result<Encoder, AppError> {
     let mut builder = Encoder::builder(preset).map_err(AppError::internal)?;
     if let Some(b) = budget {
         builder = builder.budget(Some(b));
     }
+    if let Some(redactor) = redactor {
+        builder = builder.redactor(redactor);
+    }
     Ok(builder.build())
 }
 
+/// Rules come from `RAG_SENSITIVITY_RULES` (TOML or JSON) when set, otherwise the built-ins.
+fn service_classifier() -> Result<SensitivityClassifier, AppError> {
+    let rules = match std::env::var("RAG_SENSITIVITY_RULES") {
+        Ok(path) => SensitivityRules::load(path).map_err(AppError::internal)?,
+        Err(_) => SensitivityRules::default(),
+    };
+    SensitivityClassifier::new(rules).map_err(AppError::internal)
+}
+
+fn service_redactor(params: &RagDocumentParams) -> Result<Option<Redactor>, AppError> {
+    if !params.redact_pii.unwrap_or(false) {
+        return Ok(None);
+    }
+    let mode = match params.redact_mode.as_deref() {
+        Some(value) => value
+            .parse::<RedactionMode>()
+            .map_err(AppError::bad_request)?,
+        None => RedactionMode::default(),
+    };
+    Redactor::new(RedactionConfig::with_mode(mode))
+        .map(Some)
+        .map_err(AppError::internal)
+}
+
 fn resolve_tokenizer(name: &str) -> Result<TokenizerKind, AppError> {
     match name.to_lowercase().as_str() {
         "cl100k" | "cl100k_base" => Ok(TokenizerKind::Cl100k),
@@ -289,8 +328,11 @@ fn ingest_document(
         .map_err(AppError::internal)?;
     let mut tmp = NamedTempFile::new().map_err(AppError::internal)?;
     std::io::Write::write_all(&mut tmp, &upload.data).map_err(AppError::internal)?;
-    let encoder =
-        build_service_encoder(params.preset.as_deref().unwrap_or("reports"), params.budget)?;
+    let encoder = build_service_encoder(
+        params.preset.as_deref().unwrap_or("reports"),
+        params.budget,
+        service_redactor(params)?,
+    )?;
     let (doc, _) = encoder
         .encode_path(tmp.path())
         .map_err(AppError::internal)?;
@@ -309,7 +351,10 @@ fn ingest_document(
             },
         )
         .map_err(AppError::internal)?;
-    let sensitivity = normalize_level(params.sensitivity.as_deref().unwrap_or("public"));
+    let levels = match params.sensitivity.as_deref() {
+        Some(level) => vec![normalize_level(level); ordered.len()],
+        None => service_classifier()?.classify_document(&doc),
+    };
     let recipient = if params.encrypt.unwrap_or(false) {
         Some(std::env::var("RAG_ENCRYPTION_RECIPIENT").map_err(|_| {
             AppError::bad_request("RAG_ENCRYPTION_RECIPIENT must be set when encrypt=true")
@@ -325,8 +370,16 @@ fn ingest_document(
         .embed_client
         .embed_batch(&texts)
         .map_err(AppError::internal)?;
+    let parents =
+        Chunker::new(ChunkConfig::default()).section_parents(&doc, &doc_record.source_path);
     let mut cells = Vec::with_capacity(ordered.len());
-    for ((cell, text), embedding) in ordered.iter().zip(texts.iter()).zip(embeddings.into_iter()) {
+    for ((((cell, text), embedding), sensitivity), parent_id) in ordered
+        .iter()
+        .zip(texts.iter())
+        .zip(embeddings)
+        .zip(levels)
+        .zip(parents)
+    {
         let (plain, encrypted, enc_label) = if let Some(ref recipient) = recipient {
             if text.is_empty() {
                 (None, None, None)
@@ -349,7 +402,7 @@ fn ingest_document(
         cells.push(CellInsert {
             page: cell.z,
             importance: cell.importance,
-            sensitivity: sensitivity.clone(),
+            sensitivity,
             text: plain,
             text_encrypted: encrypted,
             encryption: enc_label,
@@ -358,6 +411,7 @@ fn ingest_document(
             bbox_y: cell.y,
             bbox_w: cell.w,
             bbox_h: cell.h,
+            parent_id,
         });
     }
     state
@@ -369,6 +423,7 @@ fn ingest_document(
         document_id: doc_record.id,
         pages: doc.total_pages(),
         cells_indexed: cells.len(),
+        pii_redacted: doc.redactions.len(),
     })
 }
 
@@ -387,6 +442,12 @@ fn execute_rag_request(
     let tokenizer_kind = resolve_tokenizer(&tokenizer_name)?;
     let policy = parse_policy(body.policy.as_deref());
     let sensitivity = normalize_level(body.sensitivity_threshold.as_deref().unwrap_or("public"));
+    let numeric_check = match body.numeric_check.as_deref() {
+        Some(value) => value
+            .parse::<FaithfulnessPolicy>()
+            .map_err(AppError::bad_request)?,
+        None => FaithfulnessPolicy::Flag,
+    };
     let decrypt_identity = if matches!(policy, RagPolicy::Internal) {
         Some(
             std::env::var("RAG_DECRYPT_IDENTITY")
@@ -406,6 +467,8 @@ fn execute_rag_request(
         policy,
         tokenizer: tokenizer_kind.clone(),
         tokenizer_name: tokenizer_name.clone(),
+        numeric_check,
+        expand_parent_tokens: body.expand_parent_tokens,
     };
     let answer = execute_rag_query(
         &state.store,
@@ -440,6 +503,8 @@ fn execute_rag_request(
         raw_tokens_estimate: answer.metrics.raw_tokens_estimate,
         compressed_tokens_estimate: answer.metrics.compressed_tokens_estimate,
         compression_factor: answer.metrics.compression_factor,
+        numeric_faithfulness: answer.metrics.numeric_faithfulness,
+        unsupported_numbers: answer.metrics.unsupported_numbers.clone(),
         estimated_saving_usd: estimated_saving,
     };
     Ok(RagQueryResponse {